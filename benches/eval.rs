@@ -0,0 +1,56 @@
+//! Evaluation benchmarks. Run with `cargo bench`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use topogi_lang::loader::load_module;
+
+const FIB: &str = "(module main
+  (define fib (n) (if (= n 0) 0 (if (= n 1) 1 (+ (fib (- n 1)) (fib (- n 2))))))
+  (define main () (fib 18)))";
+
+const LOOP: &str = "(module main
+  (define count (n acc) (if (= n 0) acc (count (- n 1) (+ acc 1))))
+  (define main () (count 20000 0)))";
+
+const LISTS: &str = "(module main
+  (define upto (n acc) (if (= n 0) acc (upto (- n 1) (cons n acc))))
+  (define square (x) (* x x))
+  (define add (x acc) (+ x acc))
+  (define main () (foldr add 0 (map square (filter odd (upto 500 (list)))))))";
+
+const SHARE: &str = "(module main
+  (define upto (n acc) (if (= n 0) acc (upto (- n 1) (cons n acc))))
+  (define walk (xs n acc) (if (= n 0) acc (walk xs (- n 1) (+ acc (first xs)))))
+  (define main () (walk (upto 2000 (list)) 2000 0)))";
+
+fn bench(name: &str, source: &str) {
+    let module = load_module(source).unwrap();
+    measure(name, || module.run("main", vec![]).unwrap());
+    measure(&format!("{} (vm)", name), || {
+        module.run_compiled("main", vec![]).unwrap()
+    });
+}
+
+fn measure<T>(name: &str, mut run: impl FnMut() -> T) {
+    let mut iterations = 0u32;
+    let start = Instant::now();
+    while iterations == 0 || start.elapsed() < Duration::from_secs(2) {
+        black_box(run());
+        iterations += 1;
+    }
+    let per_iter = start.elapsed() / iterations;
+    println!(
+        "{:<8} {:>12?}/iter ({} iterations)",
+        name, per_iter, iterations
+    );
+}
+
+fn main() {
+    bench("fib", FIB);
+    bench("loop", LOOP);
+    bench("lists", LISTS);
+    bench("share", SHARE);
+}
@@ -0,0 +1,218 @@
+//! Checking the arguments of a builtin.
+//!
+//! A builtin wraps the values it is called with in [`Args`] and takes them
+//! apart by position, so that a mismatch names the builtin, the argument and
+//! what it should have been, as in
+//! `string/append expects a string as argument 2, got 1`.
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+
+use crate::{
+    ast::Exp,
+    eval::{Arity, EvalError, Result},
+    prelude::*,
+};
+
+/// The arguments of a call to the builtin `name`. Positions count from 0, and
+/// from 1 in the errors.
+#[derive(Debug, Clone, Copy)]
+pub struct Args<'a> {
+    name: &'static str,
+    args: &'a [Exp],
+}
+
+impl<'a> Args<'a> {
+    pub fn new(name: &'static str, args: &'a [Exp]) -> Self {
+        Args { name, args }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &'a [Exp] {
+        self.args
+    }
+
+    /// Fails unless the builtin was given as many arguments as it takes.
+    pub fn arity(self, expected: Arity) -> Result<Self> {
+        let fits = match expected {
+            Arity::Exactly(n) => self.len() == n,
+            Arity::AtLeast(min) => self.len() >= min,
+            Arity::Between(min, max) => (min..=max).contains(&self.len()),
+        };
+        if fits {
+            Ok(self)
+        } else {
+            Err(self.arity_mismatch(expected))
+        }
+    }
+
+    pub fn exactly(self, n: usize) -> Result<Self> {
+        self.arity(Arity::Exactly(n))
+    }
+
+    pub fn arity_mismatch(&self, expected: Arity) -> EvalError {
+        EvalError::ArityMismatch {
+            expected,
+            got: self.len(),
+            callee: Some(self.name.to_string()),
+        }
+    }
+
+    /// The error for an argument at `i` that is not `expected`, which is
+    /// described with an article, as in "a string".
+    pub fn mismatch(&self, i: usize, expected: &'static str) -> EvalError {
+        EvalError::TypeMismatch {
+            expected,
+            got: self.args[i].clone(),
+            callee: Some(self.name.to_string()),
+            position: Some(i + 1),
+        }
+    }
+
+    /// The argument at `i`. A missing argument is an arity mismatch.
+    pub fn get(&self, i: usize) -> Result<&'a Exp> {
+        self.args
+            .get(i)
+            .ok_or_else(|| self.arity_mismatch(Arity::AtLeast(i + 1)))
+    }
+
+    /// The argument at `i` as `parse` reads it, or a mismatch with `expected`
+    /// if it cannot.
+    pub fn parse<T>(
+        &self,
+        i: usize,
+        expected: &'static str,
+        parse: impl FnOnce(&'a Exp) -> Option<T>,
+    ) -> Result<T> {
+        parse(self.get(i)?.node()).ok_or_else(|| self.mismatch(i, expected))
+    }
+
+    pub fn integer(&self, i: usize) -> Result<i64> {
+        self.parse(i, "an integer", Exp::as_integer)
+    }
+
+    /// An integer that may not be negative, such as a count or an index.
+    pub fn count(&self, i: usize) -> Result<usize> {
+        self.parse(i, "a non-negative integer", |exp| {
+            exp.as_integer().and_then(|n| usize::try_from(n).ok())
+        })
+    }
+
+    pub fn bool(&self, i: usize) -> Result<bool> {
+        self.parse(i, "a boolean", Exp::as_bool)
+    }
+
+    pub fn string(&self, i: usize) -> Result<&'a str> {
+        self.parse(i, "a string", Exp::as_string)
+    }
+
+    pub fn symbol(&self, i: usize) -> Result<&'a str> {
+        self.parse(i, "a symbol", Exp::as_symbol)
+    }
+
+    pub fn keyword(&self, i: usize) -> Result<&'a str> {
+        self.parse(i, "a keyword", |exp| match exp {
+            Exp::Keyword(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn char(&self, i: usize) -> Result<char> {
+        self.parse(i, "a character", |exp| match exp {
+            Exp::Char(ch) => Some(*ch),
+            _ => None,
+        })
+    }
+
+    /// A list, of which `nil` is the empty one.
+    pub fn list(&self, i: usize) -> Result<&'a [Exp]> {
+        self.parse(i, "a list", |exp| match exp {
+            Exp::Nil => Some(&[][..]),
+            Exp::List(list) => Some(&list[..]),
+            _ => None,
+        })
+    }
+
+    pub fn vector(&self, i: usize) -> Result<&'a Rc<Vec<Exp>>> {
+        self.parse(i, "a vector", |exp| match exp {
+            Exp::Vector(vector) => Some(vector),
+            _ => None,
+        })
+    }
+
+    pub fn map(&self, i: usize) -> Result<&'a Rc<BTreeMap<Exp, Exp>>> {
+        self.parse(i, "a map", |exp| match exp {
+            Exp::Map(map) => Some(map),
+            _ => None,
+        })
+    }
+
+    pub fn set(&self, i: usize) -> Result<&'a Rc<BTreeSet<Exp>>> {
+        self.parse(i, "a set", |exp| match exp {
+            Exp::Set(set) => Some(set),
+            _ => None,
+        })
+    }
+
+    pub fn bytes(&self, i: usize) -> Result<&'a Rc<[u8]>> {
+        self.parse(i, "bytes", |exp| match exp {
+            Exp::Bytes(bytes) => Some(bytes),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::*;
+
+    #[test]
+    fn test_args() {
+        let values = [string("a"), integer(1)];
+        let args = Args::new("string/append", &values);
+        assert_eq!(args.string(0), Ok("a"));
+        assert_eq!(
+            args.string(1).map_err(|err| err.to_string()),
+            Err("string/append expects a string as argument 2, got 1".to_string())
+        );
+        assert_eq!(
+            args.get(2),
+            Err(EvalError::ArityMismatch {
+                expected: Arity::AtLeast(3),
+                got: 2,
+                callee: Some("string/append".to_string()),
+            })
+        );
+        assert!(args.exactly(2).is_ok());
+        assert_eq!(
+            args.arity(Arity::Between(3, 4))
+                .map(|_| ())
+                .map_err(|err| err.to_string()),
+            Err("string/append takes 3 to 4 arguments but was given 2".to_string())
+        );
+        assert_eq!(Args::new("f", &[Exp::Nil]).list(0), Ok(&[][..]));
+        assert_eq!(
+            Args::new("f", &[integer(-1)]).count(0),
+            Err(EvalError::TypeMismatch {
+                expected: "a non-negative integer",
+                got: integer(-1),
+                callee: Some("f".to_string()),
+                position: Some(1),
+            })
+        );
+    }
+}
@@ -1,12 +1,34 @@
-use std::{collections::HashMap, fmt::Display};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+use core::{cmp::Ordering, fmt::Display};
 
-use crate::eval::{EvalError, VariableGenerator};
+#[cfg(feature = "bignum")]
+use num_bigint::BigInt;
+
+use crate::{
+    eval::{BuiltinFn, EvalError},
+    prelude::*,
+    symbol::SymbolId,
+    token::Span,
+    HashMap,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Module {
     pub name: String,
-    pub defines: HashMap<String, Exp>,
-    pub macros: HashMap<String, (Exp, Vec<Exp>)>,
+    pub defines: HashMap<SymbolId, Exp>,
+    pub macros: HashMap<SymbolId, (Exp, Vec<Exp>)>,
+    /// The bodies of the `deftest` forms, in the order they are written.
+    pub tests: Vec<(String, Exp)>,
+    /// The modules named by `(import name)`, whose exports this one refers to
+    /// by qualified names such as `math/square`, see
+    /// [`link`](crate::loader::link).
+    pub imports: Vec<String>,
+    /// The definitions named by `(export name ...)`, which are the only ones
+    /// that modules importing this one can refer to.
+    pub exports: Vec<SymbolId>,
 }
 
 impl Module {
@@ -15,102 +37,671 @@ impl Module {
             name: name.to_string(),
             defines: HashMap::new(),
             macros: HashMap::new(),
+            tests: vec![],
+            imports: vec![],
+            exports: vec![],
         }
     }
+
+    /// Binds `name` to the builtin `f`, which is called with all the
+    /// arguments of a call at once. This replaces any definition of `name`,
+    /// including a builtin of the standard library.
+    pub fn register_builtin(&mut self, name: &str, f: BuiltinFn) {
+        self.defines.insert(SymbolId::intern(name), Exp::BuildIn(f));
+    }
+
+    /// Binds `name` to a curried function of two arguments, which calls the
+    /// builtin `f` once it has both, so that it can be partially applied as
+    /// in `(map (+ 1) xs)`.
+    pub fn register_curried_binary(&mut self, name: &str, f: BuiltinFn) {
+        self.defines.insert(
+            SymbolId::intern(name),
+            lambda(
+                "x",
+                lambda("y", list(&[Exp::BuildIn(f), symbol("x"), symbol("y")])),
+            ),
+        );
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Variable bindings visible to an expression, innermost first.
+///
+/// Environments are persistent: `bind` shares the bindings it extends, so
+/// capturing an environment in a closure is cheap.
+#[derive(Debug, Clone, Default)]
+pub struct Env(Option<Rc<(Binding, Env)>>);
+
+#[derive(Debug)]
+enum Binding {
+    One((SymbolId, Exp)),
+    /// Lambdas bound by `letrec`. They are closed over the environment that
+    /// binds them when looked up, so the environment holds no cycles.
+    Rec(Rc<[(SymbolId, Exp)]>),
+}
+
+impl Binding {
+    fn as_slice(&self) -> &[(SymbolId, Exp)] {
+        match self {
+            Binding::One(binding) => core::slice::from_ref(binding),
+            Binding::Rec(bindings) => bindings,
+        }
+    }
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Env(None)
+    }
+
+    pub fn bind(&self, name: SymbolId, value: Exp) -> Env {
+        Env(Some(Rc::new((Binding::One((name, value)), self.clone()))))
+    }
+
+    /// Binds lambdas that can refer to each other and to themselves.
+    pub fn bind_rec(&self, bindings: Rc<[(SymbolId, Exp)]>) -> Env {
+        Env(Some(Rc::new((Binding::Rec(bindings), self.clone()))))
+    }
+
+    /// Returns the environment without its innermost binding.
+    pub fn parent(&self) -> Env {
+        match &self.0 {
+            Some(binding) => binding.1.clone(),
+            None => Env::new(),
+        }
+    }
+
+    pub fn lookup(&self, name: SymbolId) -> Option<Exp> {
+        let mut env = self;
+        while let Some(node) = &env.0 {
+            if let Some((_, value)) = node.0.as_slice().iter().find(|(bound, _)| *bound == name) {
+                return Some(match (&node.0, value.node()) {
+                    (Binding::Rec(_), Exp::Lambda(x, body)) => {
+                        Exp::Closure(*x, body.clone(), env.clone())
+                    }
+                    _ => value.clone(),
+                });
+            }
+            env = &node.1;
+        }
+        None
+    }
+
+    pub fn bindings(&self) -> impl Iterator<Item = (SymbolId, &Exp)> {
+        core::iter::successors(self.0.as_deref(), |(_, parent)| parent.0.as_deref()).flat_map(
+            |(binding, _)| {
+                binding
+                    .as_slice()
+                    .iter()
+                    .map(|(name, value)| (*name, value))
+            },
+        )
+    }
+}
+
+impl Ord for Env {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.bindings().cmp(other.bindings())
+    }
+}
+
+impl PartialOrd for Env {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Env {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Env {}
+
+#[derive(Debug, Clone)]
 pub enum Exp {
     Nil,
+    Void,
     Bool(bool),
     Integer(i64),
+    /// An integer outside the range of `i64`. Smaller values are always
+    /// represented as `Integer`, see [`big_integer`].
+    #[cfg(feature = "bignum")]
+    BigInt(BigInt),
+    /// A fraction in lowest terms whose denominator is greater than 1, see
+    /// [`checked_rational`].
+    Rational(i64, i64),
+    Float(f64),
     String(String),
-    Symbol(String),
-    Lambda(String, Box<Exp>),
-    Apply(Box<Exp>, Box<Exp>),
-    List(Vec<Exp>),
-    If(Box<Exp>, Box<Exp>, Box<Exp>),
-    Quote(Box<Exp>),
-    UnQuote(Box<Exp>),
-    Let((String, Box<Exp>), Box<Exp>),
-    BuildIn(fn(&[Exp], &Module, &mut VariableGenerator) -> Result<Exp, EvalError>),
+    Char(char),
+    /// A self-evaluating name written `:name`, used to tag data.
+    Keyword(String),
+    Symbol(SymbolId),
+    Lambda(SymbolId, Rc<Exp>),
+    Apply(Rc<Exp>, Rc<Exp>),
+    List(Rc<[Exp]>),
+    Values(Rc<[Exp]>),
+    /// A map from keys to values. It is shared until it is updated, which
+    /// copies it, so updates never affect other references.
+    Map(Rc<BTreeMap<Exp, Exp>>),
+    /// A set of values, shared and copied on update like [`Exp::Map`].
+    Set(Rc<BTreeSet<Exp>>),
+    /// A contiguous sequence with constant time indexing, shared and copied on
+    /// update like [`Exp::Map`].
+    Vector(Rc<Vec<Exp>>),
+    /// An immutable string of bytes, for binary data.
+    Bytes(Rc<[u8]>),
+    /// A pair whose tail is not a list, written `(a . b)`. Pairs with a list
+    /// tail are always represented as `List`, see [`pair`].
+    Pair(Rc<Exp>, Rc<Exp>),
+    If(Rc<Exp>, Rc<Exp>, Rc<Exp>),
+    Quote(Rc<Exp>),
+    UnQuote(Rc<Exp>),
+    /// An unquoted expression whose value, a list, is spliced into the quoted
+    /// list around it.
+    UnQuoteSplicing(Rc<Exp>),
+    Let((SymbolId, Rc<Exp>), Rc<Exp>),
+    /// Lambdas that can refer to themselves and each other, see
+    /// [`Env::bind_rec`].
+    LetRec(Rc<[(SymbolId, Exp)]>, Rc<Exp>),
+    /// Arms of a pattern, an optional guard and a body, see
+    /// [`match_pattern`](crate::eval::match_pattern).
+    Case(Rc<Exp>, Vec<(Exp, Option<Exp>, Exp)>),
+    /// `(try body (catch x handler))`: the value of `body`, or if evaluating
+    /// it fails, the value of `handler` with `x` bound to the error.
+    Try(Rc<Exp>, SymbolId, Rc<Exp>),
+    BuildIn(BuiltinFn),
+    /// A function of the host program, which unlike a builtin may capture
+    /// state, see [`Interpreter::register_fn`](crate::interpreter::Interpreter::register_fn).
+    HostFn(HostFn),
+    /// A function that takes all the arguments of a call at once. They are
+    /// passed as a list to the wrapped lambda or closure, see [`variadic`].
+    Variadic(Rc<Exp>),
+    /// The value of a lambda: its parameter and body together with the
+    /// environment it was evaluated in.
+    Closure(SymbolId, Rc<Exp>, Env),
+    /// A node annotated with the source range it was parsed from. Spans are
+    /// ignored by comparison and display.
+    Spanned(Span, Rc<Exp>),
 }
 
 impl Exp {
-    pub fn as_nil(&self) -> Option<()> {
+    /// Returns the expression with any span annotations around it removed.
+    pub fn node(&self) -> &Exp {
+        match self {
+            Exp::Spanned(_, exp) => exp.node(),
+            _ => self,
+        }
+    }
+
+    pub fn into_node(self) -> Exp {
         match self {
+            Exp::Spanned(_, exp) => Rc::unwrap_or_clone(exp).into_node(),
+            _ => self,
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Exp::Spanned(span, _) => Some(*span),
+            _ => None,
+        }
+    }
+
+    pub fn as_nil(&self) -> Option<()> {
+        match self.node() {
             Exp::Nil => Some(()),
             _ => None,
         }
     }
 
     pub fn as_bool(&self) -> Option<bool> {
-        match self {
+        match self.node() {
             Exp::Bool(b) => Some(*b),
             _ => None,
         }
     }
 
     pub fn as_integer(&self) -> Option<i64> {
-        match self {
+        match self.node() {
             Exp::Integer(i) => Some(*i),
             _ => None,
         }
     }
 
+    /// Returns an integer or rational as a numerator and denominator.
+    pub fn as_rational(&self) -> Option<(i64, i64)> {
+        match self.node() {
+            Exp::Integer(i) => Some((*i, 1)),
+            Exp::Rational(n, d) => Some((*n, *d)),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self.node() {
+            Exp::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
     pub fn as_string(&self) -> Option<&str> {
-        match self {
+        match self.node() {
             Exp::String(s) => Some(s),
             _ => None,
         }
     }
 
     pub fn as_symbol(&self) -> Option<&str> {
-        match self {
-            Exp::Symbol(s) => Some(s),
+        match self.node() {
+            Exp::Symbol(s) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn as_symbol_id(&self) -> Option<SymbolId> {
+        match self.node() {
+            Exp::Symbol(s) => Some(*s),
             _ => None,
         }
     }
 
     pub fn as_list(&self) -> Option<&[Exp]> {
-        match self {
+        match self.node() {
             Exp::List(l) => Some(l),
             _ => None,
         }
     }
+
+    /// The head and tail of a non-empty list or a pair. The tail of a list is
+    /// copied, so [`Exp::head`] is cheaper when only the head is needed.
+    pub fn uncons(&self) -> Option<(Exp, Exp)> {
+        Some((self.head()?.clone(), self.tail()?))
+    }
+
+    /// The first element of a non-empty list or the head of a pair.
+    pub fn head(&self) -> Option<&Exp> {
+        match self.node() {
+            Exp::List(l) => l.first(),
+            Exp::Pair(head, _) => Some(head),
+            _ => None,
+        }
+    }
+
+    /// The elements of a non-empty list after the first, or the tail of a
+    /// pair.
+    pub fn tail(&self) -> Option<Exp> {
+        match self.node() {
+            Exp::List(l) => l.split_first().map(|(_, tail)| Exp::List(tail.into())),
+            Exp::Pair(_, tail) => Some((**tail).clone()),
+            _ => None,
+        }
+    }
 }
 
-impl Display for Exp {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+/// A function of the host. Like a builtin it takes all the arguments of a call
+/// at once. Host functions are equal only to themselves and their clones.
+#[derive(Clone)]
+pub struct HostFn(pub Rc<HostFunction>);
+
+pub type HostFunction = dyn Fn(&[Exp]) -> Result<Exp, EvalError>;
+
+impl HostFn {
+    pub fn new(f: impl Fn(&[Exp]) -> Result<Exp, EvalError> + 'static) -> Self {
+        HostFn(Rc::new(f))
+    }
+
+    fn addr(&self) -> usize {
+        Rc::as_ptr(&self.0) as *const () as usize
+    }
+}
+
+impl core::fmt::Debug for HostFn {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "HostFn({:#x})", self.addr())
+    }
+}
+
+impl Exp {
+    fn rank(&self) -> u8 {
+        match self {
+            Exp::Nil => 0,
+            Exp::Void => 1,
+            Exp::Bool(_) => 2,
+            Exp::Integer(_) => 3,
+            #[cfg(feature = "bignum")]
+            Exp::BigInt(_) => 3,
+            Exp::Rational(..) => 3,
+            Exp::Float(_) => 4,
+            Exp::String(_) => 5,
+            Exp::Symbol(_) => 6,
+            Exp::List(_) => 7,
+            Exp::Values(_) => 8,
+            Exp::Quote(_) => 9,
+            Exp::UnQuote(_) => 10,
+            Exp::Lambda(..) => 11,
+            Exp::Apply(..) => 12,
+            Exp::If(..) => 13,
+            Exp::Let(..) => 14,
+            Exp::LetRec(..) => 15,
+            Exp::Case(..) => 16,
+            Exp::BuildIn(_) => 17,
+            Exp::Closure(..) => 18,
+            Exp::Variadic(_) => 19,
+            Exp::UnQuoteSplicing(_) => 20,
+            Exp::Map(_) => 21,
+            Exp::Set(_) => 22,
+            Exp::Vector(_) => 23,
+            Exp::Bytes(_) => 24,
+            Exp::Char(_) => 25,
+            Exp::Keyword(_) => 26,
+            Exp::Pair(..) => 27,
+            Exp::Try(..) => 28,
+            Exp::HostFn(_) => 29,
+            Exp::Spanned(_, exp) => exp.rank(),
+        }
+    }
+}
+
+/// Total order over expressions.
+///
+/// Values of different kinds are ordered `Nil < Void < Bool < Integer < Float <
+/// String < Symbol < List`, followed by the remaining syntactic forms. Values of
+/// the same kind use their natural order: `Bool(false) < Bool(true)`, integers
+/// and rationals by value, floats by [`f64::total_cmp`], strings and symbols lexicographically and
+/// lists lexicographically on their elements. Span annotations are ignored.
+impl Ord for Exp {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.node(), other.node()) {
+            (Exp::Bool(a), Exp::Bool(b)) => a.cmp(b),
+            (Exp::Integer(a), Exp::Integer(b)) => a.cmp(b),
+            #[cfg(feature = "bignum")]
+            (Exp::BigInt(a), Exp::BigInt(b)) => a.cmp(b),
+            #[cfg(feature = "bignum")]
+            (Exp::Integer(a), Exp::BigInt(b)) => BigInt::from(*a).cmp(b),
+            #[cfg(feature = "bignum")]
+            (Exp::BigInt(a), Exp::Integer(b)) => a.cmp(&BigInt::from(*b)),
+            #[cfg(feature = "bignum")]
+            (Exp::BigInt(a), Exp::Rational(n, d)) => (a * d).cmp(&BigInt::from(*n)),
+            #[cfg(feature = "bignum")]
+            (Exp::Rational(n, d), Exp::BigInt(b)) => BigInt::from(*n).cmp(&(b * d)),
+            (Exp::Integer(_) | Exp::Rational(..), Exp::Integer(_) | Exp::Rational(..)) => {
+                let (n1, d1) = self.as_rational().unwrap();
+                let (n2, d2) = other.as_rational().unwrap();
+                (n1 as i128 * d2 as i128).cmp(&(n2 as i128 * d1 as i128))
+            }
+            (Exp::Float(a), Exp::Float(b)) => a.total_cmp(b),
+            (Exp::String(a), Exp::String(b)) => a.cmp(b),
+            (Exp::Symbol(a), Exp::Symbol(b)) => a.cmp(b),
+            (Exp::List(a), Exp::List(b)) => a.cmp(b),
+            (Exp::Values(a), Exp::Values(b)) => a.cmp(b),
+            (Exp::Map(a), Exp::Map(b)) => a.cmp(b),
+            (Exp::Set(a), Exp::Set(b)) => a.cmp(b),
+            (Exp::Vector(a), Exp::Vector(b)) => a.cmp(b),
+            (Exp::Bytes(a), Exp::Bytes(b)) => a.cmp(b),
+            (Exp::Char(a), Exp::Char(b)) => a.cmp(b),
+            (Exp::Keyword(a), Exp::Keyword(b)) => a.cmp(b),
+            (Exp::Pair(a1, a2), Exp::Pair(b1, b2)) => (a1, a2).cmp(&(b1, b2)),
+            (Exp::Try(a1, a2, a3), Exp::Try(b1, b2, b3)) => (a1, a2, a3).cmp(&(b1, b2, b3)),
+            (Exp::Quote(a), Exp::Quote(b)) => a.cmp(b),
+            (Exp::UnQuote(a), Exp::UnQuote(b)) => a.cmp(b),
+            (Exp::UnQuoteSplicing(a), Exp::UnQuoteSplicing(b)) => a.cmp(b),
+            (Exp::Lambda(a1, a2), Exp::Lambda(b1, b2)) => (a1, a2).cmp(&(b1, b2)),
+            (Exp::Apply(a1, a2), Exp::Apply(b1, b2)) => (a1, a2).cmp(&(b1, b2)),
+            (Exp::If(a1, a2, a3), Exp::If(b1, b2, b3)) => (a1, a2, a3).cmp(&(b1, b2, b3)),
+            (Exp::Let(a1, a2), Exp::Let(b1, b2)) => (a1, a2).cmp(&(b1, b2)),
+            (Exp::LetRec(a1, a2), Exp::LetRec(b1, b2)) => (a1, a2).cmp(&(b1, b2)),
+            (Exp::Variadic(a), Exp::Variadic(b)) => a.cmp(b),
+            (Exp::Case(a1, a2), Exp::Case(b1, b2)) => (a1, a2).cmp(&(b1, b2)),
+            (Exp::BuildIn(a), Exp::BuildIn(b)) => (*a as usize).cmp(&(*b as usize)),
+            (Exp::HostFn(a), Exp::HostFn(b)) => a.addr().cmp(&b.addr()),
+            (Exp::Closure(a1, a2, a3), Exp::Closure(b1, b2, b3)) => (a1, a2, a3).cmp(&(b1, b2, b3)),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialEq for Exp {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Exp {}
+
+impl PartialOrd for Exp {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Writes `exps` separated by spaces.
+fn write_seq<'a>(
+    f: &mut core::fmt::Formatter<'_>,
+    exps: impl IntoIterator<Item = &'a Exp>,
+    readable: bool,
+) -> core::fmt::Result {
+    for (i, exp) in exps.into_iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        exp.write(f, readable)?;
+    }
+    Ok(())
+}
+
+/// Writes a string literal that the lexer reads back as `s`.
+fn write_string_literal(f: &mut core::fmt::Formatter<'_>, s: &str) -> core::fmt::Result {
+    write!(f, "\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            '\r' => write!(f, "\\r")?,
+            ch => write!(f, "{}", ch)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl Exp {
+    /// Writes the expression. Strings are quoted and escaped if `readable`,
+    /// and written as they are otherwise.
+    fn write(&self, f: &mut core::fmt::Formatter<'_>, readable: bool) -> core::fmt::Result {
         match self {
             Exp::Nil => write!(f, "nil"),
+            Exp::Void => write!(f, "#<void>"),
             Exp::Bool(bool) => write!(f, "{}", bool),
             Exp::Integer(integer) => write!(f, "{}", integer),
+            #[cfg(feature = "bignum")]
+            Exp::BigInt(integer) => write!(f, "{}", integer),
+            Exp::Rational(numerator, denominator) => write!(f, "{}/{}", numerator, denominator),
+            // Debug formatting keeps the decimal point of whole floats.
+            Exp::Float(float) => write!(f, "{:?}", float),
+            Exp::String(str) if readable => write_string_literal(f, str),
             Exp::String(str) => write!(f, "{}", str),
             Exp::Symbol(sym) => write!(f, "{}", sym),
-            Exp::Lambda(arg, exp) => write!(f, "(\\ ({}) {})", arg, exp),
-            Exp::Apply(exp1, exp2) => write!(f, "({} {})", exp1, exp2),
-            Exp::List(exps) => write!(
+            Exp::Lambda(arg, exp) | Exp::Closure(arg, exp, _) => {
+                write!(f, "(\\ ({}) ", arg)?;
+                exp.write(f, readable)?;
+                write!(f, ")")
+            }
+            Exp::Variadic(exp) => match exp.node() {
+                Exp::Lambda(arg, exp) | Exp::Closure(arg, exp, _) => {
+                    write!(f, "(\\ (. {}) ", arg)?;
+                    exp.write(f, readable)?;
+                    write!(f, ")")
+                }
+                exp => {
+                    write!(f, "#<variadic ")?;
+                    exp.write(f, readable)?;
+                    write!(f, ">")
+                }
+            },
+            Exp::Apply(exp1, exp2) => {
+                write!(f, "(")?;
+                write_seq(f, [&**exp1, &**exp2], readable)?;
+                write!(f, ")")
+            }
+            Exp::List(exps) => {
+                write!(f, "(")?;
+                write_seq(f, exps.iter(), readable)?;
+                write!(f, ")")
+            }
+            Exp::Values(exps) => {
+                write!(f, "#<values ")?;
+                write_seq(f, exps.iter(), readable)?;
+                write!(f, ">")
+            }
+            Exp::Map(map) => {
+                write!(f, "{{")?;
+                write_seq(
+                    f,
+                    map.iter().flat_map(|(key, value)| [key, value]),
+                    readable,
+                )?;
+                write!(f, "}}")
+            }
+            Exp::Set(set) => {
+                write!(f, "#{{")?;
+                write_seq(f, set.iter(), readable)?;
+                write!(f, "}}")
+            }
+            Exp::Vector(exps) => {
+                write!(f, "[")?;
+                write_seq(f, exps.iter(), readable)?;
+                write!(f, "]")
+            }
+            Exp::Char(ch) => match ch {
+                ' ' => write!(f, "#\\space"),
+                '\n' => write!(f, "#\\newline"),
+                '\t' => write!(f, "#\\tab"),
+                '\r' => write!(f, "#\\return"),
+                '\0' => write!(f, "#\\nul"),
+                ch => write!(f, "#\\{}", ch),
+            },
+            Exp::Keyword(name) => write!(f, ":{}", name),
+            Exp::Pair(head, tail) => {
+                write!(f, "(")?;
+                head.write(f, readable)?;
+                let mut tail = &**tail;
+                while let Exp::Pair(head, rest) = tail.node() {
+                    write!(f, " ")?;
+                    head.write(f, readable)?;
+                    tail = rest;
+                }
+                write!(f, " . ")?;
+                tail.write(f, readable)?;
+                write!(f, ")")
+            }
+            Exp::Bytes(bytes) => write!(
                 f,
-                "({})",
-                exps.iter()
-                    .map(|exp| exp.to_string())
+                "#u8({})",
+                bytes
+                    .iter()
+                    .map(|byte| byte.to_string())
                     .collect::<Vec<_>>()
                     .join(" ")
             ),
-            Exp::If(cond, then, else_) => write!(f, "(if {} {} {})", cond, then, else_),
-            Exp::Quote(exp) => write!(f, "'{}", exp),
-            Exp::UnQuote(exp) => write!(f, "~{}", exp),
-            Exp::Let((bind, exp1), exp2) => write!(f, "(let ({} {}) {})", bind, exp1, exp2),
-            Exp::BuildIn(_) => write!(f, "#buildin",),
+            Exp::If(cond, then, else_) => {
+                write!(f, "(if ")?;
+                write_seq(f, [&**cond, &**then, &**else_], readable)?;
+                write!(f, ")")
+            }
+            Exp::Quote(exp) => {
+                write!(f, "'")?;
+                exp.write(f, readable)
+            }
+            Exp::UnQuote(exp) => {
+                write!(f, "~")?;
+                exp.write(f, readable)
+            }
+            Exp::UnQuoteSplicing(exp) => {
+                write!(f, "~@")?;
+                exp.write(f, readable)
+            }
+            Exp::Let((bind, exp1), exp2) => {
+                write!(f, "(let ({} ", bind)?;
+                exp1.write(f, readable)?;
+                write!(f, ") ")?;
+                exp2.write(f, readable)?;
+                write!(f, ")")
+            }
+            Exp::LetRec(binds, exp) => {
+                write!(f, "(letrec (")?;
+                for (i, (bind, exp)) in binds.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "({} ", bind)?;
+                    exp.write(f, readable)?;
+                    write!(f, ")")?;
+                }
+                write!(f, ") ")?;
+                exp.write(f, readable)?;
+                write!(f, ")")
+            }
+            Exp::Case(exp, cases) => {
+                write!(f, "(case ")?;
+                exp.write(f, readable)?;
+                for (pattern, guard, body) in cases.iter() {
+                    write!(f, " (")?;
+                    pattern.write(f, readable)?;
+                    if let Some(guard) = guard {
+                        write!(f, " (when ")?;
+                        guard.write(f, readable)?;
+                        write!(f, ")")?;
+                    }
+                    write!(f, " ")?;
+                    body.write(f, readable)?;
+                    write!(f, ")")?;
+                }
+                write!(f, ")")
+            }
+            Exp::Try(body, x, handler) => {
+                write!(f, "(try ")?;
+                body.write(f, readable)?;
+                write!(f, " (catch {} ", x)?;
+                handler.write(f, readable)?;
+                write!(f, "))")
+            }
+            Exp::BuildIn(_) | Exp::HostFn(_) => write!(f, "#buildin",),
+            Exp::Spanned(_, exp) => exp.write(f, readable),
         }
     }
+
+    /// The expression as the parser reads it back: like its [`Display`], but
+    /// with strings quoted and escaped.
+    pub fn to_readable_string(&self) -> String {
+        Readable(self).to_string()
+    }
+}
+
+/// Displays an expression readably, see [`Exp::to_readable_string`].
+pub struct Readable<'a>(pub &'a Exp);
+
+impl Display for Readable<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.0.write(f, true)
+    }
+}
+
+impl Display for Exp {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.write(f, false)
+    }
 }
 
 pub fn nil() -> Exp {
     Exp::Nil
 }
 
+pub fn void() -> Exp {
+    Exp::Void
+}
+
 pub fn bool(b: bool) -> Exp {
     Exp::Bool(b)
 }
@@ -119,42 +710,226 @@ pub fn integer(i: i64) -> Exp {
     Exp::Integer(i)
 }
 
+/// Returns `i` as an `Integer` if it fits, or as a `BigInt` otherwise.
+#[cfg(feature = "bignum")]
+pub fn big_integer(i: BigInt) -> Exp {
+    match i64::try_from(&i) {
+        Ok(i) => Exp::Integer(i),
+        Err(_) => Exp::BigInt(i),
+    }
+}
+
+fn gcd(a: i128, b: i128) -> Option<i128> {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    i128::try_from(a).ok()
+}
+
+/// Returns `numerator/denominator` in lowest terms with a positive
+/// denominator, or as an `Integer` if it is whole. Returns `None` if the
+/// denominator is zero or the result does not fit `i64`.
+pub fn checked_rational(numerator: i128, denominator: i128) -> Option<Exp> {
+    if denominator == 0 {
+        return None;
+    }
+    let divisor = gcd(numerator, denominator)? * denominator.signum();
+    let numerator = i64::try_from(numerator / divisor).ok()?;
+    let denominator = i64::try_from(denominator / divisor).ok()?;
+    Some(match denominator {
+        1 => Exp::Integer(numerator),
+        _ => Exp::Rational(numerator, denominator),
+    })
+}
+
+/// Like [`checked_rational`], but panics on a zero denominator.
+pub fn rational(numerator: i64, denominator: i64) -> Exp {
+    checked_rational(numerator.into(), denominator.into()).expect("invalid rational")
+}
+
+pub fn float(f: f64) -> Exp {
+    Exp::Float(f)
+}
+
 pub fn string(s: &str) -> Exp {
     Exp::String(s.to_string())
 }
 
 pub fn symbol(sym: &str) -> Exp {
-    Exp::Symbol(sym.to_string())
+    Exp::Symbol(SymbolId::intern(sym))
 }
 
 pub fn lambda(param: &str, body: Exp) -> Exp {
-    Exp::Lambda(param.to_string(), Box::new(body))
+    Exp::Lambda(SymbolId::intern(param), Rc::new(body))
+}
+
+/// A curried lambda of several parameters, that is nested single-parameter
+/// lambdas. Without parameters this is just `body`.
+pub fn lambda_n(params: &[&str], body: Exp) -> Exp {
+    params
+        .iter()
+        .rev()
+        .fold(body, |body, param| lambda(param, body))
 }
 
 pub fn apply(e1: Exp, e2: Exp) -> Exp {
-    Exp::Apply(Box::new(e1), Box::new(e2))
+    Exp::Apply(Rc::new(e1), Rc::new(e2))
 }
 
 pub fn list(list: &[Exp]) -> Exp {
-    Exp::List(list.to_vec())
+    Exp::List(list.into())
+}
+
+pub fn values(values: &[Exp]) -> Exp {
+    Exp::Values(values.into())
+}
+
+pub fn map_of(entries: &[(Exp, Exp)]) -> Exp {
+    Exp::Map(Rc::new(entries.iter().cloned().collect()))
+}
+
+pub fn set_of(elems: &[Exp]) -> Exp {
+    Exp::Set(Rc::new(elems.iter().cloned().collect()))
+}
+
+pub fn vector(elems: &[Exp]) -> Exp {
+    Exp::Vector(Rc::new(elems.to_vec()))
+}
+
+pub fn bytes(bytes: &[u8]) -> Exp {
+    Exp::Bytes(bytes.into())
+}
+
+/// The pair of `head` and `tail`. A list or nil tail gives a list with `head`
+/// prepended, as in other Lisps.
+pub fn pair(head: Exp, tail: Exp) -> Exp {
+    match tail.node() {
+        Exp::List(tail) => Exp::List(core::iter::once(head).chain(tail.iter().cloned()).collect()),
+        Exp::Nil => Exp::List([head].into()),
+        _ => Exp::Pair(Rc::new(head), Rc::new(tail)),
+    }
+}
+
+pub fn try_(body: Exp, x: &str, handler: Exp) -> Exp {
+    Exp::Try(Rc::new(body), x.into(), Rc::new(handler))
+}
+
+pub fn keyword(name: &str) -> Exp {
+    Exp::Keyword(name.to_string())
 }
 
 pub fn if_(cond: Exp, then: Exp, else_: Exp) -> Exp {
-    Exp::If(Box::new(cond), Box::new(then), Box::new(else_))
+    Exp::If(Rc::new(cond), Rc::new(then), Rc::new(else_))
 }
 
 pub fn let_(bind: (&str, Exp), exp: Exp) -> Exp {
-    Exp::Let((bind.0.to_string(), Box::new(bind.1)), Box::new(exp))
+    Exp::Let((SymbolId::intern(bind.0), Rc::new(bind.1)), Rc::new(exp))
+}
+
+/// A lambda that binds `rest` to the list of all the arguments it is called
+/// with, such as `(\ (. rest) body)`.
+pub fn variadic(rest: &str, body: Exp) -> Exp {
+    Exp::Variadic(Rc::new(lambda(rest, body)))
+}
+
+pub fn letrec(binds: &[(&str, Exp)], exp: Exp) -> Exp {
+    Exp::LetRec(
+        binds
+            .iter()
+            .map(|(bind, value)| (SymbolId::intern(bind), value.clone()))
+            .collect(),
+        Rc::new(exp),
+    )
+}
+
+pub fn case(exp: Exp, cases: &[(Exp, Exp)]) -> Exp {
+    Exp::Case(
+        Rc::new(exp),
+        cases
+            .iter()
+            .map(|(pattern, body)| (pattern.clone(), None, body.clone()))
+            .collect(),
+    )
 }
 
 pub fn quote(e: Exp) -> Exp {
-    Exp::Quote(Box::new(e))
+    Exp::Quote(Rc::new(e))
 }
 
 pub fn unquote(e: Exp) -> Exp {
-    Exp::UnQuote(Box::new(e))
+    Exp::UnQuote(Rc::new(e))
+}
+
+pub fn unquote_splicing(e: Exp) -> Exp {
+    Exp::UnQuoteSplicing(Rc::new(e))
+}
+
+pub fn spanned(span: Span, e: Exp) -> Exp {
+    Exp::Spanned(span, Rc::new(e))
 }
 
-pub fn buildin(f: fn(&[Exp], &Module, &mut VariableGenerator) -> Result<Exp, EvalError>) -> Exp {
+pub fn buildin(f: BuiltinFn) -> Exp {
     Exp::BuildIn(f)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::Location;
+
+    #[test]
+    fn test_rational() {
+        assert_eq!(rational(2, 4), Exp::Rational(1, 2));
+        assert_eq!(rational(3, -6), Exp::Rational(-1, 2));
+        assert_eq!(rational(-4, -2), integer(2));
+        assert_eq!(rational(0, 5), integer(0));
+        assert_eq!(rational(1, 3).to_string(), "1/3");
+        assert_eq!(checked_rational(1, 0), None);
+        assert_eq!(checked_rational(i64::MIN.into(), -1), None);
+    }
+
+    #[test]
+    fn test_ord() {
+        assert!(integer(1) < integer(2));
+        assert!(string("a") < string("b"));
+        assert!(bool(false) < bool(true));
+        assert!(integer(1) < string("a"));
+        assert!(nil() < bool(false));
+        assert!(symbol("z") < list(&[]));
+        assert!(list(&[integer(1), integer(2)]) < list(&[integer(1), integer(3)]));
+        assert!(list(&[integer(1)]) < list(&[integer(1), integer(0)]));
+        assert!(float(-0.5) < float(0.5));
+        assert!(integer(0) < rational(1, 3) && rational(1, 3) < rational(1, 2));
+        assert!(rational(-7, 2) < integer(-3));
+        assert!(integer(2) < float(1.0));
+        assert_eq!(float(f64::NAN), float(f64::NAN));
+
+        let span = Span::new(Location::new(0, 0), Location::new(0, 1));
+        assert_eq!(spanned(span, integer(1)), integer(1));
+        assert!(spanned(span, integer(1)) < integer(2));
+
+        let mut exps = vec![
+            list(&[integer(1)]),
+            symbol("a"),
+            string("b"),
+            integer(2),
+            bool(true),
+            nil(),
+            integer(-1),
+        ];
+        exps.sort();
+        assert_eq!(
+            exps,
+            vec![
+                nil(),
+                bool(true),
+                integer(-1),
+                integer(2),
+                string("b"),
+                symbol("a"),
+                list(&[integer(1)]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::Path,
+    process::ExitCode,
+};
+use topogi_lang::{
+    ast::{Exp, Module},
+    buildin::default_module,
+    diagnostics::Diagnostic,
+    eval::{eval_top_level, VariableGenerator},
+    fmt::{diff, format_source},
+    lint::lint_source,
+    loader::{link, load_module, FileLoader, LoadError},
+    parser::parse_exp,
+    testing::run_tests,
+};
+
+const USAGE: &str =
+    "usage: topogi [run <file> | test <file> | fmt [--check | --stdout] <file> | lint <file>]";
+
+fn repl() -> ExitCode {
+    let mut module = default_module();
+    let mut gen = VariableGenerator::new();
+
+    loop {
+        print!("> ");
+        io::stdout().flush().unwrap();
+
+        let mut input = String::new();
+        let read = io::stdin()
+            .read_line(&mut input)
+            .expect("Failed to read line");
+
+        let input = input.trim();
+
+        if read == 0 || input == "exit" {
+            break;
+        }
+
+        if input.is_empty() {
+            continue;
+        }
+
+        let ast = match parse_exp(input) {
+            Ok(ast) => ast,
+            Err(err) => {
+                print!(
+                    "{}",
+                    Diagnostic::from_parse_error(&err, input).render("<repl>", input)
+                );
+                continue;
+            }
+        };
+
+        match eval_top_level(ast, &mut module, &mut gen) {
+            Ok(Exp::Void) => println!(),
+            Ok(result) => println!("=> {}\n", result.pretty(80)),
+            Err(e) => {
+                print!(
+                    "{}",
+                    Diagnostic::from_eval_error(&e).render("<repl>", input)
+                );
+                continue;
+            }
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Loads the module at `path` and links the modules it imports, which are
+/// looked for next to it and then in the directories of `TOPOGI_PATH`.
+fn load(path: &str, source: &str) -> Result<Module, LoadError> {
+    let dir = match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => ".".into(),
+    };
+    let search_paths = env::var_os("TOPOGI_PATH")
+        .map(|paths| env::split_paths(&paths).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let loader = FileLoader::new(std::iter::once(dir).chain(search_paths));
+    link(load_module(source)?, &loader)
+}
+
+/// Renders an error in an imported module against the source of that module.
+fn render_load_error(err: &LoadError, path: &str, source: &str) -> String {
+    match err {
+        LoadError::Imported {
+            path,
+            source,
+            error,
+        } => render_load_error(error, path, source),
+        err => Diagnostic::from_load_error(err, source).render(path, source),
+    }
+}
+
+fn run(path: &str) -> ExitCode {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: cannot read {}: {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let module = match load(path, &source) {
+        Ok(module) => module,
+        Err(err) => {
+            eprint!("{}", render_load_error(&err, path, &source));
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match module.run("main", vec![]) {
+        Ok(Exp::Void) => ExitCode::SUCCESS,
+        Ok(result) => {
+            println!("{}", result);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprint!(
+                "{}",
+                Diagnostic::from_eval_error(&err).render(path, &source)
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn test(path: &str) -> ExitCode {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: cannot read {}: {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let module = match load(path, &source) {
+        Ok(module) => module,
+        Err(err) => {
+            eprint!("{}", render_load_error(&err, path, &source));
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let results = run_tests(&module);
+    for result in &results {
+        match &result.error {
+            None => println!("test {} ... ok", result.name),
+            Some(err) => {
+                println!("test {} ... FAILED", result.name);
+                eprint!("{}", Diagnostic::from_eval_error(err).render(path, &source));
+            }
+        }
+    }
+    let failed = results.iter().filter(|result| !result.passed()).count();
+    println!("\n{} passed; {} failed", results.len() - failed, failed);
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+#[derive(PartialEq)]
+enum FmtMode {
+    /// Rewrite the file.
+    Write,
+    /// Print the formatted source.
+    Stdout,
+    /// Print a diff and fail if the file is not formatted.
+    Check,
+}
+
+fn fmt(path: &str, mode: FmtMode) -> ExitCode {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: cannot read {}: {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let formatted = match format_source(&source) {
+        Ok(formatted) => formatted,
+        Err(err) => {
+            eprint!(
+                "{}",
+                Diagnostic::from_parse_error(&err, &source).render(path, &source)
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match mode {
+        FmtMode::Stdout => print!("{}", formatted),
+        FmtMode::Check if formatted != source => {
+            println!("--- {}\n+++ {} (formatted)", path, path);
+            print!("{}", diff(&source, &formatted));
+            return ExitCode::FAILURE;
+        }
+        FmtMode::Check => {}
+        FmtMode::Write if formatted != source => {
+            if let Err(err) = fs::write(path, formatted) {
+                eprintln!("error: cannot write {}: {}", path, err);
+                return ExitCode::FAILURE;
+            }
+        }
+        FmtMode::Write => {}
+    }
+    ExitCode::SUCCESS
+}
+
+fn lint(path: &str) -> ExitCode {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: cannot read {}: {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let lints = match lint_source(&source) {
+        Ok(lints) => lints,
+        Err(err) => {
+            eprint!(
+                "{}",
+                Diagnostic::from_parse_error(&err, &source).render(path, &source)
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for lint in &lints {
+        eprint!("{}", Diagnostic::from_lint(lint).render(path, &source));
+    }
+    if lints.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] => repl(),
+        ["run", path] => run(path),
+        ["test", path] => test(path),
+        ["fmt", path] => fmt(path, FmtMode::Write),
+        ["fmt", "--stdout", path] => fmt(path, FmtMode::Stdout),
+        ["fmt", "--check", path] => fmt(path, FmtMode::Check),
+        ["lint", path] => lint(path),
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -1,553 +1,2320 @@
-use std::ops::Not;
+use alloc::{collections::BTreeSet, rc::Rc};
+use core::{cmp::Ordering, ops::Not};
+
+#[cfg(feature = "bignum")]
+use num_bigint::BigInt;
+#[cfg(feature = "bignum")]
+use num_traits::{ToPrimitive, Zero};
 
 use crate::{
+    args::Args,
     ast::{self, apply, Exp, Module},
-    eval::{eval, EvalError, Result, VariableGenerator},
+    eval::{expand, Arity, BuiltinFn, EvalContext, EvalError, Result},
+    parser,
+    prelude::*,
+    symbol::{self, SymbolId},
 };
 
-fn parse_unary(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    if args.len() != 1 {
-        return Err(EvalError::InvalidArgs(args.to_vec()));
-    }
-
-    let exp = eval(args[0].clone(), module, gen)?;
-    Ok(exp)
+#[cfg(feature = "std-io")]
+mod io;
+#[cfg(feature = "std-math")]
+mod math;
+#[cfg(feature = "std-string")]
+mod string;
+
+// Builtins are called with already evaluated arguments, which they check
+// through `Args` so that a mismatch names the builtin and the argument.
+
+/// Operands of an arithmetic built-in. Mixed operands are converted to the
+/// more general kind: integers to rationals, and any number to a float.
+enum Numbers {
+    Integer(i64, i64),
+    /// At least one operand is a big integer and neither is a float.
+    #[cfg(feature = "bignum")]
+    BigInt(BigInt, BigInt),
+    /// Numerators and denominators, widened so that their products cannot
+    /// overflow.
+    Rational((i128, i128), (i128, i128)),
+    Float(f64, f64),
 }
 
-fn parse_binary(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<(Exp, Exp)> {
-    if args.len() != 2 {
-        return Err(EvalError::InvalidArgs(args.to_vec()));
+/// Converts any number to a float, possibly losing precision.
+fn number_to_f64(exp: &Exp) -> Option<f64> {
+    match exp.node() {
+        Exp::Integer(i) => Some(*i as f64),
+        #[cfg(feature = "bignum")]
+        Exp::BigInt(i) => Some(i.to_f64().unwrap_or(f64::NAN)),
+        Exp::Rational(numerator, denominator) => Some(*numerator as f64 / *denominator as f64),
+        Exp::Float(f) => Some(*f),
+        _ => None,
     }
-    let lhs = eval(args[0].clone(), module, gen)?;
-    let rhs = eval(args[1].clone(), module, gen)?;
-    Ok((lhs, rhs))
 }
 
-fn parse_ternary(
-    args: &[Exp],
-    module: &Module,
-    gen: &mut VariableGenerator,
-) -> Result<(Exp, Exp, Exp)> {
-    if args.len() != 3 {
-        return Err(EvalError::InvalidArgs(args.to_vec()));
+fn parse_binary_number(args: Args) -> Result<Numbers> {
+    let args = args.exactly(2)?;
+    let (lhs, rhs) = (args.get(0)?, args.get(1)?);
+    match (lhs.node(), rhs.node()) {
+        (Exp::Integer(lhs), Exp::Integer(rhs)) => return Ok(Numbers::Integer(*lhs, *rhs)),
+        #[cfg(feature = "bignum")]
+        (Exp::BigInt(lhs), Exp::BigInt(rhs)) => {
+            return Ok(Numbers::BigInt(lhs.clone(), rhs.clone()))
+        }
+        #[cfg(feature = "bignum")]
+        (Exp::BigInt(lhs), Exp::Integer(rhs)) => {
+            return Ok(Numbers::BigInt(lhs.clone(), (*rhs).into()))
+        }
+        #[cfg(feature = "bignum")]
+        (Exp::Integer(lhs), Exp::BigInt(rhs)) => {
+            return Ok(Numbers::BigInt((*lhs).into(), rhs.clone()))
+        }
+        _ => {}
+    }
+    if let (Some(lhs), Some(rhs)) = (lhs.as_rational(), rhs.as_rational()) {
+        let widen = |(n, d): (i64, i64)| (i128::from(n), i128::from(d));
+        return Ok(Numbers::Rational(widen(lhs), widen(rhs)));
+    }
+    match (number_to_f64(lhs), number_to_f64(rhs)) {
+        (Some(lhs), Some(rhs)) => Ok(Numbers::Float(lhs, rhs)),
+        (None, _) => Err(args.mismatch(0, "a number")),
+        (_, None) => Err(args.mismatch(1, "a number")),
     }
-    let lhs = eval(args[0].clone(), module, gen)?;
-    let mhs = eval(args[1].clone(), module, gen)?;
-    let rhs = eval(args[2].clone(), module, gen)?;
-    Ok((lhs, mhs, rhs))
 }
 
-fn parse_binary_integer(
-    args: &[Exp],
-    module: &Module,
-    gen: &mut VariableGenerator,
-) -> Result<(i64, i64)> {
-    if args.len() != 2 {
-        return Err(EvalError::InvalidArgs(args.to_vec()));
-    }
-    let lhs = eval(args[0].clone(), module, gen)?;
-    let rhs = eval(args[1].clone(), module, gen)?;
-    Ok((
-        lhs.as_integer()
-            .ok_or(EvalError::InvalidArgs(args.to_vec()))?,
-        rhs.as_integer()
-            .ok_or(EvalError::InvalidArgs(args.to_vec()))?,
-    ))
+fn integer_overflow(args: &[Exp]) -> EvalError {
+    EvalError::IntegerOverflow(args.iter().cloned().reduce(apply).unwrap_or(Exp::Nil))
+}
+
+fn divide_by_zero(args: &[Exp]) -> EvalError {
+    EvalError::DivideByZero(apply(args[0].clone(), args[1].clone()))
 }
 
-fn add(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let (lhs, rhs) = parse_binary_integer(args, module, gen)?;
-    Ok(Exp::Integer(lhs + rhs))
+fn rational_result(args: &[Exp], numerator: Option<i128>, denominator: i128) -> Result<Exp> {
+    numerator
+        .and_then(|numerator| ast::checked_rational(numerator, denominator))
+        .ok_or_else(|| integer_overflow(args))
 }
 
-fn sub(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let (lhs, rhs) = parse_binary_integer(args, module, gen)?;
-    Ok(Exp::Integer(lhs - rhs))
+/// Called when an integer operation overflows `i64`. With the `bignum`
+/// feature the operation is redone on big integers, otherwise it fails.
+#[cfg(feature = "bignum")]
+fn overflowed(_args: &[Exp], lhs: i64, rhs: i64, op: fn(BigInt, BigInt) -> BigInt) -> Result<Exp> {
+    Ok(ast::big_integer(op(lhs.into(), rhs.into())))
 }
 
-fn mul(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let (lhs, rhs) = parse_binary_integer(args, module, gen)?;
-    Ok(Exp::Integer(lhs * rhs))
+#[cfg(not(feature = "bignum"))]
+fn overflowed(args: &[Exp], _lhs: i64, _rhs: i64, _op: fn(i64, i64) -> i64) -> Result<Exp> {
+    Err(integer_overflow(args))
 }
 
-fn div(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let (lhs, rhs) = parse_binary_integer(args, module, gen)?;
-    if rhs == 0 {
-        return Err(EvalError::DivideByZero(apply(
-            args[0].clone(),
-            args[1].clone(),
-        )));
+fn add(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    match parse_binary_number(Args::new("math/+", args))? {
+        Numbers::Integer(lhs, rhs) => match lhs.checked_add(rhs) {
+            Some(sum) => Ok(Exp::Integer(sum)),
+            None => overflowed(args, lhs, rhs, |lhs, rhs| lhs + rhs),
+        },
+        #[cfg(feature = "bignum")]
+        Numbers::BigInt(lhs, rhs) => Ok(ast::big_integer(lhs + rhs)),
+        Numbers::Rational((n1, d1), (n2, d2)) => {
+            rational_result(args, (n1 * d2).checked_add(n2 * d1), d1 * d2)
+        }
+        Numbers::Float(lhs, rhs) => Ok(Exp::Float(lhs + rhs)),
     }
-    Ok(Exp::Integer(lhs / rhs))
 }
 
-fn odd(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let int = parse_unary(args, module, gen)?
-        .as_integer()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?;
+fn sub(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    match parse_binary_number(Args::new("math/-", args))? {
+        Numbers::Integer(lhs, rhs) => match lhs.checked_sub(rhs) {
+            Some(difference) => Ok(Exp::Integer(difference)),
+            None => overflowed(args, lhs, rhs, |lhs, rhs| lhs - rhs),
+        },
+        #[cfg(feature = "bignum")]
+        Numbers::BigInt(lhs, rhs) => Ok(ast::big_integer(lhs - rhs)),
+        Numbers::Rational((n1, d1), (n2, d2)) => {
+            rational_result(args, (n1 * d2).checked_sub(n2 * d1), d1 * d2)
+        }
+        Numbers::Float(lhs, rhs) => Ok(Exp::Float(lhs - rhs)),
+    }
+}
 
-    Ok(Exp::Bool(int % 2 != 0))
+fn mul(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    match parse_binary_number(Args::new("math/*", args))? {
+        Numbers::Integer(lhs, rhs) => match lhs.checked_mul(rhs) {
+            Some(product) => Ok(Exp::Integer(product)),
+            None => overflowed(args, lhs, rhs, |lhs, rhs| lhs * rhs),
+        },
+        #[cfg(feature = "bignum")]
+        Numbers::BigInt(lhs, rhs) => Ok(ast::big_integer(lhs * rhs)),
+        Numbers::Rational((n1, d1), (n2, d2)) => rational_result(args, Some(n1 * n2), d1 * d2),
+        Numbers::Float(lhs, rhs) => Ok(Exp::Float(lhs * rhs)),
+    }
 }
 
-fn even(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let int = parse_unary(args, module, gen)?
-        .as_integer()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?;
+/// Division of integers and rationals is exact: `(/ 1 3)` is the rational
+/// `1/3`, and fails on a zero divisor. Float division follows IEEE 754, so
+/// dividing by zero gives an infinity or NaN. Big integers that do not divide
+/// evenly give a float.
+fn div(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    match parse_binary_number(Args::new("/", args))? {
+        Numbers::Integer(_, 0) | Numbers::Rational(_, (0, _)) => Err(divide_by_zero(args)),
+        Numbers::Integer(lhs, rhs) => match lhs.checked_rem(rhs) {
+            Some(0) => Ok(Exp::Integer(lhs / rhs)),
+            Some(_) => rational_result(args, Some(lhs.into()), rhs.into()),
+            None => overflowed(args, lhs, rhs, |lhs, rhs| lhs / rhs),
+        },
+        #[cfg(feature = "bignum")]
+        Numbers::BigInt(_, rhs) if rhs.is_zero() => Err(divide_by_zero(args)),
+        #[cfg(feature = "bignum")]
+        Numbers::BigInt(lhs, rhs) if (&lhs % &rhs).is_zero() => Ok(ast::big_integer(lhs / rhs)),
+        #[cfg(feature = "bignum")]
+        Numbers::BigInt(lhs, rhs) => Ok(Exp::Float(
+            lhs.to_f64().unwrap_or(f64::NAN) / rhs.to_f64().unwrap_or(f64::NAN),
+        )),
+        Numbers::Rational((n1, d1), (n2, d2)) => rational_result(args, Some(n1 * d2), d1 * n2),
+        Numbers::Float(lhs, rhs) => Ok(Exp::Float(lhs / rhs)),
+    }
+}
 
-    Ok(Exp::Bool(int % 2 == 0))
+fn eq(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("core/=", args).exactly(2)?;
+    Ok(Exp::Bool(args.get(0)? == args.get(1)?))
 }
 
-fn eq(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let (lhs, rhs) = parse_binary(args, module, gen)?;
-    Ok(Exp::Bool(lhs == rhs))
+fn ne(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("/=", args).exactly(2)?;
+    Ok(Exp::Bool(args.get(0)? != args.get(1)?))
 }
 
-fn ne(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let (lhs, rhs) = parse_binary(args, module, gen)?;
-    Ok(Exp::Bool(lhs != rhs))
+/// Compares two numbers by value, whatever their kinds. Comparisons with NaN
+/// have no ordering.
+fn parse_compare(args: Args) -> Result<Option<Ordering>> {
+    Ok(match parse_binary_number(args)? {
+        Numbers::Integer(lhs, rhs) => Some(lhs.cmp(&rhs)),
+        #[cfg(feature = "bignum")]
+        Numbers::BigInt(lhs, rhs) => Some(lhs.cmp(&rhs)),
+        Numbers::Rational((n1, d1), (n2, d2)) => Some((n1 * d2).cmp(&(n2 * d1))),
+        Numbers::Float(lhs, rhs) => lhs.partial_cmp(&rhs),
+    })
 }
 
-fn cons(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let (lhs, rhs) = parse_binary(args, module, gen)?;
-    let mut list = vec![lhs];
-    list.extend(rhs.clone().as_list().unwrap_or(&[rhs]).iter().cloned());
-    Ok(Exp::List(list))
+fn lt(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    Ok(Exp::Bool(
+        parse_compare(Args::new("core/<", args))?.is_some_and(Ordering::is_lt),
+    ))
 }
 
-fn list(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let args = args
-        .iter()
-        .cloned()
-        .map(|exp| eval(exp, module, gen))
-        .collect::<Result<_>>()?;
-    Ok(Exp::List(args))
+fn gt(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    Ok(Exp::Bool(
+        parse_compare(Args::new("core/>", args))?.is_some_and(Ordering::is_gt),
+    ))
 }
 
-fn first(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let exp = parse_unary(args, module, gen)?;
-    exp.as_list()
-        .and_then(|list| list.get(0).cloned())
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))
+fn le(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    Ok(Exp::Bool(
+        parse_compare(Args::new("core/<=", args))?.is_some_and(Ordering::is_le),
+    ))
 }
 
-fn second(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let exp = parse_unary(args, module, gen)?;
-    exp.as_list()
-        .and_then(|list| list.get(1).cloned())
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))
+fn ge(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    Ok(Exp::Bool(
+        parse_compare(Args::new("core/>=", args))?.is_some_and(Ordering::is_ge),
+    ))
 }
 
-fn third(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let exp = parse_unary(args, module, gen)?;
-    exp.as_list()
-        .and_then(|list| list.get(2).cloned())
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))
+fn not(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let b = Args::new("core/not", args).exactly(1)?.bool(0)?;
+    Ok(Exp::Bool(!b))
 }
 
-fn nth(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    if args.len() != 2 {
-        return Err(EvalError::InvalidArgs(args.to_vec()));
-    }
-    let n = eval(args[0].clone(), module, gen)?
-        .as_integer()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?;
-    let list = eval(args[1].clone(), module, gen)?
-        .as_list()
-        .map(|l| l.to_vec())
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?;
-    list.get(n as usize)
-        .cloned()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))
+/// `(cons head tail)`: the list `tail` with `head` prepended, or the pair
+/// `(head . tail)` if `tail` is not a list.
+fn cons(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/cons", args).exactly(2)?;
+    Ok(ast::pair(args.get(0)?.clone(), args.get(1)?.clone()))
 }
 
-fn is_atom(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let exp = parse_unary(args, module, gen)?;
-    Ok(ast::bool(matches!(exp, Exp::List(_)).not()))
+fn list(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    Ok(ast::list(args))
 }
 
-fn print(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let exp = parse_unary(args, module, gen)?;
-    print!("{} ", exp);
-    Ok(Exp::Nil)
+fn values(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    Ok(ast::values(args))
 }
 
-fn println(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let exp = parse_unary(args, module, gen)?;
-    println!("{}", exp);
-    Ok(Exp::Nil)
+/// The first element of a non-empty list, or the head of a pair.
+fn first(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/first", args).exactly(1)?;
+    args.parse(0, "a non-empty list or a pair", |exp| exp.head().cloned())
 }
 
-fn string_append(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let (lhs, rhs) = parse_binary(args, module, gen)?;
-    let lhs = lhs
-        .as_string()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?;
-    let rhs = rhs
-        .as_string()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?;
-    Ok(Exp::String(format!("{}{}", lhs, rhs)))
+/// The element at `index` of the list at `i`.
+fn element(args: Args, i: usize, index: i64) -> Result<Exp> {
+    let list = args.list(i)?;
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| list.get(i).cloned())
+        .ok_or(EvalError::IndexOutOfBounds {
+            index,
+            len: list.len(),
+        })
 }
 
-fn string_head(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let exp = parse_unary(args, module, gen)?;
-    let s = exp
-        .as_string()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?;
-    Ok(Exp::String(s.chars().take(1).collect()))
+fn second(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    element(Args::new("list/second", args).exactly(1)?, 0, 1)
 }
 
-fn string_tail(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let exp = parse_unary(args, module, gen)?;
-    let s = exp
-        .as_string()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?;
-    Ok(Exp::String(s.chars().skip(1).collect()))
+fn third(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    element(Args::new("list/third", args).exactly(1)?, 0, 2)
 }
 
-fn string_init(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let exp = parse_unary(args, module, gen)?;
-    let s = exp
-        .as_string()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?;
-    Ok(Exp::String(s.chars().take(s.len() - 1).collect()))
+fn last(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/last", args).exactly(1)?;
+    args.parse(0, "a non-empty list", |exp| {
+        exp.as_list().and_then(|list| list.last().cloned())
+    })
 }
 
-fn string_last(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let exp = parse_unary(args, module, gen)?;
-    let s = exp
-        .as_string()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?;
-    Ok(Exp::String(s.chars().rev().take(1).collect()))
+/// All elements of a non-empty list but the last.
+fn init(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/init", args).exactly(1)?;
+    args.parse(0, "a non-empty list", |exp| {
+        exp.as_list()
+            .and_then(|list| list.split_last())
+            .map(|(_, init)| Exp::List(init.into()))
+    })
 }
 
-fn symbol_to_string(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let exp = parse_unary(args, module, gen)?;
-    let s = exp
-        .as_symbol()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?;
-    Ok(Exp::String(s.to_string()))
+/// All elements of a non-empty list but the first, or the tail of a pair.
+fn rest(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/rest", args).exactly(1)?;
+    args.parse(0, "a non-empty list or a pair", Exp::tail)
 }
 
-fn foldr(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let (f, mut acc, list) = parse_ternary(args, module, gen)?;
-    for elem in list
-        .as_list()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?
-    {
-        acc = eval(
-            apply(apply(f.clone(), elem.clone()), acc.clone()),
-            module,
-            gen,
-        )?;
-    }
-    Ok(acc)
+fn nth(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/nth", args).exactly(2)?;
+    element(args, 1, args.integer(0)?)
 }
 
-fn foldl(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let (f, mut acc, list) = parse_ternary(args, module, gen)?;
-    for elem in list
-        .as_list()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?
-        .iter()
-        .rev()
-        .cloned()
-    {
-        acc = eval(apply(apply(f.clone(), acc), elem), module, gen)?;
-    }
-    Ok(acc)
+fn is_atom(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let exp = Args::new("core/atom?", args).exactly(1)?.get(0)?;
+    Ok(ast::bool(matches!(exp, Exp::List(_) | Exp::Pair(..)).not()))
 }
 
-fn map(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let (f, list) = parse_binary(args, module, gen)?;
-    let list = list
-        .as_list()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?;
-    let mut result = vec![];
-    for elem in list.iter().cloned() {
-        result.push(eval(apply(f.clone(), elem), module, gen)?);
-    }
-    Ok(Exp::List(result))
+/// Whether the argument is a non-empty list or a pair, that is whether
+/// `first` and `rest` apply to it.
+fn is_pair(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let exp = Args::new("core/pair?", args).exactly(1)?.get(0)?;
+    Ok(ast::bool(exp.head().is_some()))
 }
 
-fn filter(args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    let (f, list) = parse_binary(args, module, gen)?;
-    let list = list
-        .as_list()
-        .ok_or(EvalError::InvalidArgs(args.to_vec()))?;
-    let mut result = vec![];
-    for elem in list.iter().cloned() {
-        if eval(apply(f.clone(), elem.clone()), module, gen)?
-            .as_bool()
-            .ok_or(EvalError::ExpectedBool(elem.clone()))?
-        {
-            result.push(elem)
+/// The string `write` prints.
+fn show(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let exp = Args::new("core/show", args).exactly(1)?.get(0)?;
+    Ok(Exp::String(exp.to_readable_string()))
+}
+
+/// `(format template arg ...)`: `template` with each `{}` replaced by the next
+/// argument as [`Display`](core::fmt::Display) prints it. `{:?}` prints it as
+/// [`Exp::to_readable_string`] does, and `{{` and `}}` stand for literal braces.
+/// It is not behind `std-string` since string interpolation calls it.
+fn format(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("string/format", args).arity(Arity::AtLeast(1))?;
+    let template = args.string(0)?;
+    let mut rest = args.as_slice()[1..].iter();
+    let invalid = || args.mismatch(0, "a format template that fits the arguments");
+    let mut result = String::new();
+    let mut chars = template.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                result.push('}');
+            }
+            '{' => {
+                let (directive, after) = chars.as_str().split_once('}').ok_or_else(invalid)?;
+                let arg = rest.next().ok_or_else(invalid)?;
+                match (directive, arg.node()) {
+                    ("", _) => result.push_str(&arg.to_string()),
+                    (":?", _) => result.push_str(&arg.to_readable_string()),
+                    _ => return Err(invalid()),
+                }
+                chars = after.chars();
+            }
+            '}' => return Err(invalid()),
+            ch => result.push(ch),
         }
     }
-    Ok(Exp::List(result))
+    if rest.next().is_some() {
+        return Err(invalid());
+    }
+    Ok(Exp::String(result))
 }
 
-fn insert_binary_curry_op(
-    func: fn(&[Exp], &Module, &mut VariableGenerator) -> Result<Exp>,
-    func_name: &str,
-    module: &mut Module,
-) {
-    module.defines.insert(
-        func_name.to_string(),
-        ast::lambda(
-            "x",
-            ast::lambda(
-                "y",
-                ast::list(&[Exp::BuildIn(func), ast::symbol("x"), ast::symbol("y")]),
-            ),
-        ),
-    );
+fn is_void(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let exp = Args::new("core/void?", args).exactly(1)?.get(0)?;
+    Ok(ast::bool(matches!(exp, Exp::Void)))
 }
 
-fn insert_ternary_curry_op(
-    func: fn(&[Exp], &Module, &mut VariableGenerator) -> Result<Exp>,
-    func_name: &str,
-    module: &mut Module,
-) {
-    module.defines.insert(
-        func_name.to_string(),
-        ast::lambda(
-            "x",
-            ast::lambda(
-                "y",
-                ast::lambda(
-                    "z",
-                    ast::list(&[
-                        Exp::BuildIn(func),
-                        ast::symbol("x"),
-                        ast::symbol("y"),
-                        ast::symbol("z"),
-                    ]),
-                ),
-            ),
-        ),
-    );
+fn symbol_to_string(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let s = Args::new("symbol/to-string", args).exactly(1)?.symbol(0)?;
+    Ok(Exp::String(s.to_string()))
 }
 
-fn insert_unary_op(
-    func: fn(&[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp>,
-    func_name: &str,
-    module: &mut Module,
-) {
-    module
-        .defines
-        .insert(func_name.to_string(), ast::buildin(func));
+/// Interns a string as a symbol. The string may be any non-empty text, even
+/// one the parser would not read as a symbol.
+fn string_to_symbol(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let mut interned = 0;
+    let value = fallible(args, |args| {
+        let args = Args::new("string/to-symbol", args).exactly(1)?;
+        match args.string(0)? {
+            "" => Err(args.mismatch(0, "a non-empty string")),
+            s => {
+                let (id, bytes) = SymbolId::intern_counted(s);
+                interned = bytes;
+                Ok(Exp::Symbol(id))
+            }
+        }
+    });
+    ctx.gen.charge(interned)?;
+    value
 }
 
-pub fn default_module() -> Module {
-    let mut module = Module::new("##default##");
+fn keyword_to_string(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let name = Args::new("keyword/to-string", args)
+        .exactly(1)?
+        .keyword(0)?;
+    Ok(ast::string(name))
+}
 
-    insert_binary_curry_op(add, "+", &mut module);
-    insert_binary_curry_op(sub, "-", &mut module);
-    insert_binary_curry_op(mul, "*", &mut module);
-    insert_binary_curry_op(div, "/", &mut module);
-    insert_unary_op(odd, "odd", &mut module);
-    insert_unary_op(even, "even", &mut module);
+fn string_to_keyword(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    fallible(args, |args| {
+        let args = Args::new("string/to-keyword", args).exactly(1)?;
+        match args.string(0)? {
+            "" => Err(args.mismatch(0, "a non-empty string")),
+            s => Ok(ast::keyword(s)),
+        }
+    })
+}
 
-    insert_binary_curry_op(eq, "=", &mut module);
-    insert_binary_curry_op(ne, "/=", &mut module);
+/// Returns a fresh symbol that differs from every symbol written in source and
+/// from every other symbol generated during the evaluation.
+fn gensym(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    Args::new("core/gensym", args).exactly(0)?;
+    Ok(Exp::Symbol(ctx.gen.gen_var()))
+}
 
-    insert_binary_curry_op(cons, "cons", &mut module);
-    insert_unary_op(list, "list", &mut module);
-    insert_unary_op(is_atom, "atom?", &mut module);
+/// Evaluates a quoted expression in the module, after expanding its macro
+/// calls. Local variables of the caller are not visible to it.
+fn eval(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let exp = Args::new("core/eval", args).exactly(1)?.get(0)?;
+    crate::eval::eval(
+        expand(exp.clone(), ctx.module, ctx.gen)?,
+        ctx.module,
+        ctx.gen,
+    )
+}
 
-    insert_unary_op(first, "first", &mut module);
-    insert_unary_op(second, "second", &mut module);
-    insert_unary_op(third, "third", &mut module);
-    insert_binary_curry_op(nth, "nth", &mut module);
+/// Fails with the argument, which the innermost enclosing `try` binds in its
+/// `catch`.
+fn raise(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let exp = Args::new("core/raise", args).exactly(1)?.get(0)?;
+    Err(EvalError::Raised(exp.clone()))
+}
 
-    insert_unary_op(print, "print", &mut module);
-    insert_unary_op(println, "println", &mut module);
+/// `(error message data ...)`: fails with a message for the user and any
+/// values that describe the error.
+fn error(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("core/error", args).arity(Arity::AtLeast(1))?;
+    Err(EvalError::UserError {
+        message: args.string(0)?.to_string(),
+        data: args.as_slice()[1..].to_vec(),
+    })
+}
 
-    insert_binary_curry_op(string_append, "string-append", &mut module);
-    insert_unary_op(string_head, "string-head", &mut module);
-    insert_unary_op(string_tail, "string-tail", &mut module);
-    insert_unary_op(string_init, "string-init", &mut module);
-    insert_unary_op(string_last, "string-last", &mut module);
+/// `(assert test)`: fails unless `test` is true. The parser passes the call
+/// as written as a second argument, to show when the assertion fails.
+fn assert(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let (test, call) = match args {
+        [test] => (test, ast::list(&[ast::symbol("assert"), test.clone()])),
+        [test, call] => (test, call.clone()),
+        _ => return Err(Args::new("core/assert", args).arity_mismatch(Arity::Exactly(1))),
+    };
+    match test.node() {
+        Exp::Bool(true) => Ok(Exp::Void),
+        Exp::Bool(false) => Err(EvalError::AssertionFailed(call)),
+        _ => Err(EvalError::ExpectedBool(test.clone())),
+    }
+}
 
-    insert_unary_op(symbol_to_string, "symbol->string", &mut module);
+/// `(assert-eq expected actual)`: fails unless the values are equal. Like
+/// `assert`, it may be passed the call as written.
+fn assert_eq(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let (expected, actual, call) = match args {
+        [expected, actual] => (
+            expected,
+            actual,
+            ast::list(&[ast::symbol("assert-eq"), expected.clone(), actual.clone()]),
+        ),
+        [expected, actual, call] => (expected, actual, call.clone()),
+        _ => return Err(Args::new("core/assert-eq", args).arity_mismatch(Arity::Exactly(2))),
+    };
+    if expected == actual {
+        Ok(Exp::Void)
+    } else {
+        Err(EvalError::AssertEqFailed(Box::new((
+            call,
+            expected.clone(),
+            actual.clone(),
+        ))))
+    }
+}
 
-    insert_ternary_curry_op(foldr, "foldr", &mut module);
-    insert_ternary_curry_op(foldl, "foldl", &mut module);
-    insert_binary_curry_op(map, "map", &mut module);
-    insert_binary_curry_op(filter, "filter", &mut module);
+// Results and options are tagged values: `(:ok value)` or `(:err error)`, and
+// `(:some value)` or `:none`. `case` takes them apart with keyword patterns.
 
-    module
+fn tagged(tag: &str, value: Exp) -> Exp {
+    ast::list(&[ast::keyword(tag), value])
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::{ast::*, eval::eval_default_module};
+/// The tag of a result or an option, and its value unless it is `:none`.
+fn parse_tagged(exp: &Exp) -> Option<(&str, Option<&Exp>)> {
+    match exp.node() {
+        Exp::Keyword(tag) if tag == "none" => Some((tag, None)),
+        Exp::List(list) => match &list[..] {
+            [tag, value] => match tag.node() {
+                Exp::Keyword(tag) if matches!(tag.as_str(), "ok" | "err" | "some") => {
+                    Some((tag, Some(value)))
+                }
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
 
-    #[test]
-    fn test_integer_binary_op() {
-        // (+ 1 2)
-        let e = list(&[symbol("+"), integer(1), integer(2)]);
-        assert_eq!(eval_default_module(e), Ok(Exp::Integer(3)));
+/// How a fallible builtin reports failure when its last argument is the
+/// keyword `:option` or `:result`, instead of failing with an error. The
+/// builtins that take it are `string/to-number`, `string/to-symbol`,
+/// `string/to-keyword`, `char/from-integer`, `bytes/to-string` and `read`.
+#[derive(Debug, Clone, Copy)]
+enum Failure {
+    /// `(:some value)` on success and `:none` on failure.
+    Option,
+    /// `(:ok value)` on success and `(:err message)` on failure.
+    Result,
+}
 
-        // (- 1 2)
-        let e = list(&[symbol("-"), integer(1), integer(2)]);
-        assert_eq!(eval_default_module(e), Ok(Exp::Integer(-1)));
+impl Failure {
+    fn wrap(self, result: Result<Exp>) -> Exp {
+        match (self, result) {
+            (Failure::Option, Ok(value)) => tagged("some", value),
+            (Failure::Option, Err(_)) => ast::keyword("none"),
+            (Failure::Result, Ok(value)) => tagged("ok", value),
+            (Failure::Result, Err(err)) => tagged("err", err.to_exp()),
+        }
     }
+}
 
-    #[test]
-    fn test_compare_op() {
-        // (= 1 1) => true
-        let e = list(&[symbol("="), integer(1), integer(1)]);
-        assert_eq!(eval_default_module(e), Ok(bool(true)));
-
-        // (/= 1 1) => false
-        let e = list(&[symbol("/="), integer(1), integer(1)]);
-        assert_eq!(eval_default_module(e), Ok(bool(false)));
+/// Splits a trailing `:option` or `:result` off the arguments.
+fn parse_failure(args: &[Exp]) -> (&[Exp], Option<Failure>) {
+    match args.split_last() {
+        Some((last, rest)) => match last.node() {
+            Exp::Keyword(k) if k == "option" => (rest, Some(Failure::Option)),
+            Exp::Keyword(k) if k == "result" => (rest, Some(Failure::Result)),
+            _ => (args, None),
+        },
+        None => (args, None),
+    }
+}
 
-        // (/= '(1 2) 2) => true
-        let e = list(&[
-            symbol("/="),
-            quote(list(&[integer(1), integer(2)])),
-            integer(2),
-        ]);
-        assert_eq!(eval_default_module(e), Ok(bool(true)));
+/// Calls `f`, counting the names the current thread interns meanwhile against
+/// the memory limit, since they are never freed. They are counted even if `f`
+/// fails after interning some of them.
+fn interning(ctx: &mut EvalContext, f: impl FnOnce() -> Result<Exp>) -> Result<Exp> {
+    let before = symbol::interned_bytes();
+    let value = f();
+    ctx.gen
+        .charge(symbol::interned_bytes().saturating_sub(before))?;
+    value
+}
 
-        // (= '(1 2) 2) => false
-        let e = list(&[
-            symbol("="),
-            quote(list(&[integer(1), integer(2)])),
-            integer(2),
-        ]);
-        assert_eq!(eval_default_module(e), Ok(bool(false)));
+/// Calls a fallible builtin, reporting its failure as asked by
+/// [`parse_failure`].
+fn fallible(args: &[Exp], f: impl FnOnce(&[Exp]) -> Result<Exp>) -> Result<Exp> {
+    match parse_failure(args) {
+        (args, Some(failure)) => Ok(failure.wrap(f(args))),
+        (args, None) => f(args),
     }
+}
 
-    #[test]
-    fn test_cons() {
-        // (cons 1 '(2 3)) => (1 2 3)
-        let e = list(&[
-            symbol("cons"),
-            integer(1),
-            quote(list(&[integer(2), integer(3)])),
-        ]);
-        assert_eq!(
-            eval_default_module(e),
-            Ok(list(&[integer(1), integer(2), integer(3)]))
-        );
+fn ok(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let value = Args::new("result/ok", args).exactly(1)?.get(0)?;
+    Ok(tagged("ok", value.clone()))
+}
 
-        // (cons 1 2) => (1 2)
-        let e = list(&[symbol("cons"), integer(1), integer(2)]);
-        assert_eq!(eval_default_module(e), Ok(list(&[integer(1), integer(2)])));
+fn err(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let value = Args::new("result/err", args).exactly(1)?.get(0)?;
+    Ok(tagged("err", value.clone()))
+}
 
-        // (cons '(1 2) 3) => ((1 2) 3)
-        let e = list(&[
-            symbol("cons"),
-            quote(list(&[integer(1), integer(2)])),
-            integer(3),
-        ]);
-        assert_eq!(
-            eval_default_module(e),
-            Ok(list(&[list(&[integer(1), integer(2)]), integer(3)]))
-        );
+fn some(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let value = Args::new("result/some", args).exactly(1)?.get(0)?;
+    Ok(tagged("some", value.clone()))
+}
+
+fn none(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    Args::new("result/none", args).exactly(0)?;
+    Ok(ast::keyword("none"))
+}
+
+fn is_ok(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let exp = Args::new("result/ok?", args).exactly(1)?.get(0)?;
+    Ok(ast::bool(matches!(parse_tagged(exp), Some(("ok", _)))))
+}
+
+fn is_err(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let exp = Args::new("result/err?", args).exactly(1)?.get(0)?;
+    Ok(ast::bool(matches!(parse_tagged(exp), Some(("err", _)))))
+}
+
+fn is_some(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let exp = Args::new("result/some?", args).exactly(1)?.get(0)?;
+    Ok(ast::bool(matches!(parse_tagged(exp), Some(("some", _)))))
+}
+
+fn is_none(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let exp = Args::new("result/none?", args).exactly(1)?.get(0)?;
+    Ok(ast::bool(matches!(parse_tagged(exp), Some(("none", _)))))
+}
+
+/// The value of `(:ok value)` or `(:some value)`. Raises the error of
+/// `(:err error)`, and `:none` itself.
+fn unwrap(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("result/unwrap", args).exactly(1)?;
+    let exp = args.get(0)?;
+    match parse_tagged(exp) {
+        Some(("ok" | "some", Some(value))) => Ok(value.clone()),
+        Some(("err", Some(err))) => Err(EvalError::Raised(err.clone())),
+        Some(("none", None)) => Err(EvalError::Raised(exp.clone())),
+        _ => Err(args.mismatch(0, "a result or an option")),
     }
+}
 
-    #[test]
-    fn test_list() {
-        // (list 1 2 3) => (1 2 3)
-        let e = list(&[symbol("list"), integer(1), integer(2), integer(3)]);
-        assert_eq!(
-            eval_default_module(e),
-            Ok(list(&[integer(1), integer(2), integer(3)]))
-        );
-        // (list (+ 1 2)) => (3)
-        let e = list(&[symbol("list"), list(&[symbol("+"), integer(1), integer(2)])]);
-        assert_eq!(eval_default_module(e), Ok(list(&[integer(3)])));
+/// `(unwrap-or default r)`: the value of `r` if it is `(:ok value)` or
+/// `(:some value)`, and `default` otherwise.
+fn unwrap_or(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("result/unwrap-or", args).exactly(2)?;
+    match parse_tagged(args.get(1)?) {
+        Some(("ok" | "some", Some(value))) => Ok(value.clone()),
+        Some(_) => Ok(args.get(0)?.clone()),
+        None => Err(args.mismatch(1, "a result or an option")),
     }
+}
 
-    #[test]
-    fn test_is_atom() {
-        // (atom? 1) => true
-        let e = list(&[symbol("atom?"), integer(1)]);
-        assert_eq!(eval_default_module(e), Ok(bool(true)));
+/// `(result/map f r)`: `r` with `f` applied to its value if it is `(:ok value)` or
+/// `(:some value)`, and `r` itself otherwise.
+fn map_ok(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("result/map", args).exactly(2)?;
+    let (f, exp) = (args.get(0)?, args.get(1)?);
+    match parse_tagged(exp) {
+        Some((tag @ ("ok" | "some"), Some(value))) => Ok(tagged(
+            tag,
+            ctx.call(f.clone(), core::slice::from_ref(value))?,
+        )),
+        Some(_) => Ok(exp.clone()),
+        None => Err(args.mismatch(1, "a result or an option")),
+    }
+}
 
-        // (atom? '(1 2)) => false
-        let e = list(&[symbol("atom?"), quote(list(&[integer(1), integer(2)]))]);
-        assert_eq!(eval_default_module(e), Ok(bool(false)));
+/// Parses a string into the expression it contains, unevaluated.
+fn read(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    interning(ctx, || {
+        fallible(args, |args| {
+            let s = Args::new("core/read", args).exactly(1)?.string(0)?;
+            parser::read(s).map_err(|err| EvalError::ReadError(s.to_string(), err))
+        })
+    })
+}
+
+/// `(foldr f init (x1 ... xn))` is `(f x1 (f ... (f xn init)))`.
+fn foldr(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/foldr", args).exactly(3)?;
+    let (f, mut acc) = (args.get(0)?, args.get(1)?.clone());
+    for elem in args.list(2)?.iter().rev() {
+        acc = ctx.call(f.clone(), &[elem.clone(), acc])?;
     }
+    Ok(acc)
+}
 
-    #[test]
-    fn test_nth() {
-        // (first '(1 2)) => 1
-        let e = list(&[symbol("first"), quote(list(&[integer(1), integer(2)]))]);
-        assert_eq!(eval_default_module(e), Ok(integer(1)));
+/// `(foldl f init (x1 ... xn))` is `(f (f (f init x1) ...) xn)`.
+fn foldl(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/foldl", args).exactly(3)?;
+    let (f, mut acc) = (args.get(0)?, args.get(1)?.clone());
+    for elem in args.list(2)? {
+        acc = ctx.call(f.clone(), &[acc, elem.clone()])?;
+    }
+    Ok(acc)
+}
 
-        // (second '(1 2)) => 2
-        let e = list(&[symbol("second"), quote(list(&[integer(1), integer(2)]))]);
-        assert_eq!(eval_default_module(e), Ok(integer(2)));
+/// Calls a function with the elements of a list as its arguments.
+fn apply_list(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("core/apply", args).exactly(2)?;
+    ctx.call(args.get(0)?.clone(), args.list(1)?)
+}
 
-        // (third '(1 2 3)) => 3
-        let e = list(&[
-            symbol("third"),
-            quote(list(&[integer(1), integer(2), integer(3)])),
-        ]);
-        assert_eq!(eval_default_module(e), Ok(integer(3)));
+fn map(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/map", args).exactly(2)?;
+    let (f, list) = (args.get(0)?, args.list(1)?);
+    let mut result = vec![];
+    for elem in list.iter().cloned() {
+        result.push(ctx.call(f.clone(), &[elem])?);
+    }
+    Ok(Exp::List(result.into()))
+}
 
-        // (nth 5 '(1 2 3 4 5 6 7)) => 6
-        let e = list(&[
-            symbol("nth"),
-            integer(5),
-            quote(list(&[
-                integer(1),
-                integer(2),
-                integer(3),
-                integer(4),
-                integer(5),
-                integer(6),
-                integer(7),
-            ])),
-        ]);
-        assert_eq!(eval_default_module(e), Ok(integer(6)));
+fn filter(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/filter", args).exactly(2)?;
+    let (f, list) = (args.get(0)?, args.list(1)?);
+    let mut result = vec![];
+    for elem in list.iter() {
+        if call_predicate(f, elem, ctx)? {
+            result.push(elem.clone())
+        }
     }
+    Ok(Exp::List(result.into()))
+}
 
-    #[test]
-    fn test_string_append() {
-        // (string-append "abc" "def") => "abcdef"
-        let e = list(&[symbol("string-append"), string("abc"), string("def")]);
-        assert_eq!(eval_default_module(e), Ok(string("abcdef")));
+/// Calls a predicate, which must return a boolean, on an element of a list.
+fn call_predicate(pred: &Exp, elem: &Exp, ctx: &mut EvalContext) -> Result<bool> {
+    ctx.call(pred.clone(), core::slice::from_ref(elem))?
+        .as_bool()
+        .ok_or(EvalError::ExpectedBool(elem.clone()))
+}
+
+fn is_member(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/member?", args).exactly(2)?;
+    Ok(Exp::Bool(args.list(1)?.contains(args.get(0)?)))
+}
+
+/// The first element satisfying a predicate, or nil.
+fn find(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/find", args).exactly(2)?;
+    let pred = args.get(0)?;
+    for elem in args.list(1)? {
+        if call_predicate(pred, elem, ctx)? {
+            return Ok(elem.clone());
+        }
     }
+    Ok(Exp::Nil)
+}
 
-    #[test]
-    fn test_string_head() {
-        // (string-head "abc") => "a"
-        let e = list(&[symbol("string-head"), string("abc")]);
-        assert_eq!(eval_default_module(e), Ok(string("a")));
+/// The index of the first element satisfying a predicate, or nil.
+fn position(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/position", args).exactly(2)?;
+    let pred = args.get(0)?;
+    for (i, elem) in args.list(1)?.iter().enumerate() {
+        if call_predicate(pred, elem, ctx)? {
+            return Ok(Exp::Integer(i as i64));
+        }
     }
+    Ok(Exp::Nil)
+}
 
-    #[test]
-    fn test_string_tail() {
-        // (string-tail "abc") => "bc"
-        let e = list(&[symbol("string-tail"), string("abc")]);
-        assert_eq!(eval_default_module(e), Ok(string("bc")));
+/// The number of elements satisfying a predicate.
+fn count(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/count", args).exactly(2)?;
+    let pred = args.get(0)?;
+    let mut n = 0;
+    for elem in args.list(1)? {
+        if call_predicate(pred, elem, ctx)? {
+            n += 1;
+        }
     }
+    Ok(Exp::Integer(n))
+}
 
-    #[test]
-    fn test_string_init() {
-        // (string-init "abc") => "ab"
-        let e = list(&[symbol("string-init"), string("abc")]);
-        assert_eq!(eval_default_module(e), Ok(string("ab")));
+fn length(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let list = Args::new("list/length", args).exactly(1)?.list(0)?;
+    Ok(Exp::Integer(list.len() as i64))
+}
+
+/// Concatenates any number of lists.
+fn append(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/append", args);
+    let mut result = vec![];
+    for i in 0..args.len() {
+        result.extend_from_slice(args.list(i)?);
     }
+    Ok(Exp::List(result.into()))
+}
 
-    #[test]
-    fn test_string_last() {
-        // (string-last "abc") => "c"
-        let e = list(&[symbol("string-last"), string("abc")]);
-        assert_eq!(eval_default_module(e), Ok(string("c")));
+/// Appends the lists in a list of lists.
+fn concat(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/concat", args).exactly(1)?;
+    let mut result = vec![];
+    for list in args.list(0)? {
+        let list = match list.node() {
+            Exp::Nil => &[][..],
+            Exp::List(list) => list,
+            _ => return Err(args.mismatch(0, "a list of lists")),
+        };
+        result.extend_from_slice(list);
     }
+    Ok(Exp::List(result.into()))
+}
 
-    #[test]
-    fn test_symbol_to_string() {
-        // (symbol->string 'abc) => "abc"
-        let e = list(&[symbol("symbol->string"), quote(symbol("abc"))]);
-        assert_eq!(eval_default_module(e), Ok(string("abc")));
+fn flatten_into(list: &[Exp], depth: Option<usize>, result: &mut Vec<Exp>) {
+    for elem in list {
+        match elem.as_list() {
+            Some(list) if depth != Some(0) => {
+                flatten_into(list, depth.map(|depth| depth - 1), result)
+            }
+            _ => result.push(elem.clone()),
+        }
+    }
+}
+
+/// `(flatten list)` splices nested lists into `list` at any depth, and
+/// `(flatten list depth)` only the lists nested up to `depth` levels.
+fn flatten(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/flatten", args).arity(Arity::Between(1, 2))?;
+    let depth = match args.len() {
+        1 => None,
+        _ => Some(args.count(1)?),
+    };
+    let mut result = vec![];
+    flatten_into(args.list(0)?, depth, &mut result);
+    Ok(Exp::List(result.into()))
+}
+
+fn reverse(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let list = Args::new("list/reverse", args).exactly(1)?.list(0)?;
+    Ok(Exp::List(list.iter().rev().cloned().collect()))
+}
+
+/// `(range end)`, `(range start end)` or `(range start end step)`: the integers
+/// from `start` (default 0) up to but excluding `end`, `step` (default 1) apart.
+/// A negative step counts down.
+fn range(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/range", args).arity(Arity::Between(1, 3))?;
+    let ints = (0..args.len())
+        .map(|i| args.integer(i))
+        .collect::<Result<Vec<_>>>()?;
+    let (start, end, step) = match ints.as_slice() {
+        [end] => (0, *end, 1),
+        [start, end] => (*start, *end, 1),
+        [start, end, step] if *step != 0 => (*start, *end, *step),
+        _ => return Err(args.mismatch(2, "a non-zero step")),
+    };
+    let distance = end as i128 - start as i128;
+    let len = if distance.signum() == step.signum() as i128 {
+        (distance.unsigned_abs() - 1) / step.unsigned_abs() as u128 + 1
+    } else {
+        0
+    };
+    ctx.reserve(element_bytes(len))?;
+    let mut result = vec![];
+    let mut i = start;
+    while (step > 0 && i < end) || (step < 0 && i > end) {
+        result.push(Exp::Integer(i));
+        match i.checked_add(step) {
+            Some(next) => i = next,
+            None => break,
+        }
+    }
+    Ok(Exp::List(result.into()))
+}
+
+/// The first `n` elements of a list, or all of them if there are fewer.
+fn list_take(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/take", args).exactly(2)?;
+    let (n, list) = (args.count(0)?, args.list(1)?);
+    Ok(Exp::List(list[..n.min(list.len())].into()))
+}
+
+/// The elements of a list after the first `n`.
+fn list_drop(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/drop", args).exactly(2)?;
+    let (n, list) = (args.count(0)?, args.list(1)?);
+    Ok(Exp::List(list[n.min(list.len())..].into()))
+}
+
+/// Pairs up the elements of two lists, up to the length of the shorter one.
+fn zip(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/zip", args).exactly(2)?;
+    let (xs, ys) = (args.list(0)?, args.list(1)?);
+    Ok(Exp::List(
+        xs.iter()
+            .zip(ys)
+            .map(|(x, y)| ast::list(&[x.clone(), y.clone()]))
+            .collect(),
+    ))
+}
+
+/// Pairs each element of a list with its index.
+fn enumerate(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let list = Args::new("list/enumerate", args).exactly(1)?.list(0)?;
+    Ok(Exp::List(
+        list.iter()
+            .enumerate()
+            .map(|(i, x)| ast::list(&[Exp::Integer(i as i64), x.clone()]))
+            .collect(),
+    ))
+}
+
+fn list_product(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/product", args);
+    let len = (0..args.len()).try_fold(1u128, |len, i| {
+        Ok::<_, EvalError>(len.saturating_mul(args.list(i)?.len() as u128))
+    })?;
+    ctx.reserve(element_bytes(len.saturating_mul(args.len() as u128 + 1)))?;
+    let mut product = vec![vec![]];
+    for i in 0..args.len() {
+        let list = args.list(i)?;
+        product = product
+            .into_iter()
+            .flat_map(|prefix| {
+                list.iter().map(move |elem| {
+                    let mut prefix = prefix.clone();
+                    prefix.push(elem.clone());
+                    prefix
+                })
+            })
+            .collect();
+    }
+    Ok(Exp::List(
+        product
+            .into_iter()
+            .map(|list| Exp::List(list.into()))
+            .collect(),
+    ))
+}
+
+/// The number of ways to choose `k` of `n` elements, saturating at
+/// `u128::MAX`.
+fn binomial(n: usize, k: usize) -> u128 {
+    if k > n {
+        return 0;
+    }
+    let k = k.min(n - k);
+    (0..k).fold(1u128, |acc, i| {
+        // The product of `i + 1` consecutive integers is divisible by `(i + 1)!`.
+        match acc.checked_mul((n - i) as u128) {
+            Some(acc) => acc / (i as u128 + 1),
+            None => u128::MAX,
+        }
+    })
+}
+
+/// The bytes of a list of `len` elements, saturating at `usize::MAX`, for
+/// [`EvalContext::reserve`].
+fn element_bytes(len: u128) -> usize {
+    len.saturating_mul(core::mem::size_of::<Exp>() as u128)
+        .try_into()
+        .unwrap_or(usize::MAX)
+}
+
+fn combinations(k: usize, list: &[Exp]) -> Vec<Vec<Exp>> {
+    if k == 0 {
+        return vec![vec![]];
+    }
+    let mut result = vec![];
+    for (i, elem) in list.iter().enumerate() {
+        for mut rest in combinations(k - 1, &list[i + 1..]) {
+            rest.insert(0, elem.clone());
+            result.push(rest);
+        }
+    }
+    result
+}
+
+fn list_combinations(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/combinations", args).exactly(2)?;
+    let (k, list) = (args.count(0)?, args.list(1)?);
+    ctx.reserve(element_bytes(
+        binomial(list.len(), k).saturating_mul(k as u128 + 1),
+    ))?;
+    Ok(Exp::List(
+        combinations(k, list)
+            .into_iter()
+            .map(|list| Exp::List(list.into()))
+            .collect(),
+    ))
+}
+
+fn sort_generic(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let mut list = Args::new("list/sort-generic", args)
+        .exactly(1)?
+        .list(0)?
+        .to_vec();
+    list.sort();
+    Ok(Exp::List(list.into()))
+}
+
+/// The entries of the association list at `i`, a list of `(key value)`
+/// pairs.
+fn parse_alist<'a>(args: Args<'a>, i: usize) -> Result<Vec<(&'a Exp, &'a Exp)>> {
+    args.list(i)?
+        .iter()
+        .map(|entry| match entry.as_list() {
+            Some([key, value]) => Ok((key, value)),
+            _ => Err(args.mismatch(i, "an association list")),
+        })
+        .collect()
+}
+
+/// `(assoc key alist)`: the first entry of `alist` for `key`, or nil.
+fn assoc(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("alist/assoc", args).exactly(2)?;
+    let key = args.get(0)?;
+    Ok(parse_alist(args, 1)?
+        .into_iter()
+        .find(|(k, _)| *k == key)
+        .map_or(Exp::Nil, |(k, v)| ast::list(&[k.clone(), v.clone()])))
+}
+
+/// `(alist/get key alist)`: the value of the first entry for `key`, or nil.
+fn alist_get(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("alist/get", args).exactly(2)?;
+    let key = args.get(0)?;
+    Ok(parse_alist(args, 1)?
+        .into_iter()
+        .find(|(k, _)| *k == key)
+        .map_or(Exp::Nil, |(_, v)| v.clone()))
+}
+
+/// `(alist/set key value alist)`: replaces the value of the first entry for
+/// `key`, or adds an entry at the end if there is none.
+fn alist_set(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("alist/set", args).exactly(3)?;
+    let (key, value) = (args.get(0)?, args.get(1)?);
+    let mut entries = parse_alist(args, 2)?;
+    match entries.iter().position(|(k, _)| *k == key) {
+        Some(i) => entries[i] = (key, value),
+        None => entries.push((key, value)),
+    }
+    Ok(Exp::List(
+        entries
+            .into_iter()
+            .map(|(k, v)| ast::list(&[k.clone(), v.clone()]))
+            .collect(),
+    ))
+}
+
+/// `(alist/remove key alist)`: `alist` without the entries for `key`.
+fn alist_remove(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("alist/remove", args).exactly(2)?;
+    let key = args.get(0)?;
+    Ok(Exp::List(
+        parse_alist(args, 1)?
+            .into_iter()
+            .filter(|(k, _)| *k != key)
+            .map(|(k, v)| ast::list(&[k.clone(), v.clone()]))
+            .collect(),
+    ))
+}
+
+/// `(map/new key value ...)`: a map of the given entries.
+fn map_new(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    if !args.len().is_multiple_of(2) {
+        return Err(Args::new("map/new", args).mismatch(args.len() - 1, "a key with a value"));
+    }
+    Ok(Exp::Map(Rc::new(
+        args.chunks(2)
+            .map(|entry| (entry[0].clone(), entry[1].clone()))
+            .collect(),
+    )))
+}
+
+/// `(map/get key map)`: the value for `key`, or nil.
+fn map_get(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("map/get", args).exactly(2)?;
+    let map = args.map(1)?;
+    Ok(map.get(args.get(0)?).cloned().unwrap_or(Exp::Nil))
+}
+
+/// `(map/insert key value map)`: `map` with `key` set to `value`.
+fn map_insert(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("map/insert", args).exactly(3)?;
+    let mut map = args.map(2)?.clone();
+    Rc::make_mut(&mut map).insert(args.get(0)?.clone(), args.get(1)?.clone());
+    Ok(Exp::Map(map))
+}
+
+/// `(map/remove key map)`: `map` without `key`.
+fn map_remove(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("map/remove", args).exactly(2)?;
+    let mut map = args.map(1)?.clone();
+    Rc::make_mut(&mut map).remove(args.get(0)?);
+    Ok(Exp::Map(map))
+}
+
+fn map_contains(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("map/contains?", args).exactly(2)?;
+    Ok(Exp::Bool(args.map(1)?.contains_key(args.get(0)?)))
+}
+
+/// The keys of a map in ascending order.
+fn map_keys(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let map = Args::new("map/keys", args).exactly(1)?.map(0)?;
+    Ok(Exp::List(map.keys().cloned().collect()))
+}
+
+/// The values of a map in the order of their keys.
+fn map_values(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let map = Args::new("map/values", args).exactly(1)?.map(0)?;
+    Ok(Exp::List(map.values().cloned().collect()))
+}
+
+/// `(set/new elem ...)`: a set of the arguments, without duplicates.
+fn set_new(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    Ok(Exp::Set(Rc::new(args.iter().cloned().collect())))
+}
+
+fn set_insert(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("set/insert", args).exactly(2)?;
+    let mut set = args.set(1)?.clone();
+    Rc::make_mut(&mut set).insert(args.get(0)?.clone());
+    Ok(Exp::Set(set))
+}
+
+fn set_remove(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("set/remove", args).exactly(2)?;
+    let mut set = args.set(1)?.clone();
+    Rc::make_mut(&mut set).remove(args.get(0)?);
+    Ok(Exp::Set(set))
+}
+
+fn set_contains(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("set/contains?", args).exactly(2)?;
+    Ok(Exp::Bool(args.set(1)?.contains(args.get(0)?)))
+}
+
+/// Combines two sets into a new one.
+fn combine_sets(
+    args: Args,
+    f: impl Fn(&BTreeSet<Exp>, &BTreeSet<Exp>) -> BTreeSet<Exp>,
+) -> Result<Exp> {
+    let args = args.exactly(2)?;
+    Ok(Exp::Set(Rc::new(f(args.set(0)?, args.set(1)?))))
+}
+
+fn set_union(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    combine_sets(Args::new("set/union", args), |lhs, rhs| {
+        lhs.union(rhs).cloned().collect()
+    })
+}
+
+fn set_intersect(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    combine_sets(Args::new("set/intersect", args), |lhs, rhs| {
+        lhs.intersection(rhs).cloned().collect()
+    })
+}
+
+/// The elements of the first set that are not in the second.
+fn set_difference(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    combine_sets(Args::new("set/difference", args), |lhs, rhs| {
+        lhs.difference(rhs).cloned().collect()
+    })
+}
+
+/// The elements of a set in ascending order.
+fn set_to_list(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let set = Args::new("set/to-list", args).exactly(1)?.set(0)?;
+    Ok(Exp::List(set.iter().cloned().collect()))
+}
+
+/// `(vector elem ...)`: a vector of the arguments.
+fn vector(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    Ok(ast::vector(args))
+}
+
+/// `(vector/ref i vector)`: the element at index `i`.
+fn vector_ref(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("vector/ref", args).exactly(2)?;
+    let (i, vector) = (args.integer(0)?, args.vector(1)?);
+    usize::try_from(i)
+        .ok()
+        .and_then(|i| vector.get(i).cloned())
+        .ok_or(EvalError::IndexOutOfBounds {
+            index: i,
+            len: vector.len(),
+        })
+}
+
+/// `(vector/set i value vector)`: `vector` with the element at index `i`
+/// replaced by `value`.
+fn vector_set(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("vector/set", args).exactly(3)?;
+    let (i, value) = (args.integer(0)?, args.get(1)?.clone());
+    let mut vector = args.vector(2)?.clone();
+    let len = vector.len();
+    *usize::try_from(i)
+        .ok()
+        .and_then(|i| Rc::make_mut(&mut vector).get_mut(i))
+        .ok_or(EvalError::IndexOutOfBounds { index: i, len })? = value;
+    Ok(Exp::Vector(vector))
+}
+
+fn vector_length(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let vector = Args::new("vector/length", args).exactly(1)?.vector(0)?;
+    Ok(Exp::Integer(vector.len() as i64))
+}
+
+fn vector_to_list(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let vector = Args::new("vector/to-list", args).exactly(1)?.vector(0)?;
+    Ok(ast::list(vector))
+}
+
+fn list_to_vector(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let list = Args::new("list/to-vector", args).exactly(1)?.list(0)?;
+    Ok(ast::vector(list))
+}
+
+/// `(bytes byte ...)`: a byte string of the arguments, each in `0..=255`.
+fn bytes(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("bytes/new", args);
+    let bytes = (0..args.len())
+        .map(|i| {
+            args.parse(i, "a byte from 0 to 255", |arg| {
+                arg.as_integer().and_then(|n| u8::try_from(n).ok())
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(ast::bytes(&bytes))
+}
+
+fn bytes_length(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let bytes = Args::new("bytes/length", args).exactly(1)?.bytes(0)?;
+    Ok(Exp::Integer(bytes.len() as i64))
+}
+
+/// `(bytes/ref i bytes)`: the byte at index `i` as an integer.
+fn bytes_ref(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("bytes/ref", args).exactly(2)?;
+    let (i, bytes) = (args.integer(0)?, args.bytes(1)?);
+    usize::try_from(i)
+        .ok()
+        .and_then(|i| bytes.get(i))
+        .map(|&byte| Exp::Integer(byte.into()))
+        .ok_or(EvalError::IndexOutOfBounds {
+            index: i,
+            len: bytes.len(),
+        })
+}
+
+/// `(bytes/slice start end bytes)`: the bytes from index `start` up to, but
+/// not including, `end`.
+fn bytes_slice(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("bytes/slice", args).exactly(3)?;
+    let (start, end) = (args.integer(0)?, args.integer(1)?);
+    let bytes = args.bytes(2)?;
+    usize::try_from(start)
+        .ok()
+        .zip(usize::try_from(end).ok())
+        .and_then(|(start, end)| bytes.get(start..end))
+        .map(ast::bytes)
+        .ok_or(EvalError::OutOfRange(start, end, bytes.len()))
+}
+
+/// The UTF-8 encoding of a string.
+fn string_to_bytes(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let s = Args::new("string/to-bytes", args).exactly(1)?.string(0)?;
+    Ok(ast::bytes(s.as_bytes()))
+}
+
+/// Decodes UTF-8 bytes to a string. Fails if the bytes are not valid UTF-8.
+fn bytes_to_string(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    fallible(args, |args| {
+        let args = Args::new("bytes/to-string", args).exactly(1)?;
+        core::str::from_utf8(args.bytes(0)?)
+            .map(|s| Exp::String(s.to_string()))
+            .map_err(|_| args.mismatch(0, "UTF-8 bytes"))
+    })
+}
+
+/// The Unicode scalar value of a character.
+fn char_to_integer(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let ch = Args::new("char/to-integer", args).exactly(1)?.char(0)?;
+    Ok(Exp::Integer(ch as i64))
+}
+
+/// The character with a Unicode scalar value. Fails for surrogates and values
+/// out of range.
+fn integer_to_char(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    fallible(args, |args| {
+        let args = Args::new("char/from-integer", args).exactly(1)?;
+        args.parse(0, "a Unicode scalar value", |exp| {
+            exp.as_integer()
+                .and_then(|n| u32::try_from(n).ok())
+                .and_then(char::from_u32)
+        })
+        .map(Exp::Char)
+    })
+}
+
+/// A stable merge sort. `less` need not be a consistent order, which matters
+/// for comparison functions written in topogi.
+fn merge_sort(
+    mut list: Vec<Exp>,
+    less: &mut impl FnMut(&Exp, &Exp) -> Result<bool>,
+) -> Result<Vec<Exp>> {
+    if list.len() < 2 {
+        return Ok(list);
+    }
+    let right = list.split_off(list.len() / 2);
+    let left = merge_sort(list, less)?;
+    let right = merge_sort(right, less)?;
+    let mut result = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        // Equal elements are taken from the left, which keeps the sort stable.
+        let next = if less(r, l)? { &mut right } else { &mut left };
+        result.extend(next.next());
+    }
+    result.extend(left);
+    result.extend(right);
+    Ok(result)
+}
+
+/// Orders numbers by value, strings lexicographically and characters by
+/// their Unicode scalar value.
+fn compare(lhs: &Exp, rhs: &Exp) -> Result<Ordering> {
+    match (lhs.node(), rhs.node()) {
+        (Exp::String(l), Exp::String(r)) => Ok(l.cmp(r)),
+        (Exp::Char(l), Exp::Char(r)) => Ok(l.cmp(r)),
+        _ => parse_compare(Args::new("list/sort", &[lhs.clone(), rhs.clone()]))
+            .ok()
+            .flatten()
+            .ok_or_else(|| EvalError::Incomparable(lhs.clone(), rhs.clone())),
+    }
+}
+
+/// Sorts a list of numbers, strings or characters in ascending order.
+fn sort(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let list = Args::new("list/sort", args).exactly(1)?.list(0)?.to_vec();
+    let sorted = merge_sort(list, &mut |lhs, rhs| Ok(compare(lhs, rhs)?.is_lt()))?;
+    Ok(Exp::List(sorted.into()))
+}
+
+/// Sorts a list with a function that tells whether its first argument comes
+/// before its second.
+fn sort_by(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/sort-by", args).exactly(2)?;
+    let (less, list) = (args.get(0)?, args.list(1)?.to_vec());
+    let sorted = merge_sort(list, &mut |lhs, rhs| {
+        let result = ctx.call(less.clone(), &[lhs.clone(), rhs.clone()])?;
+        result.as_bool().ok_or(EvalError::ExpectedBool(result))
+    })?;
+    Ok(Exp::List(sorted.into()))
+}
+
+fn flatten_indexed_into(list: &[Exp], path: &mut Vec<Exp>, result: &mut Vec<Exp>) {
+    for (i, elem) in list.iter().enumerate() {
+        path.push(Exp::Integer(i as i64));
+        match elem {
+            Exp::List(list) => flatten_indexed_into(list, path, result),
+            _ => result.push(ast::list(&[elem.clone(), ast::list(path)])),
+        }
+        path.pop();
+    }
+}
+
+fn flatten_indexed(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let list = Args::new("list/flatten-indexed", args)
+        .exactly(1)?
+        .list(0)?;
+    let mut result = vec![];
+    flatten_indexed_into(list, &mut vec![], &mut result);
+    Ok(Exp::List(result.into()))
+}
+
+fn insert_ternary_curry_op(func: BuiltinFn, func_name: &str, module: &mut Module) {
+    module.defines.insert(
+        SymbolId::intern(func_name),
+        ast::lambda(
+            "x",
+            ast::lambda(
+                "y",
+                ast::lambda(
+                    "z",
+                    ast::list(&[
+                        Exp::BuildIn(func),
+                        ast::symbol("x"),
+                        ast::symbol("y"),
+                        ast::symbol("z"),
+                    ]),
+                ),
+            ),
+        ),
+    );
+}
+
+/// The side effects that the builtins of a module may have. The builtins of
+/// a capability that is not granted are left out of the module, so a module
+/// with [`Capabilities::none`] only computes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Writing to standard output, with `print`, `println` and `write`.
+    pub print: bool,
+}
+
+impl Capabilities {
+    pub fn all() -> Self {
+        Capabilities { print: true }
+    }
+
+    pub fn none() -> Self {
+        Capabilities { print: false }
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// The namespaces of the standard library. Every builtin is defined under a
+/// qualified name such as `string/append`, which a program cannot redefine
+/// and a module of the same name cannot take over.
+pub const NAMESPACES: &[&str] = &[
+    "alist", "bit", "bytes", "char", "core", "io", "keyword", "list", "map", "math", "result",
+    "set", "string", "symbol", "vector",
+];
+
+/// The unqualified names of the builtins that every program sees, paired
+/// with the qualified name they alias. A program may shadow these; the rest
+/// of the standard library is only reachable by its qualified name.
+pub const PRELUDE: &[(&str, &str)] = &[
+    ("=", "core/="),
+    ("/=", "core//="),
+    ("<", "core/<"),
+    (">", "core/>"),
+    ("<=", "core/<="),
+    (">=", "core/>="),
+    ("not", "core/not"),
+    ("values", "core/values"),
+    ("atom?", "core/atom?"),
+    ("pair?", "core/pair?"),
+    ("void?", "core/void?"),
+    ("show", "core/show"),
+    ("gensym", "core/gensym"),
+    ("eval", "core/eval"),
+    ("read", "core/read"),
+    ("raise", "core/raise"),
+    ("error", "core/error"),
+    ("assert", "core/assert"),
+    ("assert-eq", "core/assert-eq"),
+    ("apply", "core/apply"),
+    ("+", "math/+"),
+    ("-", "math/-"),
+    ("*", "math/*"),
+    ("/", "math//"),
+    ("mod", "math/mod"),
+    ("rem", "math/rem"),
+    ("gcd", "math/gcd"),
+    ("abs", "math/abs"),
+    ("min", "math/min"),
+    ("max", "math/max"),
+    ("pow", "math/pow"),
+    ("floor", "math/floor"),
+    ("ceil", "math/ceil"),
+    ("round", "math/round"),
+    ("truncate", "math/truncate"),
+    ("numerator", "math/numerator"),
+    ("denominator", "math/denominator"),
+    ("odd", "math/odd"),
+    ("even", "math/even"),
+    ("float?", "math/float?"),
+    ("cons", "list/cons"),
+    ("list", "list/list"),
+    ("first", "list/first"),
+    ("car", "list/car"),
+    ("second", "list/second"),
+    ("third", "list/third"),
+    ("last", "list/last"),
+    ("init", "list/init"),
+    ("rest", "list/rest"),
+    ("cdr", "list/cdr"),
+    ("nth", "list/nth"),
+    ("length", "list/length"),
+    ("append", "list/append"),
+    ("reverse", "list/reverse"),
+    ("concat", "list/concat"),
+    ("flatten", "list/flatten"),
+    ("range", "list/range"),
+    ("take", "list/take"),
+    ("drop", "list/drop"),
+    ("zip", "list/zip"),
+    ("enumerate", "list/enumerate"),
+    ("member?", "list/member?"),
+    ("find", "list/find"),
+    ("position", "list/position"),
+    ("count", "list/count"),
+    ("map", "list/map"),
+    ("filter", "list/filter"),
+    ("foldr", "list/foldr"),
+    ("foldl", "list/foldl"),
+    ("sort", "list/sort"),
+    ("sort-by", "list/sort-by"),
+    ("assoc", "alist/assoc"),
+    ("ok", "result/ok"),
+    ("err", "result/err"),
+    ("some", "result/some"),
+    ("none", "result/none"),
+    ("ok?", "result/ok?"),
+    ("err?", "result/err?"),
+    ("some?", "result/some?"),
+    ("none?", "result/none?"),
+    ("unwrap", "result/unwrap"),
+    ("unwrap-or", "result/unwrap-or"),
+    ("vector", "vector/new"),
+    ("bytes", "bytes/new"),
+    ("format", "string/format"),
+    ("print", "io/print"),
+    ("println", "io/println"),
+    ("write", "io/write"),
+];
+
+/// The standard library with every capability.
+pub fn default_module() -> Module {
+    default_module_with(Capabilities::all())
+}
+
+/// The standard library with the builtins that `capabilities` grant, under
+/// their qualified names and the aliases of the [`PRELUDE`].
+pub fn default_module_with(capabilities: Capabilities) -> Module {
+    let mut module = Module::new("##default##");
+
+    module.register_curried_binary("math/+", add);
+    module.register_curried_binary("math/-", sub);
+    module.register_curried_binary("math/*", mul);
+    module.register_curried_binary("math//", div);
+    module.register_curried_binary("core/=", eq);
+    module.register_curried_binary("core//=", ne);
+    module.register_curried_binary("core/<", lt);
+    module.register_curried_binary("core/>", gt);
+    module.register_curried_binary("core/<=", le);
+    module.register_curried_binary("core/>=", ge);
+
+    module.register_builtin("core/not", not);
+
+    module.register_curried_binary("list/cons", cons);
+    module.register_builtin("list/list", list);
+    module.register_builtin("core/values", values);
+    module.register_builtin("core/atom?", is_atom);
+    module.register_builtin("core/pair?", is_pair);
+    module.register_builtin("core/void?", is_void);
+
+    module.register_builtin("list/first", first);
+    module.register_builtin("list/car", first);
+    module.register_builtin("list/second", second);
+    module.register_builtin("list/third", third);
+    module.register_builtin("list/last", last);
+    module.register_builtin("list/init", init);
+    module.register_builtin("list/rest", rest);
+    module.register_builtin("list/cdr", rest);
+    module.register_curried_binary("list/nth", nth);
+
+    module.register_builtin("core/show", show);
+    module.register_builtin("string/format", format);
+
+    module.register_builtin("symbol/to-string", symbol_to_string);
+    module.register_builtin("string/to-symbol", string_to_symbol);
+    module.register_builtin("keyword/to-string", keyword_to_string);
+    module.register_builtin("string/to-keyword", string_to_keyword);
+    module.register_builtin("core/gensym", gensym);
+    module.register_builtin("core/eval", eval);
+    module.register_builtin("core/read", read);
+    module.register_builtin("core/raise", raise);
+    module.register_builtin("core/error", error);
+    module.register_builtin("core/assert", assert);
+    module.register_builtin("core/assert-eq", assert_eq);
+    module.register_builtin("result/ok", ok);
+    module.register_builtin("result/err", err);
+    module.register_builtin("result/some", some);
+    module.register_builtin("result/none", none);
+    module.register_builtin("result/ok?", is_ok);
+    module.register_builtin("result/err?", is_err);
+    module.register_builtin("result/some?", is_some);
+    module.register_builtin("result/none?", is_none);
+    module.register_builtin("result/unwrap", unwrap);
+    module.register_curried_binary("result/unwrap-or", unwrap_or);
+    module.register_curried_binary("result/map", map_ok);
+
+    module.register_curried_binary("core/apply", apply_list);
+    insert_ternary_curry_op(foldr, "list/foldr", &mut module);
+    insert_ternary_curry_op(foldl, "list/foldl", &mut module);
+    module.register_curried_binary("list/map", map);
+    module.register_curried_binary("list/filter", filter);
+    module.register_builtin("list/length", length);
+    module.register_builtin("list/append", append);
+    module.register_builtin("list/reverse", reverse);
+    module.register_builtin("list/concat", concat);
+    module.register_builtin("list/flatten", flatten);
+    module.register_builtin("list/range", range);
+    module.register_curried_binary("list/take", list_take);
+    module.register_curried_binary("list/drop", list_drop);
+    module.register_curried_binary("list/zip", zip);
+    module.register_builtin("list/enumerate", enumerate);
+    module.register_curried_binary("alist/assoc", assoc);
+    module.register_curried_binary("alist/get", alist_get);
+    insert_ternary_curry_op(alist_set, "alist/set", &mut module);
+    module.register_curried_binary("alist/remove", alist_remove);
+    module.register_curried_binary("list/member?", is_member);
+    module.register_curried_binary("list/find", find);
+    module.register_curried_binary("list/position", position);
+    module.register_curried_binary("list/count", count);
+    module.register_builtin("map/new", map_new);
+    module.register_curried_binary("map/get", map_get);
+    insert_ternary_curry_op(map_insert, "map/insert", &mut module);
+    module.register_curried_binary("map/remove", map_remove);
+    module.register_curried_binary("map/contains?", map_contains);
+    module.register_builtin("map/keys", map_keys);
+    module.register_builtin("map/values", map_values);
+    module.register_builtin("set/new", set_new);
+    module.register_curried_binary("set/insert", set_insert);
+    module.register_curried_binary("set/remove", set_remove);
+    module.register_curried_binary("set/contains?", set_contains);
+    module.register_curried_binary("set/union", set_union);
+    module.register_curried_binary("set/intersect", set_intersect);
+    module.register_curried_binary("set/difference", set_difference);
+    module.register_builtin("set/to-list", set_to_list);
+    module.register_builtin("vector/new", vector);
+    module.register_curried_binary("vector/ref", vector_ref);
+    insert_ternary_curry_op(vector_set, "vector/set", &mut module);
+    module.register_builtin("vector/length", vector_length);
+    module.register_builtin("vector/to-list", vector_to_list);
+    module.register_builtin("list/to-vector", list_to_vector);
+    module.register_builtin("bytes/new", bytes);
+    module.register_builtin("bytes/length", bytes_length);
+    module.register_curried_binary("bytes/ref", bytes_ref);
+    insert_ternary_curry_op(bytes_slice, "bytes/slice", &mut module);
+    module.register_builtin("string/to-bytes", string_to_bytes);
+    module.register_builtin("bytes/to-string", bytes_to_string);
+    module.register_builtin("char/to-integer", char_to_integer);
+    module.register_builtin("char/from-integer", integer_to_char);
+    module.register_builtin("list/product", list_product);
+    module.register_builtin("list/combinations", list_combinations);
+    module.register_builtin("list/sort-generic", sort_generic);
+    module.register_builtin("list/sort", sort);
+    module.register_curried_binary("list/sort-by", sort_by);
+    module.register_builtin("list/flatten-indexed", flatten_indexed);
+
+    #[cfg(feature = "std-io")]
+    if capabilities.print {
+        io::register(&mut module);
+    }
+    #[cfg(not(feature = "std-io"))]
+    let _ = capabilities;
+    #[cfg(feature = "std-math")]
+    math::register(&mut module);
+    #[cfg(feature = "std-string")]
+    string::register(&mut module);
+
+    for (alias, name) in PRELUDE {
+        if let Some(exp) = module.defines.get(&SymbolId::intern(name)).cloned() {
+            module.defines.insert(SymbolId::intern(alias), exp);
+        }
+    }
+    module
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::*,
+        eval::{eval_default_module, eval_kind, eval_str, EvalError},
+    };
+
+    #[test]
+    fn test_integer_binary_op() {
+        // (+ 1 2)
+        let e = list(&[symbol("+"), integer(1), integer(2)]);
+        assert_eq!(eval_default_module(e), Ok(Exp::Integer(3)));
+
+        // (- 1 2)
+        let e = list(&[symbol("-"), integer(1), integer(2)]);
+        assert_eq!(eval_default_module(e), Ok(Exp::Integer(-1)));
+    }
+
+    #[test]
+    fn test_float_binary_op() {
+        // (+ 1.5 2.25)
+        let e = list(&[symbol("+"), float(1.5), float(2.25)]);
+        assert_eq!(eval_default_module(e), Ok(float(3.75)));
+
+        // (* 2 0.5)
+        let e = list(&[symbol("*"), integer(2), float(0.5)]);
+        assert_eq!(eval_default_module(e), Ok(float(1.0)));
+
+        // (/ 1 2.0)
+        let e = list(&[symbol("/"), integer(1), float(2.0)]);
+        assert_eq!(eval_default_module(e), Ok(float(0.5)));
+
+        // (/ 1.0 0) => inf
+        let e = list(&[symbol("/"), float(1.0), integer(0)]);
+        assert_eq!(eval_default_module(e), Ok(float(f64::INFINITY)));
+
+        // (/ 1 0) => error
+        let e = list(&[symbol("/"), integer(1), integer(0)]);
+        assert!(eval_default_module(e).is_err());
+    }
+
+    #[cfg(feature = "bignum")]
+    #[test]
+    fn test_bignum() {
+        // Overflowing operations promote to big integers.
+        assert_eq!(
+            eval_str("(+ 9223372036854775807 1)").map(|e| e.to_string()),
+            Ok("9223372036854775808".to_string())
+        );
+        assert_eq!(
+            eval_str("(* 9223372036854775807 -3)").map(|e| e.to_string()),
+            Ok("-27670116110564327421".to_string())
+        );
+        assert_eq!(
+            eval_str("(/ -9223372036854775808 -1)").map(|e| e.to_string()),
+            Ok("9223372036854775808".to_string())
+        );
+
+        // Results that fit again are plain integers.
+        assert_eq!(eval_str("(- 9223372036854775808 1)"), Ok(integer(i64::MAX)));
+        assert_eq!(
+            eval_str("(= (+ 9223372036854775807 1) 9223372036854775808)"),
+            Ok(bool(true))
+        );
+        assert!(eval_str("(/ 9223372036854775808 0)").is_err());
+        assert_eq!(
+            eval_str("(* 9223372036854775808 0.5)"),
+            Ok(float(4611686018427387904.0))
+        );
+    }
+
+    #[test]
+    fn test_rational() {
+        // (/ 1 3) => 1/3
+        let e = list(&[symbol("/"), integer(1), integer(3)]);
+        assert_eq!(eval_default_module(e), Ok(rational(1, 3)));
+
+        // (/ 6 3) => 2
+        let e = list(&[symbol("/"), integer(6), integer(3)]);
+        assert_eq!(eval_default_module(e), Ok(integer(2)));
+
+        // (+ 1/3 1/6) => 1/2
+        let e = list(&[symbol("+"), rational(1, 3), rational(1, 6)]);
+        assert_eq!(eval_default_module(e), Ok(rational(1, 2)));
+
+        // (- 1/2 1) => -1/2
+        let e = list(&[symbol("-"), rational(1, 2), integer(1)]);
+        assert_eq!(eval_default_module(e), Ok(rational(-1, 2)));
+
+        // (* 2/3 3/2) => 1
+        let e = list(&[symbol("*"), rational(2, 3), rational(3, 2)]);
+        assert_eq!(eval_default_module(e), Ok(integer(1)));
+
+        // (/ 1/2 -3) => -1/6
+        let e = list(&[symbol("/"), rational(1, 2), integer(-3)]);
+        assert_eq!(eval_default_module(e), Ok(rational(-1, 6)));
+
+        // (/ 1/2 0) => error
+        let e = list(&[symbol("/"), rational(1, 2), integer(0)]);
+        assert!(eval_default_module(e).is_err());
+
+        // (+ 1/2 0.25) => 0.75
+        let e = list(&[symbol("+"), rational(1, 2), float(0.25)]);
+        assert_eq!(eval_default_module(e), Ok(float(0.75)));
+    }
+
+    #[cfg(not(feature = "bignum"))]
+    #[test]
+    fn test_integer_overflow() {
+        use crate::eval::EvalError;
+
+        // (+ 9223372036854775807 1) => overflow
+        let e = list(&[symbol("+"), integer(i64::MAX), integer(1)]);
+        assert_eq!(
+            eval_default_module(e),
+            Err(EvalError::IntegerOverflow(apply(
+                integer(i64::MAX),
+                integer(1)
+            )))
+        );
+
+        // (- -9223372036854775808 1) => overflow
+        let e = list(&[symbol("-"), integer(i64::MIN), integer(1)]);
+        assert!(matches!(
+            eval_default_module(e),
+            Err(EvalError::IntegerOverflow(_))
+        ));
+
+        // (* 4611686018427387904 2) => overflow
+        let e = list(&[symbol("*"), integer(1 << 62), integer(2)]);
+        assert!(matches!(
+            eval_default_module(e),
+            Err(EvalError::IntegerOverflow(_))
+        ));
+
+        // (/ -9223372036854775808 -1) => overflow
+        let e = list(&[symbol("/"), integer(i64::MIN), integer(-1)]);
+        assert!(matches!(
+            eval_default_module(e),
+            Err(EvalError::IntegerOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_rational_overflow() {
+        use crate::eval::EvalError;
+
+        // (* 1/9223372036854775807 1/2) => overflow
+        let e = list(&[symbol("*"), rational(1, i64::MAX), rational(1, 2)]);
+        assert!(matches!(
+            eval_default_module(e),
+            Err(EvalError::IntegerOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_compare_op() {
+        // (= 1 1) => true
+        let e = list(&[symbol("="), integer(1), integer(1)]);
+        assert_eq!(eval_default_module(e), Ok(bool(true)));
+
+        // (/= 1 1) => false
+        let e = list(&[symbol("/="), integer(1), integer(1)]);
+        assert_eq!(eval_default_module(e), Ok(bool(false)));
+
+        // (/= '(1 2) 2) => true
+        let e = list(&[
+            symbol("/="),
+            quote(list(&[integer(1), integer(2)])),
+            integer(2),
+        ]);
+        assert_eq!(eval_default_module(e), Ok(bool(true)));
+
+        // (= '(1 2) 2) => false
+        let e = list(&[
+            symbol("="),
+            quote(list(&[integer(1), integer(2)])),
+            integer(2),
+        ]);
+        assert_eq!(eval_default_module(e), Ok(bool(false)));
+    }
+
+    #[test]
+    fn test_numeric_compare_op() {
+        for (op, lhs, rhs, expected) in [
+            ("<", integer(1), integer(2), true),
+            ("<", integer(2), integer(2), false),
+            (">", integer(3), integer(2), true),
+            ("<=", integer(2), integer(2), true),
+            (">=", integer(1), integer(2), false),
+            ("<", integer(1), float(1.5), true),
+            (">", rational(1, 2), rational(1, 3), true),
+            ("<=", rational(1, 2), float(0.5), true),
+            ("<", float(f64::NAN), integer(1), false),
+            (">=", float(f64::NAN), float(f64::NAN), false),
+        ] {
+            let e = list(&[symbol(op), lhs.clone(), rhs.clone()]);
+            assert_eq!(
+                eval_default_module(e),
+                Ok(bool(expected)),
+                "({} {} {})",
+                op,
+                lhs,
+                rhs
+            );
+        }
+
+        // ((< 1) 2) => true
+        let e = list(&[list(&[symbol("<"), integer(1)]), integer(2)]);
+        assert_eq!(eval_default_module(e), Ok(bool(true)));
+
+        // (< "a" "b") => error
+        let e = list(&[symbol("<"), string("a"), string("b")]);
+        assert!(eval_default_module(e).is_err());
+    }
+
+    #[test]
+    fn test_not() {
+        // (not true) => false
+        let e = list(&[symbol("not"), bool(true)]);
+        assert_eq!(eval_default_module(e), Ok(bool(false)));
+
+        // (not 1) => error
+        let e = list(&[symbol("not"), integer(1)]);
+        assert!(eval_default_module(e).is_err());
+    }
+
+    #[test]
+    fn test_cons() {
+        // (cons 1 '(2 3)) => (1 2 3)
+        let e = list(&[
+            symbol("cons"),
+            integer(1),
+            quote(list(&[integer(2), integer(3)])),
+        ]);
+        assert_eq!(
+            eval_default_module(e),
+            Ok(list(&[integer(1), integer(2), integer(3)]))
+        );
+
+        // (cons 1 2) => (1 . 2)
+        let e = list(&[symbol("cons"), integer(1), integer(2)]);
+        assert_eq!(eval_default_module(e), Ok(pair(integer(1), integer(2))));
+
+        // (cons '(1 2) 3) => ((1 2) . 3)
+        let e = list(&[
+            symbol("cons"),
+            quote(list(&[integer(1), integer(2)])),
+            integer(3),
+        ]);
+        assert_eq!(
+            eval_default_module(e),
+            Ok(pair(list(&[integer(1), integer(2)]), integer(3)))
+        );
+
+        // (cons 1 nil) => (1)
+        let e = list(&[symbol("cons"), integer(1), nil()]);
+        assert_eq!(eval_default_module(e), Ok(list(&[integer(1)])));
+    }
+
+    #[test]
+    fn test_pair() {
+        use crate::parser::parse_exp;
+
+        assert_eq!(eval_str("'(1 . 2)"), Ok(pair(integer(1), integer(2))));
+        assert_eq!(eval_str("(car '(1 . 2))"), Ok(integer(1)));
+        assert_eq!(eval_str("(cdr '(1 . 2))"), Ok(integer(2)));
+        assert_eq!(
+            eval_str("(cdr '(1 2 . 3))"),
+            Ok(pair(integer(2), integer(3)))
+        );
+        assert_eq!(
+            eval_str("'(1 . (2 3))"),
+            Ok(list(&[integer(1), integer(2), integer(3)]))
+        );
+        assert_eq!(eval_str("(= (cons 1 2) '(1 . 2))"), Ok(bool(true)));
+        assert_eq!(eval_str("(pair? (cons 1 2))"), Ok(bool(true)));
+        assert_eq!(eval_str("(pair? '())"), Ok(bool(false)));
+        assert_eq!(eval_str("(atom? (cons 1 2))"), Ok(bool(false)));
+        assert_eq!(
+            eval_str("(case (cons 1 2) ((a . b) (list a b)))"),
+            Ok(list(&[integer(1), integer(2)]))
+        );
+        assert_eq!(
+            eval_str("(case '(1 2 3) ((a . b) b))"),
+            Ok(list(&[integer(2), integer(3)]))
+        );
+        assert_eq!(
+            eval_str("(let (x 2) `(1 . ~x))"),
+            Ok(pair(integer(1), integer(2)))
+        );
+
+        // An association list.
+        assert_eq!(
+            eval_str("(cdr (first (filter (\\ (entry) (= (car entry) 'b)) '((a . 1) (b . 2)))))"),
+            Ok(integer(2))
+        );
+        assert_eq!(
+            pair(integer(1), pair(integer(2), integer(3))).to_string(),
+            "(1 2 . 3)"
+        );
+        assert!(eval_str("(car 1)").is_err());
+        assert!(parse_exp("(. 1)").is_err());
+        assert!(parse_exp("(1 . 2 3)").is_err());
+    }
+
+    #[test]
+    fn test_list() {
+        // (list 1 2 3) => (1 2 3)
+        let e = list(&[symbol("list"), integer(1), integer(2), integer(3)]);
+        assert_eq!(
+            eval_default_module(e),
+            Ok(list(&[integer(1), integer(2), integer(3)]))
+        );
+        // (list (+ 1 2)) => (3)
+        let e = list(&[symbol("list"), list(&[symbol("+"), integer(1), integer(2)])]);
+        assert_eq!(eval_default_module(e), Ok(list(&[integer(3)])));
+    }
+
+    #[test]
+    fn test_is_atom() {
+        // (atom? 1) => true
+        let e = list(&[symbol("atom?"), integer(1)]);
+        assert_eq!(eval_default_module(e), Ok(bool(true)));
+
+        // (atom? '(1 2)) => false
+        let e = list(&[symbol("atom?"), quote(list(&[integer(1), integer(2)]))]);
+        assert_eq!(eval_default_module(e), Ok(bool(false)));
+    }
+
+    #[cfg(feature = "std-io")]
+    #[test]
+    fn test_is_void() {
+        // (void? (println "x")) => true
+        let e = list(&[symbol("void?"), list(&[symbol("println"), string("x")])]);
+        assert_eq!(eval_default_module(e), Ok(bool(true)));
+
+        // (void? nil) => false
+        let e = list(&[symbol("void?"), nil()]);
+        assert_eq!(eval_default_module(e), Ok(bool(false)));
+
+        // (void? 0) => false
+        let e = list(&[symbol("void?"), integer(0)]);
+        assert_eq!(eval_default_module(e), Ok(bool(false)));
+    }
+
+    #[test]
+    fn test_nth() {
+        // (first '(1 2)) => 1
+        let e = list(&[symbol("first"), quote(list(&[integer(1), integer(2)]))]);
+        assert_eq!(eval_default_module(e), Ok(integer(1)));
+
+        // (second '(1 2)) => 2
+        let e = list(&[symbol("second"), quote(list(&[integer(1), integer(2)]))]);
+        assert_eq!(eval_default_module(e), Ok(integer(2)));
+
+        // (third '(1 2 3)) => 3
+        let e = list(&[
+            symbol("third"),
+            quote(list(&[integer(1), integer(2), integer(3)])),
+        ]);
+        assert_eq!(eval_default_module(e), Ok(integer(3)));
+
+        // (nth 5 '(1 2 3 4 5 6 7)) => 6
+        let e = list(&[
+            symbol("nth"),
+            integer(5),
+            quote(list(&[
+                integer(1),
+                integer(2),
+                integer(3),
+                integer(4),
+                integer(5),
+                integer(6),
+                integer(7),
+            ])),
+        ]);
+        assert_eq!(eval_default_module(e), Ok(integer(6)));
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            eval_str("(format \"x = {} y = {}\" 1 '(a b))"),
+            Ok(string("x = 1 y = (a b)"))
+        );
+        assert_eq!(
+            eval_str("(format \"{} {:?} {:?}\" \"a b\" \"a b\" '(c \"d\"))"),
+            Ok(string(r#"a b "a b" (c "d")"#))
+        );
+        assert_eq!(eval_str("(format \"{{}} {}\" 1)"), Ok(string("{} 1")));
+        assert_eq!(eval_str("(format \"none\")"), Ok(string("none")));
+        for source in [
+            "(format \"{} {}\" 1)",
+            "(format \"{}\" 1 2)",
+            "(format \"{\" 1)",
+            "(format \"}\")",
+            "(format \"{x}\" 1)",
+            "(format 'a)",
+        ] {
+            assert!(eval_str(source).is_err(), "{}", source);
+        }
+        // Interpolation needs no feature, as `string/format` is in the core.
+        assert_eq!(
+            eval_str(r#"(let (x 2) "x = ${x}, x + 1 = ${(+ x 1)}")"#),
+            Ok(string("x = 2, x + 1 = 3"))
+        );
+    }
+
+    #[cfg(feature = "std-io")]
+    #[test]
+    fn test_show() {
+        assert_eq!(
+            eval_str(r#"(show (list "a b" 'c #\d))"#),
+            Ok(string(r#"("a b" c #\d)"#))
+        );
+        assert_eq!(
+            eval_str(r#"(show "say \"hi\"\n")"#),
+            Ok(string(r#""say \"hi\"\n""#))
+        );
+        assert_eq!(eval_str("(void? (write \"x\"))"), Ok(bool(true)));
+
+        // Readable strings parse back to the value they were written from.
+        let value = eval_str(r#"(list "a\\b" '("c\td"))"#).unwrap();
+        assert_eq!(
+            eval_str(&format!("'{}", value.to_readable_string())),
+            Ok(value)
+        );
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(
+            eval_str("(let (x 2) [x (+ x 1)])"),
+            Ok(vector(&[integer(2), integer(3)]))
+        );
+        assert_eq!(eval_str("(map/get :b {:a 1 :b (+ 1 1)})"), Ok(integer(2)));
+        assert_eq!(
+            eval_str("(let (x 2) `[1 ~x])"),
+            Ok(vector(&[integer(1), integer(2)]))
+        );
+
+        // Printed values read back as equal values.
+        let value = eval_str(r#"{:name "app" :ports [80 443] :tags {"a" 1/2}}"#).unwrap();
+        assert_eq!(eval_str(&value.to_readable_string()), Ok(value.clone()));
+        assert_eq!(eval_str(&value.pretty(10)), Ok(value));
+    }
+
+    #[test]
+    fn test_symbol_to_string() {
+        // (symbol/to-string 'abc) => "abc"
+        let e = list(&[symbol("symbol/to-string"), quote(symbol("abc"))]);
+        assert_eq!(eval_default_module(e), Ok(string("abc")));
+    }
+
+    #[test]
+    fn test_keyword() {
+        assert_eq!(eval_str(":name"), Ok(keyword("name")));
+        assert_eq!(
+            eval_str("(list :a :b)"),
+            Ok(list(&[keyword("a"), keyword("b")]))
+        );
+        assert_eq!(eval_str("(= :a :a)"), Ok(bool(true)));
+        assert_eq!(eval_str("(map/get :b (map/new :a 1 :b 2))"), Ok(integer(2)));
+        assert_eq!(
+            eval_str("(case :ok (:error 'failed) (:ok 'done))"),
+            Ok(symbol("done"))
+        );
+        assert_eq!(eval_str("(keyword/to-string :abc)"), Ok(string("abc")));
+        assert_eq!(eval_str("(string/to-keyword \"abc\")"), Ok(keyword("abc")));
+        assert_eq!(keyword("abc").to_string(), ":abc");
+        assert!(eval_str("(keyword/to-string 'abc)").is_err());
+        assert!(eval_str("(string/to-keyword \"\")").is_err());
+    }
+
+    #[cfg(feature = "std-math")]
+    #[test]
+    fn test_result_option() {
+        assert_eq!(eval_str("(ok 1)"), Ok(list(&[keyword("ok"), integer(1)])));
+        assert_eq!(eval_str("(none)"), Ok(keyword("none")));
+        assert_eq!(eval_str("(ok? (ok 1))"), Ok(bool(true)));
+        assert_eq!(eval_str("(ok? (err 1))"), Ok(bool(false)));
+        assert_eq!(eval_str("(err? (err 1))"), Ok(bool(true)));
+        assert_eq!(eval_str("(some? (some nil))"), Ok(bool(true)));
+        assert_eq!(eval_str("(none? (none))"), Ok(bool(true)));
+        assert_eq!(eval_str("(ok? '(1 2))"), Ok(bool(false)));
+        assert_eq!(eval_str("(unwrap (some 2))"), Ok(integer(2)));
+        assert_eq!(
+            eval_kind("(unwrap (err \"failed\"))"),
+            Err(EvalError::Raised(string("failed")))
+        );
+        assert_eq!(
+            eval_str("(try (unwrap (none)) (catch e e))"),
+            Ok(keyword("none"))
+        );
+        assert_eq!(eval_str("(unwrap-or 0 (ok 1))"), Ok(integer(1)));
+        assert_eq!(eval_str("(unwrap-or 0 (err 1))"), Ok(integer(0)));
+        assert_eq!(eval_str("(unwrap-or 0 (none))"), Ok(integer(0)));
+        assert_eq!(
+            eval_str("(result/map (\\ (x) (* x 2)) (ok 21))"),
+            Ok(list(&[keyword("ok"), integer(42)]))
+        );
+        assert_eq!(
+            eval_str("(result/map (\\ (x) (* x 2)) (err 'failed))"),
+            Ok(list(&[keyword("err"), symbol("failed")]))
+        );
+        assert!(eval_str("(unwrap 1)").is_err());
+        assert_eq!(
+            eval_str("(case (string/to-number \"12\" :result) ((:ok n) n) ((:err _) 0))"),
+            Ok(integer(12))
+        );
+
+        // Fallible builtins report failure as a value when asked to.
+        assert_eq!(eval_str("(string/to-number \"x\")"), Ok(nil()));
+        assert_eq!(
+            eval_str("(string/to-number \"x\" :option)"),
+            Ok(keyword("none"))
+        );
+        assert_eq!(
+            eval_str("(string/to-number \"ff\" 16 :option)"),
+            Ok(list(&[keyword("some"), integer(255)]))
+        );
+        assert_eq!(
+            eval_str("(err? (string/to-number \"x\" :result))"),
+            Ok(bool(true))
+        );
+        assert_eq!(
+            eval_str("(char/from-integer -1 :result)"),
+            Ok(list(&[
+                keyword("err"),
+                string("char/from-integer expects a Unicode scalar value as argument 1, got -1")
+            ]))
+        );
+        assert_eq!(eval_str("(read \"(1 2\" :option)"), Ok(keyword("none")));
+        assert_eq!(
+            eval_str("(string/to-symbol \"abc\" :option)"),
+            Ok(list(&[keyword("some"), symbol("abc")]))
+        );
+        assert!(eval_str("(char/from-integer -1)").is_err());
+    }
+
+    #[test]
+    fn test_string_to_symbol() {
+        assert_eq!(eval_str("(string/to-symbol \"abc\")"), Ok(symbol("abc")));
+        assert_eq!(
+            eval_str("(symbol/to-string (string/to-symbol \"a b\"))"),
+            Ok(string("a b"))
+        );
+        assert_eq!(
+            eval_str("(eval (list (string/to-symbol \"+\") 1 2))"),
+            Ok(integer(3))
+        );
+        assert!(eval_str("(string/to-symbol \"\")").is_err());
+        assert!(eval_str("(string/to-symbol 'abc)").is_err());
+    }
+
+    #[test]
+    fn test_gensym() {
+        // (list (gensym) (gensym))
+        let e = list(&[
+            symbol("list"),
+            list(&[symbol("gensym")]),
+            list(&[symbol("gensym")]),
+        ]);
+        let syms = eval_default_module(e).unwrap();
+        let syms = syms.as_list().unwrap();
+        assert!(syms.iter().all(|sym| sym.as_symbol().is_some()));
+        assert_ne!(syms[0], syms[1]);
+
+        // (gensym 1)
+        let e = list(&[symbol("gensym"), integer(1)]);
+        assert!(eval_default_module(e).is_err());
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval_str("(eval '(+ 1 2))"), Ok(integer(3)));
+        assert_eq!(eval_str("(eval (list '* 2 (+ 1 2)))"), Ok(integer(6)));
+        assert_eq!(eval_str("(eval '(if (< 1 2) 'yes 'no))"), Ok(symbol("yes")));
+        assert_eq!(eval_str("(eval ''a)"), Ok(symbol("a")));
+        assert_eq!(eval_str("(eval 1)"), Ok(integer(1)));
+        // The caller's variables are not in scope.
+        assert!(eval_str("(let (x 1) (eval 'x))").is_err());
+    }
+
+    #[test]
+    fn test_read() {
+        assert_eq!(
+            eval_str(r#"(read "(+ 1 2)")"#),
+            Ok(list(&[symbol("+"), integer(1), integer(2)]))
+        );
+        assert_eq!(eval_str(r#"(eval (read "(+ 1 2)"))"#), Ok(integer(3)));
+        assert_eq!(eval_str(r#"(read "'a")"#), Ok(quote(symbol("a"))));
+        assert!(eval_str(r#"(read "(+ 1")"#).is_err());
+        assert!(eval_str(r#"(read "1 2")"#).is_err());
+        assert!(eval_str("(read 1)").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(eval_str("(apply + '(1 2))"), Ok(integer(3)));
+        assert_eq!(
+            eval_str("(apply list '(1 2 3))"),
+            Ok(list(&[integer(1), integer(2), integer(3)]))
+        );
+        assert_eq!(
+            eval_str("(apply (\\ (x y z) (- x (+ y z))) (list 5 3 1))"),
+            Ok(integer(1))
+        );
+        assert_eq!(
+            eval_str("(apply (\\ (. xs) xs) '(1 2))"),
+            Ok(list(&[integer(1), integer(2)]))
+        );
+        // Too few arguments leave a partially applied function.
+        assert_eq!(eval_str("((apply - '(5)) 3)"), Ok(integer(2)));
+        assert!(eval_str("(apply + 1)").is_err());
+    }
+
+    #[test]
+    fn test_last_init_rest() {
+        assert_eq!(eval_str("(last '(1 2 3))"), Ok(integer(3)));
+        assert_eq!(eval_str("(init '(1 2 3))"), eval_str("'(1 2)"));
+        assert_eq!(eval_str("(rest '(1 2 3))"), eval_str("'(2 3)"));
+        assert_eq!(eval_str("(cdr '(1))"), Ok(list(&[])));
+        for f in ["last", "init", "rest", "cdr"] {
+            let e = list(&[symbol(f), quote(list(&[]))]);
+            assert!(
+                matches!(
+                    eval_default_module(e),
+                    Err(EvalError::TypeMismatch { got, .. }) if got == list(&[])
+                ),
+                "{}",
+                f
+            );
+        }
+    }
+
+    #[test]
+    fn test_length_append_reverse() {
+        assert_eq!(eval_str("(length '(1 2 3))"), Ok(integer(3)));
+        assert_eq!(eval_str("(length nil)"), Ok(integer(0)));
+        assert!(eval_str("(length 1)").is_err());
+
+        assert_eq!(
+            eval_str("(append '(1) nil '(2 3))"),
+            Ok(list(&[integer(1), integer(2), integer(3)]))
+        );
+        assert_eq!(eval_str("(append)"), Ok(list(&[])));
+        assert!(eval_str("(append '(1) 2)").is_err());
+
+        assert_eq!(
+            eval_str("(reverse '(1 2 3))"),
+            Ok(list(&[integer(3), integer(2), integer(1)]))
+        );
+        assert_eq!(eval_str("(reverse nil)"), Ok(list(&[])));
+    }
+
+    #[test]
+    fn test_range_and_slicing() {
+        let ints = |ints: &[i64]| list(&ints.iter().map(|i| integer(*i)).collect::<Vec<_>>());
+
+        assert_eq!(eval_str("(range 3)"), Ok(ints(&[0, 1, 2])));
+        assert_eq!(eval_str("(range 2 5)"), Ok(ints(&[2, 3, 4])));
+        assert_eq!(eval_str("(range 0 10 4)"), Ok(ints(&[0, 4, 8])));
+        assert_eq!(eval_str("(range 3 0 -1)"), Ok(ints(&[3, 2, 1])));
+        assert_eq!(eval_str("(range 5 2)"), Ok(ints(&[])));
+        assert!(eval_str("(range 0 5 0)").is_err());
+        assert!(eval_str("(range 'a)").is_err());
+
+        assert_eq!(eval_str("(take 2 '(1 2 3))"), Ok(ints(&[1, 2])));
+        assert_eq!(eval_str("(take 5 '(1 2 3))"), Ok(ints(&[1, 2, 3])));
+        assert_eq!(eval_str("(drop 2 '(1 2 3))"), Ok(ints(&[3])));
+        assert_eq!(eval_str("(drop 5 '(1 2 3))"), Ok(ints(&[])));
+        assert!(eval_str("(take -1 '(1 2 3))").is_err());
+
+        assert_eq!(
+            eval_str("(zip '(1 2 3) '(a b))"),
+            Ok(list(&[
+                list(&[integer(1), symbol("a")]),
+                list(&[integer(2), symbol("b")])
+            ]))
+        );
+        assert_eq!(
+            eval_str("(enumerate '(a b))"),
+            Ok(list(&[
+                list(&[integer(0), symbol("a")]),
+                list(&[integer(1), symbol("b")])
+            ]))
+        );
     }
 
     #[test]
@@ -581,8 +2348,27 @@ mod tests {
             ])),
         ]);
         assert_eq!(eval_default_module(e), Ok(integer(-15)));
+
+        // The folds visit the elements from opposite ends.
+        assert_eq!(
+            eval_str("(foldl (\\ (acc x) (+ (* acc 10) x)) 0 '(1 2 3))"),
+            Ok(integer(123))
+        );
+        assert_eq!(
+            eval_str("(foldr (\\ (x acc) (+ (* acc 10) x)) 0 '(1 2 3))"),
+            Ok(integer(321))
+        );
+        assert_eq!(
+            eval_str("(foldr cons '() '(1 2 3))"),
+            Ok(list(&[integer(1), integer(2), integer(3)]))
+        );
+        // nil is the empty list.
+        assert_eq!(eval_str("(foldl + 0 nil)"), Ok(integer(0)));
+        assert_eq!(eval_str("(map (\\ x x) nil)"), Ok(list(&[])));
+        assert_eq!(eval_str("(filter (\\ x true) nil)"), Ok(list(&[])));
     }
 
+    #[cfg(feature = "std-math")]
     #[test]
     fn test_filter() {
         // (filter odd '(1 2 3 4 5)) => (1 3 5)
@@ -602,4 +2388,476 @@ mod tests {
             Ok(list(&[integer(1), integer(3), integer(5)]))
         );
     }
+
+    #[test]
+    fn test_map() {
+        // (map first '((a 1) (b 2))) => (a b)
+        let e = list(&[
+            symbol("map"),
+            symbol("first"),
+            quote(list(&[
+                list(&[symbol("a"), integer(1)]),
+                list(&[symbol("b"), integer(2)]),
+            ])),
+        ]);
+        assert_eq!(
+            eval_default_module(e),
+            Ok(list(&[symbol("a"), symbol("b")]))
+        );
+    }
+
+    #[test]
+    fn test_list_product() {
+        // (list/product '(a b) '(1 2)) => ((a 1) (a 2) (b 1) (b 2))
+        let e = list(&[
+            symbol("list/product"),
+            quote(list(&[symbol("a"), symbol("b")])),
+            quote(list(&[integer(1), integer(2)])),
+        ]);
+        assert_eq!(
+            eval_default_module(e),
+            Ok(list(&[
+                list(&[symbol("a"), integer(1)]),
+                list(&[symbol("a"), integer(2)]),
+                list(&[symbol("b"), integer(1)]),
+                list(&[symbol("b"), integer(2)]),
+            ]))
+        );
+
+        // (list/product '() '(1 2)) => ()
+        let e = list(&[
+            symbol("list/product"),
+            quote(list(&[])),
+            quote(list(&[integer(1), integer(2)])),
+        ]);
+        assert_eq!(eval_default_module(e), Ok(list(&[])));
+    }
+
+    #[test]
+    fn test_list_combinations() {
+        // (list/combinations 2 '(1 2 3)) => ((1 2) (1 3) (2 3))
+        let e = list(&[
+            symbol("list/combinations"),
+            integer(2),
+            quote(list(&[integer(1), integer(2), integer(3)])),
+        ]);
+        assert_eq!(
+            eval_default_module(e),
+            Ok(list(&[
+                list(&[integer(1), integer(2)]),
+                list(&[integer(1), integer(3)]),
+                list(&[integer(2), integer(3)]),
+            ]))
+        );
+
+        // (list/combinations 4 '(1 2 3)) => ()
+        let e = list(&[
+            symbol("list/combinations"),
+            integer(4),
+            quote(list(&[integer(1), integer(2), integer(3)])),
+        ]);
+        assert_eq!(eval_default_module(e), Ok(list(&[])));
+    }
+
+    #[test]
+    fn test_concat_flatten() {
+        assert_eq!(
+            eval_str("(concat '((1) () (2 (3))))"),
+            eval_str("'(1 2 (3))")
+        );
+        assert_eq!(eval_str("(concat nil)"), Ok(list(&[])));
+        assert!(eval_str("(concat '(1 2))").is_err());
+
+        assert_eq!(
+            eval_str("(flatten '(1 (2 (3 (4)))))"),
+            eval_str("'(1 2 3 4)")
+        );
+        assert_eq!(
+            eval_str("(flatten '(1 (2 (3 (4)))) 1)"),
+            eval_str("'(1 2 (3 (4)))")
+        );
+        assert_eq!(
+            eval_str("(flatten '(1 (2 (3 (4)))) 2)"),
+            eval_str("'(1 2 3 (4))")
+        );
+        assert_eq!(eval_str("(flatten '(1 (2)) 0)"), eval_str("'(1 (2))"));
+        assert!(eval_str("(flatten '(1) -1)").is_err());
+    }
+
+    #[test]
+    fn test_search() {
+        assert_eq!(eval_str("(member? 2 '(1 2 3))"), Ok(bool(true)));
+        assert_eq!(eval_str("(member? 'a '(1 2 3))"), Ok(bool(false)));
+        assert_eq!(eval_str("(member? 1 nil)"), Ok(bool(false)));
+
+        assert_eq!(eval_str("(find (\\ x (> x 1)) '(1 2 3))"), Ok(integer(2)));
+        assert_eq!(eval_str("(find (\\ x (> x 5)) '(1 2 3))"), Ok(nil()));
+        assert_eq!(
+            eval_str("(position (\\ x (> x 1)) '(1 2 3))"),
+            Ok(integer(1))
+        );
+        assert_eq!(eval_str("(position (\\ x (> x 5)) '(1 2 3))"), Ok(nil()));
+        assert_eq!(eval_str("(count (\\ x (> x 1)) '(1 2 3))"), Ok(integer(2)));
+        assert!(eval_str("(count (\\ x x) '(1 2 3))").is_err());
+    }
+
+    #[test]
+    fn test_alist() {
+        assert_eq!(eval_str("(assoc 'b '((a 1) (b 2)))"), eval_str("'(b 2)"));
+        assert_eq!(eval_str("(assoc 'c '((a 1) (b 2)))"), Ok(nil()));
+        assert_eq!(
+            eval_str("(alist/get 'b '((a 1) (b 2) (b 3)))"),
+            Ok(integer(2))
+        );
+        assert_eq!(eval_str("(alist/get 'c nil)"), Ok(nil()));
+        assert_eq!(
+            eval_str("(alist/set 'a 3 '((a 1) (b 2)))"),
+            eval_str("'((a 3) (b 2))")
+        );
+        assert_eq!(
+            eval_str("(alist/set 'c 3 '((a 1)))"),
+            eval_str("'((a 1) (c 3))")
+        );
+        assert_eq!(
+            eval_str("(alist/remove 'a '((a 1) (b 2) (a 3)))"),
+            eval_str("'((b 2))")
+        );
+        assert!(eval_str("(alist/get 'a '(a 1))").is_err());
+    }
+
+    #[test]
+    fn test_map_type() {
+        assert_eq!(
+            eval_str("(map/new 'b 2 'a 1)"),
+            Ok(map_of(&[
+                (symbol("a"), integer(1)),
+                (symbol("b"), integer(2))
+            ]))
+        );
+        assert_eq!(
+            eval_str("(map/new 'b 2 'a 1)").map(|map| map.to_string()),
+            Ok("{a 1 b 2}".to_string())
+        );
+        assert!(eval_str("(map/new 'a)").is_err());
+
+        assert_eq!(eval_str("(map/get 'a (map/new 'a 1))"), Ok(integer(1)));
+        assert_eq!(eval_str("(map/get 'b (map/new 'a 1))"), Ok(nil()));
+        assert_eq!(
+            eval_str("(map/get 'a (map/insert 'a 2 (map/new 'a 1)))"),
+            Ok(integer(2))
+        );
+        assert_eq!(
+            eval_str("(map/contains? 'a (map/remove 'a (map/new 'a 1)))"),
+            Ok(bool(false))
+        );
+        assert_eq!(
+            eval_str("(map/contains? \"k\" (map/new \"k\" 1))"),
+            Ok(bool(true))
+        );
+        // Updates leave the original map unchanged.
+        assert_eq!(
+            eval_str("(let (m (map/new 'a 1)) (list (map/insert 'b 2 m) m))")
+                .map(|e| e.to_string()),
+            Ok("({a 1 b 2} {a 1})".to_string())
+        );
+        assert_eq!(
+            eval_str("(map/keys (map/new 'b 2 'a 1))"),
+            eval_str("'(a b)")
+        );
+        assert_eq!(
+            eval_str("(map/values (map/new 'b 2 'a 1))"),
+            eval_str("'(1 2)")
+        );
+        assert!(eval_str("(map/keys '(a 1))").is_err());
+    }
+
+    #[test]
+    fn test_set() {
+        assert_eq!(
+            eval_str("(set/new 2 1 2)"),
+            Ok(set_of(&[integer(1), integer(2)]))
+        );
+        assert_eq!(
+            eval_str("(set/new 2 1 2)").map(|set| set.to_string()),
+            Ok("#{1 2}".to_string())
+        );
+        assert_eq!(
+            eval_str("(set/contains? 3 (set/insert 3 (set/new)))"),
+            Ok(bool(true))
+        );
+        assert_eq!(
+            eval_str("(set/contains? 1 (set/remove 1 (set/new 1)))"),
+            Ok(bool(false))
+        );
+        assert_eq!(
+            eval_str("(set/union (set/new 1 2) (set/new 2 3))"),
+            eval_str("(set/new 1 2 3)")
+        );
+        assert_eq!(
+            eval_str("(set/intersect (set/new 1 2) (set/new 2 3))"),
+            eval_str("(set/new 2)")
+        );
+        assert_eq!(
+            eval_str("(set/difference (set/new 1 2) (set/new 2 3))"),
+            eval_str("(set/new 1)")
+        );
+        assert_eq!(
+            eval_str("(set/to-list (apply set/new '(b a b)))"),
+            eval_str("'(a b)")
+        );
+        assert!(eval_str("(set/union (set/new) '(1))").is_err());
+    }
+
+    #[test]
+    fn test_vector() {
+        assert_eq!(
+            eval_str("(vector 1 2 3)"),
+            Ok(vector(&[integer(1), integer(2), integer(3)]))
+        );
+        assert_eq!(
+            eval_str("(vector 1 'a)").map(|v| v.to_string()),
+            Ok("[1 a]".to_string())
+        );
+        assert_eq!(eval_str("(vector/ref 1 (vector 'a 'b))"), Ok(symbol("b")));
+        assert!(eval_str("(vector/ref 2 (vector 'a 'b))").is_err());
+        assert_eq!(eval_str("(vector/length (vector))"), Ok(integer(0)));
+        // Updates leave the original vector unchanged.
+        assert_eq!(
+            eval_str("(let (v (vector 1 2)) (list (vector/set 0 9 v) v))").map(|e| e.to_string()),
+            Ok("([9 2] [1 2])".to_string())
+        );
+        assert!(eval_str("(vector/set 2 9 (vector 1 2))").is_err());
+        assert_eq!(
+            eval_str("(vector/to-list (list/to-vector '(1 2)))"),
+            eval_str("'(1 2)")
+        );
+        assert!(eval_str("(vector/length '(1 2))").is_err());
+    }
+
+    #[test]
+    fn test_bytes() {
+        assert_eq!(eval_str("(bytes 1 2 255)"), Ok(bytes(&[1, 2, 255])));
+        assert!(eval_str("(bytes 256)").is_err());
+        assert_eq!(
+            eval_str("#u8(1 2 3)").map(|b| b.to_string()),
+            Ok("#u8(1 2 3)".to_string())
+        );
+        assert_eq!(eval_str("(bytes/length #x\"00ff10\")"), Ok(integer(3)));
+        assert_eq!(eval_str("(bytes/ref 1 #x\"00ff10\")"), Ok(integer(255)));
+        assert!(eval_str("(bytes/ref 3 #u8(1 2 3))").is_err());
+        assert_eq!(
+            eval_str("(bytes/slice 1 3 #u8(1 2 3 4))"),
+            Ok(bytes(&[2, 3]))
+        );
+        assert!(eval_str("(bytes/slice 2 1 #u8(1 2 3 4))").is_err());
+        assert_eq!(
+            eval_str("(string/to-bytes \"h\u{e9}\")"),
+            Ok(bytes(&[104, 195, 169]))
+        );
+        assert_eq!(
+            eval_str("(bytes/to-string (string/to-bytes \"h\u{e9}\"))"),
+            Ok(Exp::String("h\u{e9}".to_string()))
+        );
+        assert!(eval_str("(bytes/to-string #u8(255))").is_err());
+    }
+
+    #[test]
+    fn test_char() {
+        assert_eq!(eval_str(r"#\a"), Ok(Exp::Char('a')));
+        assert_eq!(
+            eval_str(r"(list #\a #\space #\()").map(|e| e.to_string()),
+            Ok(r"(#\a #\space #\()".to_string())
+        );
+        assert_eq!(eval_str(r"(char/to-integer #\A)"), Ok(integer(65)));
+        assert_eq!(
+            eval_str("(char/from-integer 955)"),
+            Ok(Exp::Char('\u{3bb}'))
+        );
+        assert!(eval_str("(char/from-integer 55296)").is_err());
+        assert!(eval_str("(char/from-integer -1)").is_err());
+        assert_eq!(
+            eval_str(r"(sort (list #\b #\a))"),
+            Ok(list(&[Exp::Char('a'), Exp::Char('b')]))
+        );
+    }
+
+    #[test]
+    fn test_sort() {
+        assert_eq!(
+            eval_str("(sort '(3 1.5 2 1/2))"),
+            Ok(list(&[rational(1, 2), float(1.5), integer(2), integer(3)]))
+        );
+        assert_eq!(
+            eval_str(r#"(sort '("b" "c" "a"))"#),
+            Ok(list(&[string("a"), string("b"), string("c")]))
+        );
+        assert_eq!(eval_str("(sort nil)"), Ok(list(&[])));
+        assert_eq!(
+            eval_kind(r#"(sort '(1 "a"))"#),
+            Err(EvalError::Incomparable(string("a"), integer(1)))
+        );
+
+        assert_eq!(
+            eval_str("(sort-by > '(1 3 2))"),
+            Ok(list(&[integer(3), integer(2), integer(1)]))
+        );
+        // The sort is stable.
+        assert_eq!(
+            eval_str("(sort-by (\\ (a b) (< (first a) (first b))) '((1 a) (0 b) (1 c) (0 d)))"),
+            eval_str("'((0 b) (0 d) (1 a) (1 c))")
+        );
+        assert!(eval_str("(sort-by (\\ (a b) 1) '(1 2))").is_err());
+    }
+
+    #[test]
+    fn test_sort_generic() {
+        // (list/sort-generic '(3 "a" 1 b true)) => (true 1 3 "a" b)
+        let e = list(&[
+            symbol("list/sort-generic"),
+            quote(list(&[
+                integer(3),
+                string("a"),
+                integer(1),
+                symbol("b"),
+                bool(true),
+            ])),
+        ]);
+        assert_eq!(
+            eval_default_module(e),
+            Ok(list(&[
+                bool(true),
+                integer(1),
+                integer(3),
+                string("a"),
+                symbol("b"),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_flatten_indexed() {
+        // (list/flatten-indexed '(1 2 3)) => ((1 (0)) (2 (1)) (3 (2)))
+        let e = list(&[
+            symbol("list/flatten-indexed"),
+            quote(list(&[integer(1), integer(2), integer(3)])),
+        ]);
+        assert_eq!(
+            eval_default_module(e),
+            Ok(list(&[
+                list(&[integer(1), list(&[integer(0)])]),
+                list(&[integer(2), list(&[integer(1)])]),
+                list(&[integer(3), list(&[integer(2)])]),
+            ]))
+        );
+
+        // (list/flatten-indexed '((1 2) 3)) => ((1 (0 0)) (2 (0 1)) (3 (1)))
+        let e = list(&[
+            symbol("list/flatten-indexed"),
+            quote(list(&[list(&[integer(1), integer(2)]), integer(3)])),
+        ]);
+        assert_eq!(
+            eval_default_module(e),
+            Ok(list(&[
+                list(&[integer(1), list(&[integer(0), integer(0)])]),
+                list(&[integer(2), list(&[integer(0), integer(1)])]),
+                list(&[integer(3), list(&[integer(1)])]),
+            ]))
+        );
+
+        // (list/flatten-indexed '()) => ()
+        let e = list(&[symbol("list/flatten-indexed"), quote(list(&[]))]);
+        assert_eq!(eval_default_module(e), Ok(list(&[])));
+    }
+
+    #[cfg(feature = "std-io")]
+    #[test]
+    fn test_capabilities() {
+        use super::{default_module_with, Capabilities};
+        use crate::parser::parse_exp;
+
+        let module = default_module_with(Capabilities::none());
+        let eval = |source: &str| module.eval(parse_exp(source).unwrap());
+        assert_eq!(eval("(show (+ 1 2))"), Ok(string("3")));
+        for name in ["print", "println", "write", "io/print"] {
+            assert_eq!(
+                eval(&format!("({} 1)", name)).map_err(|err| err.kind().clone()),
+                Err(EvalError::SymbolNotFound(name.to_string()))
+            );
+        }
+        assert!(default_module_with(Capabilities::default())
+            .defines
+            .contains_key(&"print".into()));
+    }
+
+    #[test]
+    fn test_namespaces() {
+        use super::{default_module, NAMESPACES, PRELUDE};
+        use crate::loader::qualifier;
+        use crate::parser::parse_exp;
+
+        let module = default_module();
+        let eval = |source: &str| module.eval(parse_exp(source).unwrap());
+        assert_eq!(
+            eval("(list/append '(a) '(b))"),
+            Ok(list(&[symbol("a"), symbol("b")]))
+        );
+        assert_eq!(
+            eval("(list/map core/not '(true false))"),
+            Ok(list(&[bool(false), bool(true)]))
+        );
+        assert_eq!(
+            eval("(map-keys {:a 1})").map_err(|err| err.kind().clone()),
+            Err(EvalError::SymbolNotFound("map-keys".to_string()))
+        );
+        for (alias, name) in PRELUDE {
+            assert!(NAMESPACES.contains(&qualifier(name).unwrap()), "{}", name);
+            assert_eq!(
+                module.defines.get(&(*alias).into()),
+                module.defines.get(&(*name).into()),
+                "{}",
+                alias
+            );
+        }
+        for name in module.defines.keys() {
+            let name = name.as_str();
+            assert!(
+                PRELUDE.iter().any(|(alias, _)| *alias == name)
+                    || qualifier(name).is_some_and(|module| NAMESPACES.contains(&module)),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_register_builtin() {
+        use super::default_module;
+        use crate::{
+            args::Args,
+            eval::{EvalContext, Result},
+            parser::parse_exp,
+        };
+
+        fn double(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+            Ok(integer(
+                Args::new("double", args).exactly(1)?.integer(0)? * 2,
+            ))
+        }
+
+        fn sub(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+            let args = Args::new("sub", args).exactly(2)?;
+            Ok(integer(args.integer(0)? - args.integer(1)?))
+        }
+
+        let mut module = default_module();
+        module.register_builtin("double", double);
+        module.register_curried_binary("sub", sub);
+        let eval = |source: &str| module.eval(parse_exp(source).unwrap());
+        assert_eq!(eval("(double 21)"), Ok(integer(42)));
+        assert_eq!(eval("(sub 5 3)"), Ok(integer(2)));
+        assert_eq!(
+            eval("(map (sub 10) '(1 2))"),
+            Ok(list(&[integer(9), integer(8)]))
+        );
+    }
 }
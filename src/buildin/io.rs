@@ -0,0 +1,31 @@
+//! The builtins that write to the output of the evaluation, standard output
+//! unless the host gave [`EvalConfig::output`](crate::eval::EvalConfig::output),
+//! behind the `std-io` feature
+//! and the [`Capabilities::print`](super::Capabilities::print) capability.
+
+use super::*;
+
+pub(super) fn register(module: &mut Module) {
+    module.register_builtin("io/print", print);
+    module.register_builtin("io/println", println);
+    module.register_builtin("io/write", write);
+}
+
+fn print(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let exp = Args::new("io/print", args).exactly(1)?.get(0)?;
+    ctx.write_output(&format!("{} ", exp));
+    Ok(Exp::Void)
+}
+
+fn println(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let exp = Args::new("io/println", args).exactly(1)?.get(0)?;
+    ctx.write_output(&format!("{}\n", exp));
+    Ok(Exp::Void)
+}
+
+/// Prints a value as the parser reads it back, with strings quoted.
+fn write(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let exp = Args::new("io/write", args).exactly(1)?.get(0)?;
+    ctx.write_output(&exp.to_readable_string());
+    Ok(Exp::Void)
+}
@@ -0,0 +1,800 @@
+//! The numeric builtins beyond arithmetic and comparison, behind the
+//! `std-math` feature: rounding, powers, bitwise operations and conversions
+//! between numbers and strings.
+
+#[cfg(feature = "bignum")]
+use num_traits::Signed;
+
+use super::*;
+
+pub(super) fn register(module: &mut Module) {
+    module.register_curried_binary("math/mod", modulo);
+    module.register_curried_binary("math/rem", rem);
+    module.register_curried_binary("math/gcd", gcd);
+    module.register_builtin("math/abs", abs);
+    module.register_curried_binary("math/min", min);
+    module.register_curried_binary("math/max", max);
+    module.register_curried_binary("math/pow", pow);
+    module.register_curried_binary("bit/and", bit_and);
+    module.register_curried_binary("bit/or", bit_or);
+    module.register_curried_binary("bit/xor", bit_xor);
+    module.register_builtin("bit/not", bit_not);
+    module.register_curried_binary("bit/shift-left", shift_left);
+    module.register_curried_binary("bit/shift-right", shift_right);
+    module.register_builtin("math/float?", is_float);
+    module.register_builtin("math/floor", floor);
+    module.register_builtin("math/ceil", ceil);
+    module.register_builtin("math/round", round);
+    module.register_builtin("math/truncate", truncate);
+    module.register_builtin("math/numerator", numerator);
+    module.register_builtin("math/denominator", denominator);
+    module.register_builtin("math/to-float", rational_to_float);
+    module.register_builtin("math/odd", odd);
+    module.register_builtin("math/even", even);
+    module.register_curried_binary("math/number-base", number_base);
+    module.register_curried_binary("math/parse-number-base", parse_number_base);
+    module.register_builtin("string/to-number", string_to_number);
+    module.register_builtin("math/to-string", number_to_string);
+}
+
+fn parse_binary_integer(args: Args) -> Result<(i64, i64)> {
+    let args = args.exactly(2)?;
+    Ok((args.integer(0)?, args.integer(1)?))
+}
+
+/// Operands of a built-in that only accepts integers.
+enum Integers {
+    Small(i64, i64),
+    #[cfg(feature = "bignum")]
+    Big(BigInt, BigInt),
+}
+
+fn parse_integer_operands(args: Args) -> Result<Integers> {
+    match parse_binary_number(args)? {
+        Numbers::Integer(lhs, rhs) => Ok(Integers::Small(lhs, rhs)),
+        #[cfg(feature = "bignum")]
+        Numbers::BigInt(lhs, rhs) => Ok(Integers::Big(lhs, rhs)),
+        _ => {
+            let is_integer = |arg: &Exp| match arg.node() {
+                #[cfg(feature = "bignum")]
+                Exp::BigInt(_) => true,
+                exp => matches!(exp, Exp::Integer(_)),
+            };
+            let i = args.as_slice().iter().position(|arg| !is_integer(arg));
+            Err(args.mismatch(i.unwrap_or(0), "an integer"))
+        }
+    }
+}
+
+/// The remainder of truncating division, which has the sign of the dividend.
+fn rem(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    match parse_integer_operands(Args::new("math/rem", args))? {
+        Integers::Small(_, 0) => Err(divide_by_zero(args)),
+        // Unlike `%`, `wrapping_rem` does not overflow on `i64::MIN % -1`,
+        // whose remainder is 0.
+        Integers::Small(lhs, rhs) => Ok(Exp::Integer(lhs.wrapping_rem(rhs))),
+        #[cfg(feature = "bignum")]
+        Integers::Big(_, rhs) if rhs.is_zero() => Err(divide_by_zero(args)),
+        #[cfg(feature = "bignum")]
+        Integers::Big(lhs, rhs) => Ok(ast::big_integer(lhs % rhs)),
+    }
+}
+
+/// The remainder of flooring division, which has the sign of the divisor.
+fn modulo(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    match parse_integer_operands(Args::new("math/mod", args))? {
+        Integers::Small(_, 0) => Err(divide_by_zero(args)),
+        Integers::Small(lhs, rhs) => {
+            let rem = lhs.wrapping_rem(rhs);
+            if rem != 0 && (rem < 0) != (rhs < 0) {
+                Ok(Exp::Integer(rem + rhs))
+            } else {
+                Ok(Exp::Integer(rem))
+            }
+        }
+        #[cfg(feature = "bignum")]
+        Integers::Big(_, rhs) if rhs.is_zero() => Err(divide_by_zero(args)),
+        #[cfg(feature = "bignum")]
+        Integers::Big(lhs, rhs) => {
+            let rem = &lhs % &rhs;
+            if !rem.is_zero() && rem.is_negative() != rhs.is_negative() {
+                Ok(ast::big_integer(rem + rhs))
+            } else {
+                Ok(ast::big_integer(rem))
+            }
+        }
+    }
+}
+
+/// Returns a non-negative integer. Like [`overflowed`](super::overflowed), one too large for an
+/// `i64` becomes a big integer with the `bignum` feature and fails otherwise.
+#[cfg(feature = "bignum")]
+fn unsigned_result(_args: &[Exp], n: u64) -> Result<Exp> {
+    Ok(ast::big_integer(n.into()))
+}
+
+#[cfg(not(feature = "bignum"))]
+fn unsigned_result(args: &[Exp], n: u64) -> Result<Exp> {
+    i64::try_from(n)
+        .map(Exp::Integer)
+        .map_err(|_| integer_overflow(args))
+}
+
+fn gcd_u64(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// The greatest common divisor, which is never negative. `(gcd 0 0)` is 0.
+fn gcd(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    match parse_integer_operands(Args::new("math/gcd", args))? {
+        Integers::Small(lhs, rhs) => {
+            unsigned_result(args, gcd_u64(lhs.unsigned_abs(), rhs.unsigned_abs()))
+        }
+        #[cfg(feature = "bignum")]
+        Integers::Big(mut lhs, mut rhs) => {
+            while !rhs.is_zero() {
+                (lhs, rhs) = (rhs.clone(), lhs % rhs);
+            }
+            Ok(ast::big_integer(lhs.abs()))
+        }
+    }
+}
+
+fn abs(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("math/abs", args).exactly(1)?;
+    match args.get(0)?.node() {
+        Exp::Integer(i) => unsigned_result(args.as_slice(), i.unsigned_abs()),
+        #[cfg(feature = "bignum")]
+        Exp::BigInt(i) => Ok(Exp::BigInt(i.abs())),
+        Exp::Rational(numerator, denominator) => rational_result(
+            args.as_slice(),
+            Some(i128::from(*numerator).abs()),
+            (*denominator).into(),
+        ),
+        Exp::Float(f) => Ok(Exp::Float(f.abs())),
+        _ => Err(args.mismatch(0, "a number")),
+    }
+}
+
+/// Returns the smaller of two numbers, or NaN if either is NaN.
+fn min(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    match parse_compare(Args::new("math/min", args))? {
+        Some(Ordering::Greater) => Ok(args[1].clone()),
+        Some(_) => Ok(args[0].clone()),
+        None => Ok(Exp::Float(f64::NAN)),
+    }
+}
+
+/// Returns the larger of two numbers, or NaN if either is NaN.
+fn max(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    match parse_compare(Args::new("math/max", args))? {
+        Some(Ordering::Less) => Ok(args[1].clone()),
+        Some(_) => Ok(args[0].clone()),
+        None => Ok(Exp::Float(f64::NAN)),
+    }
+}
+
+/// Raises a rational to an integer power. A negative power gives the
+/// reciprocal.
+fn rational_pow(args: &[Exp], (numerator, denominator): (i128, i128), exp: i128) -> Result<Exp> {
+    let (numerator, denominator) = match exp {
+        _ if exp >= 0 => (numerator, denominator),
+        _ if numerator == 0 => return Err(divide_by_zero(args)),
+        _ => (denominator, numerator),
+    };
+    let power = |base: i128| {
+        u32::try_from(exp.unsigned_abs())
+            .ok()
+            .and_then(|exp| base.checked_pow(exp))
+    };
+    match (power(numerator), power(denominator)) {
+        (Some(numerator), Some(denominator)) => rational_result(args, Some(numerator), denominator),
+        _ => Err(integer_overflow(args)),
+    }
+}
+
+/// Powers with an integer exponent are exact, any other power is a float.
+fn pow(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    match parse_binary_number(Args::new("math/pow", args))? {
+        Numbers::Integer(base, exp) if exp >= 0 => {
+            match u32::try_from(exp).map(|exp| base.checked_pow(exp)) {
+                Ok(Some(power)) => Ok(Exp::Integer(power)),
+                Ok(None) => overflowed(args, base, exp, |base, exp| {
+                    base.pow(u32::try_from(exp).unwrap())
+                }),
+                Err(_) => Err(integer_overflow(args)),
+            }
+        }
+        Numbers::Integer(base, exp) => rational_pow(args, (base.into(), 1), exp.into()),
+        #[cfg(feature = "bignum")]
+        Numbers::BigInt(base, exp) => match u32::try_from(&exp) {
+            Ok(exp) => Ok(ast::big_integer(base.pow(exp))),
+            Err(_) => Err(integer_overflow(args)),
+        },
+        Numbers::Rational(base, (exp, 1)) => rational_pow(args, base, exp),
+        #[cfg(feature = "std")]
+        Numbers::Rational(..) => Ok(Exp::Float(
+            number_to_f64(&args[0])
+                .unwrap()
+                .powf(number_to_f64(&args[1]).unwrap()),
+        )),
+        #[cfg(feature = "std")]
+        Numbers::Float(base, exp) => Ok(Exp::Float(base.powf(exp))),
+        // Float powers need `std`.
+        #[cfg(not(feature = "std"))]
+        Numbers::Rational(..) | Numbers::Float(..) => {
+            Err(Args::new("math/pow", args).mismatch(1, "an integer exponent of an exact number"))
+        }
+    }
+}
+
+fn bit_and(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let (lhs, rhs) = parse_binary_integer(Args::new("bit/and", args))?;
+    Ok(Exp::Integer(lhs & rhs))
+}
+
+fn bit_or(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let (lhs, rhs) = parse_binary_integer(Args::new("bit/or", args))?;
+    Ok(Exp::Integer(lhs | rhs))
+}
+
+fn bit_xor(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let (lhs, rhs) = parse_binary_integer(Args::new("bit/xor", args))?;
+    Ok(Exp::Integer(lhs ^ rhs))
+}
+
+fn bit_not(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let int = Args::new("bit/not", args).exactly(1)?.integer(0)?;
+    Ok(Exp::Integer(!int))
+}
+
+fn parse_shift(args: Args) -> Result<(i64, u32)> {
+    let (n, amount) = parse_binary_integer(args)?;
+    match u32::try_from(amount) {
+        Ok(shift) if shift < i64::BITS => Ok((n, shift)),
+        _ => Err(EvalError::InvalidShift(amount)),
+    }
+}
+
+/// Bits shifted out on the left are dropped.
+fn shift_left(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let (n, shift) = parse_shift(Args::new("bit/shift-left", args))?;
+    Ok(Exp::Integer(n << shift))
+}
+
+/// Shifts in copies of the sign bit, so negative numbers stay negative.
+fn shift_right(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let (n, shift) = parse_shift(Args::new("bit/shift-right", args))?;
+    Ok(Exp::Integer(n >> shift))
+}
+
+fn is_float(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let exp = Args::new("math/float?", args).exactly(1)?.get(0)?;
+    Ok(ast::bool(matches!(exp.node(), Exp::Float(_))))
+}
+
+/// Rounds a number to an integer, with `f` for floats and `exact` for the
+/// numerator and (always positive) denominator of rationals. Integers are returned unchanged;
+/// floats that are not finite or do not fit an integer are rejected.
+fn parse_rounded(args: Args, f: fn(f64) -> f64, exact: fn(i128, i128) -> i128) -> Result<Exp> {
+    let args = args.exactly(1)?;
+    let exp = args.get(0)?;
+    match exp.node() {
+        Exp::Integer(_) => Ok(exp.clone()),
+        #[cfg(feature = "bignum")]
+        Exp::BigInt(_) => Ok(exp.clone()),
+        // Rounding a fraction moves it at most half-way towards zero, so the
+        // result fits.
+        Exp::Rational(numerator, denominator) => Ok(Exp::Integer(exact(
+            (*numerator).into(),
+            (*denominator).into(),
+        ) as i64)),
+        Exp::Float(float) => {
+            let rounded = f(*float);
+            if rounded.is_finite() && (i64::MIN as f64..i64::MAX as f64).contains(&rounded) {
+                Ok(Exp::Integer(rounded as i64))
+            } else {
+                Err(args.mismatch(0, "a float in the range of integers"))
+            }
+        }
+        _ => Err(args.mismatch(0, "a number")),
+    }
+}
+
+/// Rounding of floats without `std`, through the integer a float truncates
+/// to. Floats of magnitude 2^52 and more are integers already, and those out
+/// of the range of integers are rejected after rounding anyway.
+#[cfg(not(feature = "std"))]
+mod float {
+    pub fn trunc(f: f64) -> f64 {
+        if f.abs() < 4_503_599_627_370_496.0 {
+            f as i64 as f64
+        } else {
+            f
+        }
+    }
+
+    pub fn floor(f: f64) -> f64 {
+        let t = trunc(f);
+        if t > f {
+            t - 1.0
+        } else {
+            t
+        }
+    }
+
+    pub fn ceil(f: f64) -> f64 {
+        let t = trunc(f);
+        if t < f {
+            t + 1.0
+        } else {
+            t
+        }
+    }
+
+    pub fn round(f: f64) -> f64 {
+        let t = trunc(f);
+        match f - t {
+            d if d >= 0.5 => t + 1.0,
+            d if d <= -0.5 => t - 1.0,
+            _ => t,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+use f64 as float;
+
+fn floor(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    parse_rounded(
+        Args::new("math/floor", args),
+        float::floor,
+        i128::div_euclid,
+    )
+}
+
+fn ceil(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    parse_rounded(Args::new("math/ceil", args), float::ceil, |n, d| {
+        -(-n).div_euclid(d)
+    })
+}
+
+/// Rounds half-way cases away from zero.
+fn round(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    parse_rounded(Args::new("math/round", args), float::round, |n, d| {
+        (2 * n + n.signum() * d) / (2 * d)
+    })
+}
+
+fn truncate(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    parse_rounded(Args::new("math/truncate", args), float::trunc, |n, d| n / d)
+}
+
+fn numerator(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("math/numerator", args).exactly(1)?;
+    match args.get(0)?.node() {
+        #[cfg(feature = "bignum")]
+        exp @ Exp::BigInt(_) => Ok(exp.clone()),
+        exp => exp
+            .as_rational()
+            .map(|(numerator, _)| Exp::Integer(numerator))
+            .ok_or_else(|| args.mismatch(0, "a rational")),
+    }
+}
+
+fn denominator(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("math/denominator", args).exactly(1)?;
+    match args.get(0)?.node() {
+        #[cfg(feature = "bignum")]
+        Exp::BigInt(_) => Ok(Exp::Integer(1)),
+        exp => exp
+            .as_rational()
+            .map(|(_, denominator)| Exp::Integer(denominator))
+            .ok_or_else(|| args.mismatch(0, "a rational")),
+    }
+}
+
+fn rational_to_float(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("math/to-float", args).exactly(1)?;
+    args.parse(0, "a number", number_to_f64).map(Exp::Float)
+}
+
+fn odd(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let int = Args::new("math/odd", args).exactly(1)?.integer(0)?;
+
+    Ok(Exp::Bool(int % 2 != 0))
+}
+
+fn even(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let int = Args::new("math/even", args).exactly(1)?.integer(0)?;
+
+    Ok(Exp::Bool(int % 2 == 0))
+}
+
+fn parse_base(base: i64) -> Option<u32> {
+    u32::try_from(base)
+        .ok()
+        .filter(|base| (2..=36).contains(base))
+}
+
+fn parse_radix(args: Args, i: usize) -> Result<u32> {
+    args.parse(i, "a radix from 2 to 36", |exp| {
+        exp.as_integer().and_then(parse_base)
+    })
+}
+
+/// The digits of `n` in `base`, with a leading `-` if it is negative.
+fn integer_to_string(n: i64, base: u32) -> String {
+    let mut digits = vec![];
+    let mut rest = n.unsigned_abs();
+    loop {
+        let digit = (rest % base as u64) as u32;
+        digits.push(core::char::from_digit(digit, base).unwrap());
+        rest /= base as u64;
+        if rest == 0 {
+            break;
+        }
+    }
+    if n < 0 {
+        digits.push('-');
+    }
+    digits.into_iter().rev().collect()
+}
+
+fn number_base(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("math/number-base", args).exactly(2)?;
+    Ok(Exp::String(integer_to_string(
+        args.integer(0)?,
+        parse_radix(args, 1)?,
+    )))
+}
+
+fn parse_number_base(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("math/parse-number-base", args).exactly(2)?;
+    let s = args.string(0)?;
+    let base = parse_radix(args, 1)?;
+    i64::from_str_radix(s, base)
+        .map(Exp::Integer)
+        .map_err(|_| args.mismatch(0, "an integer literal"))
+}
+
+fn is_number(exp: &Exp) -> bool {
+    number_to_f64(exp).is_some()
+}
+
+/// `(string/to-number s)` or `(string/to-number s radix)`: the number written in
+/// `s`, or `nil` if `s` is not a number. Without a radix any number literal is
+/// accepted, otherwise only integers. A trailing `:option` or `:result` asks
+/// for an option or a result instead of `nil`.
+fn string_to_number(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let (args, failure) = parse_failure(args);
+    let args = Args::new("string/to-number", args);
+    let number = read_number(args);
+    match failure {
+        Some(failure) => Ok(failure.wrap(
+            number.and_then(|number| number.ok_or_else(|| args.mismatch(0, "a number literal"))),
+        )),
+        None => number.map(|number| number.unwrap_or(Exp::Nil)),
+    }
+}
+
+fn read_number(args: Args) -> Result<Option<Exp>> {
+    let args = args.arity(Arity::Between(1, 2))?;
+    let s = args.string(0)?;
+    let base = match args.len() {
+        1 => 10,
+        _ => parse_radix(args, 1)?,
+    };
+    let number = if args.len() == 1 {
+        parser::read(s).ok().filter(is_number)
+    } else {
+        let integer = i64::from_str_radix(s, base).map(Exp::Integer).ok();
+        #[cfg(feature = "bignum")]
+        let integer = integer.or_else(|| BigInt::parse_bytes(s.as_bytes(), base).map(Exp::BigInt));
+        integer
+    };
+    Ok(number)
+}
+
+/// `(math/to-string n)` or `(math/to-string n radix)`. Only integers may be
+/// written in a radix other than 10.
+fn number_to_string(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("math/to-string", args).arity(Arity::Between(1, 2))?;
+    let n = args.get(0)?;
+    if args.len() == 1 {
+        return match is_number(n) {
+            true => Ok(Exp::String(n.to_string())),
+            false => Err(args.mismatch(0, "a number")),
+        };
+    }
+    match (n.node(), parse_radix(args, 1)?) {
+        (Exp::Integer(n), base) => Ok(Exp::String(integer_to_string(*n, base))),
+        #[cfg(feature = "bignum")]
+        (Exp::BigInt(n), base) => Ok(Exp::String(n.to_str_radix(base))),
+        (_, 10) if is_number(n) => Ok(Exp::String(n.to_string())),
+        _ => Err(args.mismatch(0, "an integer")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::*,
+        eval::{eval_default_module, eval_str},
+    };
+
+    #[cfg(feature = "bignum")]
+    #[test]
+    fn test_bignum_math() {
+        assert_eq!(
+            eval_str("(pow 2 64)").map(|e| e.to_string()),
+            Ok("18446744073709551616".to_string())
+        );
+        assert_eq!(
+            eval_str("(mod (pow 2 64) -3)").map(|e| e.to_string()),
+            Ok("-2".to_string())
+        );
+        assert_eq!(
+            eval_str("(gcd (pow 2 64) (pow 6 30))").map(|e| e.to_string()),
+            Ok("1073741824".to_string())
+        );
+        assert_eq!(
+            eval_str("(abs -9223372036854775808)").map(|e| e.to_string()),
+            Ok("9223372036854775808".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rounding() {
+        // (float? 1.0) => true
+        let e = list(&[symbol("float?"), float(1.0)]);
+        assert_eq!(eval_default_module(e), Ok(bool(true)));
+
+        // (float? 1) => false
+        let e = list(&[symbol("float?"), integer(1)]);
+        assert_eq!(eval_default_module(e), Ok(bool(false)));
+
+        for (f, x, expected) in [
+            ("floor", -1.5, -2),
+            ("ceil", -1.5, -1),
+            ("round", -1.5, -2),
+            ("round", 2.4, 2),
+            ("truncate", -1.5, -1),
+        ] {
+            let e = list(&[symbol(f), float(x)]);
+            assert_eq!(eval_default_module(e), Ok(integer(expected)), "{} {}", f, x);
+        }
+
+        // (floor 3) => 3
+        let e = list(&[symbol("floor"), integer(3)]);
+        assert_eq!(eval_default_module(e), Ok(integer(3)));
+
+        // (round nan) => error
+        let e = list(&[symbol("round"), float(f64::NAN)]);
+        assert!(eval_default_module(e).is_err());
+    }
+
+    #[test]
+    fn test_integer_math() {
+        use crate::eval::EvalError;
+
+        for (op, lhs, rhs, expected) in [
+            ("mod", integer(7), integer(3), integer(1)),
+            ("mod", integer(-7), integer(3), integer(2)),
+            ("mod", integer(7), integer(-3), integer(-2)),
+            ("mod", integer(i64::MIN), integer(-1), integer(0)),
+            ("rem", integer(-7), integer(3), integer(-1)),
+            ("rem", integer(7), integer(-3), integer(1)),
+            ("gcd", integer(12), integer(-18), integer(6)),
+            ("gcd", integer(0), integer(0), integer(0)),
+            ("min", integer(1), float(0.5), float(0.5)),
+            ("max", rational(1, 2), integer(0), rational(1, 2)),
+            ("pow", integer(2), integer(10), integer(1024)),
+            ("pow", integer(2), integer(-2), rational(1, 4)),
+            ("pow", rational(-2, 3), integer(3), rational(-8, 27)),
+        ] {
+            let e = list(&[symbol(op), lhs.clone(), rhs.clone()]);
+            assert_eq!(
+                eval_default_module(e),
+                Ok(expected),
+                "({} {} {})",
+                op,
+                lhs,
+                rhs
+            );
+        }
+
+        // (pow 4 0.5) => 2.0, with `std` for float powers
+        let e = list(&[symbol("pow"), integer(4), float(0.5)]);
+        #[cfg(feature = "std")]
+        assert_eq!(eval_default_module(e), Ok(float(2.0)));
+        #[cfg(not(feature = "std"))]
+        assert!(eval_default_module(e).is_err());
+
+        // (abs -3) => 3, (abs -1/2) => 1/2, (abs -1.5) => 1.5
+        for (x, expected) in [
+            (integer(-3), integer(3)),
+            (rational(-1, 2), rational(1, 2)),
+            (float(-1.5), float(1.5)),
+        ] {
+            let e = list(&[symbol("abs"), x]);
+            assert_eq!(eval_default_module(e), Ok(expected));
+        }
+
+        // (mod 1 0), (rem 1 0), (pow 0 -1) => division by zero
+        for (op, lhs, rhs) in [("mod", 1, 0), ("rem", 1, 0), ("pow", 0, -1)] {
+            let e = list(&[symbol(op), integer(lhs), integer(rhs)]);
+            assert_eq!(
+                eval_default_module(e),
+                Err(EvalError::DivideByZero(apply(integer(lhs), integer(rhs))))
+            );
+        }
+
+        // (mod 1.5 1) => error
+        let e = list(&[symbol("mod"), float(1.5), integer(1)]);
+        assert!(eval_default_module(e).is_err());
+    }
+
+    #[cfg(not(feature = "bignum"))]
+    #[test]
+    fn test_integer_math_overflow() {
+        use crate::eval::EvalError;
+
+        for e in [
+            list(&[symbol("abs"), integer(i64::MIN)]),
+            list(&[symbol("gcd"), integer(i64::MIN), integer(0)]),
+            list(&[symbol("pow"), integer(2), integer(63)]),
+            list(&[symbol("pow"), integer(2), integer(-64)]),
+        ] {
+            assert!(
+                matches!(
+                    eval_default_module(e.clone()),
+                    Err(EvalError::IntegerOverflow(_))
+                ),
+                "{}",
+                e
+            );
+        }
+    }
+
+    #[test]
+    fn test_bitwise() {
+        use crate::eval::EvalError;
+
+        for (op, lhs, rhs, expected) in [
+            ("bit/and", 0b1100, 0b1010, 0b1000),
+            ("bit/or", 0b1100, 0b1010, 0b1110),
+            ("bit/xor", 0b1100, 0b1010, 0b0110),
+            ("bit/shift-left", 1, 4, 16),
+            ("bit/shift-left", 1, 63, i64::MIN),
+            ("bit/shift-right", 16, 4, 1),
+            ("bit/shift-right", -16, 2, -4),
+        ] {
+            let e = list(&[symbol(op), integer(lhs), integer(rhs)]);
+            assert_eq!(
+                eval_default_module(e),
+                Ok(integer(expected)),
+                "({} {} {})",
+                op,
+                lhs,
+                rhs
+            );
+        }
+
+        // (bit/not 0) => -1
+        let e = list(&[symbol("bit/not"), integer(0)]);
+        assert_eq!(eval_default_module(e), Ok(integer(-1)));
+
+        // (bit/shift-left 1 64), (bit/shift-right 1 -1) => invalid shift
+        for amount in [64, -1] {
+            let e = list(&[symbol("bit/shift-left"), integer(1), integer(amount)]);
+            assert_eq!(eval_default_module(e), Err(EvalError::InvalidShift(amount)));
+        }
+
+        // (bit/and 1.0 1) => error
+        let e = list(&[symbol("bit/and"), float(1.0), integer(1)]);
+        assert!(eval_default_module(e).is_err());
+    }
+
+    #[test]
+    fn test_number_base() {
+        // (math/number-base 10 2) => "1010"
+        let e = list(&[symbol("math/number-base"), integer(10), integer(2)]);
+        assert_eq!(eval_default_module(e), Ok(string("1010")));
+
+        // (math/number-base 255 16) => "ff"
+        let e = list(&[symbol("math/number-base"), integer(255), integer(16)]);
+        assert_eq!(eval_default_module(e), Ok(string("ff")));
+
+        // (math/number-base 35 36) => "z"
+        let e = list(&[symbol("math/number-base"), integer(35), integer(36)]);
+        assert_eq!(eval_default_module(e), Ok(string("z")));
+
+        // (math/number-base -255 16) => "-ff"
+        let e = list(&[symbol("math/number-base"), integer(-255), integer(16)]);
+        assert_eq!(eval_default_module(e), Ok(string("-ff")));
+
+        // (math/number-base 10 1) => error
+        let e = list(&[symbol("math/number-base"), integer(10), integer(1)]);
+        assert!(eval_default_module(e).is_err());
+    }
+
+    #[test]
+    fn test_parse_number_base() {
+        // (math/parse-number-base "ff" 16) => 255
+        let e = list(&[symbol("math/parse-number-base"), string("ff"), integer(16)]);
+        assert_eq!(eval_default_module(e), Ok(integer(255)));
+
+        // (math/parse-number-base "z" 36) => 35
+        let e = list(&[symbol("math/parse-number-base"), string("z"), integer(36)]);
+        assert_eq!(eval_default_module(e), Ok(integer(35)));
+
+        // (math/parse-number-base "12" 2) => error
+        let e = list(&[symbol("math/parse-number-base"), string("12"), integer(2)]);
+        assert!(eval_default_module(e).is_err());
+
+        // (math/parse-number-base "1" 37) => error
+        let e = list(&[symbol("math/parse-number-base"), string("1"), integer(37)]);
+        assert!(eval_default_module(e).is_err());
+    }
+
+    #[test]
+    fn test_number_string_conversion() {
+        assert_eq!(eval_str("(string/to-number \"42\")"), Ok(integer(42)));
+        assert_eq!(eval_str("(string/to-number \"-1.5e2\")"), Ok(float(-150.0)));
+        assert_eq!(eval_str("(string/to-number \"2/4\")"), Ok(rational(1, 2)));
+        assert_eq!(eval_str("(string/to-number \"ff\" 16)"), Ok(integer(255)));
+        assert_eq!(eval_str("(string/to-number \"-101\" 2)"), Ok(integer(-5)));
+        for source in [
+            "(string/to-number \"abc\")",
+            "(string/to-number \"1 2\")",
+            "(string/to-number \"\")",
+            "(string/to-number \"12\" 2)",
+            "(string/to-number \"1.5\" 16)",
+        ] {
+            assert_eq!(eval_str(source), Ok(nil()), "{}", source);
+        }
+        assert!(eval_str("(string/to-number \"1\" 37)").is_err());
+        assert!(eval_str("(string/to-number 1)").is_err());
+
+        assert_eq!(eval_str("(math/to-string 42)"), Ok(string("42")));
+        assert_eq!(eval_str("(math/to-string 1.5)"), Ok(string("1.5")));
+        assert_eq!(eval_str("(math/to-string -255 16)"), Ok(string("-ff")));
+        assert_eq!(eval_str("(math/to-string 1/2 10)"), Ok(string("1/2")));
+        assert!(eval_str("(math/to-string 1.5 2)").is_err());
+        assert!(eval_str("(math/to-string \"1\")").is_err());
+        assert_eq!(
+            eval_str("(string/to-number (math/to-string 3/7))"),
+            Ok(rational(3, 7))
+        );
+    }
+
+    #[test]
+    fn test_rational_functions() {
+        // (numerator -6/4) => -3, (denominator -6/4) => 2
+        let e = list(&[symbol("numerator"), rational(-6, 4)]);
+        assert_eq!(eval_default_module(e), Ok(integer(-3)));
+        let e = list(&[symbol("denominator"), rational(-6, 4)]);
+        assert_eq!(eval_default_module(e), Ok(integer(2)));
+        let e = list(&[symbol("denominator"), integer(5)]);
+        assert_eq!(eval_default_module(e), Ok(integer(1)));
+
+        // (math/to-float 1/4) => 0.25
+        let e = list(&[symbol("math/to-float"), rational(1, 4)]);
+        assert_eq!(eval_default_module(e), Ok(float(0.25)));
+
+        for (f, x, expected) in [
+            ("floor", rational(-7, 2), -4),
+            ("ceil", rational(-7, 2), -3),
+            ("round", rational(-7, 2), -4),
+            ("round", rational(1, 3), 0),
+            ("truncate", rational(-7, 2), -3),
+        ] {
+            let e = list(&[symbol(f), x.clone()]);
+            assert_eq!(eval_default_module(e), Ok(integer(expected)), "{} {}", f, x);
+        }
+    }
+}
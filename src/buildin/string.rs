@@ -0,0 +1,552 @@
+//! The string and character builtins, behind the `std-string` feature.
+
+use super::*;
+
+pub(super) fn register(module: &mut Module) {
+    module.register_curried_binary("string/append", string_append);
+    module.register_builtin("string/head", string_head);
+    module.register_builtin("string/tail", string_tail);
+    module.register_builtin("string/init", string_init);
+    module.register_builtin("string/last", string_last);
+    module.register_curried_binary("string/repeat", string_repeat);
+    module.register_curried_binary("string/contains-only?", string_contains_only);
+    module.register_curried_binary("string/split-at", string_split_at);
+    module.register_builtin("string/length", string_length);
+    insert_ternary_curry_op(substring, "string/substring", module);
+    module.register_curried_binary("string/split", string_split);
+    module.register_curried_binary("string/join", string_join);
+    module.register_builtin("string/to-list", string_to_list);
+    module.register_builtin("list/to-string", list_to_string);
+    module.register_curried_binary("string/contains?", string_contains);
+    module.register_curried_binary("string/starts-with?", string_starts_with);
+    module.register_curried_binary("string/ends-with?", string_ends_with);
+    insert_ternary_curry_op(string_replace, "string/replace", module);
+    module.register_builtin("string/trim", string_trim);
+    module.register_builtin("string/upcase", string_upcase);
+    module.register_builtin("string/downcase", string_downcase);
+    module.register_builtin("string/capitalize", string_capitalize);
+    module.register_builtin("char/upcase", char_upcase);
+    module.register_builtin("char/downcase", char_downcase);
+    module.register_builtin("char/alphabetic?", is_char_alphabetic);
+    module.register_builtin("char/numeric?", is_char_numeric);
+    module.register_builtin("char/whitespace?", is_char_whitespace);
+    module.register_builtin("char/upper-case?", is_char_uppercase);
+    module.register_builtin("char/lower-case?", is_char_lowercase);
+}
+
+fn string_append(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("string/append", args).exactly(2)?;
+    Ok(Exp::String(format!(
+        "{}{}",
+        args.string(0)?,
+        args.string(1)?
+    )))
+}
+
+fn string_head(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let s = Args::new("string/head", args).exactly(1)?.string(0)?;
+    Ok(Exp::String(s.chars().take(1).collect()))
+}
+
+fn string_tail(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let s = Args::new("string/tail", args).exactly(1)?.string(0)?;
+    Ok(Exp::String(s.chars().skip(1).collect()))
+}
+
+fn string_init(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let s = Args::new("string/init", args).exactly(1)?.string(0)?;
+    Ok(Exp::String(s.chars().take(s.len() - 1).collect()))
+}
+
+fn string_last(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let s = Args::new("string/last", args).exactly(1)?.string(0)?;
+    Ok(Exp::String(s.chars().rev().take(1).collect()))
+}
+
+/// `(string/repeat s n)`: `s` repeated `n` times. A result longer than the
+/// host can address is an integer overflow rather than a panic.
+fn string_repeat(args: &[Exp], ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("string/repeat", args).exactly(2)?;
+    let (s, n) = (args.string(0)?, args.count(1)?);
+    let len = s.len().checked_mul(n);
+    ctx.reserve(len.unwrap_or(usize::MAX))?;
+    // A `String` holds at most `isize::MAX` bytes.
+    if len.is_none_or(|len| len > isize::MAX as usize) {
+        return Err(EvalError::IntegerOverflow(args.get(1)?.clone()));
+    }
+    Ok(Exp::String(s.repeat(n)))
+}
+
+fn string_contains_only(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("string/contains-only?", args).exactly(2)?;
+    let (s, chars) = (args.string(0)?, args.string(1)?);
+    Ok(Exp::Bool(s.chars().all(|ch| chars.contains(ch))))
+}
+
+fn string_split_at(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("string/split-at", args).exactly(2)?;
+    let s = args.string(0)?;
+    let len = s.chars().count();
+    let n = args.integer(1)?;
+    let n = usize::try_from(n)
+        .ok()
+        .filter(|&n| n <= len)
+        .ok_or(EvalError::IndexOutOfBounds { index: n, len })?;
+    Ok(ast::list(&[
+        Exp::String(s.chars().take(n).collect()),
+        Exp::String(s.chars().skip(n).collect()),
+    ]))
+}
+
+/// The number of characters in a string.
+fn string_length(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let s = Args::new("string/length", args).exactly(1)?.string(0)?;
+    Ok(Exp::Integer(s.chars().count() as i64))
+}
+
+/// `(string/substring s start end)`: the characters of `s` from index `start` up to,
+/// but not including, `end`.
+fn substring(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("string/substring", args).exactly(3)?;
+    let s = args.string(0)?;
+    let (start, end) = (args.integer(1)?, args.integer(2)?);
+    let len = s.chars().count();
+    if start < 0 || start > end || end as u64 > len as u64 {
+        return Err(EvalError::OutOfRange(start, end, len));
+    }
+    Ok(Exp::String(
+        s.chars()
+            .skip(start as usize)
+            .take((end - start) as usize)
+            .collect(),
+    ))
+}
+
+/// `(string/split s sep)`: the parts of `s` between occurrences of `sep`. An
+/// empty separator splits `s` into its characters.
+fn string_split(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("string/split", args).exactly(2)?;
+    let (s, sep) = (args.string(0)?, args.string(1)?);
+    if sep.is_empty() {
+        return Ok(Exp::List(
+            s.chars().map(|ch| Exp::String(ch.to_string())).collect(),
+        ));
+    }
+    Ok(Exp::List(
+        s.split(sep)
+            .map(|part| Exp::String(part.to_string()))
+            .collect(),
+    ))
+}
+
+/// `(string/join list sep)`: the strings of `list` with `sep` between them.
+fn string_join(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("string/join", args).exactly(2)?;
+    let sep = args.string(1)?;
+    let parts = args
+        .list(0)?
+        .iter()
+        .map(|part| {
+            part.as_string()
+                .ok_or_else(|| args.mismatch(0, "a list of strings"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Exp::String(parts.join(sep)))
+}
+
+/// The characters of a string.
+fn string_to_list(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let s = Args::new("string/to-list", args).exactly(1)?.string(0)?;
+    Ok(Exp::List(s.chars().map(Exp::Char).collect()))
+}
+
+/// Concatenates a list of characters and strings.
+fn list_to_string(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("list/to-string", args).exactly(1)?;
+    let mut result = String::new();
+    for elem in args.list(0)? {
+        match elem.node() {
+            Exp::Char(ch) => result.push(*ch),
+            Exp::String(s) => result.push_str(s),
+            _ => return Err(args.mismatch(0, "a list of characters and strings")),
+        }
+    }
+    Ok(Exp::String(result))
+}
+
+fn string_upcase(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let s = Args::new("string/upcase", args).exactly(1)?.string(0)?;
+    Ok(Exp::String(s.to_uppercase()))
+}
+
+fn string_downcase(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let s = Args::new("string/downcase", args).exactly(1)?.string(0)?;
+    Ok(Exp::String(s.to_lowercase()))
+}
+
+/// Upper-cases the first letter of every word and lower-cases the rest. Words
+/// are separated by whitespace.
+fn string_capitalize(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let s = Args::new("string/capitalize", args).exactly(1)?.string(0)?;
+    let mut result = String::with_capacity(s.len());
+    let mut word_start = true;
+    for ch in s.chars() {
+        if word_start {
+            result.extend(ch.to_uppercase());
+        } else {
+            result.extend(ch.to_lowercase());
+        }
+        word_start = ch.is_whitespace();
+    }
+    Ok(Exp::String(result))
+}
+
+fn string_predicate(args: Args, predicate: fn(&str, &str) -> bool) -> Result<Exp> {
+    let args = args.exactly(2)?;
+    Ok(Exp::Bool(predicate(args.string(0)?, args.string(1)?)))
+}
+
+/// `(string/contains? s pattern)`
+fn string_contains(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    string_predicate(Args::new("string/contains?", args), |s, pattern| {
+        s.contains(pattern)
+    })
+}
+
+/// `(string/starts-with? s prefix)`
+fn string_starts_with(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    string_predicate(Args::new("string/starts-with?", args), |s, prefix| {
+        s.starts_with(prefix)
+    })
+}
+
+/// `(string/ends-with? s suffix)`
+fn string_ends_with(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    string_predicate(Args::new("string/ends-with?", args), |s, suffix| {
+        s.ends_with(suffix)
+    })
+}
+
+/// `(string/replace s from to)`: `s` with every occurrence of `from` replaced
+/// by `to`.
+fn string_replace(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let args = Args::new("string/replace", args).exactly(3)?;
+    let (s, from, to) = (args.string(0)?, args.string(1)?, args.string(2)?);
+    if from.is_empty() {
+        return Err(args.mismatch(1, "a non-empty string"));
+    }
+    Ok(Exp::String(s.replace(from, to)))
+}
+
+/// Removes leading and trailing whitespace.
+fn string_trim(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let s = Args::new("string/trim", args).exactly(1)?.string(0)?;
+    Ok(Exp::String(s.trim().to_string()))
+}
+
+/// Characters whose case mapping is more than one character, such as `ß`,
+/// are left unchanged.
+fn char_upcase(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let ch = Args::new("char/upcase", args).exactly(1)?.char(0)?;
+    let mut upper = ch.to_uppercase();
+    Ok(Exp::Char(match (upper.next(), upper.next()) {
+        (Some(upper), None) => upper,
+        _ => ch,
+    }))
+}
+
+fn char_downcase(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    let ch = Args::new("char/downcase", args).exactly(1)?.char(0)?;
+    let mut lower = ch.to_lowercase();
+    Ok(Exp::Char(match (lower.next(), lower.next()) {
+        (Some(lower), None) => lower,
+        _ => ch,
+    }))
+}
+
+fn char_predicate(args: Args, predicate: fn(char) -> bool) -> Result<Exp> {
+    let ch = args.exactly(1)?.char(0)?;
+    Ok(Exp::Bool(predicate(ch)))
+}
+
+fn is_char_alphabetic(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    char_predicate(Args::new("char/alphabetic?", args), char::is_alphabetic)
+}
+
+fn is_char_numeric(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    char_predicate(Args::new("char/numeric?", args), char::is_numeric)
+}
+
+fn is_char_whitespace(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    char_predicate(Args::new("char/whitespace?", args), char::is_whitespace)
+}
+
+fn is_char_uppercase(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    char_predicate(Args::new("char/upper-case?", args), char::is_uppercase)
+}
+
+fn is_char_lowercase(args: &[Exp], _ctx: &mut EvalContext) -> Result<Exp> {
+    char_predicate(Args::new("char/lower-case?", args), char::is_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::*,
+        eval::{eval_default_module, eval_kind, eval_str, EvalError},
+    };
+
+    #[test]
+    fn test_string_append() {
+        // (string/append "abc" "def") => "abcdef"
+        let e = list(&[symbol("string/append"), string("abc"), string("def")]);
+        assert_eq!(eval_default_module(e), Ok(string("abcdef")));
+    }
+
+    #[test]
+    fn test_string_head() {
+        // (string/head "abc") => "a"
+        let e = list(&[symbol("string/head"), string("abc")]);
+        assert_eq!(eval_default_module(e), Ok(string("a")));
+    }
+
+    #[test]
+    fn test_string_tail() {
+        // (string/tail "abc") => "bc"
+        let e = list(&[symbol("string/tail"), string("abc")]);
+        assert_eq!(eval_default_module(e), Ok(string("bc")));
+    }
+
+    #[test]
+    fn test_string_init() {
+        // (string/init "abc") => "ab"
+        let e = list(&[symbol("string/init"), string("abc")]);
+        assert_eq!(eval_default_module(e), Ok(string("ab")));
+    }
+
+    #[test]
+    fn test_string_last() {
+        // (string/last "abc") => "c"
+        let e = list(&[symbol("string/last"), string("abc")]);
+        assert_eq!(eval_default_module(e), Ok(string("c")));
+    }
+
+    #[test]
+    fn test_string_repeat() {
+        // (string/repeat "ab" 3) => "ababab"
+        let e = list(&[symbol("string/repeat"), string("ab"), integer(3)]);
+        assert_eq!(eval_default_module(e), Ok(string("ababab")));
+
+        // (string/repeat "" 100) => ""
+        let e = list(&[symbol("string/repeat"), string(""), integer(100)]);
+        assert_eq!(eval_default_module(e), Ok(string("")));
+
+        // (string/repeat "x" 0) => ""
+        let e = list(&[symbol("string/repeat"), string("x"), integer(0)]);
+        assert_eq!(eval_default_module(e), Ok(string("")));
+
+        // (string/repeat "xx" 9223372036854775807) => integer overflow
+        let e = list(&[symbol("string/repeat"), string("xx"), integer(i64::MAX)]);
+        assert_eq!(
+            eval_default_module(e).map_err(|err| err.kind().clone()),
+            Err(EvalError::IntegerOverflow(integer(i64::MAX)))
+        );
+    }
+
+    #[test]
+    fn test_string_contains_only() {
+        // (string/contains-only? "123" "0123456789") => true
+        let e = list(&[
+            symbol("string/contains-only?"),
+            string("123"),
+            string("0123456789"),
+        ]);
+        assert_eq!(eval_default_module(e), Ok(bool(true)));
+
+        // (string/contains-only? "1a3" "0123456789") => false
+        let e = list(&[
+            symbol("string/contains-only?"),
+            string("1a3"),
+            string("0123456789"),
+        ]);
+        assert_eq!(eval_default_module(e), Ok(bool(false)));
+    }
+
+    #[test]
+    fn test_string_split_at() {
+        // (string/split-at "hello world" 5) => ("hello" " world")
+        let e = list(&[symbol("string/split-at"), string("hello world"), integer(5)]);
+        assert_eq!(
+            eval_default_module(e),
+            Ok(list(&[string("hello"), string(" world")]))
+        );
+
+        // (string/split-at "abc" 0) => ("" "abc")
+        let e = list(&[symbol("string/split-at"), string("abc"), integer(0)]);
+        assert_eq!(
+            eval_default_module(e),
+            Ok(list(&[string(""), string("abc")]))
+        );
+
+        // (string/split-at "abc" 3) => ("abc" "")
+        let e = list(&[symbol("string/split-at"), string("abc"), integer(3)]);
+        assert_eq!(
+            eval_default_module(e),
+            Ok(list(&[string("abc"), string("")]))
+        );
+
+        // (string/split-at "abc" 4) => error
+        let e = list(&[symbol("string/split-at"), string("abc"), integer(4)]);
+        assert!(eval_default_module(e).is_err());
+
+        // (string/split-at "abc" -1) => error
+        let e = list(&[symbol("string/split-at"), string("abc"), integer(-1)]);
+        assert!(eval_default_module(e).is_err());
+    }
+
+    #[test]
+    fn test_substring() {
+        assert_eq!(eval_str("(string/length \"\")"), Ok(integer(0)));
+        // Characters are counted, not bytes.
+        assert_eq!(eval_str("(string/length \"h\u{e9}llo\")"), Ok(integer(5)));
+        assert_eq!(
+            eval_str("(string/substring \"h\u{e9}llo\" 1 3)"),
+            Ok(string("\u{e9}l"))
+        );
+        assert_eq!(eval_str("(string/substring \"abc\" 3 3)"), Ok(string("")));
+        assert_eq!(
+            eval_str("((string/substring \"abc\" 0) 3)"),
+            Ok(string("abc"))
+        );
+        for (source, start, end) in [
+            ("(string/substring \"abc\" 1 4)", 1, 4),
+            ("(string/substring \"abc\" 2 1)", 2, 1),
+            ("(string/substring \"abc\" -1 2)", -1, 2),
+        ] {
+            assert_eq!(eval_kind(source), Err(EvalError::OutOfRange(start, end, 3)));
+        }
+        assert!(eval_str("(string/substring 'abc 0 1)").is_err());
+    }
+
+    #[test]
+    fn test_string_split_join() {
+        assert_eq!(
+            eval_str("(string/split \"a,b,,c\" \",\")"),
+            Ok(list(&[string("a"), string("b"), string(""), string("c")]))
+        );
+        assert_eq!(
+            eval_str("(string/split \"a::b\" \"::\")"),
+            Ok(list(&[string("a"), string("b")]))
+        );
+        assert_eq!(
+            eval_str("(string/split \"\" \",\")"),
+            Ok(list(&[string("")]))
+        );
+        assert_eq!(
+            eval_str("(string/split \"ab\" \"\")"),
+            Ok(list(&[string("a"), string("b")]))
+        );
+        assert_eq!(
+            eval_str("(string/join '(\"a\" \"b\" \"c\") \", \")"),
+            Ok(string("a, b, c"))
+        );
+        assert_eq!(eval_str("(string/join nil \",\")"), Ok(string("")));
+        assert_eq!(
+            eval_str("(string/join (string/split \"a b c\" \" \") \"-\")"),
+            Ok(string("a-b-c"))
+        );
+        assert!(eval_str("(string/join '(1 2) \",\")").is_err());
+    }
+
+    #[test]
+    fn test_string_list_conversion() {
+        assert_eq!(
+            eval_str("(string/to-list \"ab\")"),
+            Ok(list(&[Exp::Char('a'), Exp::Char('b')]))
+        );
+        assert_eq!(eval_str("(string/to-list \"\")"), Ok(list(&[])));
+        assert_eq!(
+            eval_str(r#"(list/to-string (list #\a "bc" #\d))"#),
+            Ok(string("abcd"))
+        );
+        assert_eq!(eval_str("(list/to-string nil)"), Ok(string("")));
+        assert_eq!(
+            eval_str("(list/to-string (map char/upcase (string/to-list \"abc\")))"),
+            Ok(string("ABC"))
+        );
+        assert!(eval_str("(list/to-string '(1 2))").is_err());
+    }
+
+    #[test]
+    fn test_string_search_replace() {
+        assert_eq!(
+            eval_str("(string/contains? \"hello\" \"ell\")"),
+            Ok(bool(true))
+        );
+        assert_eq!(
+            eval_str("(string/contains? \"hello\" \"\")"),
+            Ok(bool(true))
+        );
+        assert_eq!(
+            eval_str("(string/contains? \"hello\" \"elo\")"),
+            Ok(bool(false))
+        );
+        assert_eq!(
+            eval_str("(string/starts-with? \"hello\" \"he\")"),
+            Ok(bool(true))
+        );
+        assert_eq!(
+            eval_str("(string/starts-with? \"hello\" \"lo\")"),
+            Ok(bool(false))
+        );
+        assert_eq!(
+            eval_str("(string/ends-with? \"hello\" \"lo\")"),
+            Ok(bool(true))
+        );
+        assert!(eval_str("(string/ends-with? \"hello\" 'lo)").is_err());
+        assert_eq!(
+            eval_str("(string/replace \"a-b-c\" \"-\" \"--\")"),
+            Ok(string("a--b--c"))
+        );
+        assert!(eval_str("(string/replace \"abc\" \"\" \"x\")").is_err());
+        assert_eq!(eval_str("(string/trim \"  a b\t\")"), Ok(string("a b")));
+    }
+
+    #[test]
+    fn test_string_case() {
+        assert_eq!(eval_str("(string/upcase \"abc-1\")"), Ok(string("ABC-1")));
+        // Case mappings are Unicode aware and may change the length.
+        assert_eq!(
+            eval_str("(string/upcase \"stra\u{df}e\")"),
+            Ok(string("STRASSE"))
+        );
+        assert_eq!(
+            eval_str("(string/downcase \"\u{39b}AMBDA\")"),
+            Ok(string("\u{3bb}ambda"))
+        );
+        assert_eq!(
+            eval_str("(string/capitalize \"hello wORLD  \u{e9}t\u{e9}\")"),
+            Ok(string("Hello World  \u{c9}t\u{e9}"))
+        );
+        assert_eq!(eval_str("(string/capitalize \"\")"), Ok(string("")));
+        assert!(eval_str("(string/upcase 'abc)").is_err());
+    }
+
+    #[test]
+    fn test_char_case() {
+        assert_eq!(
+            eval_str("(char/upcase (char/from-integer 955))"),
+            Ok(Exp::Char('\u{39b}'))
+        );
+        assert_eq!(
+            eval_str("(char/upcase (char/from-integer 223))"),
+            Ok(Exp::Char('\u{df}'))
+        );
+        assert_eq!(eval_str(r"(char/downcase #\Q)"), Ok(Exp::Char('q')));
+        assert_eq!(eval_str(r"(char/alphabetic? #\a)"), Ok(bool(true)));
+        assert_eq!(eval_str(r"(char/numeric? #\a)"), Ok(bool(false)));
+        assert_eq!(eval_str(r"(char/whitespace? #\tab)"), Ok(bool(true)));
+        assert_eq!(eval_str(r"(char/upper-case? #\a)"), Ok(bool(false)));
+        assert!(eval_str("(char/lower-case? \"a\")").is_err());
+    }
+}
@@ -0,0 +1,302 @@
+use alloc::rc::Rc;
+
+use crate::{
+    ast::{Exp, Module},
+    eval::{eval, pattern_vars, VariableGenerator},
+    prelude::*,
+    symbol::SymbolId,
+    token::Span,
+};
+
+/// A single bytecode instruction. Every expression compiles to code that pushes
+/// exactly one value onto the value stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    /// Push a constant.
+    Const(Exp),
+    /// Push the value of a variable.
+    Load(SymbolId),
+    /// Push a closure over the current environment.
+    Closure(SymbolId, Rc<Exp>),
+    /// Pop a function and push it as a variadic function.
+    Variadic,
+    /// Pop the arguments and then the function, and call it.
+    Call(usize),
+    /// Pop a value and bind it in the current environment.
+    Bind(SymbolId),
+    /// Bind the lambdas of a `letrec` in the current environment. Fails if a
+    /// bound expression is not a lambda.
+    BindRec(Rc<[(SymbolId, Exp)]>),
+    /// Drop the innermost binding of the current environment.
+    Unbind,
+    Jump(usize),
+    /// Pop a boolean and jump if it is false. The `if` is kept for error
+    /// reporting.
+    JumpUnless(usize, Exp),
+    /// Match the value on top of the stack against a `case` pattern and bind
+    /// the variables of the pattern, or jump if it does not match.
+    Match(Exp, usize),
+    /// Pop the value that no `case` arm matched and fail.
+    NoMatchingCase,
+    /// Drop the value on top of the stack.
+    Pop,
+    /// Evaluate the expression with the tree-walking evaluator. Used for forms
+    /// that have no bytecode of their own, such as macro calls, quasi-quotes
+    /// and `try`.
+    Eval(Exp),
+    /// Return the value on top of the stack from the current call.
+    Return,
+}
+
+/// Compiled code together with the span of the expression each op came from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Chunk {
+    pub ops: Vec<Op>,
+    pub spans: Vec<Option<Span>>,
+}
+
+impl Chunk {
+    fn emit(&mut self, op: Op, span: Option<Span>) -> usize {
+        self.ops.push(op);
+        self.spans.push(span);
+        self.ops.len() - 1
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        match &mut self.ops[at] {
+            Op::Jump(to) | Op::JumpUnless(to, _) | Op::Match(_, to) => *to = target,
+            op => unreachable!("patch {:?}", op),
+        }
+    }
+}
+
+fn contains_unquote(exp: &Exp) -> bool {
+    match exp {
+        Exp::UnQuote(_) | Exp::UnQuoteSplicing(_) => true,
+        Exp::Quote(_) => false,
+        Exp::List(es) | Exp::Values(es) => es.iter().any(contains_unquote),
+        Exp::Lambda(_, e) | Exp::Spanned(_, e) | Exp::Closure(_, e, _) | Exp::Variadic(e) => {
+            contains_unquote(e)
+        }
+        Exp::Apply(e1, e2) | Exp::Let((_, e1), e2) => contains_unquote(e1) || contains_unquote(e2),
+        Exp::If(c, t, e) => contains_unquote(c) || contains_unquote(t) || contains_unquote(e),
+        Exp::Try(body, _, handler) => contains_unquote(body) || contains_unquote(handler),
+        Exp::LetRec(binds, e) => {
+            binds.iter().any(|(_, b)| contains_unquote(b)) || contains_unquote(e)
+        }
+        Exp::Case(e, cases) => {
+            contains_unquote(e)
+                || cases.iter().any(|(pattern, guard, body)| {
+                    contains_unquote(pattern)
+                        || guard.as_ref().is_some_and(contains_unquote)
+                        || contains_unquote(body)
+                })
+        }
+        _ => false,
+    }
+}
+
+struct Compiler<'a> {
+    module: &'a Module,
+    gen: VariableGenerator,
+    chunk: Chunk,
+}
+
+impl Compiler<'_> {
+    fn compile(&mut self, exp: &Exp, span: Option<Span>, tail: bool) {
+        match exp {
+            Exp::Spanned(span, e) => self.compile(e, Some(*span), tail),
+            Exp::Nil
+            | Exp::Void
+            | Exp::Bool(_)
+            | Exp::Integer(_)
+            | Exp::Float(_)
+            | Exp::Rational(..)
+            | Exp::String(_)
+            | Exp::Values(_)
+            | Exp::Map(_)
+            | Exp::Set(_)
+            | Exp::Vector(_)
+            | Exp::Bytes(_)
+            | Exp::Char(_)
+            | Exp::Keyword(_)
+            | Exp::Pair(..)
+            | Exp::BuildIn(_)
+            | Exp::HostFn(_)
+            | Exp::Closure(..) => {
+                self.chunk.emit(Op::Const(exp.clone()), span);
+            }
+            #[cfg(feature = "bignum")]
+            Exp::BigInt(_) => {
+                self.chunk.emit(Op::Const(exp.clone()), span);
+            }
+            Exp::Symbol(sym) => {
+                self.chunk.emit(Op::Load(*sym), span);
+            }
+            Exp::Lambda(x, body) => {
+                self.chunk.emit(Op::Closure(*x, body.clone()), span);
+            }
+            Exp::Variadic(f) => {
+                self.compile(f, span, false);
+                self.chunk.emit(Op::Variadic, span);
+            }
+            Exp::Apply(e1, e2) => {
+                self.compile(e1, span, false);
+                self.compile(e2, span, false);
+                self.chunk.emit(Op::Call(1), span);
+            }
+            Exp::List(es) => match es.split_first() {
+                None => {
+                    self.chunk.emit(Op::Const(Exp::Nil), span);
+                }
+                Some((head, _))
+                    if head
+                        .as_symbol_id()
+                        .is_some_and(|sym| self.module.macros.contains_key(&sym)) =>
+                {
+                    self.chunk.emit(Op::Eval(exp.clone()), span);
+                }
+                Some((head, args)) => {
+                    self.compile(head, span, false);
+                    for arg in args {
+                        self.compile(arg, span, false);
+                    }
+                    self.chunk.emit(Op::Call(args.len()), span);
+                }
+            },
+            Exp::If(cond, then, else_) => {
+                self.compile(cond, span, false);
+                let branch = self.chunk.emit(
+                    Op::JumpUnless(0, Exp::If(cond.clone(), then.clone(), else_.clone())),
+                    span,
+                );
+                self.compile(then, span, tail);
+                let jump = (!tail).then(|| self.chunk.emit(Op::Jump(0), span));
+                self.chunk.patch(branch, self.chunk.ops.len());
+                self.compile(else_, span, tail);
+                if let Some(jump) = jump {
+                    self.chunk.patch(jump, self.chunk.ops.len());
+                }
+            }
+            Exp::Let((x, bind), body) => {
+                self.compile(bind, span, false);
+                self.chunk.emit(Op::Bind(*x), span);
+                self.compile(body, span, tail);
+                if !tail {
+                    self.chunk.emit(Op::Unbind, span);
+                }
+            }
+            Exp::LetRec(binds, body) => {
+                self.chunk.emit(Op::BindRec(binds.clone()), span);
+                self.compile(body, span, tail);
+                if !tail {
+                    self.chunk.emit(Op::Unbind, span);
+                }
+            }
+            Exp::Case(e, cases) => {
+                self.compile(e, span, false);
+                let mut jumps = vec![];
+                for (pattern, guard, body) in cases.iter() {
+                    let vars = pattern_vars(pattern).len();
+                    let next = self.chunk.emit(Op::Match(pattern.clone(), 0), span);
+                    let fail = guard.as_ref().map(|guard| {
+                        self.compile(guard, span, false);
+                        self.chunk.emit(Op::JumpUnless(0, guard.clone()), span)
+                    });
+                    self.chunk.emit(Op::Pop, span);
+                    self.compile(body, span, tail);
+                    if !tail {
+                        for _ in 0..vars {
+                            self.chunk.emit(Op::Unbind, span);
+                        }
+                        jumps.push(self.chunk.emit(Op::Jump(0), span));
+                    }
+                    // A failed guard drops the bindings before trying the next arm.
+                    if let Some(fail) = fail {
+                        self.chunk.patch(fail, self.chunk.ops.len());
+                        for _ in 0..vars {
+                            self.chunk.emit(Op::Unbind, span);
+                        }
+                    }
+                    self.chunk.patch(next, self.chunk.ops.len());
+                }
+                self.chunk.emit(Op::NoMatchingCase, span);
+                for jump in jumps {
+                    self.chunk.patch(jump, self.chunk.ops.len());
+                }
+            }
+            Exp::Quote(e) if !contains_unquote(e) => {
+                match eval(exp.clone(), self.module, &mut self.gen) {
+                    Ok(value) => self.chunk.emit(Op::Const(value), span),
+                    Err(_) => self.chunk.emit(Op::Eval(exp.clone()), span),
+                };
+            }
+            Exp::Quote(_) | Exp::UnQuote(_) | Exp::UnQuoteSplicing(_) | Exp::Try(..) => {
+                self.chunk.emit(Op::Eval(exp.clone()), span);
+            }
+        }
+        if tail
+            && !matches!(
+                exp,
+                Exp::Spanned(..) | Exp::If(..) | Exp::Let(..) | Exp::LetRec(..) | Exp::Case(..)
+            )
+        {
+            self.chunk.emit(Op::Return, span);
+        }
+    }
+}
+
+/// Compiles `exp` to bytecode for the [`vm`](crate::vm). The code returns the
+/// value of `exp`. Calls to macros of `module` are left to the tree-walking
+/// evaluator.
+pub fn compile(exp: &Exp, module: &Module) -> Chunk {
+    let mut compiler = Compiler {
+        module,
+        gen: VariableGenerator::new(),
+        chunk: Chunk::default(),
+    };
+    compiler.compile(exp, None, true);
+    compiler.chunk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::*;
+
+    #[test]
+    fn test_compile() {
+        let module = Module::new("test");
+
+        // (if true 1 2)
+        let chunk = compile(&if_(bool(true), integer(1), integer(2)), &module);
+        assert_eq!(
+            chunk.ops,
+            vec![
+                Op::Const(bool(true)),
+                Op::JumpUnless(4, if_(bool(true), integer(1), integer(2))),
+                Op::Const(integer(1)),
+                Op::Return,
+                Op::Const(integer(2)),
+                Op::Return,
+            ]
+        );
+
+        // (let (x 1) (f x))
+        let chunk = compile(
+            &let_(("x", integer(1)), list(&[symbol("f"), symbol("x")])),
+            &module,
+        );
+        assert_eq!(
+            chunk.ops,
+            vec![
+                Op::Const(integer(1)),
+                Op::Bind("x".into()),
+                Op::Load("f".into()),
+                Op::Load("x".into()),
+                Op::Call(1),
+                Op::Return,
+            ]
+        );
+    }
+}
@@ -0,0 +1,397 @@
+//! Converting between Rust values and topogi values.
+//!
+//! [`ToExp`] and [`FromExp`] let a host pass arguments into topogi and read
+//! results out of it without taking [`Exp`] apart by hand. A sequence becomes
+//! a list, a tuple a list of its fields, `None` is `nil`, and a map from
+//! strings a map with string keys.
+//!
+//! With the `derive` feature, `#[derive(ToExp, FromExp)]` converts a struct to
+//! a map from keywords naming its fields, or an association list with
+//! `#[topogi(alist)]`, so that topogi can serve as a typed configuration
+//! language.
+
+use alloc::{collections::BTreeMap, rc::Rc};
+
+use crate::{
+    ast::Exp,
+    eval::{EvalError, Result},
+    prelude::*,
+};
+
+#[cfg(feature = "derive")]
+pub use topogi_derive::{FromExp, ToExp};
+
+pub trait ToExp {
+    fn to_exp(&self) -> Exp;
+}
+
+pub trait FromExp: Sized {
+    /// Reads `exp` as `Self`. A value of the wrong kind is a
+    /// [`EvalError::TypeMismatch`] naming what was expected.
+    fn from_exp(exp: &Exp) -> Result<Self>;
+}
+
+/// The error for `exp`, which is not `expected`.
+fn mismatch(expected: &'static str, exp: &Exp) -> EvalError {
+    EvalError::TypeMismatch {
+        expected,
+        got: exp.clone(),
+        callee: None,
+        position: None,
+    }
+}
+
+/// The elements of a list or vector, of which `nil` is the empty one.
+fn elements(exp: &Exp) -> Option<&[Exp]> {
+    match exp.node() {
+        Exp::Nil => Some(&[]),
+        Exp::List(list) => Some(list),
+        Exp::Vector(vector) => Some(vector),
+        _ => None,
+    }
+}
+
+impl ToExp for Exp {
+    fn to_exp(&self) -> Exp {
+        self.clone()
+    }
+}
+
+impl FromExp for Exp {
+    fn from_exp(exp: &Exp) -> Result<Self> {
+        Ok(exp.node().clone())
+    }
+}
+
+impl ToExp for i64 {
+    fn to_exp(&self) -> Exp {
+        Exp::Integer(*self)
+    }
+}
+
+impl FromExp for i64 {
+    fn from_exp(exp: &Exp) -> Result<Self> {
+        exp.as_integer().ok_or_else(|| mismatch("an integer", exp))
+    }
+}
+
+impl ToExp for f64 {
+    fn to_exp(&self) -> Exp {
+        Exp::Float(*self)
+    }
+}
+
+impl FromExp for f64 {
+    fn from_exp(exp: &Exp) -> Result<Self> {
+        exp.as_float().ok_or_else(|| mismatch("a float", exp))
+    }
+}
+
+impl ToExp for bool {
+    fn to_exp(&self) -> Exp {
+        Exp::Bool(*self)
+    }
+}
+
+impl FromExp for bool {
+    fn from_exp(exp: &Exp) -> Result<Self> {
+        exp.as_bool().ok_or_else(|| mismatch("a boolean", exp))
+    }
+}
+
+impl ToExp for str {
+    fn to_exp(&self) -> Exp {
+        Exp::String(self.to_string())
+    }
+}
+
+impl ToExp for String {
+    fn to_exp(&self) -> Exp {
+        Exp::String(self.clone())
+    }
+}
+
+impl FromExp for String {
+    fn from_exp(exp: &Exp) -> Result<Self> {
+        exp.as_string()
+            .map(str::to_string)
+            .ok_or_else(|| mismatch("a string", exp))
+    }
+}
+
+impl<T: ToExp + ?Sized> ToExp for &T {
+    fn to_exp(&self) -> Exp {
+        (**self).to_exp()
+    }
+}
+
+impl<T: ToExp> ToExp for [T] {
+    fn to_exp(&self) -> Exp {
+        Exp::List(self.iter().map(ToExp::to_exp).collect())
+    }
+}
+
+impl<T: ToExp> ToExp for Vec<T> {
+    fn to_exp(&self) -> Exp {
+        self.as_slice().to_exp()
+    }
+}
+
+impl<T: FromExp> FromExp for Vec<T> {
+    fn from_exp(exp: &Exp) -> Result<Self> {
+        elements(exp)
+            .ok_or_else(|| mismatch("a list", exp))?
+            .iter()
+            .map(T::from_exp)
+            .collect()
+    }
+}
+
+impl<T: ToExp> ToExp for Option<T> {
+    fn to_exp(&self) -> Exp {
+        match self {
+            Some(value) => value.to_exp(),
+            None => Exp::Nil,
+        }
+    }
+}
+
+impl<T: FromExp> FromExp for Option<T> {
+    fn from_exp(exp: &Exp) -> Result<Self> {
+        match exp.node() {
+            Exp::Nil => Ok(None),
+            _ => T::from_exp(exp).map(Some),
+        }
+    }
+}
+
+/// The map with the string keys of `entries`.
+fn map_to_exp<'a, T: ToExp + 'a>(entries: impl Iterator<Item = (&'a String, &'a T)>) -> Exp {
+    Exp::Map(Rc::new(
+        entries
+            .map(|(key, value)| (key.to_exp(), value.to_exp()))
+            .collect(),
+    ))
+}
+
+/// The entries of a map whose keys are all strings.
+fn map_from_exp<T: FromExp, M: FromIterator<(String, T)>>(exp: &Exp) -> Result<M> {
+    match exp.node() {
+        Exp::Map(map) => map
+            .iter()
+            .map(|(key, value)| Ok((String::from_exp(key)?, T::from_exp(value)?)))
+            .collect(),
+        _ => Err(mismatch("a map", exp)),
+    }
+}
+
+impl<T: ToExp> ToExp for BTreeMap<String, T> {
+    fn to_exp(&self) -> Exp {
+        map_to_exp(self.iter())
+    }
+}
+
+impl<T: FromExp> FromExp for BTreeMap<String, T> {
+    fn from_exp(exp: &Exp) -> Result<Self> {
+        map_from_exp(exp)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ToExp, S> ToExp for std::collections::HashMap<String, T, S> {
+    fn to_exp(&self) -> Exp {
+        map_to_exp(self.iter())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: FromExp, S: core::hash::BuildHasher + Default> FromExp
+    for std::collections::HashMap<String, T, S>
+{
+    fn from_exp(exp: &Exp) -> Result<Self> {
+        map_from_exp(exp)
+    }
+}
+
+/// The value of a struct with the `fields`: a map from keywords, or with
+/// `alist` a list of `(:field value)` entries. Derived [`ToExp`] calls this.
+pub fn fields_to_exp(fields: &[(&str, Exp)], alist: bool) -> Exp {
+    let entries = fields
+        .iter()
+        .map(|(name, value)| (Exp::Keyword(name.to_string()), value.clone()));
+    if alist {
+        Exp::List(
+            entries
+                .map(|(key, value)| Exp::List([key, value].into()))
+                .collect(),
+        )
+    } else {
+        Exp::Map(Rc::new(entries.collect()))
+    }
+}
+
+/// The name of a field given as a keyword, symbol or string.
+fn field_name(key: &Exp) -> Option<&str> {
+    match key.node() {
+        Exp::Keyword(name) | Exp::String(name) => Some(name),
+        Exp::Symbol(name) => Some(name.as_str()),
+        _ => None,
+    }
+}
+
+/// The fields of a struct read from a map or an association list, whose keys
+/// name the fields. Derived [`FromExp`] reads through this.
+#[derive(Debug, Clone)]
+pub struct Fields<'a>(Vec<(&'a str, &'a Exp)>);
+
+impl<'a> Fields<'a> {
+    /// The entries of a map, or of a list of `(key value)` lists or
+    /// `(key . value)` pairs.
+    pub fn new(exp: &'a Exp) -> Result<Self> {
+        let entry = |key: &'a Exp, value: &'a Exp| {
+            field_name(key)
+                .map(|name| (name, value))
+                .ok_or_else(|| mismatch("a field name", key))
+        };
+        let entries: Result<_> = match exp.node() {
+            Exp::Map(map) => map.iter().map(|(key, value)| entry(key, value)).collect(),
+            exp => elements(exp)
+                .ok_or_else(|| mismatch("a map or an association list", exp))?
+                .iter()
+                .map(|item| match item.node() {
+                    Exp::List(kv) if kv.len() == 2 => entry(&kv[0], &kv[1]),
+                    Exp::Pair(key, value) => entry(key, value),
+                    _ => Err(mismatch("a field entry", item)),
+                })
+                .collect(),
+        };
+        entries.map(Fields)
+    }
+
+    /// The field `name` read as `T`. A missing field is read from `nil`, so
+    /// that an `Option` field may be left out.
+    pub fn get<T: FromExp>(&self, name: &str) -> Result<T> {
+        match self.0.iter().find(|(field, _)| *field == name) {
+            Some((_, value)) => T::from_exp(value),
+            None => T::from_exp(&Exp::Nil).map_err(|_| EvalError::MissingField(name.to_string())),
+        }
+    }
+}
+
+macro_rules! impl_tuple {
+    ($len:literal; $($t:ident $i:tt),+) => {
+        impl<$($t: ToExp),+> ToExp for ($($t,)+) {
+            fn to_exp(&self) -> Exp {
+                Exp::List([$(self.$i.to_exp()),+].into())
+            }
+        }
+
+        impl<$($t: FromExp),+> FromExp for ($($t,)+) {
+            fn from_exp(exp: &Exp) -> Result<Self> {
+                match elements(exp) {
+                    Some(elems) if elems.len() == $len => Ok(($($t::from_exp(&elems[$i])?,)+)),
+                    _ => Err(mismatch(concat!("a list of ", $len, " elements"), exp)),
+                }
+            }
+        }
+    };
+}
+
+impl_tuple!(1; A 0);
+impl_tuple!(2; A 0, B 1);
+impl_tuple!(3; A 0, B 1, C 2);
+impl_tuple!(4; A 0, B 1, C 2, D 3);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::*, interpreter::Interpreter, HashMap};
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(42i64.to_exp(), integer(42));
+        assert_eq!(i64::from_exp(&integer(42)), Ok(42));
+        assert_eq!("a".to_exp(), string("a"));
+        assert_eq!(String::from_exp(&string("a")), Ok("a".to_string()));
+        assert_eq!(
+            vec![Some(1i64), None].to_exp(),
+            list(&[integer(1), Exp::Nil])
+        );
+        assert_eq!(
+            Vec::<Option<i64>>::from_exp(&list(&[integer(1), Exp::Nil])),
+            Ok(vec![Some(1), None])
+        );
+        assert_eq!(Vec::<i64>::from_exp(&Exp::Nil), Ok(vec![]));
+        assert_eq!(
+            (1i64, true, "x").to_exp(),
+            list(&[integer(1), bool(true), string("x")])
+        );
+        assert_eq!(
+            <(i64, bool)>::from_exp(&vector(&[integer(1), bool(false)])),
+            Ok((1, false))
+        );
+
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), vec![1i64, 2]);
+        let exp = map.to_exp();
+        assert_eq!(
+            exp,
+            map_of(&[(string("a"), list(&[integer(1), integer(2)]))])
+        );
+        assert_eq!(HashMap::from_exp(&exp), Ok(map));
+    }
+
+    #[test]
+    fn test_mismatch() {
+        assert_eq!(
+            i64::from_exp(&string("1")),
+            Err(EvalError::TypeMismatch {
+                expected: "an integer",
+                got: string("1"),
+                callee: None,
+                position: None,
+            })
+        );
+        assert!(Vec::<i64>::from_exp(&list(&[integer(1), string("2")])).is_err());
+        assert!(<(i64, i64)>::from_exp(&list(&[integer(1)])).is_err());
+        assert!(BTreeMap::<String, i64>::from_exp(&map_of(&[(integer(1), integer(1))])).is_err());
+    }
+
+    #[test]
+    fn test_fields() {
+        let exp = fields_to_exp(&[("name", string("a")), ("port", integer(80))], false);
+        assert_eq!(
+            exp,
+            map_of(&[
+                (keyword("name"), string("a")),
+                (keyword("port"), integer(80))
+            ])
+        );
+        let fields = Fields::new(&exp).unwrap();
+        assert_eq!(fields.get("port"), Ok(80i64));
+        assert_eq!(fields.get::<Option<i64>>("timeout"), Ok(None));
+        assert_eq!(
+            fields.get::<i64>("timeout"),
+            Err(EvalError::MissingField("timeout".to_string()))
+        );
+
+        let alist = fields_to_exp(&[("port", integer(80))], true);
+        assert_eq!(alist, list(&[list(&[keyword("port"), integer(80)])]));
+        assert_eq!(Fields::new(&alist).unwrap().get("port"), Ok(80i64));
+        let pairs = list(&[pair(symbol("port"), integer(80))]);
+        assert_eq!(Fields::new(&pairs).unwrap().get("port"), Ok(80i64));
+        assert!(Fields::new(&integer(1)).is_err());
+    }
+
+    #[test]
+    fn test_host_fn() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_fn("sum", |args| {
+            let numbers = Vec::<i64>::from_exp(&args[0])?;
+            Ok(numbers.iter().sum::<i64>().to_exp())
+        });
+        interpreter.define("numbers", vec![1i64, 2, 3].to_exp());
+        let sum = interpreter.eval_str("(sum numbers)").unwrap();
+        assert_eq!(i64::from_exp(&sum), Ok(6));
+    }
+}
@@ -0,0 +1,451 @@
+use crate::{
+    ast::Exp,
+    buildin::NAMESPACES,
+    eval::{describe_trace, EvalError},
+    lexer::LexerError,
+    lint::{Lint, LintKind},
+    loader::{is_reserved, qualifier, LoadError},
+    parser::ParseError,
+    prelude::*,
+    token::{get_token_word, Location, Span},
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+    /// Where the error happened, such as the calls it was raised inside.
+    pub note: Option<String>,
+    pub hint: Option<String>,
+}
+
+fn token_span(loc: Location, input: &str) -> Span {
+    let word = get_token_word(loc, input);
+    Span::new(
+        loc,
+        Location::new(loc.line, loc.column + word.chars().count().max(1)),
+    )
+}
+
+impl Diagnostic {
+    pub fn new(message: &str) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.to_string(),
+            span: None,
+            note: None,
+            hint: None,
+        }
+    }
+
+    pub fn warning(message: &str) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::new(message)
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.note = Some(note.to_string());
+        self
+    }
+
+    pub fn with_hint(mut self, hint: &str) -> Self {
+        self.hint = Some(hint.to_string());
+        self
+    }
+
+    pub fn from_parse_error(error: &ParseError, input: &str) -> Self {
+        match error {
+            ParseError::LexerError(LexerError::IsNotInteger(loc)) => {
+                Diagnostic::new("invalid number literal")
+                    .with_span(token_span(*loc, input))
+                    .with_hint(
+                        "numbers are decimal with an optional point and exponent, or integers prefixed with 0x, 0o or 0b, and _ may only separate digits",
+                    )
+            }
+            ParseError::LexerError(LexerError::InvalidSymbol(loc)) => {
+                Diagnostic::new("invalid symbol")
+                    .with_span(token_span(*loc, input))
+                    .with_hint("symbols may not start with a digit")
+            }
+            ParseError::LexerError(LexerError::InvalidBytes(loc)) => {
+                Diagnostic::new("invalid byte string literal")
+                    .with_span(token_span(*loc, input))
+                    .with_hint("bytes are written as #u8(0 255) or as hex digits #x\"00ff\"")
+            }
+            ParseError::LexerError(LexerError::InvalidChar(loc)) => {
+                Diagnostic::new("invalid character literal")
+                    .with_span(token_span(*loc, input))
+                    .with_hint("characters are written as #\\a, or by name as #\\space, #\\newline, #\\tab, #\\return or #\\nul")
+            }
+            ParseError::LexerError(LexerError::Eot(loc)) => {
+                Diagnostic::new("unexpected end of input")
+                    .with_span(Span::new(*loc, Location::new(loc.line, loc.column + 1)))
+                    .with_hint("is a closing parenthesis missing?")
+            }
+            ParseError::UnmatchedParen(token) => Diagnostic::new("unexpected parentheses")
+                .with_span(token_span(token.loc, input))
+                .with_hint("parentheses must be balanced"),
+            ParseError::ExpectedSymbol(token) => {
+                Diagnostic::new("expected symbol").with_span(token_span(token.loc, input))
+            }
+            ParseError::TrailingInput(token) => Diagnostic::new("unexpected input")
+                .with_span(token_span(token.loc, input))
+                .with_hint("only a single expression is expected here"),
+            ParseError::InvalidInterpolation(token) => Diagnostic::new("invalid interpolation")
+                .with_span(token_span(token.loc, input))
+                .with_hint("each ${...} in a string must hold exactly one expression"),
+            ParseError::OddMapLiteral(token) => Diagnostic::new("key without a value")
+                .with_span(token_span(token.loc, input))
+                .with_hint("a map literal holds keys and values in turn, as in {:a 1 :b 2}"),
+            ParseError::UnexpectedDot(token) => Diagnostic::new("unexpected dot")
+                .with_span(token_span(token.loc, input))
+                .with_hint("a dot may only precede the tail of a pair or the rest parameter of a lambda"),
+        }
+    }
+
+    pub fn from_load_error(error: &LoadError, input: &str) -> Self {
+        match error {
+            LoadError::DuplicateDefinition(name) => {
+                Diagnostic::new(&format!("duplicate definition of {}", name))
+                    .with_hint("each name may only be defined once per module")
+            }
+            LoadError::ParseError(err) => Diagnostic::from_parse_error(err, input),
+            LoadError::EvalError(err) => Diagnostic::from_eval_error(err),
+            LoadError::UnknownExport(name) => {
+                Diagnostic::new(&format!("export of undefined {}", name))
+                    .with_hint("a module may only export names it defines")
+            }
+            LoadError::ModuleNotFound(name) => {
+                Diagnostic::new(&format!("module {} not found", name))
+                    .with_hint("check the name in the import against the module declaration")
+            }
+            LoadError::Unreadable(path, reason) => {
+                Diagnostic::new(&format!("cannot read {}: {}", path, reason))
+            }
+            // The span of the error is in the source of the imported module,
+            // so it is given as a note rather than rendered in `input`.
+            LoadError::Imported {
+                path,
+                source,
+                error,
+            } => {
+                let diagnostic = Diagnostic::from_load_error(error, source);
+                let note = match diagnostic.span {
+                    Some(span) => format!(
+                        "in {}:{}:{}",
+                        path,
+                        span.start.line + 1,
+                        span.start.column + 1
+                    ),
+                    None => format!("in {}", path),
+                };
+                Diagnostic {
+                    span: None,
+                    note: Some(note),
+                    ..diagnostic
+                }
+            }
+            LoadError::ImportCycle(name) => {
+                Diagnostic::new(&format!("module {} imports itself", name))
+                    .with_hint("move the definitions both modules need into a module of their own")
+            }
+            LoadError::ReservedName(name) => {
+                Diagnostic::new(&format!("{} is reserved by the standard library", name)).with_hint(
+                    &format!(
+                        "the namespaces {} belong to the standard library",
+                        NAMESPACES.join(", ")
+                    ),
+                )
+            }
+        }
+    }
+
+    pub fn from_eval_error(error: &EvalError) -> Self {
+        let diagnostic = Diagnostic::new(&error.kind().to_string());
+        let diagnostic = match error.kind() {
+            // The message is the program's own, so the data goes below it.
+            EvalError::UserError { message, data } if !data.is_empty() => {
+                Diagnostic::new(message).with_hint(&format!(
+                    "data: {}",
+                    data.iter()
+                        .map(Exp::to_readable_string)
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ))
+            }
+            EvalError::ArityMismatch {
+                callee: Some(callee),
+                ..
+            } => diagnostic.with_hint(&format!(
+                "check the call against the parameters of {}",
+                callee
+            )),
+            EvalError::ArityMismatch { .. } => {
+                diagnostic.with_hint("check the number of arguments in the call")
+            }
+            EvalError::TypeMismatch { .. } => {
+                diagnostic.with_hint("check the types of the arguments")
+            }
+            EvalError::IndexOutOfBounds { .. } => {
+                diagnostic.with_hint("indexes start at 0 and must be less than the length")
+            }
+            EvalError::DivideByZero(_) => diagnostic.with_hint("the divisor evaluated to 0"),
+            EvalError::IntegerOverflow(_) => {
+                diagnostic.with_hint("the result does not fit a 64-bit integer")
+            }
+            EvalError::SymbolNotFound(sym) if is_reserved(sym) => {
+                diagnostic.with_hint(&format!(
+                    "the standard library has no `{}`",
+                    sym
+                ))
+            }
+            EvalError::SymbolNotFound(sym) if qualifier(sym).is_some() => diagnostic.with_hint(&format!(
+                "`{}` names a definition of another module, which must be imported and export it",
+                sym
+            )),
+            EvalError::SymbolNotFound(sym) => diagnostic.with_hint(&format!(
+                "`{}` is not defined in this module or the standard library",
+                sym
+            )),
+            EvalError::ExpectedBool(_) => {
+                diagnostic.with_hint("conditions must evaluate to true or false")
+            }
+            EvalError::FailedToApply(..) => {
+                diagnostic.with_hint("only lambdas and builtins can be applied")
+            }
+            EvalError::NoMatchingCase(_) => {
+                diagnostic.with_hint("add an arm for this value or an `else` arm to the case")
+            }
+            EvalError::ExpansionTooDeep(_) => {
+                diagnostic.with_hint("a macro keeps expanding into another macro call")
+            }
+            EvalError::DepthLimitExceeded => diagnostic.with_hint(
+                "a function may be recursing without end outside tail position",
+            ),
+            EvalError::FuelExhausted => diagnostic
+                .with_hint("the program may loop forever, or need more steps than it was given"),
+            EvalError::MemoryLimitExceeded => {
+                diagnostic.with_hint("the program keeps building larger values")
+            }
+            EvalError::Cancelled => {
+                diagnostic.with_hint("the host stopped the evaluation or its deadline passed")
+            }
+            EvalError::MissingField(_) => {
+                diagnostic.with_hint("add an entry for the field, or make it an Option")
+            }
+            EvalError::Incomparable(..) => diagnostic.with_hint(
+                "numbers compare with numbers, strings with strings and characters with characters, use sort-by for other values",
+            ),
+            EvalError::OutOfRange(..) => {
+                diagnostic.with_hint("a range must satisfy 0 <= start <= end <= length")
+            }
+            EvalError::ReadError(input, err) => {
+                match Diagnostic::from_parse_error(err, input).hint {
+                    Some(hint) => diagnostic.with_hint(&hint),
+                    None => diagnostic,
+                }
+            }
+            EvalError::Raised(_) => {
+                diagnostic.with_hint("handle it with (try ... (catch e ...))")
+            }
+            EvalError::UnquoteOutsideQuote(_) => {
+                diagnostic.with_hint("~ may only be used inside a quoted expression")
+            }
+            _ => diagnostic,
+        };
+        let diagnostic = match error.trace() {
+            [] => diagnostic,
+            trace => diagnostic.with_note(&describe_trace(trace)),
+        };
+        match error.span() {
+            Some(span) => diagnostic.with_span(span),
+            None => diagnostic,
+        }
+    }
+
+    pub fn from_lint(lint: &Lint) -> Self {
+        let diagnostic = Diagnostic::warning(&lint.kind.to_string());
+        let diagnostic = match &lint.kind {
+            LintKind::UnusedBinding(_) => diagnostic
+                .with_hint("remove the binding, or prefix its name with _ to keep it unused"),
+            LintKind::ShadowedParameter(_) => {
+                diagnostic.with_hint("the parameter cannot be referred to inside this binding")
+            }
+            LintKind::DuplicatePattern(_) => {
+                diagnostic.with_hint("an earlier arm already matches this pattern")
+            }
+            LintKind::UnknownSymbol(name) => diagnostic.with_hint(&format!(
+                "`{}` is not defined in this module or the standard library",
+                name
+            )),
+        };
+        match lint.span {
+            Some(span) => diagnostic.with_span(span),
+            None => diagnostic,
+        }
+    }
+
+    /// Renders the diagnostic with the offending line of `input` and a caret
+    /// underline. `name` identifies the input, usually a file path.
+    pub fn render(&self, name: &str, input: &str) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let mut out = format!("{}: {}\n", severity, self.message);
+        if let Some(span) = self.span {
+            let line = input.split('\n').nth(span.start.line).unwrap_or("");
+            let number = (span.start.line + 1).to_string();
+            let gutter = " ".repeat(number.len());
+            let width = if span.end.line == span.start.line {
+                span.end.column.saturating_sub(span.start.column)
+            } else {
+                line.chars().count().saturating_sub(span.start.column)
+            };
+            out += &format!(
+                "{}--> {}:{}:{}\n",
+                gutter,
+                name,
+                span.start.line + 1,
+                span.start.column + 1
+            );
+            out += &format!("{} |\n", gutter);
+            out += &format!("{} | {}\n", number, line);
+            out += &format!(
+                "{} | {}{}\n",
+                gutter,
+                " ".repeat(span.start.column),
+                "^".repeat(width.max(1))
+            );
+        }
+        let gutter = match self.span {
+            Some(span) => " ".repeat((span.start.line + 1).to_string().len()),
+            None => String::new(),
+        };
+        if let Some(note) = &self.note {
+            out += &format!("{} = note: {}\n", gutter, note);
+        }
+        if let Some(hint) = &self.hint {
+            out += &format!("{} = hint: {}\n", gutter, hint);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::eval_str, lint::lint_source, loader::load_module, parser::parse_exp};
+
+    #[test]
+    fn test_render_eval_error() {
+        let source = "(+ 1\n   (first 2))";
+        let err = eval_str(source).unwrap_err();
+        assert_eq!(
+            Diagnostic::from_eval_error(&err).render("main.tg", source),
+            "error: list/first expects a non-empty list or a pair as argument 1, got 2
+ --> main.tg:2:4
+  |
+2 |    (first 2))
+  |    ^^^^^^^^^
+  = hint: check the types of the arguments
+"
+        );
+    }
+
+    #[test]
+    fn test_render_user_error() {
+        let source = r#"(error "no such user" "alice" 42)"#;
+        let err = eval_str(source).unwrap_err();
+        assert_eq!(
+            Diagnostic::from_eval_error(&err).render("main.tg", source),
+            r#"error: no such user
+ --> main.tg:1:1
+  |
+1 | (error "no such user" "alice" 42)
+  | ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+  = hint: data: "alice" 42
+"#
+        );
+    }
+
+    #[test]
+    fn test_render_trace() {
+        let source = "(module main
+  (define f (x) (first x))
+  (define g (xs) (map f xs))
+  (define main () (length (g '((1) 2)))))";
+        let err = load_module(source)
+            .unwrap()
+            .run("main", vec![])
+            .unwrap_err();
+        assert_eq!(
+            Diagnostic::from_eval_error(&err).render("main.tg", source),
+            "error: list/first expects a non-empty list or a pair as argument 1, got 2
+ --> main.tg:2:17
+  |
+2 |   (define f (x) (first x))
+  |                 ^^^^^^^^^
+  = note: in f, called from g, called from main
+  = hint: check the types of the arguments
+"
+        );
+    }
+
+    #[test]
+    fn test_render_parse_error() {
+        let source = "(+ 1 2) 3";
+        let err = parse_exp(source).unwrap_err();
+        assert_eq!(
+            Diagnostic::from_parse_error(&err, source).render("<repl>", source),
+            "error: unexpected input
+ --> <repl>:1:9
+  |
+1 | (+ 1 2) 3
+  |         ^
+  = hint: only a single expression is expected here
+"
+        );
+    }
+
+    #[test]
+    fn test_render_without_span() {
+        let err = LoadError::DuplicateDefinition("x".to_string());
+        assert_eq!(
+            Diagnostic::from_load_error(&err, "").render("main.tg", ""),
+            "error: duplicate definition of x
+ = hint: each name may only be defined once per module
+"
+        );
+    }
+
+    #[test]
+    fn test_render_lint() {
+        let source = "(module main\n  (define main () (frobnicate 1)))";
+        let lints = lint_source(source).unwrap();
+        assert_eq!(
+            Diagnostic::from_lint(&lints[0]).render("main.tg", source),
+            "warning: call to unknown symbol frobnicate
+ --> main.tg:2:20
+  |
+2 |   (define main () (frobnicate 1)))
+  |                    ^^^^^^^^^^
+  = hint: `frobnicate` is not defined in this module or the standard library
+"
+        );
+    }
+}
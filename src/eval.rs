@@ -1,64 +1,705 @@
-use crate::{ast::*, buildin::default_module};
+use alloc::{rc::Rc, sync::Arc};
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use crate::{
+    ast::*, buildin::default_module, diagnostics::Diagnostic, parser::ParseError, prelude::*,
+    symbol::SymbolId, token::Span,
+};
+
+/// How many arguments a function takes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+    /// Between the bounds, inclusive.
+    Between(usize, usize),
+}
+
+impl core::fmt::Display for Arity {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Arity::Exactly(n) => write!(f, "{}", n),
+            Arity::AtLeast(n) => write!(f, "at least {}", n),
+            Arity::Between(min, max) => write!(f, "{} to {}", min, max),
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum EvalError {
     IsNotNumber(Exp),
-    InvalidArgs(Vec<Exp>),
+    /// A function was called with the wrong number of arguments. The callee is
+    /// known if it was called by name.
+    ArityMismatch {
+        expected: Arity,
+        got: usize,
+        callee: Option<String>,
+    },
+    /// An argument is not of the kind the function takes, which is described
+    /// with an article, as in "an integer". The position counts from 1.
+    TypeMismatch {
+        expected: &'static str,
+        got: Exp,
+        callee: Option<String>,
+        position: Option<usize>,
+    },
+    /// An index outside `0..len`.
+    IndexOutOfBounds {
+        index: i64,
+        len: usize,
+    },
     DivideByZero(Exp),
+    /// The result of an integer operation on the operands does not fit an
+    /// `i64`.
+    IntegerOverflow(Exp),
+    /// A bit shift by an amount outside `0..64`.
+    InvalidShift(i64),
     SymbolNotFound(String),
     Unexpected(Exp),
     ExpectedBool(Exp),
     ExpectedLambda(Exp),
     FailedToApply(Exp, Exp),
     NeverMatched(Exp),
+    /// No arm of a `case` matched the value.
+    NoMatchingCase(Exp),
     UnquoteOutsideQuote(Exp),
+    /// A list was expected, such as the value spliced by `~@`.
+    ExpectedList(Exp),
+    /// A macro call kept expanding into further macro calls, see
+    /// [`MAX_EXPANSION_DEPTH`].
+    ExpansionTooDeep(Exp),
+    /// Two values have no ordering, such as a number and a string passed to
+    /// `sort`.
+    Incomparable(Exp, Exp),
+    /// The range `start..end` is not within a sequence of the given length.
+    OutOfRange(i64, i64, usize),
+    /// The string passed to `read` could not be parsed.
+    ReadError(String, ParseError),
+    /// A value passed to `raise` and not caught by a `try`.
+    Raised(Exp),
+    /// An error signalled by `(error message data ...)`.
+    UserError {
+        message: String,
+        data: Vec<Exp>,
+    },
+    /// The test of the `assert` call is false.
+    AssertionFailed(Exp),
+    /// The values passed to the `assert-eq` call differ: the call, the
+    /// expected value and the actual one, boxed to keep errors small.
+    AssertEqFailed(Box<(Exp, Exp, Exp)>),
+    /// An error raised while evaluating the expression parsed from `Span`.
+    Located(Span, Box<EvalError>),
+    /// The evaluation nested deeper than the limit, see [`EvalConfig`].
+    DepthLimitExceeded,
+    /// The evaluation took all the steps it was allowed, see [`EvalConfig`].
+    /// A `try` cannot recover from it, since its handler has no fuel left.
+    FuelExhausted,
+    /// The values created by the evaluation took more memory than it was
+    /// allowed, see [`EvalConfig`].
+    MemoryLimitExceeded,
+    /// The host cancelled the evaluation or its deadline passed, see
+    /// [`EvalConfig`].
+    Cancelled,
+    /// A value read as a Rust struct has no entry for the named field, see
+    /// [`Fields`](crate::convert::Fields).
+    MissingField(String),
+    /// The error, raised inside calls of the named functions, innermost first.
+    Traced(Vec<SymbolId>, Box<EvalError>),
 }
 
-pub type Result<T> = std::result::Result<T, EvalError>;
+impl EvalError {
+    /// Attaches `span` unless the error already knows a more precise location.
+    pub fn located(self, span: Span) -> Self {
+        match self {
+            EvalError::Located(..) => self,
+            EvalError::Traced(trace, err) => EvalError::Traced(trace, Box::new(err.located(span))),
+            err => EvalError::Located(span, Box::new(err)),
+        }
+    }
 
-fn is_value(e: &Exp) -> bool {
-    matches!(
-        e,
-        Exp::Integer(_)
-            | Exp::Bool(_)
-            | Exp::Nil
-            | Exp::String(_)
-            | Exp::Quote(_)
-            | Exp::Symbol(_)
-            | Exp::Lambda(..)
-    )
+    /// Names the callee of an arity mismatch that does not know it yet.
+    pub(crate) fn with_callee(self, name: Option<SymbolId>) -> Self {
+        match (self, name) {
+            (
+                EvalError::ArityMismatch {
+                    expected,
+                    got,
+                    callee: None,
+                },
+                Some(name),
+            ) => EvalError::ArityMismatch {
+                expected,
+                got,
+                callee: Some(name.to_string()),
+            },
+            (err, _) => err,
+        }
+    }
+
+    /// Records that the error was raised inside a call of `name`, outside the
+    /// calls recorded so far.
+    pub fn called_from(self, name: SymbolId) -> Self {
+        match self {
+            EvalError::Traced(mut trace, err) => {
+                trace.push(name);
+                EvalError::Traced(trace, err)
+            }
+            err => EvalError::Traced(vec![name], Box::new(err)),
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            EvalError::Located(span, _) => Some(*span),
+            EvalError::Traced(_, err) => err.span(),
+            _ => None,
+        }
+    }
+
+    /// The functions whose calls the error was raised inside, innermost first.
+    /// Tail calls replace their caller, see [`Frame::Call`].
+    pub fn trace(&self) -> &[SymbolId] {
+        match self {
+            EvalError::Traced(trace, _) => trace,
+            _ => &[],
+        }
+    }
+
+    /// Returns the error without its location.
+    pub fn kind(&self) -> &EvalError {
+        match self {
+            EvalError::Located(_, err) | EvalError::Traced(_, err) => err.kind(),
+            err => err,
+        }
+    }
+
+    /// The value a `catch` binds for the error: the value passed to `raise`,
+    /// a map `{:message message :data (data ...)}` for `error`, or the message
+    /// of any other error.
+    pub fn to_exp(&self) -> Exp {
+        match self.kind() {
+            EvalError::Raised(exp) => exp.clone(),
+            EvalError::UserError { message, data } => map_of(&[
+                (keyword("message"), Exp::String(message.clone())),
+                (keyword("data"), list(data)),
+            ]),
+            err => Exp::String(err.to_string()),
+        }
+    }
+}
+
+/// Values longer than this are shown in error messages on lines of their own.
+const ERROR_WIDTH: usize = 60;
+
+/// A value as shown in an error message: readable and on the same line if it
+/// is short, and pretty printed on indented lines of its own otherwise.
+fn show(exp: &Exp) -> String {
+    let readable = exp.to_readable_string();
+    if readable.chars().count() <= ERROR_WIDTH {
+        return readable;
+    }
+    let lines = exp
+        .pretty(ERROR_WIDTH)
+        .lines()
+        .map(|line| format!("    {}", line))
+        .collect::<Vec<_>>();
+    format!("\n{}\n", lines.join("\n"))
+}
+
+/// `1 argument`, `at least 2 arguments`.
+fn plural(arity: Arity, noun: &str) -> String {
+    match arity {
+        Arity::Exactly(1) => format!("1 {}", noun),
+        arity => format!("{} {}s", arity, noun),
+    }
+}
+
+/// `in f, called from g, called from main`. Repeated calls, as in a recursion
+/// that ran too deep, are counted rather than listed: `called from f (3 times)`.
+pub(crate) fn describe_trace(trace: &[SymbolId]) -> String {
+    let (first, mut names) = match trace.split_first() {
+        Some((name, names)) => (name, names),
+        None => return String::new(),
+    };
+    let mut out = format!("in {}", first);
+    while let Some(name) = names.first() {
+        let times = names.iter().take_while(|next| *next == name).count();
+        out += &match times {
+            1 => format!(", called from {}", name),
+            n => format!(", called from {} ({} times)", name, n),
+        };
+        names = &names[times..];
+    }
+    out
+}
+
+impl core::fmt::Display for EvalError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EvalError::IsNotNumber(exp) => write!(f, "{} is not a number", show(exp)),
+            EvalError::ArityMismatch {
+                expected,
+                got,
+                callee: Some(callee),
+            } => write!(
+                f,
+                "{} takes {} but was given {}",
+                callee,
+                plural(*expected, "argument"),
+                got
+            ),
+            EvalError::ArityMismatch {
+                expected,
+                got,
+                callee: None,
+            } => write!(
+                f,
+                "expected {} but was given {}",
+                plural(*expected, "argument"),
+                got
+            ),
+            EvalError::TypeMismatch {
+                expected,
+                got,
+                callee,
+                position,
+            } => {
+                match callee {
+                    Some(callee) => write!(f, "{} expects {}", callee, expected)?,
+                    None => write!(f, "expected {}", expected)?,
+                }
+                if let Some(position) = position {
+                    write!(f, " as argument {}", position)?;
+                }
+                write!(f, ", got {}", show(got))
+            }
+            EvalError::IndexOutOfBounds { index, len } => {
+                write!(f, "index {} is out of bounds for length {}", index, len)
+            }
+            EvalError::DivideByZero(exp) => write!(f, "division by zero: {}", show(exp)),
+            EvalError::IntegerOverflow(exp) => write!(f, "integer overflow: {}", show(exp)),
+            EvalError::InvalidShift(amount) => write!(f, "invalid shift amount: {}", amount),
+            EvalError::SymbolNotFound(sym) => write!(f, "symbol not found: {}", sym),
+            EvalError::Unexpected(exp) => write!(f, "unexpected expression: {}", show(exp)),
+            EvalError::ExpectedBool(exp) => write!(f, "expected a boolean: {}", show(exp)),
+            EvalError::ExpectedLambda(exp) => write!(f, "expected a lambda: {}", show(exp)),
+            EvalError::FailedToApply(e1, e2) => {
+                write!(f, "cannot apply {} to {}", show(e1), show(e2))
+            }
+            EvalError::NeverMatched(exp) => write!(f, "never matched {}", show(exp)),
+            EvalError::NoMatchingCase(exp) => write!(f, "no case matched {}", show(exp)),
+            EvalError::UnquoteOutsideQuote(exp) => {
+                write!(f, "unquote outside quote: ~{}", show(exp))
+            }
+            EvalError::ExpectedList(exp) => write!(f, "expected a list: {}", show(exp)),
+            EvalError::ExpansionTooDeep(exp) => {
+                write!(f, "macro expansion too deep: {}", show(exp))
+            }
+            EvalError::Incomparable(e1, e2) => {
+                write!(f, "cannot compare {} with {}", show(e1), show(e2))
+            }
+            EvalError::OutOfRange(start, end, len) => write!(
+                f,
+                "range {}..{} is out of bounds for length {}",
+                start, end, len
+            ),
+            EvalError::ReadError(input, err) => write!(
+                f,
+                "cannot read {:?}: {}",
+                input,
+                Diagnostic::from_parse_error(err, input).message
+            ),
+            EvalError::Raised(exp) => write!(f, "uncaught exception: {}", show(exp)),
+            EvalError::UserError { message, data } if data.is_empty() => write!(f, "{}", message),
+            EvalError::UserError { message, data } => write!(
+                f,
+                "{}: {}",
+                message,
+                data.iter().map(show).collect::<Vec<_>>().join(" ")
+            ),
+            EvalError::AssertionFailed(call) => write!(f, "assertion failed: {}", show(call)),
+            EvalError::AssertEqFailed(failed) => write!(
+                f,
+                "assertion failed: {}: expected {}, got {}",
+                show(&failed.0),
+                show(&failed.1),
+                show(&failed.2)
+            ),
+            EvalError::DepthLimitExceeded => write!(f, "evaluation nested too deeply"),
+            EvalError::FuelExhausted => write!(f, "evaluation ran out of fuel"),
+            EvalError::MemoryLimitExceeded => write!(f, "evaluation ran out of memory"),
+            EvalError::Cancelled => write!(f, "evaluation was cancelled"),
+            EvalError::MissingField(name) => write!(f, "missing field :{}", name),
+            EvalError::Traced(trace, err) => write!(f, "{} ({})", err, describe_trace(trace)),
+            EvalError::Located(span, err) => write!(
+                f,
+                "{}:{}: {}",
+                span.start.line + 1,
+                span.start.column + 1,
+                err
+            ),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, EvalError>;
+
+/// A handle through which the host, possibly from another thread, stops an
+/// evaluation. The evaluation fails with [`EvalError::Cancelled`] within a few
+/// steps, see [`CANCEL_CHECK_INTERVAL`].
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Tokens are equal if they cancel the same evaluations.
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancelToken {}
+
+/// A sink for the output of an evaluation, which the host keeps a handle to
+/// in order to read what was written.
+#[derive(Clone)]
+pub struct Output(pub Rc<RefCell<dyn core::fmt::Write>>);
+
+impl core::fmt::Debug for Output {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Output({:p})", Rc::as_ptr(&self.0))
+    }
+}
+
+/// Outputs are equal if they write to the same sink.
+impl PartialEq for Output {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Output {}
+
+/// The number of steps between checks of the [`CancelToken`] and the deadline
+/// of an evaluation.
+pub const CANCEL_CHECK_INTERVAL: u64 = 1024;
+
+/// Limits that keep a runaway or untrusted program from exhausting the host:
+/// an evaluation that exceeds one fails with [`EvalError::DepthLimitExceeded`],
+/// [`EvalError::FuelExhausted`] or [`EvalError::MemoryLimitExceeded`], and
+/// one that is cancelled or runs past its deadline with
+/// [`EvalError::Cancelled`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalConfig {
+    /// The most frames that may wait for a value at once, which bounds how
+    /// deeply calls that are not in tail position nest.
+    pub max_depth: usize,
+    /// The most evaluations that builtins such as `map` may nest by calling
+    /// back into the evaluator. Each one takes space on the host stack.
+    pub max_nesting: usize,
+    /// The most steps the evaluation may take, each evaluating one
+    /// subexpression or running one instruction of the [`Vm`](crate::vm::Vm),
+    /// or `None` for no limit. The evaluations that builtins nest draw on the
+    /// same fuel.
+    pub fuel: Option<u64>,
+    /// The most bytes that builtins may allocate for the values they return,
+    /// or `None` for no limit. The size of a value is approximated from the
+    /// length of its lists, strings and maps, not counting the values shared
+    /// with its elements. Values are counted when they are created and never
+    /// given back, so this bounds the memory of a short evaluation rather
+    /// than what is live at any one time. The names that become symbols, as
+    /// with `gensym`, `string/to-symbol` or `read`, are never freed and are
+    /// counted the first time they appear.
+    pub max_memory: Option<usize>,
+    /// Cancels the evaluation when the host calls [`CancelToken::cancel`].
+    pub cancel: Option<CancelToken>,
+    /// The time after which the evaluation is cancelled.
+    #[cfg(feature = "std")]
+    pub deadline: Option<Instant>,
+    /// Where `print` and the other output builtins write, or `None` for
+    /// standard output.
+    pub output: Option<Output>,
 }
 
+impl Default for EvalConfig {
+    fn default() -> Self {
+        EvalConfig {
+            max_depth: 1_000_000,
+            max_nesting: 100,
+            fuel: None,
+            max_memory: None,
+            cancel: None,
+            #[cfg(feature = "std")]
+            deadline: None,
+            output: None,
+        }
+    }
+}
+
+impl EvalConfig {
+    /// Whether the host has cancelled the evaluation or its deadline passed.
+    fn is_cancelled(&self) -> bool {
+        let cancelled = self.cancel.as_ref().is_some_and(CancelToken::is_cancelled);
+        #[cfg(feature = "std")]
+        let cancelled = cancelled
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline);
+        cancelled
+    }
+}
+
+/// Generates fresh variable names. It is passed through every step of an
+/// evaluation, so it also carries the [`EvalConfig`] and how deep the
+/// evaluation is, which builtins hand on when they call back into the
+/// evaluator.
+#[derive(Default)]
 pub struct VariableGenerator {
     counter: u64,
+    config: EvalConfig,
+    /// The frames pending in the evaluations that the current one is nested
+    /// in, and the number of those evaluations.
+    depth: usize,
+    nesting: usize,
+    /// The steps left to take, if the fuel is limited.
+    fuel: Option<u64>,
+    /// The bytes allocated so far, see [`EvalConfig::max_memory`].
+    allocated: usize,
+    /// The steps taken so far, and whether the evaluation has been found to
+    /// be cancelled.
+    steps: u64,
+    cancelled: bool,
 }
 
 impl VariableGenerator {
     pub fn new() -> Self {
-        Self { counter: 0 }
+        Self::default()
+    }
+
+    pub fn with_config(config: EvalConfig) -> Self {
+        Self {
+            fuel: config.fuel,
+            config,
+            ..Self::default()
+        }
+    }
+
+    pub fn config(&self) -> &EvalConfig {
+        &self.config
+    }
+
+    /// The steps left to take, or `None` if the fuel is not limited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// The bytes that builtins have allocated so far, see
+    /// [`EvalConfig::max_memory`].
+    pub fn allocated(&self) -> usize {
+        self.allocated
+    }
+
+    /// Counts the memory of a value a builtin created, which fails once it
+    /// exceeds the limit.
+    fn allocate(&mut self, value: &Exp) -> Result<()> {
+        self.charge(allocated_size(value))
+    }
+
+    /// Counts `bytes` of memory, which fails once it exceeds the limit.
+    pub(crate) fn charge(&mut self, bytes: usize) -> Result<()> {
+        self.allocated = self.allocated.saturating_add(bytes);
+        match self.config.max_memory {
+            Some(max) if self.allocated > max => Err(EvalError::MemoryLimitExceeded),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if allocating `bytes` more would exceed the limit, without
+    /// counting them: the value is counted once the builtin returns it.
+    fn reserve(&self, bytes: usize) -> Result<()> {
+        match self.config.max_memory {
+            Some(max) if self.allocated.saturating_add(bytes) > max => {
+                Err(EvalError::MemoryLimitExceeded)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Takes a step, which fails once the fuel has run out or the evaluation
+    /// has been cancelled. A cancelled evaluation stays cancelled, so that a
+    /// `try` cannot carry on with it.
+    pub(crate) fn step(&mut self) -> Result<()> {
+        match &mut self.fuel {
+            Some(0) => return Err(EvalError::FuelExhausted),
+            Some(fuel) => *fuel -= 1,
+            None => {}
+        }
+        self.steps += 1;
+        if self.steps.is_multiple_of(CANCEL_CHECK_INTERVAL) {
+            self.cancelled |= self.config.is_cancelled();
+        }
+        if self.cancelled {
+            return Err(EvalError::Cancelled);
+        }
+        Ok(())
     }
 
-    pub fn gen_var(&mut self) -> String {
+    /// A fresh variable. Its name stays interned, so it is counted against
+    /// the memory limit, which the next builtin checks.
+    pub fn gen_var(&mut self) -> SymbolId {
         let var = format!("#{}", self.counter);
         self.counter += 1;
-        var
+        let (id, bytes) = SymbolId::intern_counted(&var);
+        self.allocated = self.allocated.saturating_add(bytes);
+        id
+    }
+}
+
+/// The bytes allocated for `value` itself, leaving out the elements it shares
+/// with other values.
+fn allocated_size(value: &Exp) -> usize {
+    let exp = core::mem::size_of::<Exp>();
+    match value.node() {
+        Exp::List(es) | Exp::Values(es) => es.len() * exp,
+        Exp::Vector(es) => es.len() * exp,
+        Exp::Map(map) => map.len() * 2 * exp,
+        Exp::Set(set) => set.len() * exp,
+        Exp::Pair(..) => 2 * exp,
+        Exp::String(s) => s.len(),
+        Exp::Bytes(bytes) => bytes.len(),
+        _ => 0,
+    }
+}
+
+/// A builtin, called with its evaluated arguments.
+pub type BuiltinFn = fn(&[Exp], &mut EvalContext) -> Result<Exp>;
+
+/// What a builtin is called with besides its arguments: the module and the
+/// generator of the evaluation that calls it, and through the generator the
+/// config and the output.
+pub struct EvalContext<'a> {
+    pub module: &'a Module,
+    pub gen: &'a mut VariableGenerator,
+}
+
+impl<'a> EvalContext<'a> {
+    pub fn new(module: &'a Module, gen: &'a mut VariableGenerator) -> Self {
+        EvalContext { module, gen }
+    }
+
+    pub fn config(&self) -> &EvalConfig {
+        self.gen.config()
+    }
+
+    /// Checks that the value a builtin is about to build, of about `bytes`
+    /// bytes, fits the [`EvalConfig::max_memory`], so that a builtin whose
+    /// arguments ask for too large a value fails before it allocates it.
+    /// Sizes that overflow can be given as `usize::MAX`.
+    pub fn reserve(&self, bytes: usize) -> Result<()> {
+        self.gen.reserve(bytes)
+    }
+
+    /// Calls the function `f` with `args`, nesting an evaluation, see [`call`].
+    pub fn call(&mut self, f: Exp, args: &[Exp]) -> Result<Exp> {
+        call(f, args, self.module, self.gen)
+    }
+
+    /// Writes `s` to the [`EvalConfig::output`], or to standard output if the
+    /// host gave none. Without the `std` feature there is no standard output
+    /// and `s` is dropped.
+    pub fn write_output(&mut self, s: &str) {
+        match &self.gen.config.output {
+            // Like `print!`, output that cannot be written is not an error
+            // of the program.
+            Some(output) => {
+                let _ = output.0.borrow_mut().write_str(s);
+            }
+            #[cfg(feature = "std")]
+            None => print!("{}", s),
+            #[cfg(not(feature = "std"))]
+            None => {}
+        }
     }
 }
 
+/// Calls the builtin `f`, counting the value it returns against the memory
+/// limit.
+pub(crate) fn call_builtin(
+    f: BuiltinFn,
+    args: &[Exp],
+    module: &Module,
+    gen: &mut VariableGenerator,
+) -> Result<Exp> {
+    let value = f(args, &mut EvalContext::new(module, gen))?;
+    gen.allocate(&value)?;
+    Ok(value)
+}
+
+/// Calls the host function `f`, which is charged for the memory of its value
+/// like a builtin.
+pub(crate) fn call_host(f: &HostFn, args: &[Exp], gen: &mut VariableGenerator) -> Result<Exp> {
+    let value = (f.0)(args)?;
+    gen.allocate(&value)?;
+    Ok(value)
+}
+
 // [e2/x]e1
-fn subst(e2: Exp, x: String, e1: Exp, gen: &mut VariableGenerator) -> Exp {
+fn subst(e2: Exp, x: SymbolId, e1: Exp, gen: &mut VariableGenerator) -> Exp {
     match e1 {
-        Exp::Nil | Exp::Integer(_) | Exp::Bool(_) | Exp::String(_) | Exp::BuildIn(_) => e1,
+        Exp::Nil
+        | Exp::Void
+        | Exp::Integer(_)
+        | Exp::Float(_)
+        | Exp::Rational(..)
+        | Exp::Bool(_)
+        | Exp::String(_)
+        | Exp::Values(_)
+        | Exp::Map(_)
+        | Exp::Set(_)
+        | Exp::Vector(_)
+        | Exp::Bytes(_)
+        | Exp::Char(_)
+        | Exp::Keyword(_)
+        | Exp::Pair(..)
+        | Exp::BuildIn(_)
+        | Exp::HostFn(_)
+        | Exp::Closure(..) => e1,
+        #[cfg(feature = "bignum")]
+        Exp::BigInt(_) => e1,
         Exp::Lambda(y, e) => {
             let yy = gen.gen_var();
             Exp::Lambda(
-                yy.clone(),
-                Box::new(subst(e2, x, subst(Exp::Symbol(yy), y, *e, gen), gen)),
+                yy,
+                Rc::new(subst(
+                    e2,
+                    x,
+                    subst(Exp::Symbol(yy), y, Rc::unwrap_or_clone(e), gen),
+                    gen,
+                )),
             )
         }
+        Exp::Variadic(f) => Exp::Variadic(Rc::new(subst(e2, x, Rc::unwrap_or_clone(f), gen))),
         Exp::Apply(e11, e12) => Exp::Apply(
-            Box::new(subst(e2.clone(), x.clone(), *e11, gen)),
-            Box::new(subst(e2, x, *e12, gen)),
+            Rc::new(subst(e2.clone(), x, Rc::unwrap_or_clone(e11), gen)),
+            Rc::new(subst(e2, x, Rc::unwrap_or_clone(e12), gen)),
         ),
         Exp::Symbol(sym) => {
             if sym == x {
@@ -68,236 +709,1308 @@ fn subst(e2: Exp, x: String, e1: Exp, gen: &mut VariableGenerator) -> Exp {
             }
         }
         Exp::If(e11, e12, e13) => Exp::If(
-            Box::new(subst(e2.clone(), x.clone(), *e11, gen)),
-            Box::new(subst(e2.clone(), x.clone(), *e12, gen)),
-            Box::new(subst(e2, x, *e13, gen)),
+            Rc::new(subst(e2.clone(), x, Rc::unwrap_or_clone(e11), gen)),
+            Rc::new(subst(e2.clone(), x, Rc::unwrap_or_clone(e12), gen)),
+            Rc::new(subst(e2, x, Rc::unwrap_or_clone(e13), gen)),
         ),
         Exp::Let(bind, e) => {
             let (sym, body) = bind;
-            let e1 = apply(lambda(&sym, *e), *body);
+            let e1 = apply(Exp::Lambda(sym, e), Rc::unwrap_or_clone(body));
             subst(e2, x, e1, gen)
         }
-        Exp::Quote(e11) => Exp::Quote(Box::new(subst_unquote(e2, x, *e11, gen))),
-        Exp::UnQuote(_) => unreachable!("subst unquote"),
+        Exp::LetRec(binds, e) => {
+            // Rename the bound variables apart first, so that they neither
+            // shadow `x` nor capture free variables of `e2`.
+            let renames = binds
+                .iter()
+                .map(|(y, _)| (*y, gen.gen_var()))
+                .collect::<Vec<_>>();
+            let rename = |e: Exp, gen: &mut VariableGenerator| {
+                renames
+                    .iter()
+                    .fold(e, |e, (y, yy)| subst(Exp::Symbol(*yy), *y, e, gen))
+            };
+            let binds = binds
+                .iter()
+                .zip(&renames)
+                .map(|((_, b), (_, yy))| {
+                    let b = rename(b.clone(), gen);
+                    (*yy, subst(e2.clone(), x, b, gen))
+                })
+                .collect();
+            let e = rename(Rc::unwrap_or_clone(e), gen);
+            Exp::LetRec(binds, Rc::new(subst(e2, x, e, gen)))
+        }
+        Exp::Case(e, cases) => Exp::Case(
+            Rc::new(subst(e2.clone(), x, Rc::unwrap_or_clone(e), gen)),
+            cases
+                .into_iter()
+                .map(|(pattern, guard, body)| {
+                    // The variables of the pattern are renamed like the
+                    // parameter of a lambda.
+                    let (pattern, guard, body) = pattern_vars(&pattern).into_iter().fold(
+                        (pattern, guard, body),
+                        |(pattern, guard, body), y| {
+                            let yy = Exp::Symbol(gen.gen_var());
+                            (
+                                subst(yy.clone(), y, pattern, gen),
+                                guard.map(|guard| subst(yy.clone(), y, guard, gen)),
+                                subst(yy, y, body, gen),
+                            )
+                        },
+                    );
+                    (
+                        pattern,
+                        guard.map(|guard| subst(e2.clone(), x, guard, gen)),
+                        subst(e2.clone(), x, body, gen),
+                    )
+                })
+                .collect(),
+        ),
+        Exp::Try(body, y, handler) => {
+            // The variable of the handler is renamed like the parameter of a
+            // lambda.
+            let yy = gen.gen_var();
+            let handler = subst(Exp::Symbol(yy), y, Rc::unwrap_or_clone(handler), gen);
+            Exp::Try(
+                Rc::new(subst(e2.clone(), x, Rc::unwrap_or_clone(body), gen)),
+                yy,
+                Rc::new(subst(e2, x, handler, gen)),
+            )
+        }
+        Exp::Quote(e11) => Exp::Quote(Rc::new(subst_unquote(e2, x, Rc::unwrap_or_clone(e11), gen))),
+        Exp::UnQuote(_) | Exp::UnQuoteSplicing(_) => unreachable!("subst unquote"),
+        Exp::Spanned(span, e) => {
+            Exp::Spanned(span, Rc::new(subst(e2, x, Rc::unwrap_or_clone(e), gen)))
+        }
         Exp::List(list) => Exp::List(
-            list.into_iter()
-                .map(|e| subst(e2.clone(), x.clone(), e, gen))
+            list.iter()
+                .map(|e| subst(e2.clone(), x, e.clone(), gen))
                 .collect(),
         ),
     }
 }
 
-fn subst_unquote(e2: Exp, x: String, e1: Exp, gen: &mut VariableGenerator) -> Exp {
+fn subst_unquote(e2: Exp, x: SymbolId, e1: Exp, gen: &mut VariableGenerator) -> Exp {
     match e1 {
         Exp::Nil
+        | Exp::Void
         | Exp::Bool(_)
         | Exp::Integer(_)
+        | Exp::Float(_)
+        | Exp::Rational(..)
         | Exp::String(_)
         | Exp::Symbol(_)
-        | Exp::BuildIn(_) => e1,
+        | Exp::Values(_)
+        | Exp::Map(_)
+        | Exp::Set(_)
+        | Exp::Vector(_)
+        | Exp::Bytes(_)
+        | Exp::Char(_)
+        | Exp::Keyword(_)
+        | Exp::Pair(..)
+        | Exp::BuildIn(_)
+        | Exp::HostFn(_)
+        | Exp::Closure(..) => e1,
+        #[cfg(feature = "bignum")]
+        Exp::BigInt(_) => e1,
         Exp::List(es) => list(
-            &es.into_iter()
-                .map(|e| subst_unquote(e2.clone(), x.clone(), e, gen))
+            &es.iter()
+                .map(|e| subst_unquote(e2.clone(), x, e.clone(), gen))
                 .collect::<Vec<_>>(),
         ),
-        Exp::Lambda(s, e) => lambda(&s, subst_unquote(e2, x, *e, gen)),
+        Exp::Lambda(s, e) => Exp::Lambda(
+            s,
+            Rc::new(subst_unquote(e2, x, Rc::unwrap_or_clone(e), gen)),
+        ),
+        Exp::Variadic(f) => {
+            Exp::Variadic(Rc::new(subst_unquote(e2, x, Rc::unwrap_or_clone(f), gen)))
+        }
         Exp::Apply(e1, e2) => apply(
-            subst_unquote(*e2.clone(), x.clone(), *e1, gen),
-            subst_unquote(*e2.clone(), x, *e2, gen),
+            subst_unquote((*e2).clone(), x, Rc::unwrap_or_clone(e1), gen),
+            subst_unquote((*e2).clone(), x, Rc::unwrap_or_clone(e2), gen),
         ),
         Exp::If(c, t, e) => if_(
-            subst_unquote(e2.clone(), x.clone(), *c, gen),
-            subst_unquote(e2.clone(), x.clone(), *t, gen),
-            subst_unquote(e2, x, *e, gen),
+            subst_unquote(e2.clone(), x, Rc::unwrap_or_clone(c), gen),
+            subst_unquote(e2.clone(), x, Rc::unwrap_or_clone(t), gen),
+            subst_unquote(e2, x, Rc::unwrap_or_clone(e), gen),
+        ),
+        Exp::Quote(e) => Rc::unwrap_or_clone(e),
+        Exp::UnQuote(e11) => unquote(subst(e2, x, Rc::unwrap_or_clone(e11), gen)),
+        Exp::UnQuoteSplicing(e11) => unquote_splicing(subst(e2, x, Rc::unwrap_or_clone(e11), gen)),
+        Exp::Spanned(span, e) => Exp::Spanned(
+            span,
+            Rc::new(subst_unquote(e2, x, Rc::unwrap_or_clone(e), gen)),
         ),
-        Exp::Quote(e) => *e,
-        Exp::UnQuote(e11) => unquote(subst(e2, x, *e11, gen)),
         Exp::Let((s, b), e) => let_(
-            (&s, subst_unquote(e2.clone(), x.clone(), *b, gen)),
-            subst_unquote(e2, x, *e, gen),
+            (
+                s.as_str(),
+                subst_unquote(e2.clone(), x, Rc::unwrap_or_clone(b), gen),
+            ),
+            subst_unquote(e2, x, Rc::unwrap_or_clone(e), gen),
+        ),
+        Exp::LetRec(binds, e) => Exp::LetRec(
+            binds
+                .iter()
+                .map(|(s, b)| (*s, subst_unquote(e2.clone(), x, b.clone(), gen)))
+                .collect(),
+            Rc::new(subst_unquote(e2, x, Rc::unwrap_or_clone(e), gen)),
+        ),
+        Exp::Case(e, cases) => Exp::Case(
+            Rc::new(subst_unquote(e2.clone(), x, Rc::unwrap_or_clone(e), gen)),
+            cases
+                .into_iter()
+                .map(|(pattern, guard, body)| {
+                    (
+                        subst_unquote(e2.clone(), x, pattern, gen),
+                        guard.map(|guard| subst_unquote(e2.clone(), x, guard, gen)),
+                        subst_unquote(e2.clone(), x, body, gen),
+                    )
+                })
+                .collect(),
+        ),
+        Exp::Try(body, y, handler) => Exp::Try(
+            Rc::new(subst_unquote(e2.clone(), x, Rc::unwrap_or_clone(body), gen)),
+            y,
+            Rc::new(subst_unquote(e2, x, Rc::unwrap_or_clone(handler), gen)),
         ),
     }
 }
 
-fn eval_app(e1: Exp, e2: Exp, module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    match e1 {
-        Exp::Lambda(x, e11) => {
-            if is_value(&e2) {
-                let e = subst(e2, x, *e11, gen);
-                eval(e, module, gen)
-            } else {
-                let e2 = eval(e2, module, gen)?;
-                eval(
-                    Exp::Apply(Box::new(Exp::Lambda(x, e11)), Box::new(e2)),
-                    module,
-                    gen,
-                )
-            }
-        }
-        Exp::Symbol(sym) => {
-            if let Some(e1) = module.defines.get(&sym) {
-                eval_app(e1.clone(), e2, module, gen)
-            } else {
-                Err(EvalError::SymbolNotFound(sym))
+enum ListPattern<'a> {
+    Cons(&'a Exp, &'a Exp),
+    Elements(&'a [Exp]),
+}
+
+fn list_pattern(patterns: &[Exp]) -> ListPattern<'_> {
+    let is = |head: &Exp, name: &str| head.as_symbol_id() == Some(SymbolId::intern(name));
+    match patterns {
+        [head, first, rest] if is(head, "cons") => ListPattern::Cons(first, rest),
+        [head, elements @ ..] if is(head, "list") => ListPattern::Elements(elements),
+        elements => ListPattern::Elements(elements),
+    }
+}
+
+/// Matches `value` against the pattern of a `case` arm and returns `env`
+/// extended with the variables the pattern binds.
+///
+/// A symbol binds the value and `_` matches anything without binding it.
+/// `(cons first rest)` or `(first . rest)` matches a non-empty list or a pair,
+/// and `(list p ...)` or just `(p ...)` a list with an element for each pattern.
+/// Quoted patterns and other literals match equal values.
+pub(crate) fn match_pattern(pattern: &Exp, value: &Exp, env: Env) -> Option<Env> {
+    match pattern.node() {
+        Exp::Symbol(x) if x.as_str() == "_" => Some(env),
+        Exp::Symbol(x) => Some(env.bind(*x, value.clone())),
+        Exp::Quote(datum) => (**datum == *value).then_some(env),
+        Exp::Pair(first, rest) => match_cons(first, rest, value, env),
+        Exp::List(patterns) => match list_pattern(patterns) {
+            ListPattern::Cons(first, rest) => match_cons(first, rest, value, env),
+            ListPattern::Elements(patterns) => {
+                let values = value.as_list()?;
+                if patterns.len() != values.len() {
+                    return None;
+                }
+                patterns
+                    .iter()
+                    .zip(values)
+                    .try_fold(env, |env, (pattern, value)| {
+                        match_pattern(pattern, value, env)
+                    })
             }
-        }
-        Exp::Apply(..) => {
-            let e1 = eval(e1, module, gen)?;
-            eval(Exp::Apply(Box::new(e1), Box::new(e2)), module, gen)
-        }
-        Exp::List(es) => {
-            let e1 = eval(Exp::List(es), module, gen)?;
-            eval(Exp::Apply(Box::new(e1), Box::new(e2)), module, gen)
-        }
-        Exp::BuildIn(f) => f(&[e2], module, gen),
-        _ => Err(EvalError::FailedToApply(e1, e2)),
+        },
+        pattern => (pattern == value).then_some(env),
     }
 }
 
-pub fn eval(exp: Exp, module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    match exp.clone() {
-        Exp::Integer(_) | Exp::Nil | Exp::Bool(_) | Exp::String(_) | Exp::BuildIn(_) => Ok(exp),
-        Exp::Symbol(sym) => {
-            if let Some(e) = module.defines.get(&sym) {
-                Ok(e.clone())
-            } else if let Some(_) = module.macros.get(&sym) {
-                Ok(exp)
-            } else {
-                Err(EvalError::SymbolNotFound(sym))
+fn match_cons(first: &Exp, rest: &Exp, value: &Exp, env: Env) -> Option<Env> {
+    let (head, tail) = value.uncons()?;
+    let env = match_pattern(first, &head, env)?;
+    match_pattern(rest, &tail, env)
+}
+
+/// The variables a `case` pattern binds, in the order [`match_pattern`] binds
+/// them.
+pub(crate) fn pattern_vars(pattern: &Exp) -> Vec<SymbolId> {
+    match pattern.node() {
+        Exp::Symbol(x) if x.as_str() == "_" => vec![],
+        Exp::Symbol(x) => vec![*x],
+        Exp::Pair(first, rest) => [first, rest]
+            .into_iter()
+            .flat_map(|p| pattern_vars(p))
+            .collect(),
+        Exp::List(patterns) => match list_pattern(patterns) {
+            ListPattern::Cons(first, rest) => {
+                [first, rest].into_iter().flat_map(pattern_vars).collect()
             }
-        }
-        Exp::Lambda(..) => Ok(exp),
-        Exp::Apply(e1, e2) => eval_app(*e1, *e2, module, gen),
-        Exp::If(e1, e2, e3) => match eval(*e1, module, gen)? {
-            Exp::Bool(true) => eval(*e2, module, gen),
-            Exp::Bool(false) => eval(*e3, module, gen),
-            _ => Err(EvalError::ExpectedBool(exp)),
+            ListPattern::Elements(patterns) => patterns.iter().flat_map(pattern_vars).collect(),
         },
-        Exp::Let(bind, exp) => {
-            let (sym, body) = bind;
-            eval(apply(lambda(&sym, *exp), *body), module, gen)
-        }
-        Exp::Quote(e) => eval_unquote(*e, module, gen),
-        Exp::UnQuote(e) => Err(EvalError::UnquoteOutsideQuote(*e)),
-        Exp::List(list) => {
-            if let Some((head, tail)) = list.split_first() {
-                let head = eval(head.clone(), module, gen)?;
-
-                if let Some(sym) = head.as_symbol() {
-                    if let Some((mut macro_, args)) = module.macros.get(sym).cloned() {
-                        if args.len() == tail.len() {
-                            for (arg, exp) in args.iter().zip(tail.iter()) {
-                                if let Some(arg) = arg.as_symbol() {
-                                    macro_ =
-                                        subst(quote(exp.clone()), arg.to_string(), macro_, gen);
-                                } else {
-                                    return Err(EvalError::InvalidArgs(tail.to_vec()));
-                                }
-                            }
-                            let expanded = eval(macro_, module, gen)?;
-                            return eval(expanded, module, gen);
-                        } else {
-                            return Err(EvalError::InvalidArgs(tail.to_vec()));
-                        }
-                    }
-                }
+        _ => vec![],
+    }
+}
 
-                if let Exp::BuildIn(f) = head {
-                    let args = tail.to_vec();
-                    f(&args, module, gen)
-                } else {
-                    let e = tail.iter().fold(head.clone(), |acc, e| {
-                        Exp::Apply(Box::new(acc), Box::new(e.clone()))
-                    });
-                    eval(e, module, gen)
-                }
-            } else {
-                Ok(Exp::Nil)
+type CaseArms = alloc::vec::IntoIter<(Exp, Option<Exp>, Exp)>;
+
+/// Evaluates the body of the first of `arms` whose pattern matches `value` and
+/// whose guard, if any, holds.
+fn match_arms(value: Exp, mut arms: CaseArms, env: Env, stack: &mut Vec<Frame>) -> State {
+    while let Some((pattern, guard, body)) = arms.next() {
+        let Some(bound) = match_pattern(&pattern, &value, env.clone()) else {
+            continue;
+        };
+        return match guard {
+            Some(guard) => {
+                stack.push(Frame::Guard(
+                    guard.clone(),
+                    body,
+                    bound.clone(),
+                    value,
+                    arms,
+                    env,
+                ));
+                State::Eval(guard, bound)
             }
-        }
+            None => State::Eval(body, bound),
+        };
     }
+    State::Return(Err(EvalError::NoMatchingCase(value)))
 }
 
-fn eval_unquote(exp: Exp, module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    match exp {
-        Exp::Nil
-        | Exp::Bool(_)
-        | Exp::Integer(_)
-        | Exp::String(_)
-        | Exp::Symbol(_)
-        | Exp::BuildIn(_)
-        | Exp::Quote(_) => Ok(exp),
-        Exp::List(es) => Ok(Exp::List(
-            es.into_iter()
-                .map(|e| eval_unquote(e, module, gen))
-                .collect::<Result<_>>()?,
-        )),
-        Exp::Lambda(s, e) => Ok(lambda(&s, eval_unquote(*e, module, gen)?)),
-        Exp::Apply(e1, e2) => Ok(apply(
-            eval_unquote(*e1, module, gen)?,
-            eval_unquote(*e2, module, gen)?,
-        )),
-        Exp::If(c, t, e) => Ok(if_(
-            eval_unquote(*c, module, gen)?,
-            eval_unquote(*t, module, gen)?,
-            eval_unquote(*e, module, gen)?,
-        )),
-        Exp::UnQuote(e) => eval(*e, module, gen),
-        Exp::Let((s, b), e) => Ok(let_((&s, *b), eval_unquote(*e, module, gen)?)),
+/// A pending piece of work waiting for the value of a subexpression.
+enum Frame {
+    /// Evaluate the argument and apply the value to it.
+    AppFun(Exp, Env),
+    /// Apply the function to the value.
+    AppArg(Exp),
+    /// Choose a branch with the value; the `if` is kept for error reporting.
+    If(Exp, Exp, Exp, Env),
+    /// Bind the value and evaluate the body.
+    Let(SymbolId, Exp, Env),
+    /// Match the value against the cases.
+    Case(Vec<(Exp, Option<Exp>, Exp)>, Env),
+    /// Evaluate the body of the matched arm if the value of its guard is true,
+    /// otherwise match the value against the remaining arms.
+    Guard(Exp, Exp, Env, Exp, CaseArms, Env),
+    /// Dispatch on the value of the head of a list, named if the head is a
+    /// symbol.
+    ListHead(Option<SymbolId>, Vec<Exp>, Env),
+    /// Collect the value as an argument of a call to the function.
+    CallArgs(
+        Option<SymbolId>,
+        Exp,
+        Vec<Exp>,
+        alloc::vec::IntoIter<Exp>,
+        Env,
+    ),
+    /// Apply the value to the remaining arguments of a call, last argument
+    /// first.
+    ApplyArgs(Vec<Exp>),
+    /// Evaluate the value, which is the expansion of a macro.
+    Expand(Env),
+    /// Attribute errors to the span.
+    Located(Span),
+    /// Evaluate the handler with the variable bound to the error if the body
+    /// fails, see [`unwind`].
+    Catch(SymbolId, Exp, Env),
+    /// Attribute errors to a call of the named function, for the trace of the
+    /// error. A call in tail position replaces the frame of its caller, see
+    /// [`push_call`].
+    Call(SymbolId),
+}
+
+enum State {
+    Eval(Exp, Env),
+    Return(Result<Exp>),
+}
+
+fn push_located(stack: &mut Vec<Frame>, span: Span) {
+    // Only the innermost span is reported, so a frame directly beneath another
+    // `Located` frame is never needed. Replacing it keeps tail calls in
+    // constant space.
+    if let Some(Frame::Located(top)) = stack.last_mut() {
+        *top = span;
+    } else {
+        stack.push(Frame::Located(span));
     }
 }
 
-pub fn eval_macro(macro_: Exp, module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
-    match macro_ {
-        Exp::Lambda(param, body) => Ok(Exp::Lambda(param, body)),
-        Exp::List(es) => {
-            let mut es = es.into_iter().map(|e| eval_macro(e, module, gen));
-            let head = es.next().ok_or(EvalError::NeverMatched(Exp::Nil))?;
-            let head = head?;
-            let tail = es.collect::<Result<Vec<_>>>()?;
-            Ok(Exp::List(
-                [head].iter().chain(tail.iter()).cloned().collect(),
-            ))
-        }
-        _ => Ok(macro_),
+fn push_call(stack: &mut Vec<Frame>, name: SymbolId) {
+    // The frames of a call in tail position are only a `Call` frame and the
+    // `Located` frame of its body, so the callee takes the place of the
+    // caller, and tail calls run in constant space.
+    match stack.as_mut_slice() {
+        [.., Frame::Call(top)] | [.., Frame::Call(top), Frame::Located(_)] => *top = name,
+        _ => stack.push(Frame::Call(name)),
     }
 }
 
-pub fn eval_empty_module(exp: Exp) -> Result<Exp> {
-    let mut gen = VariableGenerator::new();
-    let module = Module::new("empty");
-    eval(exp, &module, &mut gen)
+/// Whether `f` is one of the lambdas that curry a builtin, such as `+`, which
+/// are left out of traces.
+fn is_curried_builtin(f: &Exp) -> bool {
+    match f.node() {
+        Exp::Closure(_, body, _) | Exp::Lambda(_, body) => is_curried_builtin(body),
+        Exp::List(list) => matches!(list.first().map(Exp::node), Some(Exp::BuildIn(_))),
+        _ => false,
+    }
 }
 
-pub fn eval_default_module(exp: Exp) -> Result<Exp> {
-    let mut gen = VariableGenerator::new();
-    let module = default_module();
-    eval(exp, &module, &mut gen)
+fn expand_macro(
+    name: SymbolId,
+    macro_: Exp,
+    args: &[Exp],
+    tail: &[Exp],
+    gen: &mut VariableGenerator,
+) -> Result<Exp> {
+    if args.len() != tail.len() {
+        return Err(EvalError::ArityMismatch {
+            expected: Arity::Exactly(args.len()),
+            got: tail.len(),
+            callee: Some(name.to_string()),
+        });
+    }
+    args.iter()
+        .zip(tail.iter())
+        .enumerate()
+        .try_fold(macro_, |macro_, (i, (arg, exp))| {
+            let arg = arg.as_symbol_id().ok_or(EvalError::TypeMismatch {
+                expected: "a symbol",
+                got: arg.clone(),
+                callee: Some(name.to_string()),
+                position: Some(i + 1),
+            })?;
+            Ok(subst(quote(exp.clone()), arg, macro_, gen))
+        })
 }
 
-impl Module {
-    pub fn run(&self, name: &str, args: Vec<Exp>) -> Result<Exp> {
-        let mut exp = self
-            .defines
-            .get(name)
-            .cloned()
-            .ok_or_else(|| EvalError::SymbolNotFound(name.to_string()))?;
-        let mut gen = VariableGenerator::new();
-        for arg in args {
-            exp = apply(exp, arg);
-        }
-        eval(exp, self, &mut gen)
+pub(crate) fn lookup(sym: SymbolId, env: &Env, module: &Module) -> Result<Exp> {
+    if let Some(value) = env.lookup(sym) {
+        return Ok(value);
     }
+    match module.defines.get(&sym).map(Exp::node) {
+        // Top-level definitions only see other top-level definitions.
+        Some(Exp::Lambda(x, body)) => Ok(Exp::Closure(*x, body.clone(), Env::new())),
+        Some(value) => Ok(value.clone()),
+        None if module.macros.contains_key(&sym) => Ok(Exp::Symbol(sym)),
+        None => Err(EvalError::SymbolNotFound(sym.to_string())),
+    }
+}
 
-    pub fn eval(&self, exp: Exp) -> Result<Exp> {
-        let mut gen = VariableGenerator::new();
-        eval(exp, self, &mut gen)
+fn apply_value(f: Exp, arg: Exp, module: &Module, gen: &mut VariableGenerator) -> State {
+    match f {
+        Exp::Closure(x, body, env) => {
+            let env = env.bind(x, arg);
+            State::Eval(Rc::unwrap_or_clone(body), env)
+        }
+        Exp::Lambda(x, body) => State::Eval(Rc::unwrap_or_clone(body), Env::new().bind(x, arg)),
+        Exp::BuildIn(f) => State::Return(call_builtin(f, &[arg], module, gen)),
+        Exp::HostFn(f) => State::Return(call_host(&f, &[arg], gen)),
+        f => State::Return(Err(EvalError::FailedToApply(f, arg))),
+    }
+}
+
+/// Calls `f` with the values of the arguments. Builtins get all of them at once,
+/// other functions are applied to them one at a time. A call of a function by
+/// `name` is recorded for the trace of errors.
+fn call_values(
+    name: Option<SymbolId>,
+    f: Exp,
+    mut args: Vec<Exp>,
+    module: &Module,
+    gen: &mut VariableGenerator,
+    stack: &mut Vec<Frame>,
+) -> State {
+    match f {
+        Exp::BuildIn(f) => {
+            State::Return(call_builtin(f, &args, module, gen).map_err(|err| err.with_callee(name)))
+        }
+        Exp::HostFn(f) => {
+            State::Return(call_host(&f, &args, gen).map_err(|err| err.with_callee(name)))
+        }
+        f => {
+            if let Some(name) = name.filter(|_| !is_curried_builtin(&f)) {
+                push_call(stack, name);
+            }
+            args.reverse();
+            apply_args(f, args, module, gen, stack)
+        }
+    }
+}
+
+/// Applies `f` to `args`, given last argument first. A variadic function takes
+/// all of the remaining arguments.
+fn apply_args(
+    mut f: Exp,
+    mut args: Vec<Exp>,
+    module: &Module,
+    gen: &mut VariableGenerator,
+    stack: &mut Vec<Frame>,
+) -> State {
+    loop {
+        if let Exp::Variadic(f) = f {
+            args.reverse();
+            return apply_value(Rc::unwrap_or_clone(f), Exp::List(args.into()), module, gen);
+        }
+        let Some(arg) = args.pop() else {
+            return State::Return(Ok(f));
+        };
+        if let Some(g) = apply_to_variadic(&f, &arg) {
+            f = g;
+            continue;
+        }
+        if !args.is_empty() {
+            stack.push(Frame::ApplyArgs(args));
+        }
+        return apply_value(f, arg, module, gen);
+    }
+}
+
+/// If the body of the function `f` is a variadic lambda, returns the variadic
+/// closure that applying `f` to `arg` evaluates to. It takes the remaining
+/// arguments of the call, even if there are none.
+pub(crate) fn apply_to_variadic(f: &Exp, arg: &Exp) -> Option<Exp> {
+    let (x, body, env) = match f {
+        Exp::Closure(x, body, env) => (*x, body, env.clone()),
+        Exp::Lambda(x, body) => (*x, body, Env::new()),
+        _ => return None,
+    };
+    match body.node() {
+        Exp::Variadic(g) => match g.node() {
+            Exp::Lambda(rest, body) => Some(Exp::Variadic(Rc::new(Exp::Closure(
+                *rest,
+                body.clone(),
+                env.bind(x, arg.clone()),
+            )))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn eval_step(
+    exp: Exp,
+    env: Env,
+    module: &Module,
+    gen: &mut VariableGenerator,
+    stack: &mut Vec<Frame>,
+) -> State {
+    match exp {
+        Exp::Integer(_)
+        | Exp::Float(_)
+        | Exp::Rational(..)
+        | Exp::Nil
+        | Exp::Void
+        | Exp::Bool(_)
+        | Exp::String(_)
+        | Exp::Values(_)
+        | Exp::Map(_)
+        | Exp::Set(_)
+        | Exp::Vector(_)
+        | Exp::Bytes(_)
+        | Exp::Char(_)
+        | Exp::Keyword(_)
+        | Exp::Pair(..)
+        | Exp::BuildIn(_)
+        | Exp::HostFn(_)
+        | Exp::Closure(..) => State::Return(Ok(exp)),
+        #[cfg(feature = "bignum")]
+        Exp::BigInt(_) => State::Return(Ok(exp)),
+        Exp::Lambda(x, body) => State::Return(Ok(Exp::Closure(x, body, env))),
+        Exp::Variadic(f) => match Rc::unwrap_or_clone(f).into_node() {
+            Exp::Lambda(x, body) => {
+                State::Return(Ok(Exp::Variadic(Rc::new(Exp::Closure(x, body, env)))))
+            }
+            f => State::Return(Ok(Exp::Variadic(Rc::new(f)))),
+        },
+        Exp::Symbol(sym) => State::Return(lookup(sym, &env, module)),
+        Exp::Apply(e1, e2) => {
+            stack.push(Frame::AppFun(Rc::unwrap_or_clone(e2), env.clone()));
+            State::Eval(Rc::unwrap_or_clone(e1), env)
+        }
+        Exp::If(e1, e2, e3) => {
+            let cond = (*e1).clone();
+            stack.push(Frame::If(
+                Rc::unwrap_or_clone(e1),
+                Rc::unwrap_or_clone(e2),
+                Rc::unwrap_or_clone(e3),
+                env.clone(),
+            ));
+            State::Eval(cond, env)
+        }
+        Exp::Let((x, bind), body) => {
+            stack.push(Frame::Let(x, Rc::unwrap_or_clone(body), env.clone()));
+            State::Eval(Rc::unwrap_or_clone(bind), env)
+        }
+        Exp::LetRec(binds, body) => {
+            match binds
+                .iter()
+                .find(|(_, b)| !matches!(b.node(), Exp::Lambda(..)))
+            {
+                Some((_, b)) => State::Return(Err(EvalError::ExpectedLambda(b.clone()))),
+                None => State::Eval(Rc::unwrap_or_clone(body), env.bind_rec(binds)),
+            }
+        }
+        Exp::Case(e, cases) => {
+            stack.push(Frame::Case(cases, env.clone()));
+            State::Eval(Rc::unwrap_or_clone(e), env)
+        }
+        Exp::Try(body, x, handler) => {
+            stack.push(Frame::Catch(x, Rc::unwrap_or_clone(handler), env.clone()));
+            State::Eval(Rc::unwrap_or_clone(body), env)
+        }
+        Exp::Quote(e) => State::Return(eval_unquote(Rc::unwrap_or_clone(e), &env, module, gen)),
+        Exp::UnQuote(e) | Exp::UnQuoteSplicing(e) => {
+            State::Return(Err(EvalError::UnquoteOutsideQuote(Rc::unwrap_or_clone(e))))
+        }
+        Exp::Spanned(span, e) => {
+            push_located(stack, span);
+            State::Eval(Rc::unwrap_or_clone(e), env)
+        }
+        Exp::List(list) => match list.split_first() {
+            Some((head, tail)) => {
+                stack.push(Frame::ListHead(
+                    head.as_symbol_id(),
+                    tail.to_vec(),
+                    env.clone(),
+                ));
+                State::Eval(head.clone(), env)
+            }
+            None => State::Return(Ok(Exp::Nil)),
+        },
+    }
+}
+
+fn continue_step(
+    frame: Frame,
+    value: Exp,
+    module: &Module,
+    gen: &mut VariableGenerator,
+    stack: &mut Vec<Frame>,
+) -> State {
+    match frame {
+        Frame::AppFun(arg, env) => {
+            stack.push(Frame::AppArg(value));
+            State::Eval(arg, env)
+        }
+        Frame::AppArg(f) => apply_args(f, vec![value], module, gen, stack),
+        Frame::If(cond, then, else_, env) => match value {
+            Exp::Bool(true) => State::Eval(then, env),
+            Exp::Bool(false) => State::Eval(else_, env),
+            _ => State::Return(Err(EvalError::ExpectedBool(if_(cond, then, else_)))),
+        },
+        Frame::Let(x, body, env) => State::Eval(body, env.bind(x, value)),
+        Frame::Case(cases, env) => match_arms(value, cases.into_iter(), env, stack),
+        Frame::Guard(guard, body, bound, scrutinee, arms, env) => match value {
+            Exp::Bool(true) => State::Eval(body, bound),
+            Exp::Bool(false) => match_arms(scrutinee, arms, env, stack),
+            _ => State::Return(Err(EvalError::ExpectedBool(guard))),
+        },
+        Frame::ListHead(name, tail, env) => {
+            if let Some((sym, (macro_, args))) = value
+                .as_symbol_id()
+                .and_then(|sym| Some((sym, module.macros.get(&sym)?)))
+            {
+                return match expand_macro(sym, macro_.clone(), args, &tail, gen) {
+                    Ok(macro_) => {
+                        stack.push(Frame::Expand(env.clone()));
+                        State::Eval(macro_, env)
+                    }
+                    Err(err) => State::Return(Err(err)),
+                };
+            }
+            let mut args = tail.into_iter();
+            match args.next() {
+                Some(arg) => {
+                    stack.push(Frame::CallArgs(name, value, vec![], args, env.clone()));
+                    State::Eval(arg, env)
+                }
+                None => call_values(name, value, vec![], module, gen, stack),
+            }
+        }
+        Frame::CallArgs(name, f, mut args, mut rest, env) => {
+            args.push(value);
+            match rest.next() {
+                Some(arg) => {
+                    stack.push(Frame::CallArgs(name, f, args, rest, env.clone()));
+                    State::Eval(arg, env)
+                }
+                None => call_values(name, f, args, module, gen, stack),
+            }
+        }
+        Frame::ApplyArgs(args) => apply_args(value, args, module, gen, stack),
+        Frame::Expand(env) => State::Eval(value, env),
+        Frame::Located(_) | Frame::Catch(..) | Frame::Call(_) => State::Return(Ok(value)),
+    }
+}
+
+/// Pops the frames of the computations `err` aborts, up to the innermost
+/// `catch`, and evaluates its handler. Fails with `err`, attributed to the
+/// innermost span, if there is no `catch`.
+fn unwind(err: EvalError, stack: &mut Vec<Frame>) -> core::result::Result<State, EvalError> {
+    let mut err = err;
+    while let Some(frame) = stack.pop() {
+        match frame {
+            Frame::Located(span) => err = err.located(span),
+            Frame::Call(name) => err = err.called_from(name),
+            Frame::Catch(x, handler, env) => {
+                return Ok(State::Eval(handler, env.bind(x, err.to_exp())))
+            }
+            _ => {}
+        }
+    }
+    Err(err)
+}
+
+/// Evaluates `exp` in `module`.
+///
+/// Variables are looked up in an environment of bindings, falling back to the
+/// definitions of `module`; lambdas evaluate to closures that capture the
+/// environment they were evaluated in.
+///
+/// Subexpressions are evaluated with an explicit stack of pending frames rather
+/// than by recursion, so deeply nested expressions cannot overflow the host
+/// stack. Expressions in tail position (the branches of `if` and `case`, the
+/// body of `let` and the body of an applied lambda) push no frame at all, and
+/// a call in tail position takes over the frame that records its caller for
+/// error traces, so tail-recursive topogi functions run in constant space.
+pub fn eval(exp: Exp, module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
+    eval_in(exp, Env::new(), module, gen)
+}
+
+pub(crate) fn eval_in(
+    exp: Exp,
+    env: Env,
+    module: &Module,
+    gen: &mut VariableGenerator,
+) -> Result<Exp> {
+    run(State::Eval(exp, env), vec![], module, gen)
+}
+
+/// Calls the function value `f` with the already evaluated `args`, like a call
+/// `(f arg ...)` does.
+pub fn call(f: Exp, args: &[Exp], module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
+    let mut stack = vec![];
+    let state = call_values(None, f.clone(), args.to_vec(), module, gen, &mut stack);
+    // The name of a function passed as a value is only looked for if the call
+    // fails, so that builtins such as `map` call it at no extra cost.
+    run(state, stack, module, gen).map_err(|err| match (&f, defined_name(&f, module)) {
+        (Exp::BuildIn(_) | Exp::HostFn(_), name) => err.with_callee(name),
+        (_, Some(name)) => err.called_from(name),
+        (_, None) => err,
+    })
+}
+
+/// The name of the top-level definition that the closure or builtin `f` was
+/// looked up from, if any.
+fn defined_name(f: &Exp, module: &Module) -> Option<SymbolId> {
+    let is_f = |define: &Exp| match (f, define.node()) {
+        (Exp::Closure(_, body, _), Exp::Lambda(_, b)) => Rc::ptr_eq(b, body),
+        (Exp::BuildIn(f), Exp::BuildIn(g)) => *f as usize == *g as usize,
+        (Exp::HostFn(f), Exp::HostFn(g)) => Rc::ptr_eq(&f.0, &g.0),
+        _ => false,
+    };
+    module
+        .defines
+        .iter()
+        .find(|(_, define)| is_f(define))
+        .map(|(name, _)| *name)
+}
+
+fn run(
+    state: State,
+    stack: Vec<Frame>,
+    module: &Module,
+    gen: &mut VariableGenerator,
+) -> Result<Exp> {
+    if gen.nesting >= gen.config.max_nesting {
+        return Err(EvalError::DepthLimitExceeded);
+    }
+    let outer = gen.depth;
+    gen.nesting += 1;
+    let result = run_nested(state, stack, module, gen);
+    gen.nesting -= 1;
+    gen.depth = outer;
+    result
+}
+
+fn run_nested(
+    mut state: State,
+    mut stack: Vec<Frame>,
+    module: &Module,
+    gen: &mut VariableGenerator,
+) -> Result<Exp> {
+    let outer = gen.depth;
+    loop {
+        // Builtins that call back into the evaluator start from this depth.
+        gen.depth = outer + stack.len();
+        state = match state {
+            State::Eval(..) if gen.depth > gen.config.max_depth => {
+                State::Return(Err(EvalError::DepthLimitExceeded))
+            }
+            State::Eval(exp, env) => match gen.step() {
+                Ok(()) => eval_step(exp, env, module, gen, &mut stack),
+                Err(err) => State::Return(Err(err)),
+            },
+            State::Return(Ok(value)) => match stack.pop() {
+                Some(frame) => continue_step(frame, value, module, gen, &mut stack),
+                None => return Ok(value),
+            },
+            State::Return(Err(err)) => unwind(err, &mut stack)?,
+        }
+    }
+}
+
+fn eval_unquote(exp: Exp, env: &Env, module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
+    match exp {
+        Exp::Nil
+        | Exp::Void
+        | Exp::Bool(_)
+        | Exp::Integer(_)
+        | Exp::Float(_)
+        | Exp::Rational(..)
+        | Exp::String(_)
+        | Exp::Symbol(_)
+        | Exp::Values(_)
+        | Exp::Set(_)
+        | Exp::Bytes(_)
+        | Exp::Char(_)
+        | Exp::Keyword(_)
+        | Exp::BuildIn(_)
+        | Exp::HostFn(_)
+        | Exp::Closure(..)
+        | Exp::Quote(_) => Ok(exp),
+        Exp::Vector(exps) => Ok(Exp::Vector(Rc::new(
+            exps.iter()
+                .map(|e| eval_unquote(e.clone(), env, module, gen))
+                .collect::<Result<_>>()?,
+        ))),
+        Exp::Map(map) => Ok(Exp::Map(Rc::new(
+            map.iter()
+                .map(|(key, value)| {
+                    Ok((
+                        eval_unquote(key.clone(), env, module, gen)?,
+                        eval_unquote(value.clone(), env, module, gen)?,
+                    ))
+                })
+                .collect::<Result<_>>()?,
+        ))),
+        #[cfg(feature = "bignum")]
+        Exp::BigInt(_) => Ok(exp),
+        Exp::List(es) => {
+            let mut list = vec![];
+            for e in es.iter() {
+                match e.node() {
+                    Exp::UnQuoteSplicing(e) => {
+                        match eval_in((**e).clone(), env.clone(), module, gen)? {
+                            Exp::List(es) => list.extend(es.iter().cloned()),
+                            Exp::Nil => {}
+                            value => return Err(EvalError::ExpectedList(value)),
+                        }
+                    }
+                    _ => list.push(eval_unquote(e.clone(), env, module, gen)?),
+                }
+            }
+            Ok(Exp::List(list.into()))
+        }
+        Exp::Pair(head, tail) => Ok(pair(
+            eval_unquote(Rc::unwrap_or_clone(head), env, module, gen)?,
+            eval_unquote(Rc::unwrap_or_clone(tail), env, module, gen)?,
+        )),
+        Exp::Lambda(s, e) => Ok(Exp::Lambda(
+            s,
+            Rc::new(eval_unquote(Rc::unwrap_or_clone(e), env, module, gen)?),
+        )),
+        Exp::Variadic(f) => Ok(Exp::Variadic(Rc::new(eval_unquote(
+            Rc::unwrap_or_clone(f),
+            env,
+            module,
+            gen,
+        )?))),
+        Exp::Apply(e1, e2) => Ok(apply(
+            eval_unquote(Rc::unwrap_or_clone(e1), env, module, gen)?,
+            eval_unquote(Rc::unwrap_or_clone(e2), env, module, gen)?,
+        )),
+        Exp::If(c, t, e) => Ok(if_(
+            eval_unquote(Rc::unwrap_or_clone(c), env, module, gen)?,
+            eval_unquote(Rc::unwrap_or_clone(t), env, module, gen)?,
+            eval_unquote(Rc::unwrap_or_clone(e), env, module, gen)?,
+        )),
+        Exp::UnQuote(e) => eval_in(Rc::unwrap_or_clone(e), env.clone(), module, gen),
+        // Splicing is only meaningful inside a list, see above.
+        Exp::UnQuoteSplicing(e) => Err(EvalError::Unexpected(unquote_splicing(
+            Rc::unwrap_or_clone(e),
+        ))),
+        Exp::Spanned(_, e) => eval_unquote(Rc::unwrap_or_clone(e), env, module, gen),
+        Exp::Let((s, b), e) => Ok(Exp::Let(
+            (s, b),
+            Rc::new(eval_unquote(Rc::unwrap_or_clone(e), env, module, gen)?),
+        )),
+        Exp::LetRec(binds, e) => Ok(Exp::LetRec(
+            binds
+                .iter()
+                .map(|(s, b)| Ok((*s, eval_unquote(b.clone(), env, module, gen)?)))
+                .collect::<Result<_>>()?,
+            Rc::new(eval_unquote(Rc::unwrap_or_clone(e), env, module, gen)?),
+        )),
+        Exp::Case(e, cases) => Ok(Exp::Case(
+            Rc::new(eval_unquote(Rc::unwrap_or_clone(e), env, module, gen)?),
+            cases
+                .into_iter()
+                .map(|(pattern, guard, body)| {
+                    Ok((
+                        eval_unquote(pattern, env, module, gen)?,
+                        guard
+                            .map(|guard| eval_unquote(guard, env, module, gen))
+                            .transpose()?,
+                        eval_unquote(body, env, module, gen)?,
+                    ))
+                })
+                .collect::<Result<_>>()?,
+        )),
+        Exp::Try(body, x, handler) => Ok(Exp::Try(
+            Rc::new(eval_unquote(Rc::unwrap_or_clone(body), env, module, gen)?),
+            x,
+            Rc::new(eval_unquote(
+                Rc::unwrap_or_clone(handler),
+                env,
+                module,
+                gen,
+            )?),
+        )),
+    }
+}
+
+#[allow(clippy::only_used_in_recursion)]
+pub fn eval_macro(macro_: Exp, module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
+    match macro_ {
+        Exp::Lambda(param, body) => Ok(Exp::Lambda(param, body)),
+        Exp::List(es) => {
+            let mut es = es.iter().map(|e| eval_macro(e.clone(), module, gen));
+            let head = es.next().ok_or(EvalError::NeverMatched(Exp::Nil))?;
+            let head = head?;
+            let tail = es.collect::<Result<Vec<_>>>()?;
+            Ok(Exp::List(
+                [head].iter().chain(tail.iter()).cloned().collect(),
+            ))
+        }
+        _ => Ok(macro_),
+    }
+}
+
+/// The number of nested macro expansions after which [`expand`] gives up.
+pub const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// Expands the macro calls in `exp` ahead of evaluation.
+///
+/// A macro body is evaluated with the unevaluated arguments of the call
+/// substituted for its parameters, and its value replaces the call and is
+/// expanded in turn. Local variables and definitions of `module` shadow macros
+/// of the same name. Quoted expressions are left to be expanded when they are
+/// evaluated.
+pub fn expand(exp: Exp, module: &Module, gen: &mut VariableGenerator) -> Result<Exp> {
+    Expander {
+        bound: vec![],
+        module,
+        gen,
+    }
+    .expand(&exp, 0)
+}
+
+struct Expander<'a> {
+    /// Variables bound around the expression being expanded.
+    bound: Vec<SymbolId>,
+    module: &'a Module,
+    gen: &'a mut VariableGenerator,
+}
+
+impl Expander<'_> {
+    fn is_macro(&self, sym: SymbolId) -> bool {
+        !self.bound.contains(&sym) && !self.module.defines.contains_key(&sym)
+    }
+
+    fn expand_call(&mut self, es: &[Exp], depth: usize) -> Option<Result<Exp>> {
+        let sym = es.first().and_then(Exp::as_symbol_id)?;
+        let (macro_, args) = self
+            .module
+            .macros
+            .get(&sym)
+            .filter(|_| self.is_macro(sym))?;
+        if depth >= MAX_EXPANSION_DEPTH {
+            return Some(Err(EvalError::ExpansionTooDeep(Exp::List(es.into()))));
+        }
+        Some(
+            expand_macro(sym, macro_.clone(), args, &es[1..], self.gen)
+                .and_then(|macro_| eval(macro_, self.module, self.gen))
+                .and_then(|expansion| self.expand(&expansion, depth + 1)),
+        )
+    }
+
+    fn expand_rc(&mut self, exp: &Rc<Exp>, depth: usize) -> Result<Rc<Exp>> {
+        self.expand(exp, depth).map(Rc::new)
+    }
+
+    /// Expands `exp` with `vars` bound.
+    fn expand_scoped(
+        &mut self,
+        vars: impl IntoIterator<Item = SymbolId>,
+        exp: &Exp,
+        depth: usize,
+    ) -> Result<Exp> {
+        let len = self.bound.len();
+        self.bound.extend(vars);
+        let exp = self.expand(exp, depth);
+        self.bound.truncate(len);
+        exp
+    }
+
+    fn expand(&mut self, exp: &Exp, depth: usize) -> Result<Exp> {
+        Ok(match exp {
+            Exp::List(es) => {
+                if let Some(expansion) = self.expand_call(es, depth) {
+                    return expansion;
+                }
+                let mut expanded = Vec::with_capacity(es.len());
+                for e in es.iter() {
+                    expanded.push(self.expand(e, depth)?);
+                }
+                Exp::List(expanded.into())
+            }
+            Exp::Lambda(x, body) => {
+                Exp::Lambda(*x, Rc::new(self.expand_scoped([*x], body, depth)?))
+            }
+            Exp::Variadic(f) => Exp::Variadic(self.expand_rc(f, depth)?),
+            Exp::Apply(e1, e2) => {
+                Exp::Apply(self.expand_rc(e1, depth)?, self.expand_rc(e2, depth)?)
+            }
+            Exp::If(c, t, e) => Exp::If(
+                self.expand_rc(c, depth)?,
+                self.expand_rc(t, depth)?,
+                self.expand_rc(e, depth)?,
+            ),
+            Exp::Let((x, e1), e2) => Exp::Let(
+                (*x, self.expand_rc(e1, depth)?),
+                Rc::new(self.expand_scoped([*x], e2, depth)?),
+            ),
+            Exp::LetRec(binds, body) => {
+                let vars = binds.iter().map(|(x, _)| *x).collect::<Vec<_>>();
+                let mut expanded = Vec::with_capacity(binds.len());
+                for (x, b) in binds.iter() {
+                    expanded.push((*x, self.expand_scoped(vars.clone(), b, depth)?));
+                }
+                Exp::LetRec(
+                    expanded.into(),
+                    Rc::new(self.expand_scoped(vars, body, depth)?),
+                )
+            }
+            Exp::Case(e, cases) => {
+                let mut expanded = Vec::with_capacity(cases.len());
+                for (pattern, guard, body) in cases {
+                    let vars = pattern_vars(pattern);
+                    let guard = match guard {
+                        Some(guard) => Some(self.expand_scoped(vars.clone(), guard, depth)?),
+                        None => None,
+                    };
+                    let body = self.expand_scoped(vars, body, depth)?;
+                    expanded.push((pattern.clone(), guard, body));
+                }
+                Exp::Case(self.expand_rc(e, depth)?, expanded)
+            }
+            Exp::Try(body, x, handler) => Exp::Try(
+                self.expand_rc(body, depth)?,
+                *x,
+                Rc::new(self.expand_scoped([*x], handler, depth)?),
+            ),
+            Exp::Spanned(span, e) => Exp::Spanned(
+                *span,
+                self.expand_rc(e, depth).map_err(|err| err.located(*span))?,
+            ),
+            _ => exp.clone(),
+        })
+    }
+}
+
+fn define_values(
+    names: &[Exp],
+    producer: Exp,
+    module: &mut Module,
+    gen: &mut VariableGenerator,
+) -> Result<Exp> {
+    let values = match eval(producer, module, gen)? {
+        Exp::Values(values) => values,
+        value => Rc::from([value]),
+    };
+    if names.len() != values.len() {
+        return Err(EvalError::ArityMismatch {
+            expected: Arity::Exactly(names.len()),
+            got: values.len(),
+            callee: Some("define-values".to_string()),
+        });
+    }
+    let names = names
+        .iter()
+        .map(|name| {
+            name.as_symbol_id().ok_or(EvalError::TypeMismatch {
+                expected: "a symbol",
+                got: name.clone(),
+                callee: Some("define-values".to_string()),
+                position: None,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    module
+        .defines
+        .extend(names.into_iter().zip(values.iter().cloned()));
+    Ok(Exp::Void)
+}
+
+fn params(args: &[Exp]) -> Result<Vec<SymbolId>> {
+    args.iter()
+        .map(|arg| {
+            arg.as_symbol_id().ok_or(EvalError::TypeMismatch {
+                expected: "a symbol",
+                got: arg.clone(),
+                callee: None,
+                position: None,
+            })
+        })
+        .collect()
+}
+
+/// Evaluates `exp` as a top-level form, which may add definitions to `module`.
+///
+/// Besides ordinary expressions this accepts the module-level forms
+/// `(define name (args) body)`, `(macro name (args) body)` (or `define-macro`)
+/// and `(define-values (names) producer)`, as well as `(define name exp)` which
+/// binds the value of `exp`. Macro calls are [`expand`]ed before evaluation.
+pub fn eval_top_level(exp: Exp, module: &mut Module, gen: &mut VariableGenerator) -> Result<Exp> {
+    let span = exp.span();
+    let form = exp
+        .as_list()
+        .map(|form| form.iter().map(Exp::node).collect::<Vec<_>>());
+    let result = match form.as_deref() {
+        Some([Exp::Symbol(sym), Exp::Symbol(name), Exp::List(args), body])
+            if sym.as_str() == "define" =>
+        {
+            params(args)
+                .and_then(|params| {
+                    let body = params
+                        .iter()
+                        .rev()
+                        .fold((*body).clone(), |acc, arg| Exp::Lambda(*arg, Rc::new(acc)));
+                    expand(body, module, gen)
+                })
+                .map(|body| {
+                    module.defines.insert(*name, body);
+                    Exp::Void
+                })
+        }
+        Some([Exp::Symbol(sym), Exp::Symbol(name), exp]) if sym.as_str() == "define" => {
+            expand((*exp).clone(), module, gen)
+                .and_then(|exp| eval(exp, module, gen))
+                .map(|value| {
+                    module.defines.insert(*name, value);
+                    Exp::Void
+                })
+        }
+        Some([Exp::Symbol(sym), Exp::Symbol(name), Exp::List(args), body])
+            if matches!(sym.as_str(), "macro" | "define-macro") =>
+        {
+            params(args).map(|_| {
+                module
+                    .macros
+                    .insert(*name, ((*body).clone(), args.to_vec()));
+                Exp::Void
+            })
+        }
+        Some([Exp::Symbol(sym), Exp::List(names), producer]) if sym.as_str() == "define-values" => {
+            define_values(names, (*producer).clone(), module, gen)
+        }
+        _ => return expand(exp, module, gen).and_then(|exp| eval(exp, module, gen)),
+    };
+    match span {
+        Some(span) => result.map_err(|err| err.located(span)),
+        None => result,
+    }
+}
+
+pub type CompiledExp = Exp;
+
+fn is_constant(e: &Exp) -> bool {
+    matches!(
+        e,
+        Exp::Integer(_)
+            | Exp::Rational(..)
+            | Exp::Float(_)
+            | Exp::Bool(_)
+            | Exp::Nil
+            | Exp::String(_)
+    )
+}
+
+fn fold_integer_op(op: &str, lhs: i64, rhs: i64) -> Option<i64> {
+    match op {
+        "+" => lhs.checked_add(rhs),
+        "-" => lhs.checked_sub(rhs),
+        "*" => lhs.checked_mul(rhs),
+        // Only exact divisions fold to an integer.
+        "/" => lhs
+            .checked_rem(rhs)
+            .filter(|rem| *rem == 0)
+            .and_then(|_| lhs.checked_div(rhs)),
+        _ => None,
+    }
+}
+
+fn compile_app(f: Exp, arg: Exp, gen: &mut VariableGenerator) -> Exp {
+    match f.into_node() {
+        Exp::Lambda(x, body) if is_constant(&arg) => {
+            compile_exp(subst(arg, x, Rc::unwrap_or_clone(body), gen), gen)
+        }
+        f => apply(f, arg),
+    }
+}
+
+fn compile_exp(exp: Exp, gen: &mut VariableGenerator) -> Exp {
+    match exp {
+        Exp::Lambda(x, body) => {
+            Exp::Lambda(x, Rc::new(compile_exp(Rc::unwrap_or_clone(body), gen)))
+        }
+        Exp::Variadic(f) => Exp::Variadic(Rc::new(compile_exp(Rc::unwrap_or_clone(f), gen))),
+        Exp::Apply(e1, e2) => {
+            let e1 = compile_exp(Rc::unwrap_or_clone(e1), gen);
+            let e2 = compile_exp(Rc::unwrap_or_clone(e2), gen);
+            compile_app(e1, e2, gen)
+        }
+        Exp::If(cond, then, else_) => {
+            let cond = compile_exp(Rc::unwrap_or_clone(cond), gen);
+            match cond.as_bool() {
+                Some(true) => compile_exp(Rc::unwrap_or_clone(then), gen),
+                Some(false) => compile_exp(Rc::unwrap_or_clone(else_), gen),
+                None => if_(
+                    cond,
+                    compile_exp(Rc::unwrap_or_clone(then), gen),
+                    compile_exp(Rc::unwrap_or_clone(else_), gen),
+                ),
+            }
+        }
+        Exp::Let((x, bind), body) => {
+            let bind = compile_exp(Rc::unwrap_or_clone(bind), gen);
+            let body = compile_exp(Rc::unwrap_or_clone(body), gen);
+            if is_constant(&bind) {
+                compile_exp(subst(bind, x, body, gen), gen)
+            } else {
+                Exp::Let((x, Rc::new(bind)), Rc::new(body))
+            }
+        }
+        Exp::LetRec(binds, body) => Exp::LetRec(
+            binds
+                .iter()
+                .map(|(x, bind)| (*x, compile_exp(bind.clone(), gen)))
+                .collect(),
+            Rc::new(compile_exp(Rc::unwrap_or_clone(body), gen)),
+        ),
+        Exp::List(es) => {
+            let es = es
+                .iter()
+                .map(|e| compile_exp(e.clone(), gen))
+                .collect::<Vec<_>>();
+            match es.iter().map(Exp::node).collect::<Vec<_>>().as_slice() {
+                [Exp::Symbol(op), Exp::Integer(lhs), Exp::Integer(rhs)] => {
+                    match fold_integer_op(op.as_str(), *lhs, *rhs) {
+                        Some(int) => integer(int),
+                        None => Exp::List(es.into()),
+                    }
+                }
+                [Exp::Lambda(..), ..] => {
+                    let mut es = es.into_iter();
+                    let head = es.next().unwrap();
+                    es.fold(head, |acc, e| compile_app(acc, e, gen))
+                }
+                _ => Exp::List(es.into()),
+            }
+        }
+        Exp::Spanned(span, e) => match compile_exp(Rc::unwrap_or_clone(e), gen) {
+            e if is_constant(&e) => e,
+            e => spanned(span, e),
+        },
+        _ => exp,
+    }
+}
+
+/// Rewrites `exp` into an equivalent but cheaper expression.
+///
+/// Folds integer arithmetic on literals, drops `if` branches whose condition is a
+/// literal boolean and beta-reduces lambdas applied to literal arguments. The
+/// arithmetic folding assumes `+`, `-`, `*` and `/` refer to the default builtins.
+pub fn compile(exp: Exp) -> CompiledExp {
+    let mut gen = VariableGenerator::new();
+    compile_exp(exp, &mut gen)
+}
+
+pub fn eval_compiled(
+    compiled: &CompiledExp,
+    module: &Module,
+    gen: &mut VariableGenerator,
+) -> Result<Exp> {
+    eval(compiled.clone(), module, gen)
+}
+
+pub fn eval_empty_module(exp: Exp) -> Result<Exp> {
+    let mut gen = VariableGenerator::new();
+    let module = Module::new("empty");
+    eval(exp, &module, &mut gen)
+}
+
+pub fn eval_default_module(exp: Exp) -> Result<Exp> {
+    let mut gen = VariableGenerator::new();
+    let module = default_module();
+    eval(exp, &module, &mut gen)
+}
+
+/// Parses and evaluates `source` in the default module, for tests.
+#[cfg(test)]
+pub(crate) fn eval_str(source: &str) -> Result<Exp> {
+    eval_default_module(crate::parser::parse_exp(source).unwrap())
+}
+
+/// Like [`eval_str`], keeping only the kind of the error.
+#[cfg(test)]
+pub(crate) fn eval_kind(source: &str) -> Result<Exp> {
+    eval_str(source).map_err(|err| err.kind().clone())
+}
+
+impl Module {
+    pub fn run(&self, name: &str, args: Vec<Exp>) -> Result<Exp> {
+        let mut exp = self
+            .defines
+            .get(&SymbolId::intern(name))
+            .cloned()
+            .ok_or_else(|| EvalError::SymbolNotFound(name.to_string()))?;
+        let mut gen = VariableGenerator::new();
+        for arg in args {
+            exp = apply(exp, arg);
+        }
+        let stack = vec![Frame::Call(SymbolId::intern(name))];
+        run(State::Eval(exp, Env::new()), stack, self, &mut gen)
+    }
+
+    pub fn eval(&self, exp: Exp) -> Result<Exp> {
+        self.eval_with(exp, EvalConfig::default())
+    }
+
+    /// Evaluates `exp` within the limits of `config`.
+    pub fn eval_with(&self, exp: Exp, config: EvalConfig) -> Result<Exp> {
+        let mut gen = VariableGenerator::with_config(config);
+        eval(exp, self, &mut gen)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{buildin::default_module, loader::load_module};
+    use crate::{
+        buildin::default_module,
+        loader::{load_module, LoadError},
+        parser::parse_exp,
+    };
 
     use super::*;
 
@@ -306,7 +2019,7 @@ mod test {
         // [2/x]x => 2
         let e = subst(
             integer(2),
-            "x".to_string(),
+            "x".into(),
             symbol("x"),
             &mut VariableGenerator::new(),
         );
@@ -316,7 +2029,7 @@ mod test {
         // => ((\ #0 1) 2)
         let e = subst(
             integer(2),
-            "x".to_string(),
+            "x".into(),
             apply(lambda("x", integer(1)), symbol("x")),
             &mut VariableGenerator::new(),
         );
@@ -326,7 +2039,7 @@ mod test {
         // => ((\ #0 #0) 1)
         let e = subst(
             integer(2),
-            "x".to_string(),
+            "x".into(),
             apply(lambda("x", symbol("x")), integer(1)),
             &mut VariableGenerator::new(),
         );
@@ -337,7 +2050,7 @@ mod test {
         // => ((\ #0 #0) 1)
         let e = subst(
             integer(2),
-            "x".to_string(),
+            "x".into(),
             let_(("x", integer(1)), symbol("x")),
             &mut VariableGenerator::new(),
         );
@@ -348,7 +2061,7 @@ mod test {
         // => ((\#0 2) 1)
         let e = subst(
             integer(2),
-            "x".to_string(),
+            "x".into(),
             let_(("y", integer(1)), symbol("x")),
             &mut VariableGenerator::new(),
         );
@@ -397,16 +2110,37 @@ mod test {
         );
         assert_eq!(eval_empty_module(e), Ok(symbol("b")));
 
-        // ((\x (\y (x y))) y)
-        // => (\#0 (y #0))
+        // ((\x (\y (x y))) 'f)
+        // => closure of (\y (x y)) with x = f
         let e = apply(
             lambda("x", lambda("y", apply(symbol("x"), symbol("y")))),
-            symbol("y"),
+            quote(symbol("f")),
         );
         assert_eq!(
             eval_empty_module(e),
-            Ok(lambda("#0", apply(symbol("y"), symbol("#0")))),
+            Ok(Exp::Closure(
+                "y".into(),
+                Rc::new(apply(symbol("x"), symbol("y"))),
+                Env::new().bind("x".into(), symbol("f")),
+            )),
+        );
+
+        // (((\x (\x x)) 1) 2) => 2
+        let e = apply(
+            apply(lambda("x", lambda("x", symbol("x"))), integer(1)),
+            integer(2),
         );
+        assert_eq!(eval_empty_module(e), Ok(integer(2)));
+
+        // (let (x 1) (let (f (\y x)) (let (x 2) (f 0)))) => 1
+        let e = let_(
+            ("x", integer(1)),
+            let_(
+                ("f", lambda("y", symbol("x"))),
+                let_(("x", integer(2)), apply(symbol("f"), integer(0))),
+            ),
+        );
+        assert_eq!(eval_empty_module(e), Ok(integer(1)));
     }
 
     #[test]
@@ -421,10 +2155,128 @@ mod test {
     }
 
     #[test]
-    fn test_frac() {
+    fn test_case() {
+        // (case 2 (1 'one) (2 'two)) => two
+        let e = case(
+            integer(2),
+            &[
+                (integer(1), quote(symbol("one"))),
+                (integer(2), quote(symbol("two"))),
+            ],
+        );
+        assert_eq!(eval_empty_module(e), Ok(symbol("two")));
+
+        // (case 'b ('a 1) ('b 2)) => 2
+        let e = case(
+            quote(symbol("b")),
+            &[
+                (quote(symbol("a")), integer(1)),
+                (quote(symbol("b")), integer(2)),
+            ],
+        );
+        assert_eq!(eval_empty_module(e), Ok(integer(2)));
+
+        // (case 3 (1 'one)) => error
+        let e = case(integer(3), &[(integer(1), quote(symbol("one")))]);
+        assert_eq!(
+            eval_empty_module(e),
+            Err(EvalError::NoMatchingCase(integer(3)))
+        );
+    }
+
+    #[test]
+    fn test_case_destructuring() {
+        assert_eq!(
+            eval_str("(case (list 1 2 3) ((cons h t) (list h t)))"),
+            Ok(list(&[integer(1), list(&[integer(2), integer(3)])]))
+        );
+        assert_eq!(
+            eval_str("(case (list 1 (list 2 3)) ((list a (b c)) (+ a (* b c))))"),
+            Ok(integer(7))
+        );
+        // Literals inside a pattern must match, falling through otherwise.
+        assert_eq!(
+            eval_str("(case (list 'add 1 2) (('sub x y) (- x y)) (('add x y) (+ x y)))"),
+            Ok(integer(3))
+        );
+        assert_eq!(
+            eval_str("(case (list) ((cons h t) 'cons) (() 'empty))"),
+            Ok(symbol("empty"))
+        );
+        // Bound variables shadow outer ones only in their own arm.
+        assert_eq!(
+            eval_str("(let (x 1) (list (case 2 (x x)) x))"),
+            Ok(list(&[integer(2), integer(1)]))
+        );
+
+        let module = load_module(
+            "(module test
+              (define sum (xs acc)
+                (case xs
+                  (() acc)
+                  ((cons x rest) (sum rest (+ acc x)))))
+              (define main () (sum (list 1 2 3 4) 0)))",
+        )
+        .unwrap();
+        assert_eq!(module.run("main", vec![]), Ok(integer(10)));
+
+        // Substitution does not replace variables bound by a pattern.
+        let e = parse_exp("(let (x 1) (case (list 2) ((list x) x)))").unwrap();
+        assert_eq!(eval_default_module(compile(e)), Ok(integer(2)));
+    }
+
+    #[test]
+    fn test_case_guard() {
+        let sign = |n: i64| {
+            eval_kind(&format!(
+                "(case {} (n (when (> n 0)) 'pos) (n (when (< n 0)) 'neg) (n 'zero))",
+                n
+            ))
+        };
+        assert_eq!(sign(3), Ok(symbol("pos")));
+        assert_eq!(sign(-3), Ok(symbol("neg")));
+        assert_eq!(sign(0), Ok(symbol("zero")));
+        // The guard sees the variables of the pattern.
+        assert_eq!(
+            eval_kind("(case (list 1 2) ((list a b) (when (> a b)) a) ((list a b) b))"),
+            Ok(integer(2))
+        );
+        assert_eq!(
+            eval_kind("(case 1 (n (when false) n))"),
+            Err(EvalError::NoMatchingCase(integer(1)))
+        );
+        assert!(matches!(
+            eval_kind("(case 1 (n (when n) n))"),
+            Err(EvalError::ExpectedBool(_))
+        ));
+    }
+
+    #[test]
+    fn test_case_wildcard() {
+        assert_eq!(
+            eval_kind("(case (list 1 2 3) ((list _ x _) x))"),
+            Ok(integer(2))
+        );
+        assert_eq!(
+            eval_kind("(case 5 (1 'one) (else 'other))"),
+            Ok(symbol("other"))
+        );
+        // `_` binds nothing.
+        assert!(matches!(
+            eval_kind("(case 5 (_ _))"),
+            Err(EvalError::SymbolNotFound(_))
+        ));
+        assert_eq!(
+            eval_kind("(case (list 1) ((list) 'empty))"),
+            Err(EvalError::NoMatchingCase(list(&[integer(1)])))
+        );
+    }
+
+    #[test]
+    fn test_frac() {
         let mut module = default_module();
         module.defines.insert(
-            "frac".to_string(),
+            "frac".into(),
             lambda(
                 "n",
                 if_(
@@ -470,6 +2322,412 @@ mod test {
         assert_eq!(eval_default_module(e), Ok(integer(2)));
     }
 
+    #[test]
+    fn test_compile() {
+        // (+ 2 3) => 5
+        let e = list(&[symbol("+"), integer(2), integer(3)]);
+        assert_eq!(compile(e), integer(5));
+
+        // (if true 1 2) => 1
+        let e = if_(bool(true), integer(1), integer(2));
+        assert_eq!(compile(e), integer(1));
+
+        // ((\ x (+ x 1)) 5) => 6
+        let e = list(&[
+            lambda("x", list(&[symbol("+"), symbol("x"), integer(1)])),
+            integer(5),
+        ]);
+        assert_eq!(compile(e), integer(6));
+
+        // (/ 1 0) is left for eval to report
+        let e = list(&[symbol("/"), integer(1), integer(0)]);
+        assert_eq!(compile(e.clone()), e);
+
+        // (\ x (if (= x 0) (* 2 3) x)) => (\ x (if (= x 0) 6 x))
+        let e = lambda(
+            "x",
+            if_(
+                list(&[symbol("="), symbol("x"), integer(0)]),
+                list(&[symbol("*"), integer(2), integer(3)]),
+                symbol("x"),
+            ),
+        );
+        let compiled = compile(e);
+        assert_eq!(
+            compiled,
+            lambda(
+                "x",
+                if_(
+                    list(&[symbol("="), symbol("x"), integer(0)]),
+                    integer(6),
+                    symbol("x"),
+                ),
+            )
+        );
+        assert_eq!(
+            eval_compiled(
+                &apply(compiled, integer(0)),
+                &default_module(),
+                &mut VariableGenerator::new()
+            ),
+            Ok(integer(6))
+        );
+    }
+
+    #[test]
+    fn test_define_values() {
+        let mut module = default_module();
+        let mut gen = VariableGenerator::new();
+
+        // (define-values (x y) (values 10 20))
+        let e = list(&[
+            symbol("define-values"),
+            list(&[symbol("x"), symbol("y")]),
+            list(&[symbol("values"), integer(10), integer(20)]),
+        ]);
+        assert_eq!(eval_top_level(e, &mut module, &mut gen), Ok(void()));
+        // (+ x y) => 30
+        let e = list(&[symbol("+"), symbol("x"), symbol("y")]);
+        assert_eq!(eval_top_level(e, &mut module, &mut gen), Ok(integer(30)));
+
+        // (define-values (a b) (values 1 2 3)) => error
+        let e = list(&[
+            symbol("define-values"),
+            list(&[symbol("a"), symbol("b")]),
+            list(&[symbol("values"), integer(1), integer(2), integer(3)]),
+        ]);
+        assert!(eval_top_level(e, &mut module, &mut gen).is_err());
+        assert!(!module.defines.contains_key(&"a".into()));
+
+        // (define-values (z) 5)
+        let e = list(&[symbol("define-values"), list(&[symbol("z")]), integer(5)]);
+        assert_eq!(eval_top_level(e, &mut module, &mut gen), Ok(void()));
+        assert_eq!(module.defines.get(&"z".into()), Some(&integer(5)));
+    }
+
+    #[test]
+    fn test_define_top_level() {
+        let mut module = default_module();
+        let mut gen = VariableGenerator::new();
+
+        // (define double (x) (* x 2))
+        let e = list(&[
+            symbol("define"),
+            symbol("double"),
+            list(&[symbol("x")]),
+            list(&[symbol("*"), symbol("x"), integer(2)]),
+        ]);
+        assert_eq!(eval_top_level(e, &mut module, &mut gen), Ok(void()));
+
+        // (define ten (double 5))
+        let e = list(&[
+            symbol("define"),
+            symbol("ten"),
+            list(&[symbol("double"), integer(5)]),
+        ]);
+        assert_eq!(eval_top_level(e, &mut module, &mut gen), Ok(void()));
+        assert_eq!(module.defines.get(&"ten".into()), Some(&integer(10)));
+
+        // (macro swap (a b) '(list ~b ~a))
+        let e = list(&[
+            symbol("macro"),
+            symbol("swap"),
+            list(&[symbol("a"), symbol("b")]),
+            quote(list(&[
+                symbol("list"),
+                unquote(symbol("b")),
+                unquote(symbol("a")),
+            ])),
+        ]);
+        assert_eq!(eval_top_level(e, &mut module, &mut gen), Ok(void()));
+
+        // (swap ten (double ten)) => (20 10)
+        let e = list(&[
+            symbol("swap"),
+            symbol("ten"),
+            list(&[symbol("double"), symbol("ten")]),
+        ]);
+        assert_eq!(
+            eval_top_level(e, &mut module, &mut gen),
+            Ok(list(&[integer(20), integer(10)]))
+        );
+    }
+
+    #[test]
+    fn test_error_span() {
+        use crate::token::Location;
+
+        let exp = parse_exp("(+ 1\n   (first 2))").unwrap();
+        let err = eval_default_module(exp).unwrap_err();
+        assert_eq!(
+            err.span(),
+            Some(Span::new(Location::new(1, 3), Location::new(1, 12)))
+        );
+        assert_eq!(
+            err.kind(),
+            &EvalError::TypeMismatch {
+                expected: "a non-empty list or a pair",
+                got: integer(2),
+                callee: Some("list/first".to_string()),
+                position: Some(1),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "2:4: list/first expects a non-empty list or a pair as argument 1, got 2"
+        );
+
+        let source = r#"
+        (module test
+            (define main () (foo 1)))
+        "#;
+        let module = load_module(source).unwrap();
+        let err = module.run("main", vec![]).unwrap_err();
+        assert_eq!(
+            err.span(),
+            Some(Span::new(Location::new(2, 29), Location::new(2, 32)))
+        );
+        assert_eq!(err.kind(), &EvalError::SymbolNotFound("foo".to_string()));
+    }
+
+    #[test]
+    fn test_tail_call() {
+        let source = r#"
+        (module test
+            (define count (n acc) (if (= n 0) acc (count (- n 1) (+ acc 1))))
+            (define parity (n) (let (m (- n 2)) (if (= n 0) true (if (= n 1) false (parity m))))))
+        "#;
+        let module = load_module(source).unwrap();
+        assert_eq!(
+            module.run("count", vec![integer(20000), integer(0)]),
+            Ok(integer(20000))
+        );
+        assert_eq!(module.run("parity", vec![integer(20000)]), Ok(bool(true)));
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        // (+ 1 (+ 1 ... (+ 1 0)))
+        let e = (0..10000).fold(integer(0), |acc, _| list(&[symbol("+"), integer(1), acc]));
+        assert_eq!(eval_default_module(e), Ok(integer(10000)));
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let source = r#"
+        (module test
+            (define count (n) (if (= n 0) 0 (+ 1 (count (- n 1)))))
+            (define nest (x) (map nest (list x))))
+        "#;
+        let module = load_module(source).unwrap();
+        let config = EvalConfig {
+            max_depth: 1000,
+            ..EvalConfig::default()
+        };
+        let call = |name: &str, n| list(&[symbol(name), integer(n)]);
+        assert_eq!(
+            module.eval_with(call("count", 100), config.clone()),
+            Ok(integer(100))
+        );
+        let err = module
+            .eval_with(call("count", 10000), config.clone())
+            .unwrap_err();
+        assert_eq!(err.kind(), &EvalError::DepthLimitExceeded);
+        assert!(err
+            .to_string()
+            .ends_with("evaluation nested too deeply (in count, called from count (249 times))"));
+        // Recursion through a builtin nests evaluations on the host stack.
+        assert_eq!(
+            module
+                .eval(call("nest", 1))
+                .map_err(|err| err.kind().clone()),
+            Err(EvalError::DepthLimitExceeded)
+        );
+        assert_eq!(
+            module.eval_with(
+                parse_exp("(try (count 10000) (catch e e))").unwrap(),
+                config
+            ),
+            Ok(string("evaluation nested too deeply"))
+        );
+    }
+
+    #[test]
+    fn test_fuel() {
+        let source = r#"
+        (module test
+            (define loop (n) (loop (+ n 1)))
+            (define sum (xs) (foldl + 0 xs)))
+        "#;
+        let module = load_module(source).unwrap();
+        let config = EvalConfig {
+            fuel: Some(10_000),
+            ..EvalConfig::default()
+        };
+        let eval = |source| module.eval_with(parse_exp(source).unwrap(), config.clone());
+        assert_eq!(eval("(sum '(1 2 3))"), Ok(integer(6)));
+        assert_eq!(
+            eval("(loop 0)").map_err(|err| err.kind().clone()),
+            Err(EvalError::FuelExhausted)
+        );
+        assert_eq!(
+            eval("(try (loop 0) (catch e 0))").map_err(|err| err.kind().clone()),
+            Err(EvalError::FuelExhausted)
+        );
+        // Builtins that call back into the evaluator draw on the same fuel.
+        assert_eq!(
+            eval("(map loop '(1))").map_err(|err| err.kind().clone()),
+            Err(EvalError::FuelExhausted)
+        );
+
+        let mut gen = VariableGenerator::with_config(config);
+        eval_in(parse_exp("(+ 1 2)").unwrap(), Env::new(), &module, &mut gen).unwrap();
+        assert!(gen.fuel().is_some_and(|fuel| fuel < 10_000));
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let source = r#"
+        (module test
+            (define grow (xs) (grow (append xs xs)))
+            (define pile (x) (pile (cons x x))))
+        "#;
+        let module = load_module(source).unwrap();
+        let config = EvalConfig {
+            max_memory: Some(1 << 20),
+            ..EvalConfig::default()
+        };
+        let mut gen = VariableGenerator::with_config(config.clone());
+        let mut eval = |source| {
+            eval_in(parse_exp(source).unwrap(), Env::new(), &module, &mut gen)
+                .map_err(|err| err.kind().clone())
+        };
+        assert_eq!(eval("(length (range 100))"), Ok(integer(100)));
+        assert_eq!(eval("(grow '(1))"), Err(EvalError::MemoryLimitExceeded));
+        assert!(gen.allocated() > 1 << 20);
+
+        let pile = module.eval_with(parse_exp("(pile 1)").unwrap(), config.clone());
+        assert_eq!(
+            pile.map_err(|err| err.kind().clone()),
+            Err(EvalError::MemoryLimitExceeded)
+        );
+
+        // Builtins fail before they build a value that is too large.
+        let eval = |source| {
+            module
+                .eval_with(parse_exp(source).unwrap(), config.clone())
+                .map_err(|err| err.kind().clone())
+        };
+        assert_eq!(eval("(length (range 1000))"), Ok(integer(1000)));
+        assert_eq!(
+            eval("(range 0 9223372036854775807)"),
+            Err(EvalError::MemoryLimitExceeded)
+        );
+        #[cfg(feature = "std-string")]
+        assert_eq!(
+            eval(r#"(string/repeat "xx" 9223372036854775807)"#),
+            Err(EvalError::MemoryLimitExceeded)
+        );
+        assert_eq!(
+            eval("(list/combinations 30 (range 60))"),
+            Err(EvalError::MemoryLimitExceeded)
+        );
+        let module = load_module("(module test (define main () (range 0 9223372036854775807)))");
+        assert_eq!(
+            module
+                .unwrap()
+                .run_compiled_with("main", vec![], config)
+                .map_err(|err| err.kind().clone()),
+            Err(EvalError::MemoryLimitExceeded)
+        );
+    }
+
+    #[test]
+    fn test_memory_limit_symbols() {
+        // Only the names of the symbols take memory here, as they stay interned.
+        let module = load_module(
+            "(module test (define gensyms (n) (if (= (gensym) 'x) n (gensyms (+ n 1)))))",
+        )
+        .unwrap();
+        let config = EvalConfig {
+            max_memory: Some(1 << 16),
+            ..EvalConfig::default()
+        };
+        assert_eq!(
+            module
+                .eval_with(parse_exp("(gensyms 0)").unwrap(), config)
+                .map_err(|err| err.kind().clone()),
+            Err(EvalError::MemoryLimitExceeded)
+        );
+
+        // A read that fails still keeps the names it interned.
+        let config = EvalConfig {
+            max_memory: Some(1),
+            ..EvalConfig::default()
+        };
+        assert_eq!(
+            module
+                .eval_with(parse_exp("(read \"(read-fails\")").unwrap(), config)
+                .map_err(|err| err.kind().clone()),
+            Err(EvalError::MemoryLimitExceeded)
+        );
+    }
+
+    #[test]
+    fn test_cancel() {
+        let module = load_module("(module test (define loop (n) (loop (+ n 1))))").unwrap();
+        let forever = parse_exp("(try (loop 0) (catch e e))").unwrap();
+
+        let cancel = CancelToken::new();
+        let canceller = {
+            let cancel = cancel.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                cancel.cancel();
+            })
+        };
+        let config = EvalConfig {
+            cancel: Some(cancel),
+            ..EvalConfig::default()
+        };
+        assert_eq!(
+            module
+                .eval_with(forever.clone(), config)
+                .map_err(|err| err.kind().clone()),
+            Err(EvalError::Cancelled)
+        );
+        canceller.join().unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_deadline() {
+        let module = load_module("(module test (define loop (n) (loop (+ n 1))))").unwrap();
+        let forever = parse_exp("(try (loop 0) (catch e e))").unwrap();
+        let config = EvalConfig {
+            deadline: Some(Instant::now() + std::time::Duration::from_millis(20)),
+            ..EvalConfig::default()
+        };
+        assert_eq!(
+            module
+                .eval_with(forever, config)
+                .map_err(|err| err.kind().clone()),
+            Err(EvalError::Cancelled)
+        );
+    }
+
+    #[cfg(feature = "std-math")]
+    #[test]
+    fn test_computed_list_argument() {
+        // (map odd (list 1 2)) => (true false)
+        let e = list(&[
+            symbol("map"),
+            symbol("odd"),
+            list(&[symbol("list"), integer(1), integer(2)]),
+        ]);
+        assert_eq!(eval_default_module(e), Ok(list(&[bool(true), bool(false)])));
+    }
+
     #[test]
     fn test_run_func_with_arg() {
         let source = r#"
@@ -484,6 +2742,29 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_unquote_splicing() {
+        assert_eq!(
+            eval_kind("(let (xs (list 2 3)) `(1 ,@xs 4 ,(+ 2 3)))"),
+            Ok(list(&[
+                integer(1),
+                integer(2),
+                integer(3),
+                integer(4),
+                integer(5)
+            ]))
+        );
+        assert_eq!(eval_kind("`(a ~@nil)"), Ok(list(&[symbol("a")])));
+        assert_eq!(
+            eval_kind("`(a ,@1)"),
+            Err(EvalError::ExpectedList(integer(1)))
+        );
+        assert!(matches!(
+            eval_kind("(list ,@(list 1))"),
+            Err(EvalError::UnquoteOutsideQuote(_))
+        ));
+    }
+
     #[test]
     fn test_unquote() {
         let source = r#"
@@ -533,10 +2814,8 @@ mod test {
     fn test_macro() {
         let source = r#"
         (module test
-            (macro unless (cond then else) '(if ~cond ~else ~then))
-            (macro and (a b) '(if ~a ~b ~a))
-            (macro or (a b) '(if ~a ~a ~b))
-            (define test1 () (unless (= 1 1) (/ 1 0) 'b))
+            (macro if-not (cond then else) '(if ~cond ~else ~then))
+            (define test1 () (if-not (= 1 1) (/ 1 0) 'b))
             (define test2 () (and false (/ 1 0)))
             (define test3 () (or true (/ 1 0))))"#;
         let module = load_module(source).unwrap();
@@ -545,4 +2824,349 @@ mod test {
         assert_eq!(module.run("test2", vec![]), Ok(bool(false)));
         assert_eq!(module.run("test3", vec![]), Ok(bool(true)));
     }
+
+    #[test]
+    fn test_define_macro() {
+        let source = r#"
+        (module test
+            (define-macro my-or (a b) '(if ~a true ~b))
+            (define test1 () (my-or false (when-not false 'b)))
+            (define test2 () (let (when-not (\ (c e) e)) (when-not true 'shadowed)))
+            (define-macro when-not (c e) '(if ~c nil ~e)))"#;
+        let module = load_module(source).unwrap();
+        // Macro calls are expanded when the module is loaded.
+        assert_eq!(
+            module.defines.get(&"test1".into()),
+            Some(&if_(
+                bool(false),
+                bool(true),
+                if_(bool(false), nil(), quote(symbol("b")))
+            ))
+        );
+        assert_eq!(module.run("test1", vec![]), Ok(symbol("b")));
+        assert_eq!(module.run("test2", vec![]), Ok(symbol("shadowed")));
+
+        let source = r#"
+        (module test
+            (define-macro forever (x) '(list (forever ~x)))
+            (define main () (forever 1)))"#;
+        match load_module(source) {
+            Err(LoadError::EvalError(err)) => {
+                assert!(matches!(err.kind(), EvalError::ExpansionTooDeep(_)))
+            }
+            result => panic!("expected an expansion error, got {:?}", result),
+        }
+
+        let mut module = default_module();
+        let mut gen = VariableGenerator::new();
+        let exp = parse_exp("(define-macro swap (a b) '(list ~b ~a))").unwrap();
+        assert_eq!(eval_top_level(exp, &mut module, &mut gen), Ok(Exp::Void));
+        let exp = parse_exp("(define f (x) (swap x 2))").unwrap();
+        assert_eq!(eval_top_level(exp, &mut module, &mut gen), Ok(Exp::Void));
+        assert_eq!(
+            module.defines.get(&"f".into()),
+            Some(&lambda(
+                "x",
+                list(&[symbol("list"), integer(2), symbol("x")])
+            ))
+        );
+    }
+
+    #[test]
+    fn test_cond() {
+        let source = r#"
+        (module test
+            (define sign (n)
+                (cond ((< n 0) 'negative)
+                      ((= n 0) 'zero)
+                      (else 'positive)))
+            (define small (n) (cond ((< n 10) 'small))))"#;
+        let module = load_module(source).unwrap();
+        assert_eq!(
+            module.run("sign", vec![integer(-5)]),
+            Ok(symbol("negative"))
+        );
+        assert_eq!(module.run("sign", vec![integer(0)]), Ok(symbol("zero")));
+        assert_eq!(module.run("sign", vec![integer(5)]), Ok(symbol("positive")));
+        assert_eq!(module.run("small", vec![integer(50)]), Ok(nil()));
+    }
+
+    #[cfg(feature = "std-io")]
+    #[test]
+    fn test_begin() {
+        let source = r#"
+        (module test
+            (define count (n)
+                (if (= n 0)
+                    'done
+                    (begin (+ n 1) (count (- n 1)))))
+            (define main ()
+                (begin (print 1) (print 2) (+ 1 2))))"#;
+        let module = load_module(source).unwrap();
+        assert_eq!(module.run("main", vec![]), Ok(integer(3)));
+        // The last expression is in tail position.
+        assert_eq!(
+            module.run("count", vec![integer(100000)]),
+            Ok(symbol("done"))
+        );
+    }
+
+    #[cfg(feature = "std-io")]
+    #[test]
+    fn test_when_unless() {
+        assert_eq!(eval_str("(when (< 1 2) 'yes)"), Ok(symbol("yes")));
+        assert_eq!(eval_str("(when (> 1 2) (/ 1 0))"), Ok(nil()));
+        assert_eq!(eval_str("(unless (> 1 2) 'yes)"), Ok(symbol("yes")));
+        assert_eq!(eval_str("(unless (< 1 2) (/ 1 0))"), Ok(nil()));
+        assert_eq!(eval_str("(when true (print 1) 'done)"), Ok(symbol("done")));
+    }
+
+    #[test]
+    fn test_multi_param_lambda() {
+        assert_eq!(eval_str("((\\ (x y) (- x y)) 5 3)"), Ok(integer(2)));
+        // Applying fewer arguments leaves a function of the rest.
+        assert_eq!(
+            eval_str("(let (sub5 ((\\ (x y z) (- x (+ y z))) 5)) (sub5 1 2))"),
+            Ok(integer(2))
+        );
+    }
+
+    #[test]
+    fn test_variadic() {
+        assert_eq!(
+            eval_str("((\\ (. xs) xs) 1 2 3)"),
+            Ok(list(&[integer(1), integer(2), integer(3)]))
+        );
+        assert_eq!(
+            eval_str("((\\ (x . rest) (list x rest)) 1 2 3)"),
+            Ok(list(&[integer(1), list(&[integer(2), integer(3)])]))
+        );
+        // The rest parameter is bound even without further arguments.
+        assert_eq!(eval_str("((\\ (x . rest) rest) 1)"), Ok(list(&[])));
+        assert_eq!(eval_str("((\\ (. xs) xs))"), Ok(list(&[])));
+        // A variadic body takes the rest of the arguments of the same call.
+        assert_eq!(
+            eval_str("((\\ (x y) (\\ (. zs) (list x y zs))) 1 2 3)"),
+            Ok(list(&[integer(1), integer(2), list(&[integer(3)])]))
+        );
+        assert_eq!(
+            eval_str("((\\ (x y) (\\ (. zs) (list x y zs))) 1 2)"),
+            Ok(list(&[integer(1), integer(2), list(&[])]))
+        );
+        assert_eq!(
+            eval_str("(map (\\ (. xs) xs) (list 1 2))"),
+            Ok(list(&[list(&[integer(1)]), list(&[integer(2)])]))
+        );
+
+        let module = load_module(
+            "(module test
+              (define sum (. xs) (foldr + 0 xs))
+              (define main () (sum 1 2 3)))",
+        )
+        .unwrap();
+        assert_eq!(module.run("main", vec![]), Ok(integer(6)));
+    }
+
+    #[test]
+    fn test_let_sequential() {
+        let exp = parse_exp("(let ((x 1) (y (+ x 1)) (x (* y 10))) (list x y))").unwrap();
+        assert_eq!(
+            eval_default_module(exp),
+            Ok(list(&[integer(20), integer(2)]))
+        );
+    }
+
+    #[test]
+    fn test_named_let() {
+        assert_eq!(
+            eval_str("(let loop ((i 3) (acc 1)) (if (= i 0) acc (loop (- i 1) (* i acc))))"),
+            Ok(integer(6))
+        );
+        // The recursive call is in tail position.
+        assert_eq!(
+            eval_str("(let loop ((i 0)) (if (= i 100000) i (loop (+ i 1))))"),
+            Ok(integer(100000))
+        );
+    }
+
+    #[test]
+    fn test_letrec() {
+        assert_eq!(
+            eval_kind("(letrec (fact (\\ n (if (= n 0) 1 (* n (fact (- n 1)))))) (fact 5))"),
+            Ok(integer(120))
+        );
+        assert_eq!(
+            eval_kind(
+                "(letrec ((even? (\\ n (if (= n 0) true (odd? (- n 1)))))
+                          (odd? (\\ n (if (= n 0) false (even? (- n 1))))))
+                   (even? 100001))"
+            ),
+            Ok(bool(false))
+        );
+        // The bindings are not visible outside the body.
+        assert_eq!(
+            eval_kind("(let (f (letrec (f (\\ x (f x))) 1)) f)"),
+            Ok(integer(1))
+        );
+        assert_eq!(
+            eval_kind("(letrec (x 1) x)"),
+            Err(EvalError::ExpectedLambda(integer(1)))
+        );
+
+        // Substituting into a letrec must not capture its bound names.
+        let e =
+            parse_exp("(let (f 3) (letrec (g (\\ n (if (= n 0) f (g (- n 1))))) (g 2)))").unwrap();
+        assert_eq!(eval_default_module(compile(e)), Ok(integer(3)));
+    }
+
+    #[test]
+    fn test_and_or() {
+        assert_eq!(eval_kind("(and true (< 1 2) (= 1 1))"), Ok(bool(true)));
+        assert_eq!(eval_kind("(and true false (/ 1 0))"), Ok(bool(false)));
+        assert_eq!(eval_kind("(or false (= 1 1) (/ 1 0))"), Ok(bool(true)));
+        assert_eq!(eval_kind("(or false false)"), Ok(bool(false)));
+        assert_eq!(eval_kind("(and true 'x)"), Ok(symbol("x")));
+        assert!(matches!(
+            eval_kind("(and 1 true)"),
+            Err(EvalError::ExpectedBool(_))
+        ));
+    }
+
+    #[test]
+    fn test_try() {
+        assert_eq!(eval_kind("(try (+ 1 2) (catch e 'failed))"), Ok(integer(3)));
+        assert_eq!(
+            eval_kind("(try (+ 1 (raise 'oops)) (catch e (list 'caught e)))"),
+            Ok(list(&[symbol("caught"), symbol("oops")]))
+        );
+        // Errors other than raise are caught as their message.
+        assert_eq!(
+            eval_kind("(try (/ 1 0) (catch e e))"),
+            Ok(string("division by zero: (1 0)"))
+        );
+        // The innermost handler catches, and may raise to the next one.
+        assert_eq!(
+            eval_kind("(try (try (raise 1) (catch e (raise (+ e 1)))) (catch e (* e 10)))"),
+            Ok(integer(20))
+        );
+        // Raising inside a function called by a builtin unwinds through it.
+        assert_eq!(
+            eval_kind("(try (map (\\ (x) (if (= x 2) (raise x) x)) '(1 2 3)) (catch e (list e)))"),
+            Ok(list(&[integer(2)]))
+        );
+        assert_eq!(
+            eval_kind("(let (e 1) (+ e (try (raise 2) (catch e e))))"),
+            Ok(integer(3))
+        );
+        assert_eq!(
+            eval_kind("(raise \"oops\")"),
+            Err(EvalError::Raised(string("oops")))
+        );
+
+        let source = r#"
+        (module test
+            (define safe-div (a b) (try (/ a b) (catch _ 0)))
+            (define main () (+ (safe-div 6 3) (safe-div 1 0))))
+        "#;
+        let module = load_module(source).unwrap();
+        assert_eq!(module.run("main", vec![]), Ok(integer(2)));
+    }
+
+    #[test]
+    fn test_trace() {
+        let source = r#"
+        (module test
+            (define check (x) (if (< x 3) x (error "too big" x)))
+            (define checked (xs) (map check xs))
+            (define sum (xs) (foldl + 0 (checked xs)))
+            (define count (n) (if (= n 0) (first n) (count (- n 1))))
+            (define main () (+ 1 (sum '(1 2 3)))))
+        "#;
+        let module = load_module(source).unwrap();
+        let err = module.run("main", vec![]).unwrap_err();
+        assert_eq!(
+            err.trace(),
+            ["check", "checked", "sum", "main"].map(SymbolId::intern)
+        );
+        assert_eq!(err.kind().to_string(), "too big: 3",);
+        assert!(err
+            .to_string()
+            .ends_with("(in check, called from checked, called from sum, called from main)"));
+
+        // A tail call replaces its caller, so deep recursion leaves one entry.
+        let err = module.run("count", vec![integer(1000)]).unwrap_err();
+        assert_eq!(err.trace(), [SymbolId::intern("count")]);
+
+        // The call frames inside a try unwind to its handler.
+        assert_eq!(
+            module.eval(parse_exp("(try (check 5) (catch e 0))").unwrap()),
+            Ok(integer(0))
+        );
+    }
+
+    #[test]
+    fn test_argument_errors() {
+        assert_eq!(
+            eval_kind("(length '(1) '(2))"),
+            Err(EvalError::ArityMismatch {
+                expected: Arity::Exactly(1),
+                got: 2,
+                callee: Some("list/length".to_string()),
+            })
+        );
+        assert_eq!(
+            eval_kind("(apply length '((1) (2)))").map_err(|err| err.to_string()),
+            Err("list/length takes 1 argument but was given 2".to_string())
+        );
+        assert_eq!(
+            eval_kind("(range 1 2 3 4)").map_err(|err| err.to_string()),
+            Err("list/range takes 1 to 3 arguments but was given 4".to_string())
+        );
+        assert_eq!(
+            eval_kind(r#"(+ 1 "a")"#),
+            Err(EvalError::TypeMismatch {
+                expected: "a number",
+                got: string("a"),
+                callee: Some("math/+".to_string()),
+                position: Some(2),
+            })
+        );
+        assert_eq!(
+            eval_kind("(nth 5 '(1 2))"),
+            Err(EvalError::IndexOutOfBounds { index: 5, len: 2 })
+        );
+        assert_eq!(
+            eval_kind("(vector/ref -1 [1 2 3])").map_err(|err| err.to_string()),
+            Err("index -1 is out of bounds for length 3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_user_error() {
+        assert_eq!(
+            eval_kind(r#"(error "not found" 'key 404)"#),
+            Err(EvalError::UserError {
+                message: "not found".to_string(),
+                data: vec![symbol("key"), integer(404)],
+            })
+        );
+        assert_eq!(
+            eval_kind(r#"(error "not found" 'key 404)"#)
+                .unwrap_err()
+                .to_string(),
+            "not found: key 404"
+        );
+        assert_eq!(
+            eval_kind(r#"(try (error "not found" 'key) (catch e (map/get :data e)))"#),
+            Ok(list(&[symbol("key")]))
+        );
+        assert_eq!(
+            eval_kind(r#"(try (error "empty") (catch e (map/get :message e)))"#),
+            Ok(string("empty"))
+        );
+        assert!(matches!(
+            eval_kind("(error 'oops)"),
+            Err(EvalError::TypeMismatch { .. })
+        ));
+    }
 }
@@ -0,0 +1,437 @@
+//! The source formatter behind `topogi fmt`.
+//!
+//! The parser drops comments and desugars forms such as `define` and `cond`,
+//! so the formatter reads the source into a tree of its own that keeps the
+//! text of every atom and comment. The tree is then laid out with the
+//! [pretty-printer](crate::pretty).
+
+use crate::{
+    parser::{parse_module, ParseError},
+    prelude::*,
+    pretty::{align, group, nest, text, Doc},
+};
+
+/// Line width the formatter aims for.
+pub const WIDTH: usize = 80;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    /// An atom or a block comment, as written.
+    Atom(String),
+    /// A comment, without its line break.
+    Comment(String),
+    /// A list, vector or map literal, given by its opening delimiter.
+    List(char, Vec<Item>),
+    /// A quote, unquote or datum comment prefix and the node it applies to.
+    Prefix(String, Box<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Item {
+    node: Node,
+    /// Whether the item starts on the line the previous item ends on.
+    same_line: bool,
+    /// Whether an empty line separates the item from the previous one.
+    blank_before: bool,
+}
+
+struct Reader {
+    input: Vec<char>,
+    pos: usize,
+}
+
+fn separator(ch: char) -> bool {
+    matches!(ch, '(' | ')' | '[' | ']' | '{' | '}') || ch.is_whitespace()
+}
+
+fn closing(open: char) -> char {
+    match open {
+        '[' => ']',
+        '{' => '}',
+        _ => ')',
+    }
+}
+
+impl Reader {
+    fn peek(&self) -> Option<char> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, ch)| self.input.get(self.pos + i) == Some(&ch))
+    }
+
+    /// Skips whitespace and returns the number of line breaks skipped.
+    fn skip_whitespace(&mut self) -> usize {
+        let mut lines = 0;
+        while let Some(ch) = self.peek().filter(|ch| ch.is_whitespace()) {
+            if ch == '\n' {
+                lines += 1;
+            }
+            self.pos += 1;
+        }
+        lines
+    }
+
+    /// Reads the items up to a closing delimiter or the end of the input.
+    fn items(&mut self) -> Vec<Item> {
+        let mut items = vec![];
+        loop {
+            let lines = self.skip_whitespace();
+            match self.peek() {
+                None | Some(')' | ']' | '}') => return items,
+                Some(_) => items.push(Item {
+                    node: self.node(),
+                    same_line: lines == 0 && !items.is_empty(),
+                    blank_before: lines > 1 && !items.is_empty(),
+                }),
+            }
+        }
+    }
+
+    fn take_while(&mut self, mut pred: impl FnMut(char) -> bool) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(&mut pred) {
+            self.pos += 1;
+        }
+        self.input[start..self.pos].iter().collect()
+    }
+
+    /// The text from `start` up to the current position.
+    fn since(&self, start: usize) -> String {
+        self.input[start..self.pos.min(self.input.len())]
+            .iter()
+            .collect()
+    }
+
+    fn node(&mut self) -> Node {
+        for prefix in ["~@", ",@", "'", "`", "~", ",", "#;"] {
+            if self.starts_with(prefix) {
+                self.pos += prefix.len();
+                self.skip_whitespace();
+                return Node::Prefix(prefix.to_string(), Box::new(self.node()));
+            }
+        }
+        match self.peek() {
+            Some(';') => Node::Comment(self.take_while(|ch| ch != '\n').trim_end().to_string()),
+            Some(open @ ('(' | '[' | '{')) => {
+                self.pos += 1;
+                let items = self.items();
+                self.pos += 1;
+                Node::List(open, items)
+            }
+            Some('"') => {
+                let start = self.pos;
+                self.pos += 1;
+                while let Some(ch) = self.peek() {
+                    self.pos += if ch == '\\' { 2 } else { 1 };
+                    if ch == '"' {
+                        break;
+                    }
+                }
+                Node::Atom(self.since(start))
+            }
+            Some('#') if self.starts_with("#|") => {
+                let start = self.pos;
+                let mut depth = 0;
+                while self.pos < self.input.len() {
+                    if self.starts_with("#|") {
+                        depth += 1;
+                        self.pos += 2;
+                    } else if self.starts_with("|#") {
+                        depth -= 1;
+                        self.pos += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        self.pos += 1;
+                    }
+                }
+                Node::Atom(self.since(start))
+            }
+            Some('#') if self.starts_with("#\\") => {
+                // The first character is taken as is, so that `#\(` is one atom.
+                let start = self.pos;
+                self.pos += 3;
+                self.take_while(|ch| !separator(ch));
+                Node::Atom(self.since(start))
+            }
+            Some('#') if self.starts_with("#u8(") => {
+                let start = self.pos;
+                self.take_while(|ch| ch != ')');
+                self.pos += 1;
+                Node::Atom(self.since(start))
+            }
+            Some('#') if self.starts_with("#x\"") => {
+                let start = self.pos;
+                self.pos += 3;
+                self.take_while(|ch| ch != '"');
+                self.pos += 1;
+                Node::Atom(self.since(start))
+            }
+            _ => Node::Atom(self.take_while(|ch| !separator(ch))),
+        }
+    }
+}
+
+/// The number of leading elements of forms with a body that stay on the line
+/// of the head, such as the name and parameters of a `define`.
+fn header_len(head: &str) -> Option<usize> {
+    match head {
+        "define" | "macro" | "define-macro" => Some(2),
+        "deftest" => Some(1),
+        "module" | "let" | "letrec" | "if" | "\\" | "case" | "when" | "unless" | "catch" => Some(1),
+        "cond" | "begin" | "try" => Some(0),
+        _ => None,
+    }
+}
+
+/// The separator before `item`, which follows `prev`.
+fn separator_doc(prev: &Item, item: &Item) -> Doc {
+    match (&prev.node, &item.node) {
+        // Nothing may follow a comment on its line.
+        (Node::Comment(_), _) => Doc::HardLine(item.blank_before),
+        (_, Node::Comment(_)) if item.same_line => text(" "),
+        _ if item.blank_before => Doc::HardLine(true),
+        _ => Doc::Line,
+    }
+}
+
+/// The items separated by lines, or kept on one line if they fit.
+fn items_doc(items: &[Item]) -> Doc {
+    let mut docs = vec![];
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            docs.push(separator_doc(&items[i - 1], item));
+        }
+        docs.push(node_doc(&item.node));
+    }
+    if let Some(Item {
+        node: Node::Comment(_),
+        ..
+    }) = items.last()
+    {
+        // The closing parenthesis may not follow a comment.
+        docs.push(Doc::HardLine(false));
+    }
+    Doc::Concat(docs)
+}
+
+fn is_symbol(item: &Item) -> bool {
+    match &item.node {
+        Node::Atom(atom) => {
+            !atom.starts_with(|ch: char| ch.is_ascii_digit() || ch == '"' || ch == '#')
+        }
+        _ => false,
+    }
+}
+
+fn list_doc(items: &[Item]) -> Doc {
+    let Some((head, args)) = items.split_first() else {
+        return text("()");
+    };
+    if !is_symbol(head) || args.is_empty() {
+        return group(Doc::Concat(vec![
+            text("("),
+            align(items_doc(items)),
+            text(")"),
+        ]));
+    }
+    let Node::Atom(name) = &head.node else {
+        unreachable!()
+    };
+    match header_len(name) {
+        Some(n)
+            if args.len() > n
+                && !args[..n]
+                    .iter()
+                    .any(|arg| matches!(arg.node, Node::Comment(_))) =>
+        {
+            let mut docs = vec![text("("), text(name)];
+            for arg in &args[..n] {
+                docs.push(text(" "));
+                docs.push(node_doc(&arg.node));
+            }
+            let mut body = vec![];
+            for (i, item) in args[n..].iter().enumerate() {
+                let prev = if i == 0 { head } else { &args[n + i - 1] };
+                body.push(match separator_doc(prev, item) {
+                    // The body never shares the line of the header.
+                    Doc::Text(_) if i == 0 => Doc::Line,
+                    separator => separator,
+                });
+                body.push(node_doc(&item.node));
+            }
+            if let Some(Node::Comment(_)) = args.last().map(|arg| &arg.node) {
+                body.push(Doc::HardLine(false));
+            }
+            docs.push(nest(2, Doc::Concat(body)));
+            docs.push(text(")"));
+            group(align(Doc::Concat(docs)))
+        }
+        _ => group(Doc::Concat(vec![
+            text("("),
+            node_doc(&head.node),
+            text(" "),
+            align(items_doc(args)),
+            text(")"),
+        ])),
+    }
+}
+
+fn node_doc(node: &Node) -> Doc {
+    match node {
+        Node::Atom(atom) | Node::Comment(atom) => text(atom),
+        Node::List('(', items) => list_doc(items),
+        Node::List(open, items) => group(Doc::Concat(vec![
+            text(&open.to_string()),
+            align(items_doc(items)),
+            text(&closing(*open).to_string()),
+        ])),
+        Node::Prefix(prefix, node) => Doc::Concat(vec![text(prefix), node_doc(node)]),
+    }
+}
+
+/// Reprints a module with canonical indentation. Comments and single empty
+/// lines between forms are kept. Fails if the source is not a valid module.
+pub fn format_source(input: &str) -> Result<String, ParseError> {
+    parse_module(input)?;
+    let mut reader = Reader {
+        input: input.chars().collect(),
+        pos: 0,
+    };
+    let items = reader.items();
+    let mut docs = vec![];
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            docs.push(Doc::HardLine(item.blank_before));
+        }
+        docs.push(node_doc(&item.node));
+    }
+    let mut output = Doc::Concat(docs).layout(WIDTH);
+    output.push('\n');
+    Ok(output)
+}
+
+/// A line diff of `old` and `new` in the style of `diff -u`, without hunk
+/// headers. Unchanged lines are left out.
+pub fn diff(old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    // lcs[i][j] is the length of the longest common subsequence of old[i..]
+    // and new[j..].
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut out = String::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out += &format!("-{}\n", old[i]);
+            i += 1;
+        } else {
+            out += &format!("+{}\n", new[j]);
+            j += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_source() {
+        let source = "; A module.
+(module main
+    ; Adds one.
+    (define inc (x) (+ x 1)) ; trailing
+
+
+  (define main ()
+     (let (xs '(1 2 3)) (map inc xs))))
+";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "; A module.
+(module main
+  ; Adds one.
+  (define inc (x) (+ x 1)) ; trailing
+
+  (define main () (let (xs '(1 2 3)) (map inc xs))))
+"
+        );
+        assert_eq!(format_source(&formatted), Ok(formatted));
+    }
+
+    #[test]
+    fn test_format_comments() {
+        let source = "(module main
+  #| Block comments
+     are kept as written. |#
+  (define main ()   #; (debug 1)
+    (+ 1 2)))
+";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "(module main
+  #| Block comments
+     are kept as written. |#
+  (define main () #;(debug 1) (+ 1 2)))
+"
+        );
+        assert_eq!(format_source(&formatted), Ok(formatted));
+    }
+
+    #[test]
+    fn test_format_literals() {
+        let source = "(module main (define config () {:name   \"app\"
+   :ports [80
+           443]}))
+";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "(module main (define config () {:name \"app\" :ports [80 443]}))\n"
+        );
+    }
+
+    #[test]
+    fn test_format_long_call() {
+        let source = format!(
+            "(module main (define main () (list {})))",
+            ["\"a string\""; 10].join(" ")
+        );
+        let formatted = format_source(&source).unwrap();
+        assert!(formatted.lines().all(|line| line.len() <= WIDTH));
+        assert!(formatted.contains("(list \"a string\"\n          \"a string\""));
+        assert_eq!(format_source(&formatted), Ok(formatted));
+    }
+
+    #[test]
+    fn test_format_invalid() {
+        assert!(format_source("(module main (define main () (+ 1 2))").is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(diff("a\nb\nc\n", "a\nc\nd\n"), "-b\n+d\n");
+        assert_eq!(diff("a\n", "a\n"), "");
+    }
+}
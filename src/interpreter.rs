@@ -0,0 +1,220 @@
+//! An interpreter for hosts that embed topogi.
+//!
+//! [`Interpreter`] keeps a module together with the [`VariableGenerator`] its
+//! evaluations share, so that the host can evaluate one form after another
+//! the way the REPL does, without passing both around by hand.
+
+use crate::{
+    ast::{Exp, HostFn, Module},
+    buildin::default_module,
+    eval::{eval_top_level, EvalConfig, EvalError, Result, VariableGenerator},
+    parser::parse_exp,
+    prelude::*,
+    symbol::SymbolId,
+    vm::Vm,
+};
+
+pub struct Interpreter {
+    module: Module,
+    gen: VariableGenerator,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// An interpreter with the standard library and no limits.
+    pub fn new() -> Self {
+        Self::with_config(EvalConfig::default())
+    }
+
+    /// An interpreter with the standard library whose evaluations stay within
+    /// `config`. The limits are shared by everything it evaluates until it is
+    /// [`reset`](Interpreter::reset), so that a host can bound the work of a
+    /// script as a whole.
+    pub fn with_config(config: EvalConfig) -> Self {
+        Interpreter {
+            module: default_module(),
+            gen: VariableGenerator::with_config(config),
+        }
+    }
+
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    pub fn config(&self) -> &EvalConfig {
+        self.gen.config()
+    }
+
+    /// Parses `source` as a single form and evaluates it. A form that cannot
+    /// be parsed fails with [`EvalError::ReadError`].
+    pub fn eval_str(&mut self, source: &str) -> Result<Exp> {
+        let exp = parse_exp(source).map_err(|err| EvalError::ReadError(source.to_string(), err))?;
+        self.eval_exp(exp)
+    }
+
+    /// Evaluates `exp` as a top-level form, so that a `define` adds to the
+    /// definitions later forms can refer to.
+    pub fn eval_exp(&mut self, exp: Exp) -> Result<Exp> {
+        eval_top_level(exp, &mut self.module, &mut self.gen)
+    }
+
+    /// Applies the definition `name` to `args` and runs it on the [`Vm`],
+    /// within the same limits as the forms the interpreter evaluates.
+    pub fn run_compiled(&mut self, name: &str, args: Vec<Exp>) -> Result<Exp> {
+        let mut vm = Vm::with_generator(&self.module, core::mem::take(&mut self.gen));
+        let result = vm.run_function(name, args);
+        self.gen = vm.into_generator();
+        result
+    }
+
+    /// Binds `name` to `value`, replacing any definition of the name.
+    pub fn define(&mut self, name: &str, value: Exp) {
+        self.module.defines.insert(SymbolId::intern(name), value);
+    }
+
+    /// Binds `name` to the host function `f`. It is called with all the
+    /// arguments of a call at once, like a builtin, and may capture state of
+    /// the host.
+    pub fn register_fn(&mut self, name: &str, f: impl Fn(&[Exp]) -> Result<Exp> + 'static) {
+        self.define(name, Exp::HostFn(HostFn::new(f)));
+    }
+
+    /// The definition of `name`, which for a function is its lambda.
+    pub fn get(&self, name: &str) -> Option<&Exp> {
+        self.module.defines.get(&SymbolId::intern(name))
+    }
+
+    /// Forgets every definition and macro made since the interpreter was
+    /// created and gives its evaluations the full limits of the config again.
+    pub fn reset(&mut self) {
+        self.module = default_module();
+        self.gen = VariableGenerator::with_config(self.gen.config().clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::*;
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+
+    #[test]
+    fn test_interpreter() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.eval_str("(+ 1 2)"), Ok(integer(3)));
+        assert_eq!(
+            interpreter.eval_str("(define double (x) (* x 2))"),
+            Ok(Exp::Void)
+        );
+        interpreter.define("n", integer(21));
+        assert_eq!(interpreter.eval_str("(double n)"), Ok(integer(42)));
+        assert_eq!(interpreter.get("n"), Some(&integer(21)));
+        assert!(matches!(
+            interpreter.eval_str("(double"),
+            Err(EvalError::ReadError(..))
+        ));
+
+        interpreter.reset();
+        assert_eq!(interpreter.get("n"), None);
+        assert!(interpreter.eval_str("(double 1)").is_err());
+        assert_eq!(interpreter.eval_str("(+ 1 2)"), Ok(integer(3)));
+    }
+
+    #[test]
+    fn test_register_fn() {
+        let mut interpreter = Interpreter::new();
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        interpreter.register_fn("count", move |args| {
+            counter.set(counter.get() + 1);
+            Ok(integer(args.len() as i64))
+        });
+        assert_eq!(interpreter.eval_str("(count 1 2 3)"), Ok(integer(3)));
+        assert_eq!(
+            interpreter.eval_str("(map count (list 1 2))"),
+            Ok(list(&[integer(1), integer(1)]))
+        );
+        assert_eq!(calls.get(), 3);
+
+        interpreter.register_fn("fail", |_| Err(EvalError::Raised(integer(1))));
+        assert!(interpreter.eval_str("(fail)").is_err());
+
+        let count = interpreter.get("count").unwrap().clone();
+        assert_eq!(&count, interpreter.get("count").unwrap());
+        assert_ne!(&count, interpreter.get("fail").unwrap());
+    }
+
+    #[cfg(feature = "std-io")]
+    #[test]
+    fn test_output() {
+        let buffer = Rc::new(core::cell::RefCell::new(String::new()));
+        let mut interpreter = Interpreter::with_config(EvalConfig {
+            output: Some(crate::eval::Output(buffer.clone())),
+            ..EvalConfig::default()
+        });
+        interpreter
+            .eval_str(r#"(begin (print 1) (println "a") (write "a"))"#)
+            .unwrap();
+        assert_eq!(*buffer.borrow(), "1 a\n\"a\"");
+    }
+
+    #[test]
+    fn test_interpreter_limits() {
+        let mut interpreter = Interpreter::with_config(EvalConfig {
+            fuel: Some(1000),
+            ..EvalConfig::default()
+        });
+        interpreter
+            .eval_str("(define loop (n) (loop (+ n 1)))")
+            .unwrap();
+        assert_eq!(
+            interpreter
+                .eval_str("(loop 0)")
+                .map_err(|err| err.kind().clone()),
+            Err(EvalError::FuelExhausted)
+        );
+        assert_eq!(
+            interpreter
+                .eval_str("(+ 1 2)")
+                .map_err(|err| err.kind().clone()),
+            Err(EvalError::FuelExhausted)
+        );
+        interpreter.reset();
+        assert_eq!(interpreter.eval_str("(+ 1 2)"), Ok(integer(3)));
+    }
+
+    #[test]
+    fn test_run_compiled() {
+        let mut interpreter = Interpreter::with_config(EvalConfig {
+            fuel: Some(1000),
+            ..EvalConfig::default()
+        });
+        interpreter
+            .eval_str("(define loop (n) (loop (+ n 1)))")
+            .unwrap();
+        interpreter.eval_str("(define double (x) (* x 2))").unwrap();
+        assert_eq!(
+            interpreter.run_compiled("double", vec![integer(21)]),
+            Ok(integer(42))
+        );
+        assert_eq!(
+            interpreter
+                .run_compiled("loop", vec![integer(0)])
+                .map_err(|err| err.kind().clone()),
+            Err(EvalError::FuelExhausted)
+        );
+        // The VM used up the fuel of the interpreter.
+        assert_eq!(
+            interpreter
+                .eval_str("(+ 1 2)")
+                .map_err(|err| err.kind().clone()),
+            Err(EvalError::FuelExhausted)
+        );
+    }
+}
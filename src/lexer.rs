@@ -1,5 +1,9 @@
-use crate::token::{get_token_word, Location, Token, TokenKind};
-use std::ops::Not;
+use crate::{
+    ast::checked_rational,
+    prelude::*,
+    token::{get_token_word, Location, Token, TokenKind},
+};
+use core::ops::Not;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Lexer {
@@ -14,6 +18,8 @@ pub enum LexerError {
     IsNotInteger(Location),
     Eot(Location),
     InvalidSymbol(Location),
+    InvalidBytes(Location),
+    InvalidChar(Location),
 }
 
 pub fn lexer_error_message(error: LexerError, input: &str) -> String {
@@ -38,6 +44,14 @@ pub fn lexer_error_message(error: LexerError, input: &str) -> String {
             let word = get_token_word(loc, input);
             format!("{}\n{} invalid symbol", word, "^".repeat(word.len()))
         }
+        LexerError::InvalidBytes(loc) => {
+            let word = get_token_word(loc, input);
+            format!("{}\n{} invalid byte string", word, "^".repeat(word.len()))
+        }
+        LexerError::InvalidChar(loc) => {
+            let word = get_token_word(loc, input);
+            format!("{}\n{} invalid character", word, "^".repeat(word.len()))
+        }
     }
 }
 
@@ -53,8 +67,22 @@ fn valid_sym(ch: char) -> bool {
     )
 }
 
+/// Removes the `_` separators from the digits of a number literal, or fails
+/// if a separator does not sit between two digits.
+fn strip_separators(digits: &str, radix: u32) -> Option<String> {
+    let chars = digits.chars().collect::<Vec<_>>();
+    let between_digits = |i: usize| {
+        i > 0
+            && chars[i - 1].is_digit(radix)
+            && chars.get(i + 1).is_some_and(|ch| ch.is_digit(radix))
+    };
+    (0..chars.len())
+        .all(|i| chars[i] != '_' || between_digits(i))
+        .then(|| chars.iter().filter(|&&ch| ch != '_').collect())
+}
+
 fn separator(ch: char) -> bool {
-    matches!(ch, '(' | ')' | '\n') || ch.is_whitespace()
+    matches!(ch, '(' | ')' | '[' | ']' | '{' | '}' | '\n') || ch.is_whitespace()
 }
 
 impl Lexer {
@@ -67,6 +95,10 @@ impl Lexer {
         }
     }
 
+    pub fn loc(&self) -> Location {
+        self.loc
+    }
+
     fn check_eof(&self) -> Result<(), LexerError> {
         if self.pos >= self.input.len() {
             Err(LexerError::Eot(self.loc))
@@ -141,6 +173,8 @@ impl Lexer {
         Ok(value)
     }
 
+    /// Reads the digits of an integer, a fraction such as `1/3`, or a float
+    /// with a decimal point and/or an exponent such as `1.5e-3`.
     pub fn numbers(&mut self) -> Result<String, LexerError> {
         let loc = self.loc;
         let mut value = String::new();
@@ -149,7 +183,10 @@ impl Lexer {
                 self.dec();
                 break;
             }
-            if !ch.is_ascii_digit() {
+            // Letters are checked by `number`, as they may be hex digits or a
+            // radix prefix.
+            let exponent_sign = matches!(ch, '+' | '-') && value.ends_with(['e', 'E']);
+            if !ch.is_ascii_alphanumeric() && !matches!(ch, '.' | '_' | '/') && !exponent_sign {
                 return Err(LexerError::IsNotInteger(loc));
             }
             value.push(ch);
@@ -157,6 +194,171 @@ impl Lexer {
         Ok(value)
     }
 
+    fn number(value: &str, loc: Location) -> Result<Token, LexerError> {
+        let (sign, unsigned) = match value.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", value),
+        };
+        let radix = match unsigned.get(..2) {
+            Some("0x" | "0X") => Some(16),
+            Some("0o" | "0O") => Some(8),
+            Some("0b" | "0B") => Some(2),
+            _ => None,
+        };
+        let kind = if let Some(radix) = radix {
+            strip_separators(&unsigned[2..], radix)
+                .filter(|digits| !digits.is_empty())
+                .and_then(|digits| {
+                    let digits = format!("{}{}", sign, digits);
+                    let integer = i64::from_str_radix(&digits, radix)
+                        .map(TokenKind::Integer)
+                        .ok();
+                    #[cfg(feature = "bignum")]
+                    let integer = integer.or_else(|| {
+                        num_bigint::BigInt::parse_bytes(digits.as_bytes(), radix)
+                            .map(TokenKind::BigInt)
+                    });
+                    integer
+                })
+        } else if let Some(value) = strip_separators(value, 10) {
+            Self::decimal(&value)
+        } else {
+            None
+        };
+        kind.map(|kind| Token::new(kind, loc))
+            .ok_or(LexerError::IsNotInteger(loc))
+    }
+
+    /// The token of a decimal integer, float or rational literal.
+    fn decimal(value: &str) -> Option<TokenKind> {
+        if let Some((numerator, denominator)) = value.split_once('/') {
+            match (numerator.parse::<i64>(), denominator.parse::<i64>()) {
+                (Ok(numerator), Ok(denominator))
+                    if checked_rational(numerator.into(), denominator.into()).is_some() =>
+                {
+                    Some(TokenKind::Rational(numerator, denominator))
+                }
+                _ => None,
+            }
+        } else if value.contains(['.', 'e', 'E']) {
+            value.parse().map(TokenKind::Float).ok()
+        } else {
+            let integer = value.parse().map(TokenKind::Integer).ok();
+            // Integer literals too large for `i64` become big integers.
+            #[cfg(feature = "bignum")]
+            let integer = integer.or_else(|| value.parse().map(TokenKind::BigInt).ok());
+            integer
+        }
+    }
+
+    /// Skips a block comment `#| ... |#`, which may nest. The `#|` has
+    /// already been read.
+    fn block_comment(&mut self) -> Result<(), LexerError> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next_cher()? {
+                '|' if self.input.get(self.pos) == Some(&'#') => {
+                    self.inc()?;
+                    depth -= 1;
+                }
+                '#' if self.input.get(self.pos) == Some(&'|') => {
+                    self.inc()?;
+                    depth += 1;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Skips the tokens of one datum, for a datum comment `#;`. A closing
+    /// parenthesis is left in place, so `(f #;)` comments out nothing.
+    fn skip_datum(&mut self) -> Result<(), LexerError> {
+        let token = self.next_token()?;
+        match token.kind {
+            TokenKind::LParen => {
+                while self.peek_token()?.kind != TokenKind::RParen {
+                    self.skip_datum()?;
+                }
+                self.token = None;
+                Ok(())
+            }
+            TokenKind::Quote
+            | TokenKind::QuasiQuote
+            | TokenKind::UnQuote
+            | TokenKind::UnQuoteSplicing => self.skip_datum(),
+            TokenKind::RParen => {
+                self.token = Some(token);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads a byte string, either the bytes `#u8(1 2 255)` or the hex digits
+    /// `#x"0102ff"`. The `#` has already been read.
+    fn bytes(&mut self, loc: Location) -> Result<Token, LexerError> {
+        let invalid = LexerError::InvalidBytes(loc);
+        let mut bytes = vec![];
+        match self.next_cher()? {
+            'u' if self.next_cher()? == '8' && self.next_cher()? == '(' => loop {
+                while self.current_char()?.is_whitespace() {
+                    self.inc()?;
+                }
+                if self.current_char()? == ')' {
+                    self.inc()?;
+                    break;
+                }
+                let value = self.numbers().map_err(|_| invalid.clone())?;
+                bytes.push(value.parse().map_err(|_| invalid.clone())?);
+            },
+            'x' if self.next_cher()? == '"' => {
+                let mut digits = String::new();
+                while self.current_char()? != '"' {
+                    digits.push(self.next_cher()?);
+                }
+                self.inc()?;
+                if !digits.len().is_multiple_of(2)
+                    || !digits.chars().all(|ch| ch.is_ascii_hexdigit())
+                {
+                    return Err(invalid);
+                }
+                for i in (0..digits.len()).step_by(2) {
+                    bytes.push(u8::from_str_radix(&digits[i..i + 2], 16).unwrap());
+                }
+            }
+            _ => return Err(invalid),
+        }
+        Ok(Token::new(TokenKind::Bytes(bytes), loc))
+    }
+
+    /// Reads a character such as `#\\a`, `#\\(` or `#\\space`. The `#\\` has
+    /// already been read.
+    fn character(&mut self, loc: Location) -> Result<Token, LexerError> {
+        // The first character is taken as is, so that `#\\(` is a character.
+        let mut name = String::from(self.next_cher()?);
+        while let Ok(ch) = self.next_cher() {
+            if separator(ch) {
+                self.dec();
+                break;
+            }
+            name.push(ch);
+        }
+        let mut chars = name.chars();
+        let ch = match (chars.next(), chars.next()) {
+            (Some(ch), None) => ch,
+            _ => match name.as_str() {
+                "space" => ' ',
+                "newline" => '\n',
+                "tab" => '\t',
+                "return" => '\r',
+                "nul" => '\0',
+                _ => return Err(LexerError::InvalidChar(loc)),
+            },
+        };
+        Ok(Token::new(TokenKind::Char(ch), loc))
+    }
+
     pub fn peek_token(&mut self) -> Result<Token, LexerError> {
         if let Some(token) = &self.token {
             Ok(token.clone())
@@ -189,11 +391,54 @@ impl Lexer {
                 self.inc()?;
                 Ok(Token::new(TokenKind::Quote, loc))
             }
-            '~' => {
+            '`' => {
+                let loc = self.loc;
+                self.inc()?;
+                Ok(Token::new(TokenKind::QuasiQuote, loc))
+            }
+            '~' | ',' => {
                 let loc = self.loc;
                 self.inc()?;
+                if self.input.get(self.pos) == Some(&'@') {
+                    self.inc()?;
+                    return Ok(Token::new(TokenKind::UnQuoteSplicing, loc));
+                }
                 Ok(Token::new(TokenKind::UnQuote, loc))
             }
+            '.' if self.input.get(self.pos + 1).is_none_or(|ch| separator(*ch)) => {
+                let loc = self.loc;
+                self.inc()?;
+                Ok(Token::new(TokenKind::Dot, loc))
+            }
+            '#' => {
+                let loc = self.loc;
+                self.inc()?;
+                match self.input.get(self.pos) {
+                    Some('\\') => {
+                        self.inc()?;
+                        self.character(loc)
+                    }
+                    Some('|') => {
+                        self.inc()?;
+                        self.block_comment()?;
+                        self.next_token()
+                    }
+                    Some(';') => {
+                        self.inc()?;
+                        self.skip_datum()?;
+                        self.next_token()
+                    }
+                    _ => self.bytes(loc),
+                }
+            }
+            ':' => {
+                let loc = self.loc;
+                self.inc()?;
+                match self.symbol()? {
+                    name if name.is_empty() => Err(LexerError::InvalidSymbol(loc)),
+                    name => Ok(Token::new(TokenKind::Keyword(name), loc)),
+                }
+            }
             '(' => {
                 let loc = self.loc;
                 self.inc()?;
@@ -204,33 +449,27 @@ impl Lexer {
                 self.inc()?;
                 Ok(Token::new(TokenKind::RParen, loc))
             }
-            '0' => {
+            '[' | ']' | '{' | '}' => {
                 let loc = self.loc;
-                let value = self.numbers()?;
-                Ok(Token::new(
-                    TokenKind::Integer(value.parse().map_err(|_| LexerError::IsNotInteger(loc))?),
-                    loc,
-                ))
+                let kind = match self.next_cher()? {
+                    '[' => TokenKind::LBracket,
+                    ']' => TokenKind::RBracket,
+                    '{' => TokenKind::LBrace,
+                    _ => TokenKind::RBrace,
+                };
+                Ok(Token::new(kind, loc))
             }
-            '1'..='9' => {
+            '0'..='9' => {
                 let loc = self.loc;
                 let value = self.numbers()?;
-                Ok(Token::new(
-                    TokenKind::Integer(value.parse().map_err(|_| LexerError::IsNotInteger(loc))?),
-                    loc,
-                ))
+                Self::number(&value, loc)
             }
             '-' => {
                 let loc = self.loc;
                 self.inc()?;
                 if let Some('0'..='9') = self.input.get(self.pos) {
                     let value = format!("-{}", self.numbers()?);
-                    Ok(Token::new(
-                        TokenKind::Integer(
-                            value.parse().map_err(|_| LexerError::IsNotInteger(loc))?,
-                        ),
-                        loc,
-                    ))
+                    Self::number(&value, loc)
                 } else {
                     let value = format!("-{}", self.symbol()?);
                     Ok(Token::new(TokenKind::Symbol(value), loc))
@@ -240,10 +479,23 @@ impl Lexer {
                 let mut value = String::new();
                 let loc = self.loc;
                 self.inc()?;
-                while self.input[self.pos] != '"' {
-                    value.push(self.next_cher()?);
+                loop {
+                    match self.next_cher()? {
+                        '"' => break,
+                        '\\' => match self.next_cher()? {
+                            'n' => value.push('\n'),
+                            't' => value.push('\t'),
+                            'r' => value.push('\r'),
+                            ch @ ('"' | '\\') => value.push(ch),
+                            // Unknown escapes are kept as written.
+                            ch => {
+                                value.push('\\');
+                                value.push(ch);
+                            }
+                        },
+                        ch => value.push(ch),
+                    }
                 }
-                self.inc()?;
                 Ok(Token::new(TokenKind::String(value), loc))
             }
             _ => {
@@ -337,6 +589,142 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_string_escape() {
+        let mut lexer = Lexer::new(r#""a\"b\\c\nd\te\q""#);
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(
+                TokenKind::String("a\"b\\c\nd\te\\q".to_string()),
+                Location::new(0, 0)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut lexer = Lexer::new(r#"#u8( 1 2 255) #x"00fF" #u8()"#);
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(
+                TokenKind::Bytes(vec![1, 2, 255]),
+                Location::new(0, 0)
+            ))
+        );
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(
+                TokenKind::Bytes(vec![0, 255]),
+                Location::new(0, 14)
+            ))
+        );
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(TokenKind::Bytes(vec![]), Location::new(0, 23)))
+        );
+
+        for source in ["#u8(256)", "#u8(1.5)", r#"#x"abc""#, r#"#x"zz""#, "#abc"] {
+            assert_eq!(
+                Lexer::new(source).next_token(),
+                Err(LexerError::InvalidBytes(Location::new(0, 0))),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_char() {
+        let mut lexer = Lexer::new(r"#\a #\( #\space)");
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(TokenKind::Char('a'), Location::new(0, 0)))
+        );
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(TokenKind::Char('('), Location::new(0, 4)))
+        );
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(TokenKind::Char(' '), Location::new(0, 8)))
+        );
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(TokenKind::RParen, Location::new(0, 15)))
+        );
+
+        assert_eq!(
+            Lexer::new(r"#\spaces").next_token(),
+            Err(LexerError::InvalidChar(Location::new(0, 0)))
+        );
+    }
+
+    #[test]
+    fn test_brackets() {
+        let mut lexer = Lexer::new("[a]{:b 1}");
+        let kinds = core::iter::from_fn(|| lexer.next_token().ok())
+            .map(|token| token.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::LBracket,
+                TokenKind::Symbol("a".to_string()),
+                TokenKind::RBracket,
+                TokenKind::LBrace,
+                TokenKind::Keyword("b".to_string()),
+                TokenKind::Integer(1),
+                TokenKind::RBrace,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keyword() {
+        let mut lexer = Lexer::new(":name :a-b?)");
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(
+                TokenKind::Keyword("name".to_string()),
+                Location::new(0, 0)
+            ))
+        );
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(
+                TokenKind::Keyword("a-b?".to_string()),
+                Location::new(0, 6)
+            ))
+        );
+        assert_eq!(
+            Lexer::new(": a").next_token(),
+            Err(LexerError::InvalidSymbol(Location::new(0, 0)))
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let mut lexer =
+            Lexer::new("a ; line\n#| block #| nested |# |# b #;(c (d) 'e) #; 'f g (h #;)");
+        let kinds = core::iter::from_fn(|| lexer.next_token().ok())
+            .map(|token| token.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Symbol("a".to_string()),
+                TokenKind::Symbol("b".to_string()),
+                TokenKind::Symbol("g".to_string()),
+                TokenKind::LParen,
+                TokenKind::Symbol("h".to_string()),
+                TokenKind::RParen,
+            ]
+        );
+
+        let mut lexer = Lexer::new("#| open");
+        assert!(matches!(lexer.next_token(), Err(LexerError::Eot(_))));
+    }
+
     #[test]
     fn test_number() {
         let mut lexer = Lexer::new("123 456");
@@ -353,6 +741,72 @@ mod tests {
             lexer.next_token(),
             Err(LexerError::IsNotInteger(Location::new(0, 0)))
         );
+
+        let mut lexer = Lexer::new("1.5 -0.25 2e3 1.5e-3");
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(TokenKind::Float(1.5), Location::new(0, 0)))
+        );
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(TokenKind::Float(-0.25), Location::new(0, 4)))
+        );
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(TokenKind::Float(2000.0), Location::new(0, 10)))
+        );
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(TokenKind::Float(0.0015), Location::new(0, 14)))
+        );
+        let mut lexer = Lexer::new("1/3 -2/4");
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(TokenKind::Rational(1, 3), Location::new(0, 0)))
+        );
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(TokenKind::Rational(-2, 4), Location::new(0, 4)))
+        );
+        let mut lexer = Lexer::new("1/0");
+        assert_eq!(
+            lexer.next_token(),
+            Err(LexerError::IsNotInteger(Location::new(0, 0)))
+        );
+        let mut lexer = Lexer::new("1.2.3");
+        assert_eq!(
+            lexer.next_token(),
+            Err(LexerError::IsNotInteger(Location::new(0, 0)))
+        );
+    }
+
+    #[test]
+    fn test_number_radix() {
+        let mut lexer = Lexer::new("0xff 0b1010 0o17 -0x10 1_000_000 0xdead_beef 1_000.5");
+        let kinds = core::iter::from_fn(|| lexer.next_token().ok())
+            .map(|token| token.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Integer(255),
+                TokenKind::Integer(10),
+                TokenKind::Integer(15),
+                TokenKind::Integer(-16),
+                TokenKind::Integer(1_000_000),
+                TokenKind::Integer(0xdead_beef),
+                TokenKind::Float(1000.5),
+            ]
+        );
+
+        for source in ["0x", "0xfg", "0b102", "0o8", "1__0", "1_", "0x_1", "1_.5"] {
+            assert_eq!(
+                Lexer::new(source).next_token(),
+                Err(LexerError::IsNotInteger(Location::new(0, 0))),
+                "{}",
+                source
+            );
+        }
     }
 
     #[test]
@@ -383,6 +837,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_quasiquote() {
+        let mut lexer = Lexer::new("`(,a ,@b ~@c)");
+        let kinds = core::iter::from_fn(|| lexer.next_token().ok())
+            .map(|token| token.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::QuasiQuote,
+                TokenKind::LParen,
+                TokenKind::UnQuote,
+                TokenKind::Symbol("a".to_string()),
+                TokenKind::UnQuoteSplicing,
+                TokenKind::Symbol("b".to_string()),
+                TokenKind::UnQuoteSplicing,
+                TokenKind::Symbol("c".to_string()),
+                TokenKind::RParen,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dot() {
+        let mut lexer = Lexer::new("(x . y)");
+        lexer.skip_token();
+        lexer.skip_token();
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::new(TokenKind::Dot, Location::new(0, 3)))
+        );
+
+        let mut lexer = Lexer::new(".x");
+        assert_eq!(
+            lexer.next_token(),
+            Err(LexerError::InvalidSymbol(Location::new(0, 0)))
+        );
+    }
+
     #[test]
     fn test_uniop_minus() {
         let mut lexer = Lexer::new("(- 1)");
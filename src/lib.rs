@@ -1,7 +1,43 @@
+//! Without the `std` feature the interpreter needs only `alloc`, so it can be
+//! embedded where there is no operating system. Definitions are then kept in
+//! ordered maps, and evaluation has no deadline.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod args;
 pub mod ast;
 pub mod buildin;
+pub mod compile;
+pub mod convert;
+pub mod diagnostics;
 pub mod eval;
+pub mod fmt;
+pub mod interpreter;
 pub mod lexer;
+pub mod lint;
 pub mod loader;
 pub mod parser;
+pub mod pretty;
+pub mod symbol;
+pub mod testing;
 pub mod token;
+pub mod vm;
+
+/// What the standard prelude would bring into scope, for builds without it.
+mod prelude {
+    pub(crate) use alloc::{
+        boxed::Box,
+        format,
+        string::{String, ToString},
+        vec,
+        vec::Vec,
+    };
+}
+
+#[cfg(feature = "std")]
+pub(crate) use std::collections::{HashMap, HashSet};
+
+#[cfg(not(feature = "std"))]
+pub(crate) use alloc::collections::{BTreeMap as HashMap, BTreeSet as HashSet};
@@ -0,0 +1,386 @@
+//! The static checks behind `topogi lint`.
+//!
+//! The linter walks the parsed, unexpanded module, so it sees the program as
+//! written. Macro definitions and the arguments of macro calls are skipped,
+//! since they need not be code.
+
+use core::fmt;
+
+use crate::{
+    ast::{Exp, Module},
+    buildin::default_module,
+    eval::pattern_vars,
+    loader::qualifier,
+    parser::{parse_module, ParseError},
+    prelude::*,
+    symbol::SymbolId,
+    token::Span,
+    HashSet,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LintKind {
+    /// A `let` or `letrec` binding that is never referred to.
+    UnusedBinding(String),
+    /// A binding that hides a parameter of an enclosing lambda.
+    ShadowedParameter(String),
+    /// A `case` pattern that an earlier arm already matches.
+    DuplicatePattern(Exp),
+    /// A call to a symbol that is bound nowhere.
+    UnknownSymbol(String),
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LintKind::UnusedBinding(name) => write!(f, "unused binding {}", name),
+            LintKind::ShadowedParameter(name) => write!(f, "binding shadows parameter {}", name),
+            LintKind::DuplicatePattern(pattern) => {
+                write!(f, "duplicate pattern {}", pattern.to_readable_string())
+            }
+            LintKind::UnknownSymbol(name) => write!(f, "call to unknown symbol {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub kind: LintKind,
+    pub span: Option<Span>,
+}
+
+/// Names starting with `_` are bound on purpose without being used, and the
+/// parser binds `#_` when sequencing expressions.
+fn is_ignored(name: SymbolId) -> bool {
+    name.as_str().starts_with(['_', '#'])
+}
+
+/// Whether `name` occurs anywhere in `exp`. Quoted occurrences count, since a
+/// quasi-quote or a macro may turn them into references.
+fn mentions(exp: &Exp, name: SymbolId) -> bool {
+    match exp {
+        Exp::Symbol(sym) => *sym == name,
+        Exp::List(es) | Exp::Values(es) => es.iter().any(|e| mentions(e, name)),
+        Exp::Vector(es) => es.iter().any(|e| mentions(e, name)),
+        Exp::Map(map) => map
+            .iter()
+            .any(|(key, value)| mentions(key, name) || mentions(value, name)),
+        Exp::Lambda(_, e)
+        | Exp::Spanned(_, e)
+        | Exp::Closure(_, e, _)
+        | Exp::Variadic(e)
+        | Exp::Quote(e)
+        | Exp::UnQuote(e)
+        | Exp::UnQuoteSplicing(e) => mentions(e, name),
+        Exp::Apply(e1, e2) | Exp::Pair(e1, e2) | Exp::Let((_, e1), e2) => {
+            mentions(e1, name) || mentions(e2, name)
+        }
+        Exp::If(c, t, e) => mentions(c, name) || mentions(t, name) || mentions(e, name),
+        Exp::Try(body, _, handler) => mentions(body, name) || mentions(handler, name),
+        Exp::LetRec(binds, e) => binds.iter().any(|(_, b)| mentions(b, name)) || mentions(e, name),
+        Exp::Case(e, cases) => {
+            mentions(e, name)
+                || cases.iter().any(|(pattern, guard, body)| {
+                    mentions(pattern, name)
+                        || guard.as_ref().is_some_and(|guard| mentions(guard, name))
+                        || mentions(body, name)
+                })
+        }
+        _ => false,
+    }
+}
+
+/// Whether a `case` pattern matches a single value without binding anything.
+fn is_literal_pattern(pattern: &Exp) -> bool {
+    matches!(
+        pattern.node(),
+        Exp::Nil
+            | Exp::Bool(_)
+            | Exp::Integer(_)
+            | Exp::Float(_)
+            | Exp::Rational(..)
+            | Exp::String(_)
+            | Exp::Bytes(_)
+            | Exp::Char(_)
+            | Exp::Keyword(_)
+            | Exp::Quote(_)
+    )
+}
+
+struct Linter<'a> {
+    module: &'a Module,
+    /// The names defined by the module and the standard library.
+    globals: HashSet<SymbolId>,
+    /// The local bindings in scope, innermost last, and whether each is a
+    /// parameter.
+    scope: Vec<(SymbolId, bool)>,
+    span: Option<Span>,
+    lints: Vec<Lint>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, kind: LintKind, span: Option<Span>) {
+        self.lints.push(Lint {
+            kind,
+            span: span.or(self.span),
+        });
+    }
+
+    fn bind(&mut self, name: SymbolId, is_param: bool, span: Option<Span>) {
+        if self
+            .scope
+            .iter()
+            .any(|&(bound, param)| bound == name && param)
+        {
+            self.report(LintKind::ShadowedParameter(name.to_string()), span);
+        }
+        self.scope.push((name, is_param));
+    }
+
+    fn unbind(&mut self, n: usize) {
+        self.scope.truncate(self.scope.len() - n);
+    }
+
+    fn is_bound(&self, name: SymbolId) -> bool {
+        self.scope.iter().any(|&(bound, _)| bound == name)
+            || self.module.defines.contains_key(&name)
+            || self.module.macros.contains_key(&name)
+            || self.globals.contains(&name)
+            || qualifier(name.as_str())
+                .is_some_and(|module| self.module.imports.iter().any(|import| import == module))
+    }
+
+    fn walk(&mut self, exp: &Exp) {
+        match exp {
+            Exp::Spanned(span, e) => {
+                let outer = self.span.replace(*span);
+                self.walk(e);
+                self.span = outer;
+            }
+            Exp::List(es) => match es.first().map(Exp::node) {
+                Some(Exp::Symbol(name)) if self.module.macros.contains_key(name) => {}
+                Some(Exp::Symbol(name)) => {
+                    if !self.is_bound(*name) {
+                        self.report(LintKind::UnknownSymbol(name.to_string()), es[0].span());
+                    }
+                    es[1..].iter().for_each(|arg| self.walk(arg));
+                }
+                _ => es.iter().for_each(|e| self.walk(e)),
+            },
+            Exp::Lambda(x, body) => {
+                self.bind(*x, true, None);
+                self.walk(body);
+                self.unbind(1);
+            }
+            Exp::Variadic(f) => self.walk(f),
+            Exp::Apply(e1, e2) => {
+                self.walk(e1);
+                self.walk(e2);
+            }
+            Exp::If(cond, then, else_) => {
+                self.walk(cond);
+                self.walk(then);
+                self.walk(else_);
+            }
+            Exp::Let((x, bind), body) => {
+                self.walk(bind);
+                if !is_ignored(*x) && !mentions(body, *x) {
+                    self.report(LintKind::UnusedBinding(x.to_string()), None);
+                }
+                self.bind(*x, false, None);
+                self.walk(body);
+                self.unbind(1);
+            }
+            Exp::LetRec(binds, body) => {
+                for (x, _) in binds.iter() {
+                    let used = mentions(body, *x) || binds.iter().any(|(_, b)| mentions(b, *x));
+                    if !is_ignored(*x) && !used {
+                        self.report(LintKind::UnusedBinding(x.to_string()), None);
+                    }
+                    self.bind(*x, false, None);
+                }
+                for (_, bind) in binds.iter() {
+                    self.walk(bind);
+                }
+                self.walk(body);
+                self.unbind(binds.len());
+            }
+            Exp::Case(e, cases) => {
+                self.walk(e);
+                let mut seen = HashSet::new();
+                for (pattern, guard, body) in cases {
+                    if guard.is_none()
+                        && is_literal_pattern(pattern)
+                        && !seen.insert(pattern.to_readable_string())
+                    {
+                        self.report(
+                            LintKind::DuplicatePattern(pattern.node().clone()),
+                            pattern.span(),
+                        );
+                    }
+                    let vars = pattern_vars(pattern);
+                    for var in &vars {
+                        self.bind(*var, false, pattern.span());
+                    }
+                    if let Some(guard) = guard {
+                        self.walk(guard);
+                    }
+                    self.walk(body);
+                    self.unbind(vars.len());
+                }
+            }
+            Exp::Try(body, x, handler) => {
+                self.walk(body);
+                self.bind(*x, false, None);
+                self.walk(handler);
+                self.unbind(1);
+            }
+            Exp::Quote(e) => self.walk_quoted(e),
+            Exp::UnQuote(e) | Exp::UnQuoteSplicing(e) => self.walk(e),
+            _ => {}
+        }
+    }
+
+    /// Walks the unquoted parts of a quoted expression.
+    fn walk_quoted(&mut self, exp: &Exp) {
+        match exp {
+            Exp::Spanned(span, e) => {
+                let outer = self.span.replace(*span);
+                self.walk_quoted(e);
+                self.span = outer;
+            }
+            Exp::List(es) => es.iter().for_each(|e| self.walk_quoted(e)),
+            Exp::Vector(es) => es.iter().for_each(|e| self.walk_quoted(e)),
+            Exp::Map(map) => map.iter().for_each(|(key, value)| {
+                self.walk_quoted(key);
+                self.walk_quoted(value);
+            }),
+            Exp::Pair(head, tail) => {
+                self.walk_quoted(head);
+                self.walk_quoted(tail);
+            }
+            Exp::UnQuote(e) | Exp::UnQuoteSplicing(e) => self.walk(e),
+            _ => {}
+        }
+    }
+}
+
+/// Checks the definitions of a parsed module for likely mistakes. The lints
+/// are ordered by position.
+pub fn lint_module(module: &Module) -> Vec<Lint> {
+    let prelude = default_module();
+    let mut linter = Linter {
+        module,
+        globals: prelude
+            .defines
+            .keys()
+            .chain(prelude.macros.keys())
+            .copied()
+            .collect(),
+        scope: vec![],
+        span: None,
+        lints: vec![],
+    };
+    for exp in module
+        .defines
+        .values()
+        .chain(module.tests.iter().map(|(_, body)| body))
+    {
+        linter.walk(exp);
+    }
+    let mut lints = linter.lints;
+    lints.sort_by_key(|lint| {
+        lint.span
+            .map(|span| (span.start.line, span.start.column))
+            .unwrap_or_default()
+    });
+    lints
+}
+
+/// Parses a module and lints it.
+pub fn lint_source(input: &str) -> Result<Vec<Lint>, ParseError> {
+    Ok(lint_module(&parse_module(input)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint_kinds(input: &str) -> Vec<LintKind> {
+        lint_source(input)
+            .unwrap()
+            .into_iter()
+            .map(|lint| lint.kind)
+            .collect()
+    }
+
+    #[cfg(feature = "std-io")]
+    #[test]
+    fn test_unused_binding() {
+        assert_eq!(
+            lint_kinds("(module main (define main () (let ((x 1) (y 2)) (+ y 1))))"),
+            vec![LintKind::UnusedBinding("x".to_string())]
+        );
+        assert_eq!(
+            lint_kinds("(module main (define main () (let (_x 1) (begin (print 1) 2))))"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_shadowed_parameter() {
+        assert_eq!(
+            lint_kinds("(module main (define f (x) (let (x (+ x 1)) (\\ (x) x))))"),
+            vec![
+                LintKind::ShadowedParameter("x".to_string()),
+                LintKind::ShadowedParameter("x".to_string()),
+            ]
+        );
+        assert_eq!(
+            lint_kinds("(module main (define f (x) (let (y x) (\\ (z) (+ y z)))))"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_pattern() {
+        let lints = lint_source(
+            "(module main
+  (define f (x)
+    (case x
+      (1 'one)
+      (\"a\" 'a)
+      (1 'uno)
+      (_ 'other))))",
+        )
+        .unwrap();
+        assert_eq!(
+            lints.iter().map(|lint| &lint.kind).collect::<Vec<_>>(),
+            vec![&LintKind::DuplicatePattern(Exp::Integer(1))]
+        );
+        assert_eq!(lints[0].span.map(|span| span.start.line), Some(5));
+    }
+
+    #[test]
+    fn test_unknown_symbol() {
+        assert_eq!(
+            lint_kinds(
+                "(module main
+  (define-macro twice (e) `(begin ~e ~e))
+  (define g (x) (* x 2))
+  (define main () (twice (frobnicate (g (first '(1 2)))))))"
+            ),
+            vec![]
+        );
+        assert_eq!(
+            lint_kinds("(module main (define main () (frobnicate 1)))"),
+            vec![LintKind::UnknownSymbol("frobnicate".to_string())]
+        );
+        assert_eq!(
+            lint_kinds(
+                "(module main (import arith) (define main () (+ (arith/square 2) (geo/area))))"
+            ),
+            vec![LintKind::UnknownSymbol("geo/area".to_string())]
+        );
+    }
+}
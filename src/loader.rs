@@ -1,17 +1,66 @@
+use alloc::rc::Rc;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
 use crate::{
-    ast::Module,
-    buildin::default_module,
-    parser::{ParseError, Parser},
+    ast::{Exp, Module},
+    buildin::{default_module, NAMESPACES},
+    eval::{expand, pattern_vars, EvalError, VariableGenerator},
+    parser::{parse_error_message, ParseError, Parser},
+    prelude::*,
+    symbol::SymbolId,
+    HashMap, HashSet,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LoadError {
     DuplicateDefinition(String),
     ParseError(ParseError),
+    /// Expanding the macro calls of a definition failed.
+    EvalError(EvalError),
+    /// A module exports a name it does not define.
+    UnknownExport(String),
+    /// The [`ModuleLoader`] has no module of the name.
+    ModuleNotFound(String),
+    /// The module imports itself, directly or through the modules it imports.
+    ImportCycle(String),
+    /// A definition or an imported module takes a namespace of the standard
+    /// library, such as `string/append` or `(import string)`.
+    ReservedName(String),
+    /// The source of a module could not be read, with the reason.
+    Unreadable(String, String),
+    /// Loading an imported module from the source at the path failed.
+    Imported {
+        path: String,
+        source: String,
+        error: Box<LoadError>,
+    },
 }
 
-pub type Result<T> = std::result::Result<T, LoadError>;
+pub type Result<T> = core::result::Result<T, LoadError>;
+
+pub fn load_error_message(error: LoadError, input: &str) -> String {
+    match error {
+        LoadError::DuplicateDefinition(name) => format!("duplicate definition of {}", name),
+        LoadError::ParseError(err) => parse_error_message(err, input),
+        LoadError::EvalError(err) => err.to_string(),
+        LoadError::UnknownExport(name) => format!("export of undefined {}", name),
+        LoadError::ModuleNotFound(name) => format!("module {} not found", name),
+        LoadError::ImportCycle(name) => format!("module {} imports itself", name),
+        LoadError::ReservedName(name) => format!("{} is reserved by the standard library", name),
+        LoadError::Unreadable(path, reason) => format!("cannot read {}: {}", path, reason),
+        LoadError::Imported {
+            path,
+            source,
+            error,
+        } => format!("{}: {}", path, load_error_message(*error, &source)),
+    }
+}
 
+/// Parses a module and adds the standard library to it. Definitions of the
+/// module shadow the unqualified aliases of the
+/// [`PRELUDE`](crate::buildin::PRELUDE), so that adding a builtin does not
+/// break programs that already define it, but not the qualified names.
 pub fn load_module(source: &str) -> Result<Module> {
     let mut parser = Parser::new(source);
     let module = parser.parse_module().map_err(LoadError::ParseError)?;
@@ -21,25 +70,340 @@ pub fn load_module(source: &str) -> Result<Module> {
         (module.defines, module.macros)
     };
 
+    let mut defined = HashSet::new();
     for (name, exp) in module.1.into_iter() {
-        if defines.contains_key(&name) {
+        if is_reserved(&name) {
+            return Err(LoadError::ReservedName(name));
+        }
+        let id = SymbolId::intern(&name);
+        if !defined.insert(id) {
             return Err(LoadError::DuplicateDefinition(name));
         }
-        defines.insert(name, exp);
+        defines.insert(id, exp);
+    }
+
+    let mut tests = vec![];
+    for (name, exp) in module.3.into_iter() {
+        if tests.iter().any(|(test, _)| *test == name) {
+            return Err(LoadError::DuplicateDefinition(name));
+        }
+        tests.push((name, exp));
     }
 
     for (name, exp, args_count) in module.2.into_iter() {
-        if macros.contains_key(&name) {
+        if is_reserved(&name) {
+            return Err(LoadError::ReservedName(name));
+        }
+        let id = SymbolId::intern(&name);
+        if macros.contains_key(&id) {
             return Err(LoadError::DuplicateDefinition(name));
         }
-        macros.insert(name, (exp, args_count));
+        // Definitions take precedence over macros, so a builtin would hide it.
+        if !defined.contains(&id) {
+            defines.remove(&id);
+        }
+        macros.insert(id, (exp, args_count));
     }
 
-    Ok(Module {
+    let interface = module.4;
+    if let Some(name) = interface
+        .imports
+        .iter()
+        .find(|name| NAMESPACES.contains(&name.as_str()))
+    {
+        return Err(LoadError::ReservedName(name.clone()));
+    }
+    let mut exports = vec![];
+    for name in interface.exports {
+        let id = SymbolId::intern(&name);
+        if !defined.contains(&id) {
+            return Err(LoadError::UnknownExport(name));
+        }
+        exports.push(id);
+    }
+
+    let mut module = Module {
         name: module.0,
         defines,
         macros,
-    })
+        tests,
+        imports: interface.imports,
+        exports,
+    };
+
+    // Macros are expanded once all of them are known, so a definition may use
+    // macros defined further down the module.
+    let mut gen = VariableGenerator::new();
+    module.defines = module
+        .defines
+        .iter()
+        .map(|(name, exp)| Ok((*name, expand(exp.clone(), &module, &mut gen)?)))
+        .collect::<core::result::Result<_, EvalError>>()
+        .map_err(LoadError::EvalError)?;
+    module.tests = module
+        .tests
+        .iter()
+        .map(|(name, exp)| Ok((name.clone(), expand(exp.clone(), &module, &mut gen)?)))
+        .collect::<core::result::Result<_, EvalError>>()
+        .map_err(LoadError::EvalError)?;
+
+    Ok(module)
+}
+
+/// The module that a qualified name such as `arith/square` refers to. Names
+/// such as `/` and `a/` are not qualified.
+pub fn qualifier(name: &str) -> Option<&str> {
+    match name.split_once('/') {
+        Some((module, name)) if !module.is_empty() && !name.is_empty() => Some(module),
+        _ => None,
+    }
+}
+
+/// Whether `name` is qualified by a namespace of the standard library.
+pub(crate) fn is_reserved(name: &str) -> bool {
+    qualifier(name).is_some_and(|module| NAMESPACES.contains(&module))
+}
+
+/// A source of the modules that `(import name)` refers to.
+pub trait ModuleLoader {
+    /// The module `name`, as [`load_module`] loads it, with its own imports
+    /// not yet linked.
+    fn load(&self, name: &str) -> Result<Module>;
+}
+
+/// Serves the modules of the slice by their names.
+impl ModuleLoader for [Module] {
+    fn load(&self, name: &str) -> Result<Module> {
+        self.iter()
+            .find(|module| module.name == name)
+            .cloned()
+            .ok_or_else(|| LoadError::ModuleNotFound(name.to_string()))
+    }
+}
+
+/// Loads modules from sources kept in memory, such as scripts embedded in the
+/// host binary, by the name they are imported as.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryLoader {
+    pub sources: HashMap<String, String>,
+}
+
+impl MemoryLoader {
+    pub fn new(sources: HashMap<String, String>) -> Self {
+        MemoryLoader { sources }
+    }
+
+    /// Adds the source of the module `name`, replacing any it had.
+    pub fn insert(&mut self, name: &str, source: &str) {
+        self.sources.insert(name.to_string(), source.to_string());
+    }
+}
+
+impl ModuleLoader for MemoryLoader {
+    fn load(&self, name: &str) -> Result<Module> {
+        let source = self
+            .sources
+            .get(name)
+            .ok_or_else(|| LoadError::ModuleNotFound(name.to_string()))?;
+        load_module(source).map_err(|error| LoadError::Imported {
+            path: name.to_string(),
+            source: source.clone(),
+            error: Box::new(error),
+        })
+    }
+}
+
+/// Loads the module `name` from the file `name.tg` in the first of its
+/// search paths that has one.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileLoader {
+    pub search_paths: Vec<PathBuf>,
+}
+
+#[cfg(feature = "std")]
+impl FileLoader {
+    pub const EXTENSION: &'static str = "tg";
+
+    pub fn new(search_paths: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        FileLoader {
+            search_paths: search_paths.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// The file of the module `name`. A name that is not a plain file name,
+    /// such as `../a`, is never found, so that imports stay within the
+    /// search paths.
+    pub fn find(&self, name: &str) -> Option<PathBuf> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return None;
+        }
+        self.search_paths
+            .iter()
+            .map(|dir| dir.join(name).with_extension(Self::EXTENSION))
+            .find(|path| path.is_file())
+    }
+}
+
+#[cfg(feature = "std")]
+impl ModuleLoader for FileLoader {
+    fn load(&self, name: &str) -> Result<Module> {
+        let path = self
+            .find(name)
+            .ok_or_else(|| LoadError::ModuleNotFound(name.to_string()))?;
+        let display = path.display().to_string();
+        let source = std::fs::read_to_string(&path)
+            .map_err(|err| LoadError::Unreadable(display.clone(), err.to_string()))?;
+        load_module(&source).map_err(|error| LoadError::Imported {
+            path: display,
+            source,
+            error: Box::new(error),
+        })
+    }
+}
+
+/// Links the modules that `module` imports into it, loading them through
+/// `loader`. They may import further modules in turn. An export `x` of an
+/// imported module `m` becomes the definition `m/x`, and the definitions it
+/// refers to are brought along under names that cannot be written in source,
+/// so that they neither clash with the definitions of `module` nor can be
+/// referred to from it.
+pub fn link<L: ModuleLoader + ?Sized>(module: Module, loader: &L) -> Result<Module> {
+    link_in(module, loader, &default_module(), &mut vec![])
+}
+
+/// Links `module` whose importers, outermost first, are being linked.
+fn link_in<L: ModuleLoader + ?Sized>(
+    mut module: Module,
+    loader: &L,
+    builtins: &Module,
+    linking: &mut Vec<String>,
+) -> Result<Module> {
+    if linking.contains(&module.name) {
+        return Err(LoadError::ImportCycle(module.name));
+    }
+    linking.push(module.name.clone());
+    for name in module.imports.clone() {
+        let imported = link_in(loader.load(&name)?, loader, builtins, linking)?;
+        link_exports(&mut module, &imported, builtins);
+    }
+    linking.pop();
+    Ok(module)
+}
+
+/// Adds the exports of `imported` to `module` under qualified names.
+fn link_exports(module: &mut Module, imported: &Module, builtins: &Module) {
+    // A builtin that neither module redefines is referred to as it is. Every
+    // other definition is renamed in the definitions brought along, even one
+    // equal to a definition of `module`, whose body would otherwise refer to
+    // the definitions of `module`.
+    let renames = imported
+        .defines
+        .iter()
+        .filter(|(name, value)| {
+            let shared = builtins.defines.get(name) == Some(value)
+                && module.defines.get(name) == Some(value);
+            imported.exports.contains(name) || !shared
+        })
+        .map(|(name, _)| {
+            let hidden = if imported.exports.contains(name) {
+                ""
+            } else {
+                "#"
+            };
+            let qualified = format!("{}{}/{}", hidden, imported.name, name);
+            (*name, SymbolId::intern(&qualified))
+        })
+        .collect::<HashMap<_, _>>();
+    for (name, qualified) in &renames {
+        let exp = rename(&imported.defines[name], &renames, &mut vec![]);
+        module.defines.insert(*qualified, exp);
+    }
+}
+
+/// `exp` with the free occurrences of the names in `renames` renamed. The
+/// names in `bound` are bound around `exp`.
+fn rename(exp: &Exp, renames: &HashMap<SymbolId, SymbolId>, bound: &mut Vec<SymbolId>) -> Exp {
+    let under = |names: &[SymbolId], exp: &Exp, bound: &mut Vec<SymbolId>| {
+        bound.extend_from_slice(names);
+        let exp = rename(exp, renames, bound);
+        bound.truncate(bound.len() - names.len());
+        exp
+    };
+    let rc = |exp: Exp| Rc::new(exp);
+    match exp {
+        Exp::Symbol(sym) if !bound.contains(sym) => {
+            Exp::Symbol(renames.get(sym).copied().unwrap_or(*sym))
+        }
+        Exp::List(es) => Exp::List(es.iter().map(|e| rename(e, renames, bound)).collect()),
+        Exp::Lambda(x, body) => Exp::Lambda(*x, rc(under(&[*x], body, bound))),
+        Exp::Variadic(f) => Exp::Variadic(rc(rename(f, renames, bound))),
+        Exp::Apply(e1, e2) => Exp::Apply(
+            rc(rename(e1, renames, bound)),
+            rc(rename(e2, renames, bound)),
+        ),
+        Exp::If(c, t, e) => Exp::If(
+            rc(rename(c, renames, bound)),
+            rc(rename(t, renames, bound)),
+            rc(rename(e, renames, bound)),
+        ),
+        Exp::Let((x, value), body) => Exp::Let(
+            (*x, rc(rename(value, renames, bound))),
+            rc(under(&[*x], body, bound)),
+        ),
+        Exp::LetRec(binds, body) => {
+            let names = binds.iter().map(|(x, _)| *x).collect::<Vec<_>>();
+            Exp::LetRec(
+                binds
+                    .iter()
+                    .map(|(x, value)| (*x, under(&names, value, bound)))
+                    .collect(),
+                rc(under(&names, body, bound)),
+            )
+        }
+        Exp::Case(e, cases) => Exp::Case(
+            rc(rename(e, renames, bound)),
+            cases
+                .iter()
+                .map(|(pattern, guard, body)| {
+                    let vars = pattern_vars(pattern);
+                    (
+                        pattern.clone(),
+                        guard.as_ref().map(|guard| under(&vars, guard, bound)),
+                        under(&vars, body, bound),
+                    )
+                })
+                .collect(),
+        ),
+        Exp::Try(body, x, handler) => Exp::Try(
+            rc(rename(body, renames, bound)),
+            *x,
+            rc(under(&[*x], handler, bound)),
+        ),
+        Exp::Quote(e) => Exp::Quote(rc(rename_quoted(e, renames, bound))),
+        Exp::Spanned(span, e) => Exp::Spanned(*span, rc(rename(e, renames, bound))),
+        _ => exp.clone(),
+    }
+}
+
+/// Like [`rename`] in a quoted `exp`, where only unquoted expressions refer
+/// to definitions.
+fn rename_quoted(
+    exp: &Exp,
+    renames: &HashMap<SymbolId, SymbolId>,
+    bound: &mut Vec<SymbolId>,
+) -> Exp {
+    match exp {
+        Exp::UnQuote(e) => Exp::UnQuote(Rc::new(rename(e, renames, bound))),
+        Exp::UnQuoteSplicing(e) => Exp::UnQuoteSplicing(Rc::new(rename(e, renames, bound))),
+        Exp::List(es) => Exp::List(
+            es.iter()
+                .map(|e| rename_quoted(e, renames, bound))
+                .collect(),
+        ),
+        Exp::Spanned(span, e) => Exp::Spanned(*span, Rc::new(rename_quoted(e, renames, bound))),
+        _ => exp.clone(),
+    }
 }
 
 #[cfg(test)]
@@ -56,8 +420,8 @@ mod tests {
         "#;
         let module = load_module(source).unwrap();
         assert_eq!(module.defines.len(), default_module().defines.len() + 2);
-        assert_eq!(module.defines.get("x"), Some(&Exp::Integer(1)));
-        assert_eq!(module.defines.get("y"), Some(&Exp::Integer(2)));
+        assert_eq!(module.defines.get(&"x".into()), Some(&Exp::Integer(1)));
+        assert_eq!(module.defines.get(&"y".into()), Some(&Exp::Integer(2)));
     }
 
     #[test]
@@ -70,4 +434,206 @@ mod tests {
         let err = load_module(source).unwrap_err();
         assert_eq!(err, LoadError::DuplicateDefinition("x".to_string()));
     }
+
+    #[test]
+    fn test_link() {
+        let arith = load_module(
+            r#"
+        (module arith
+            (export square sum-squares)
+            (define square (x) (* x x))
+            (define count (xs) (length xs))
+            (define sum-squares (xs) (foldl + 0 (map square xs))))
+        "#,
+        )
+        .unwrap();
+        let stats = load_module(
+            r#"
+        (module stats
+            (import arith)
+            (export mean-square)
+            (define mean-square (xs) (/ (arith/sum-squares xs) (length xs))))
+        "#,
+        )
+        .unwrap();
+        let main = load_module(
+            r#"
+        (module main
+            (import stats)
+            (import arith)
+            (define square (x) x)
+            (define main ()
+                (list (arith/square 3) (square 3) (stats/mean-square '(1 2 3)))))
+        "#,
+        )
+        .unwrap();
+        let program = link(main, &[arith, stats][..]).unwrap();
+        assert_eq!(
+            program.run("main", vec![]),
+            Ok(Exp::List(
+                [Exp::Integer(9), Exp::Integer(3), Exp::Rational(14, 3)].into()
+            ))
+        );
+        // Only exports can be referred to, and private definitions of the
+        // imported module do not shadow builtins.
+        assert!(!program.defines.contains_key(&"arith/count".into()));
+        assert!(program.defines.contains_key(&"#arith/count".into()));
+        assert_eq!(
+            program.eval(crate::parser::parse_exp("(count (\\ x (= x 1)) '(1 1 2))").unwrap()),
+            Ok(Exp::Integer(2))
+        );
+    }
+
+    #[test]
+    fn test_link_isolation() {
+        let b = load_module(
+            "(module b
+                (export run)
+                (define g (x) 1)
+                (define f (x) (g x))
+                (define run (x) (list (f x) (length '(1 2)))))",
+        )
+        .unwrap();
+        // `f` is equal in both modules, but refers to a different `g`, and
+        // `length` is only the builtin in `b`.
+        let main = load_module(
+            "(module main
+                (import b)
+                (define g (x) 2)
+                (define f (x) (g x))
+                (define length (xs) 0)
+                (define main () (list (b/run 0) (f 0))))",
+        )
+        .unwrap();
+        let program = link(main, &[b][..]).unwrap();
+        assert_eq!(
+            program.run("main", vec![]),
+            Ok(Exp::List(
+                [
+                    Exp::List([Exp::Integer(1), Exp::Integer(2)].into()),
+                    Exp::Integer(2)
+                ]
+                .into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_link_errors() {
+        let a = load_module("(module a (import b))").unwrap();
+        let b = load_module("(module b (import a))").unwrap();
+        assert_eq!(
+            link(a.clone(), &[a.clone(), b][..]),
+            Err(LoadError::ImportCycle("a".to_string()))
+        );
+        assert_eq!(
+            link(a, &[][..]),
+            Err(LoadError::ModuleNotFound("b".to_string()))
+        );
+        assert_eq!(
+            load_module("(module a (export f))"),
+            Err(LoadError::UnknownExport("f".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_reserved_names() {
+        assert_eq!(
+            load_module("(module a (define string/append (x y) x))"),
+            Err(LoadError::ReservedName("string/append".to_string()))
+        );
+        assert_eq!(
+            load_module("(module a (import list))"),
+            Err(LoadError::ReservedName("list".to_string()))
+        );
+        // Interpolation calls `string/format`, so a local `format` does not
+        // change it.
+        let module =
+            load_module(r#"(module a (define format (x) x) (define main () "${(+ 1 2)}!"))"#)
+                .unwrap();
+        assert_eq!(module.run("main", vec![]), Ok(Exp::String("3!".into())));
+    }
+
+    #[test]
+    fn test_memory_loader() {
+        let mut loader = MemoryLoader::default();
+        loader.insert(
+            "arith",
+            "(module arith (export square) (define square (x) (* x x)))",
+        );
+        loader.insert(
+            "geometry",
+            "(module geometry (import arith) (export area) (define area (r) (* 3 (arith/square r))))",
+        );
+        loader.insert("broken", "(module broken (export x))");
+        let main =
+            load_module("(module main (import geometry) (define main () (geometry/area 2)))");
+        let program = link(main.unwrap(), &loader).unwrap();
+        assert_eq!(program.run("main", vec![]), Ok(Exp::Integer(12)));
+
+        let main = load_module("(module main (import broken))").unwrap();
+        assert_eq!(
+            link(main, &loader),
+            Err(LoadError::Imported {
+                path: "broken".to_string(),
+                source: "(module broken (export x))".to_string(),
+                error: Box::new(LoadError::UnknownExport("x".to_string())),
+            })
+        );
+        assert_eq!(
+            loader.load("missing"),
+            Err(LoadError::ModuleNotFound("missing".to_string()))
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_file_loader() {
+        let dir = std::env::temp_dir().join(format!("topogi-loader-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("arith.tg"),
+            "(module arith (export square) (define square (x) (* x x)))",
+        )
+        .unwrap();
+        std::fs::write(dir.join("broken.tg"), "(module broken (define x ()").unwrap();
+
+        let loader = FileLoader::new([std::env::temp_dir(), dir.clone()]);
+        assert_eq!(loader.find("arith"), Some(dir.join("arith.tg")));
+        assert_eq!(loader.find("../arith"), None);
+        let main = load_module("(module main (import arith) (define main () (arith/square 3)))");
+        let program = link(main.unwrap(), &loader).unwrap();
+        assert_eq!(program.run("main", vec![]), Ok(Exp::Integer(9)));
+
+        assert_eq!(
+            loader.load("missing"),
+            Err(LoadError::ModuleNotFound("missing".to_string()))
+        );
+        assert!(matches!(
+            loader.load("broken"),
+            Err(LoadError::Imported { error, .. }) if matches!(*error, LoadError::ParseError(_))
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_module_shadows_builtins() {
+        let source = r#"
+        (module test
+            (define count (n) n)
+            (macro length (x) '(list ~x ~x))
+            (define main () (list (count 1) (length 2))))
+        "#;
+        let module = load_module(source).unwrap();
+        assert_eq!(
+            module.run("main", vec![]),
+            Ok(Exp::List(
+                [
+                    Exp::Integer(1),
+                    Exp::List([Exp::Integer(2), Exp::Integer(2)].into())
+                ]
+                .into()
+            ))
+        );
+    }
 }
@@ -1,10 +1,101 @@
+use alloc::rc::Rc;
+
 use crate::ast::*;
 use crate::lexer::{lexer_error_message, Lexer, LexerError};
-use crate::token::{get_token_word, Token, TokenKind};
+use crate::prelude::*;
+use crate::symbol::SymbolId;
+use crate::token::{get_token_word, Location, Span, Token, TokenKind};
+
+fn params_lambda(params: &[String], rest: Option<String>, body: Exp) -> Exp {
+    let body = match rest {
+        Some(rest) => variadic(&rest, body),
+        None => body,
+    };
+    let params = params.iter().map(String::as_str).collect::<Vec<_>>();
+    lambda_n(&params, body)
+}
+
+pub fn parse_exp(input: &str) -> Result<Exp, ParseError> {
+    let mut parser = Parser::new(input);
+    let exp = parser.parse_exp()?;
+    parser.parse_eof()?;
+    Ok(exp)
+}
+
+/// Like [`parse_exp`], but without spans, for expressions that are read at
+/// runtime rather than from the source of the program.
+pub fn read(input: &str) -> Result<Exp, ParseError> {
+    let mut parser = Parser::new(input);
+    parser.spans = false;
+    let exp = parser.parse_exp()?;
+    parser.parse_eof()?;
+    Ok(exp)
+}
+
+pub fn parse_module(input: &str) -> Result<Module, ParseError> {
+    let mut parser = Parser::new(input);
+    let (name, defines, macros, tests, interface) = parser.parse_module()?;
+    parser.parse_eof()?;
+
+    let mut module = Module::new(&name);
+    module.defines.extend(
+        defines
+            .into_iter()
+            .map(|(name, body)| (SymbolId::intern(&name), body)),
+    );
+    module.macros.extend(
+        macros
+            .into_iter()
+            .map(|(name, body, args)| (SymbolId::intern(&name), (body, args))),
+    );
+    module.tests = tests;
+    module.imports = interface.imports;
+    module.exports = interface
+        .exports
+        .iter()
+        .map(|name| SymbolId::intern(name))
+        .collect();
+    Ok(module)
+}
+
+/// Sequences `exps` as lets whose bindings are never used, so that they are
+/// evaluated in order and the value of the last one is returned. The bound
+/// name cannot be written in source, so it never shadows a user variable.
+fn sequence(mut exps: Vec<Exp>) -> Exp {
+    let Some(last) = exps.pop() else {
+        return nil();
+    };
+    exps.into_iter()
+        .rev()
+        .fold(last, |rest, exp| let_(("#_", exp), rest))
+}
+
+pub type Define = (String, Exp);
+pub type Macro = (String, Exp, Vec<Exp>);
+pub type Test = (String, Exp);
+
+/// The `(import name)` and `(export name ...)` forms of a module, in the
+/// order they are written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Interface {
+    pub imports: Vec<String>,
+    pub exports: Vec<String>,
+}
+
+/// The definitions, macros and tests of a module, in the order they are
+/// written, and its interface.
+pub type Items = (Vec<Define>, Vec<Macro>, Vec<Test>, Interface);
+/// The name of a module and its items.
+pub type ParsedModule = (String, Vec<Define>, Vec<Macro>, Vec<Test>, Interface);
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Parser {
     lexer: Lexer,
+    /// Whether parsed expressions are annotated with their spans.
+    spans: bool,
+    /// The number of quotes around the expression being parsed, less the
+    /// unquotes. Literals in a quote are data, see [`Parser::parse_vector`].
+    quoted: usize,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -12,6 +103,12 @@ pub enum ParseError {
     LexerError(LexerError),
     UnmatchedParen(Token),
     ExpectedSymbol(Token),
+    TrailingInput(Token),
+    UnexpectedDot(Token),
+    /// A `${...}` in a string does not hold exactly one expression.
+    InvalidInterpolation(Token),
+    /// A map literal, given by its `{`, with a key but no value.
+    OddMapLiteral(Token),
 }
 
 pub fn parse_error_message(error: ParseError, input: &str) -> String {
@@ -29,6 +126,55 @@ pub fn parse_error_message(error: ParseError, input: &str) -> String {
             let word = get_token_word(token.loc, input);
             format!("{}\n{} expected symbol", word, "^".repeat(word.len()))
         }
+        ParseError::TrailingInput(token) => {
+            let word = get_token_word(token.loc, input);
+            format!("{}\n{} unexpected input", word, "^".repeat(word.len()))
+        }
+        ParseError::UnexpectedDot(token) => {
+            let word = get_token_word(token.loc, input);
+            format!("{}\n{} unexpected dot", word, "^".repeat(word.len()))
+        }
+        ParseError::InvalidInterpolation(token) => {
+            let word = get_token_word(token.loc, input);
+            format!("{}\n{} invalid interpolation", word, "^".repeat(word.len()))
+        }
+        ParseError::OddMapLiteral(token) => {
+            let word = get_token_word(token.loc, input);
+            format!("{}\n{} key without a value", word, "^".repeat(word.len()))
+        }
+    }
+}
+
+/// Whether the value of `exp` is `exp` itself, without evaluating anything.
+fn is_constant(exp: &Exp) -> bool {
+    match exp.node() {
+        Exp::Nil
+        | Exp::Bool(_)
+        | Exp::Integer(_)
+        | Exp::Rational(..)
+        | Exp::Float(_)
+        | Exp::String(_)
+        | Exp::Char(_)
+        | Exp::Keyword(_)
+        | Exp::Bytes(_)
+        | Exp::Map(_)
+        | Exp::Vector(_) => true,
+        #[cfg(feature = "bignum")]
+        Exp::BigInt(_) => true,
+        _ => false,
+    }
+}
+
+/// `exp` without its span annotations, as a datum inside a literal.
+fn datum(exp: Exp) -> Exp {
+    match exp.into_node() {
+        Exp::List(exps) => Exp::List(exps.iter().cloned().map(datum).collect()),
+        Exp::Pair(head, tail) => pair(
+            datum(Rc::unwrap_or_clone(head)),
+            datum(Rc::unwrap_or_clone(tail)),
+        ),
+        Exp::Quote(exp) => quote(datum(Rc::unwrap_or_clone(exp))),
+        exp => exp,
     }
 }
 
@@ -36,6 +182,8 @@ impl Parser {
     pub fn new(input: &str) -> Parser {
         Parser {
             lexer: Lexer::new(input),
+            spans: true,
+            quoted: 0,
         }
     }
 
@@ -43,6 +191,14 @@ impl Parser {
         self.lexer.next_token().map_err(ParseError::LexerError)
     }
 
+    pub fn parse_eof(&mut self) -> Result<(), ParseError> {
+        match self.lexer.peek_token() {
+            Ok(token) => Err(ParseError::TrailingInput(token)),
+            Err(LexerError::Eot(_)) => Ok(()),
+            Err(err) => Err(ParseError::LexerError(err)),
+        }
+    }
+
     pub fn parse_right_param(&mut self) -> Result<(), ParseError> {
         match self.next_token()? {
             token if token.kind == TokenKind::RParen => Ok(()),
@@ -74,18 +230,42 @@ impl Parser {
         }
     }
 
+    /// Parses `(\ x body)` or `(\ (x y ...) body)`, which is curried into
+    /// nested single-parameter lambdas. A parameter list ending in `. rest`
+    /// collects any further arguments of a call into a list, see [`variadic`].
     pub fn parse_lambda(&mut self) -> Result<Exp, ParseError> {
         self.lexer.skip_token();
 
-        let token = self.next_token()?;
-        let param = token
-            .as_symbol()
-            .ok_or(ParseError::ExpectedSymbol(token.clone()))?;
+        let (params, rest) = if self.peek_left_param() {
+            self.parse_params()?
+        } else {
+            (vec![self.parse_symbol()?], None)
+        };
         let body = self.parse_exp()?;
 
         self.parse_right_param()?;
 
-        Ok(lambda(param, body))
+        Ok(params_lambda(&params, rest, body))
+    }
+
+    /// Parses a parameter list `(x y ...)` or `(x y ... . rest)`.
+    pub fn parse_params(&mut self) -> Result<(Vec<String>, Option<String>), ParseError> {
+        self.parse_left_param()?;
+        let mut params = vec![];
+        let mut rest = None;
+        loop {
+            match self.lexer.peek_token() {
+                Ok(token) if token.kind == TokenKind::RParen => break,
+                Ok(token) if token.kind == TokenKind::Dot => {
+                    self.lexer.skip_token();
+                    rest = Some(self.parse_symbol()?);
+                    break;
+                }
+                _ => params.push(self.parse_symbol()?),
+            }
+        }
+        self.parse_right_param()?;
+        Ok((params, rest))
     }
 
     pub fn parse_exps(&mut self) -> Result<Vec<Exp>, ParseError> {
@@ -105,6 +285,93 @@ impl Parser {
         Ok(elems)
     }
 
+    /// Parses an expression at a quote depth of `quoted`.
+    fn parse_quoted(&mut self, quoted: usize) -> Result<Exp, ParseError> {
+        let outer = core::mem::replace(&mut self.quoted, quoted);
+        let exp = self.parse_exp();
+        self.quoted = outer;
+        exp
+    }
+
+    /// Parses expressions up to and including the closing token `close`.
+    fn parse_until(&mut self, close: TokenKind) -> Result<Vec<Exp>, ParseError> {
+        let mut elems = Vec::new();
+        loop {
+            let token = self.lexer.peek_token().map_err(ParseError::LexerError)?;
+            if token.kind == close {
+                self.lexer.skip_token();
+                return Ok(elems);
+            }
+            elems.push(self.parse_exp()?);
+        }
+    }
+
+    /// Parses the rest of a vector literal `[a b ...]`. If the elements are
+    /// constants, or the literal is quoted, it is read as a vector, and it is
+    /// a call of `vector` that evaluates the elements otherwise.
+    fn parse_vector(&mut self) -> Result<Exp, ParseError> {
+        let elems = self.parse_until(TokenKind::RBracket)?;
+        if self.quoted > 0 || elems.iter().all(is_constant) {
+            return Ok(vector(&elems.into_iter().map(datum).collect::<Vec<_>>()));
+        }
+        Ok(list(
+            &core::iter::once(symbol("vector"))
+                .chain(elems)
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    /// Parses the rest of a map literal `{k v ...}`, which is read like a
+    /// [vector literal](Parser::parse_vector) but calls `map/new`.
+    fn parse_map(&mut self, open: Token) -> Result<Exp, ParseError> {
+        let elems = self.parse_until(TokenKind::RBrace)?;
+        if elems.len() % 2 != 0 {
+            return Err(ParseError::OddMapLiteral(open));
+        }
+        if self.quoted > 0 || elems.iter().all(is_constant) {
+            let entries = elems
+                .into_iter()
+                .map(datum)
+                .collect::<Vec<_>>()
+                .chunks(2)
+                .map(|entry| (entry[0].clone(), entry[1].clone()))
+                .collect::<Vec<_>>();
+            return Ok(map_of(&entries));
+        }
+        Ok(list(
+            &core::iter::once(symbol("map/new"))
+                .chain(elems)
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    /// Parses the rest of a list `(a b ...)` or of a dotted list
+    /// `(a b ... . tail)`, which becomes nested [pairs](pair).
+    fn parse_list(&mut self) -> Result<Exp, ParseError> {
+        let mut elems = Vec::new();
+        loop {
+            match self.lexer.peek_token() {
+                Ok(token) if token.kind == TokenKind::RParen => break,
+                Ok(token) if token.kind == TokenKind::Dot => {
+                    if elems.is_empty() {
+                        return Err(ParseError::UnexpectedDot(token));
+                    }
+                    self.lexer.skip_token();
+                    let tail = self.parse_exp()?;
+                    self.parse_right_param()?;
+                    return Ok(elems
+                        .into_iter()
+                        .rev()
+                        .fold(tail, |tail, head| pair(head, tail)));
+                }
+                Ok(_) => elems.push(self.parse_exp()?),
+                Err(_) => break,
+            }
+        }
+        self.parse_right_param()?;
+        Ok(list(&elems))
+    }
+
     pub fn parse_if(&mut self) -> Result<Exp, ParseError> {
         self.lexer.skip_token();
         let cond = self.parse_exp()?;
@@ -114,38 +381,291 @@ impl Parser {
         Ok(if_(cond, then, else_))
     }
 
+    /// Parses `(and e ...)` into nested `if`s, so that the operands after the
+    /// first false one are not evaluated.
+    pub fn parse_and(&mut self) -> Result<Exp, ParseError> {
+        self.lexer.skip_token();
+        let mut exps = self.parse_exps()?;
+        let Some(last) = exps.pop() else {
+            return Ok(bool(true));
+        };
+        Ok(exps
+            .into_iter()
+            .rev()
+            .fold(last, |rest, exp| if_(exp, rest, bool(false))))
+    }
+
+    /// Parses `(or e ...)` into nested `if`s, so that the operands after the
+    /// first true one are not evaluated.
+    pub fn parse_or(&mut self) -> Result<Exp, ParseError> {
+        self.lexer.skip_token();
+        let mut exps = self.parse_exps()?;
+        let Some(last) = exps.pop() else {
+            return Ok(bool(false));
+        };
+        Ok(exps
+            .into_iter()
+            .rev()
+            .fold(last, |rest, exp| if_(exp, bool(true), rest)))
+    }
+
+    /// Parses `(cond (test exp) ... (else exp))` into nested `if`s. Without an
+    /// `else` clause the value is `nil` if no test holds.
+    pub fn parse_cond(&mut self) -> Result<Exp, ParseError> {
+        self.lexer.skip_token();
+        let mut clauses = vec![];
+        let mut else_ = None;
+        while let Ok(token) = self.lexer.peek_token() {
+            if token.kind == TokenKind::RParen {
+                break;
+            }
+            if else_.is_some() {
+                return Err(ParseError::TrailingInput(token));
+            }
+            self.parse_left_param()?;
+            if self
+                .lexer
+                .peek_token()
+                .ok()
+                .as_ref()
+                .and_then(Token::as_symbol)
+                == Some("else")
+            {
+                self.lexer.skip_token();
+                else_ = Some(self.parse_exp()?);
+            } else {
+                clauses.push((self.parse_exp()?, self.parse_exp()?));
+            }
+            self.parse_right_param()?;
+        }
+        self.parse_right_param()?;
+        Ok(clauses
+            .into_iter()
+            .rev()
+            .fold(else_.unwrap_or(nil()), |rest, (test, exp)| {
+                if_(test, exp, rest)
+            }))
+    }
+
+    /// Parses `(begin exp ...)`, which evaluates the expressions in order and
+    /// returns the value of the last one, or `nil` if there are none.
+    pub fn parse_begin(&mut self) -> Result<Exp, ParseError> {
+        self.lexer.skip_token();
+        Ok(sequence(self.parse_exps()?))
+    }
+
+    /// Parses `(when test exp ...)` into an `if` that is `nil` unless `test`
+    /// holds.
+    pub fn parse_when(&mut self) -> Result<Exp, ParseError> {
+        self.lexer.skip_token();
+        let test = self.parse_exp()?;
+        let body = sequence(self.parse_exps()?);
+        Ok(if_(test, body, nil()))
+    }
+
+    /// Parses `(unless test exp ...)` into an `if` that is `nil` if `test`
+    /// holds.
+    pub fn parse_unless(&mut self) -> Result<Exp, ParseError> {
+        self.lexer.skip_token();
+        let test = self.parse_exp()?;
+        let body = sequence(self.parse_exps()?);
+        Ok(if_(test, nil(), body))
+    }
+
+    /// Parses `(let (x exp) body)` or `(let ((x exp) ...) body)`. Multiple
+    /// bindings are evaluated in order, each seeing the ones before it.
     pub fn parse_let(&mut self) -> Result<Exp, ParseError> {
         self.lexer.skip_token();
+        if matches!(self.lexer.peek_token(), Ok(token) if token.as_symbol().is_some()) {
+            return self.parse_named_let();
+        }
+        let bindings = self.parse_bindings()?;
+        let exp = self.parse_exp()?;
+        self.parse_right_param()?;
+        Ok(bindings
+            .into_iter()
+            .rev()
+            .fold(exp, |exp, (var, bind)| let_((&var, bind), exp)))
+    }
 
+    /// Parses `(let name ((x exp) ...) body)` into a local recursive function
+    /// `name` of the bound variables, which is called with their initial values:
+    /// `(letrec (name (\ x ... body)) (name exp ...))`.
+    pub fn parse_named_let(&mut self) -> Result<Exp, ParseError> {
+        let name = self.parse_symbol()?;
+        let bindings = self.parse_bindings()?;
+        let body = self.parse_exp()?;
+        self.parse_right_param()?;
+        let params = bindings
+            .iter()
+            .map(|(var, _)| var.as_str())
+            .collect::<Vec<_>>();
+        let function = lambda_n(&params, body);
+        let call = core::iter::once(symbol(&name))
+            .chain(bindings.into_iter().map(|(_, init)| init))
+            .collect::<Vec<_>>();
+        Ok(letrec(&[(&name, function)], list(&call)))
+    }
+
+    /// Parses the bindings of a `let` or `letrec`: either a single `(x exp)` or
+    /// a possibly empty list `((x exp) ...)`.
+    pub fn parse_bindings(&mut self) -> Result<Vec<(String, Exp)>, ParseError> {
         self.parse_left_param()?;
-        let token = self.next_token()?;
-        let var = token
-            .as_symbol()
-            .ok_or(ParseError::ExpectedSymbol(token.clone()))?;
-        let bind = (var, self.parse_exp()?);
+        if self.peek_right_param() {
+            self.lexer.skip_token();
+            return Ok(vec![]);
+        }
+        if !self.peek_left_param() {
+            return Ok(vec![self.parse_binding()?]);
+        }
+        let mut bindings = vec![];
+        while self.peek_left_param() {
+            self.parse_left_param()?;
+            bindings.push(self.parse_binding()?);
+        }
         self.parse_right_param()?;
+        Ok(bindings)
+    }
 
+    /// Parses `x exp)`, the rest of a binding after its opening paren.
+    fn parse_binding(&mut self) -> Result<(String, Exp), ParseError> {
+        let var = self.parse_symbol()?;
         let exp = self.parse_exp()?;
+        self.parse_right_param()?;
+        Ok((var, exp))
+    }
 
+    fn peek_left_param(&mut self) -> bool {
+        matches!(self.lexer.peek_token(), Ok(token) if token.kind == TokenKind::LParen)
+    }
+
+    fn peek_right_param(&mut self) -> bool {
+        matches!(self.lexer.peek_token(), Ok(token) if token.kind == TokenKind::RParen)
+    }
+
+    pub fn parse_letrec(&mut self) -> Result<Exp, ParseError> {
+        self.lexer.skip_token();
+        let bindings = self.parse_bindings()?;
+        let exp = self.parse_exp()?;
         self.parse_right_param()?;
-        Ok(let_(bind, exp))
+        let bindings = bindings
+            .iter()
+            .map(|(var, exp)| (var.as_str(), exp.clone()))
+            .collect::<Vec<_>>();
+        Ok(letrec(&bindings, exp))
     }
 
-    pub fn parse_one_case(&mut self) -> Result<(Exp, Exp), ParseError> {
+    /// Parses `(pattern body)` or `(pattern (when guard) body)`. A `when`
+    /// followed by the end of the arm is its body instead. The pattern `else`
+    /// is the wildcard `_`.
+    pub fn parse_one_case(&mut self) -> Result<(Exp, Option<Exp>, Exp), ParseError> {
         self.parse_left_param()?;
-        let key = self.parse_exp()?;
-        let value = self.parse_exp()?;
+        let pattern = match self.parse_exp()? {
+            pattern if pattern.as_symbol_id() == Some(SymbolId::intern("else")) => symbol("_"),
+            pattern => pattern,
+        };
+
+        let token = self.next_token()?;
+        let start = token.loc;
+        let is_when = token.kind == TokenKind::LParen
+            && matches!(self.lexer.peek_token(), Ok(token) if token.as_symbol() == Some("when"));
+        let body = if is_when {
+            self.lexer.skip_token();
+            let test = self.parse_exp()?;
+            let body = self.parse_exps()?;
+            if body.is_empty() && !self.peek_right_param() {
+                let body = self.parse_exp()?;
+                self.parse_right_param()?;
+                return Ok((pattern, Some(test), body));
+            }
+            if_(test, sequence(body), nil())
+        } else {
+            self.parse_exp_from(token)?
+        };
+        let body = spanned(Span::new(start, self.lexer.loc()), body);
+
         self.parse_right_param()?;
-        Ok((key, value))
+        Ok((pattern, None, body))
+    }
+
+    pub fn parse_case(&mut self) -> Result<Exp, ParseError> {
+        self.lexer.skip_token();
+        let exp = self.parse_exp()?;
+        let mut cases = vec![];
+        let mut else_ = false;
+        while let Ok(token) = self.lexer.peek_token() {
+            if token.kind == TokenKind::RParen {
+                break;
+            }
+            // Arms after a catch-all arm could never match.
+            if else_ {
+                return Err(ParseError::TrailingInput(token));
+            }
+            let (pattern, guard, body) = self.parse_one_case()?;
+            else_ = guard.is_none() && pattern.as_symbol_id() == Some(SymbolId::intern("_"));
+            cases.push((pattern, guard, body));
+        }
+        self.parse_right_param()?;
+        Ok(Exp::Case(Rc::new(exp), cases))
+    }
+
+    /// Parses a call of `assert` or `assert-eq`, and passes it the call itself,
+    /// quoted, as a last argument, so that a failed assertion shows the call as
+    /// written.
+    pub fn parse_assert(&mut self) -> Result<Exp, ParseError> {
+        let call = self.parse_list()?;
+        let Exp::List(args) = &call else {
+            return Ok(call);
+        };
+        let mut args = args.to_vec();
+        args.push(quote(call.clone()));
+        Ok(list(&args))
+    }
+
+    /// Parses `(try body (catch x handler))`.
+    pub fn parse_try(&mut self) -> Result<Exp, ParseError> {
+        self.lexer.skip_token();
+        let body = self.parse_exp()?;
+        self.parse_left_param()?;
+        self.parse_special_symbol("catch")?;
+        let x = self.parse_symbol()?;
+        let handler = self.parse_exp()?;
+        self.parse_right_param()?;
+        self.parse_right_param()?;
+        Ok(try_(body, &x, handler))
     }
 
     pub fn parse_exp(&mut self) -> Result<Exp, ParseError> {
         let token = self.next_token()?;
+        let start = token.loc;
+        let exp = self.parse_exp_from(token)?;
+        if !self.spans {
+            return Ok(exp);
+        }
+        Ok(spanned(Span::new(start, self.lexer.loc()), exp))
+    }
+
+    fn parse_exp_from(&mut self, token: Token) -> Result<Exp, ParseError> {
         match token.kind {
-            TokenKind::Quote => Ok(quote(self.parse_exp()?)),
-            TokenKind::UnQuote => Ok(unquote(self.parse_exp()?)),
+            TokenKind::Quote | TokenKind::QuasiQuote => {
+                Ok(quote(self.parse_quoted(self.quoted + 1)?))
+            }
+            TokenKind::UnQuote => Ok(unquote(self.parse_quoted(self.quoted.saturating_sub(1))?)),
+            TokenKind::UnQuoteSplicing => Ok(unquote_splicing(
+                self.parse_quoted(self.quoted.saturating_sub(1))?,
+            )),
+            TokenKind::LBracket => self.parse_vector(),
+            TokenKind::LBrace => self.parse_map(token),
             TokenKind::Integer(int) => Ok(integer(int)),
+            #[cfg(feature = "bignum")]
+            TokenKind::BigInt(int) => Ok(Exp::BigInt(int)),
+            TokenKind::Rational(numerator, denominator) => Ok(rational(numerator, denominator)),
+            TokenKind::Float(f) => Ok(float(f)),
+            TokenKind::String(s) if s.contains("${") => self.parse_interpolation(s, token.loc),
             TokenKind::String(s) => Ok(Exp::String(s)),
+            TokenKind::Bytes(b) => Ok(bytes(&b)),
+            TokenKind::Char(ch) => Ok(Exp::Char(ch)),
+            TokenKind::Keyword(name) => Ok(Exp::Keyword(name)),
             TokenKind::Symbol(sym) => match sym.as_str() {
                 "nil" => Ok(nil()),
                 "false" => Ok(bool(false)),
@@ -156,34 +676,77 @@ impl Parser {
                 Ok(TokenKind::Symbol(sym)) => match sym.as_str() {
                     "\\" => self.parse_lambda(),
                     "if" => self.parse_if(),
+                    "and" => self.parse_and(),
+                    "or" => self.parse_or(),
+                    "cond" => self.parse_cond(),
+                    "begin" => self.parse_begin(),
+                    "when" => self.parse_when(),
+                    "unless" => self.parse_unless(),
                     "let" => self.parse_let(),
-                    _ => Ok(list(&self.parse_exps()?)),
+                    "letrec" => self.parse_letrec(),
+                    "case" => self.parse_case(),
+                    "try" => self.parse_try(),
+                    "assert" | "assert-eq" => self.parse_assert(),
+                    _ => self.parse_list(),
                 },
-                _ => Ok(list(&self.parse_exps()?)),
+                _ => self.parse_list(),
             },
-            TokenKind::RParen => Err(ParseError::UnmatchedParen(token)),
+            TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => {
+                Err(ParseError::UnmatchedParen(token))
+            }
+            TokenKind::Dot => Err(ParseError::UnexpectedDot(token)),
         }
     }
 
-    pub fn parse_def(&mut self) -> Result<(String, Exp), ParseError> {
+    /// Desugars a string with `${exp}` holes into a call of `string/format`
+    /// that displays the value of each `exp` in its place. The qualified name
+    /// keeps a definition of `format` in the program from changing it.
+    fn parse_interpolation(&mut self, s: String, loc: Location) -> Result<Exp, ParseError> {
+        let invalid =
+            || ParseError::InvalidInterpolation(Token::new(TokenKind::String(s.clone()), loc));
+        let mut template = String::new();
+        let mut exps = vec![symbol("string/format")];
+        let mut rest = s.as_str();
+        while let Some((literal, after)) = rest.split_once("${") {
+            template.push_str(&literal.replace('{', "{{").replace('}', "}}"));
+            template.push_str("{}");
+            let mut depth = 0;
+            let end = after
+                .char_indices()
+                .find(|&(_, ch)| {
+                    match ch {
+                        '{' => depth += 1,
+                        '}' if depth == 0 => return true,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    false
+                })
+                .map(|(end, _)| end)
+                .ok_or_else(invalid)?;
+            // The source of the hole is not the program source, so its spans
+            // would be meaningless.
+            exps.push(read(&after[..end]).map_err(|_| invalid())?);
+            rest = &after[end + 1..];
+        }
+        template.push_str(&rest.replace('{', "{{").replace('}', "}}"));
+        exps.insert(1, Exp::String(template));
+        Ok(list(&exps))
+    }
+
+    pub fn parse_def(&mut self) -> Result<Define, ParseError> {
         let name = self.parse_symbol()?;
 
-        self.parse_left_param()?;
-        let args = self.parse_exps()?;
+        let (params, rest) = self.parse_params()?;
 
         let body = self.parse_exp()?;
 
         self.parse_right_param()?;
 
-        let exp = args
-            .into_iter()
-            .rev()
-            .fold(body, |acc, arg| lambda(arg.as_symbol().unwrap(), acc));
-
-        Ok((name, exp))
+        Ok((name, params_lambda(&params, rest, body)))
     }
 
-    pub fn parse_macro(&mut self) -> Result<(String, Exp, Vec<Exp>), ParseError> {
+    pub fn parse_macro(&mut self) -> Result<Macro, ParseError> {
         let name = self.parse_symbol()?;
 
         self.parse_left_param()?;
@@ -196,11 +759,19 @@ impl Parser {
         Ok((name, body, args))
     }
 
-    pub fn parse_defines_or_macros(
-        &mut self,
-    ) -> Result<(Vec<(String, Exp)>, Vec<(String, Exp, Vec<Exp>)>), ParseError> {
+    /// Parses `(deftest name body)`.
+    pub fn parse_test(&mut self) -> Result<Test, ParseError> {
+        let name = self.parse_symbol()?;
+        let body = self.parse_exp()?;
+        self.parse_right_param()?;
+        Ok((name, body))
+    }
+
+    pub fn parse_defines_or_macros(&mut self) -> Result<Items, ParseError> {
         let mut defines = vec![];
         let mut macros = vec![];
+        let mut tests = vec![];
+        let mut interface = Interface::default();
         while let Ok(token) = self.lexer.peek_token() {
             if token.kind == TokenKind::RParen {
                 break;
@@ -209,32 +780,43 @@ impl Parser {
 
             match self.next_token()?.as_symbol() {
                 Some("define") => defines.push(self.parse_def()?),
-                Some("macro") => macros.push(self.parse_macro()?),
+                Some("macro" | "define-macro") => macros.push(self.parse_macro()?),
+                Some("deftest") => tests.push(self.parse_test()?),
+                Some("import") => {
+                    interface.imports.push(self.parse_symbol()?);
+                    self.parse_right_param()?;
+                }
+                Some("export") => {
+                    while !self.peek_right_param() {
+                        interface.exports.push(self.parse_symbol()?);
+                    }
+                    self.parse_right_param()?;
+                }
                 _ => return Err(ParseError::ExpectedSymbol(token)),
             }
         }
-        Ok((defines, macros))
+        Ok((defines, macros, tests, interface))
     }
 
-    pub fn parse_module(
-        &mut self,
-    ) -> Result<(String, Vec<(String, Exp)>, Vec<(String, Exp, Vec<Exp>)>), ParseError> {
+    pub fn parse_module(&mut self) -> Result<ParsedModule, ParseError> {
         self.parse_left_param()?;
         self.parse_special_symbol("module")?;
 
         let module_name = self.parse_symbol()?;
-        let (defines, macros) = self.parse_defines_or_macros()?;
+        let (defines, macros, tests, interface) = self.parse_defines_or_macros()?;
 
         self.parse_right_param()?;
 
-        Ok((module_name, defines, macros))
+        Ok((module_name, defines, macros, tests, interface))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Parser;
+    use super::{parse_exp, parse_module, Interface, ParseError, Parser};
     use crate::ast::*;
+    use crate::token::{Location, Span};
+    use std::rc::Rc;
 
     #[test]
     fn test_parse_integer() {
@@ -242,6 +824,21 @@ mod tests {
         assert_eq!(parser.parse_exp(), Ok(integer(123)));
     }
 
+    #[test]
+    fn test_parse_float() {
+        let mut parser = Parser::new("-1.25");
+        assert_eq!(parser.parse_exp(), Ok(float(-1.25)));
+
+        let mut parser = Parser::new("-6/4");
+        assert_eq!(parser.parse_exp(), Ok(rational(-3, 2)));
+
+        // Floats display in a form that parses back to the same value.
+        for f in [1.0, 0.1 + 0.2, 1e100, -2.5e-7] {
+            let mut parser = Parser::new(&float(f).to_string());
+            assert_eq!(parser.parse_exp(), Ok(float(f)));
+        }
+    }
+
     #[test]
     fn test_parse_nil() {
         let mut parser = Parser::new("nil");
@@ -272,18 +869,107 @@ mod tests {
         assert_eq!(parser.parse_exp(), Ok(string("hello")));
     }
 
+    #[test]
+    fn test_parse_interpolation() {
+        assert_eq!(
+            parse_exp(r#""a {b} ${(+ 1 2)}${x}!""#),
+            Ok(list(&[
+                symbol("string/format"),
+                string("a {{b}} {}{}!"),
+                list(&[symbol("+"), integer(1), integer(2)]),
+                symbol("x"),
+            ]))
+        );
+        assert_eq!(
+            parse_exp(r#""${'(1 2)}""#),
+            Ok(list(&[
+                symbol("string/format"),
+                string("{}"),
+                quote(list(&[integer(1), integer(2)])),
+            ]))
+        );
+        assert_eq!(
+            parse_exp(r#""${(map/get :a {:a 1})}""#),
+            Ok(list(&[
+                symbol("string/format"),
+                string("{}"),
+                list(&[
+                    symbol("map/get"),
+                    keyword("a"),
+                    map_of(&[(keyword("a"), integer(1))]),
+                ]),
+            ]))
+        );
+        for source in [r#""${}""#, r#""${1 2}""#, r#""${(+ 1 2)""#] {
+            assert!(
+                matches!(parse_exp(source), Err(ParseError::InvalidInterpolation(_))),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_literals() {
+        assert_eq!(
+            parse_exp("[1 \"two\" :three]"),
+            Ok(vector(&[integer(1), string("two"), keyword("three")]))
+        );
+        assert_eq!(
+            parse_exp("{:a [1 2] :b {}}"),
+            Ok(map_of(&[
+                (keyword("a"), vector(&[integer(1), integer(2)])),
+                (keyword("b"), map_of(&[])),
+            ]))
+        );
+        // Literals with expressions in them evaluate them.
+        assert_eq!(
+            parse_exp("[x (+ 1 2)]"),
+            Ok(list(&[
+                symbol("vector"),
+                symbol("x"),
+                list(&[symbol("+"), integer(1), integer(2)]),
+            ]))
+        );
+        assert_eq!(
+            parse_exp("{:a x}"),
+            Ok(list(&[symbol("map/new"), keyword("a"), symbol("x")]))
+        );
+        // Quoted literals are data.
+        assert_eq!(
+            parse_exp("'[a (b c)]"),
+            Ok(quote(vector(&[
+                symbol("a"),
+                list(&[symbol("b"), symbol("c")])
+            ])))
+        );
+
+        assert!(matches!(
+            parse_exp("{:a 1 :b}"),
+            Err(ParseError::OddMapLiteral(_))
+        ));
+        assert!(matches!(
+            parse_exp("[1 2)"),
+            Err(ParseError::UnmatchedParen(_))
+        ));
+        assert!(matches!(
+            parse_exp("(1 2]"),
+            Err(ParseError::UnmatchedParen(_))
+        ));
+    }
+
     #[test]
     fn test_parse_quote() {
         let mut parser = Parser::new("'(html (head '(body (h1 \"hello\"))))");
         assert_eq!(
             parser.parse_exp(),
-            Ok(quote(list(&vec![
+            Ok(quote(list(&[
                 symbol("html"),
-                list(&vec![
+                list(&[
                     symbol("head"),
-                    quote(list(&vec![
+                    quote(list(&[
                         symbol("body"),
-                        list(&vec![symbol("h1"), string("hello")])
+                        list(&[symbol("h1"), string("hello")])
                     ]))
                 ])
             ])))
@@ -295,18 +981,63 @@ mod tests {
         let mut parser = Parser::new("'(a b ~c)");
         assert_eq!(
             parser.parse_exp(),
-            Ok(quote(list(&vec![
+            Ok(quote(list(&[
                 symbol("a"),
                 symbol("b"),
                 unquote(symbol("c"))
             ])))
         );
+
+        let mut parser = Parser::new("`(a ,b ,@c ~@d)");
+        assert_eq!(
+            parser.parse_exp(),
+            Ok(quote(list(&[
+                symbol("a"),
+                unquote(symbol("b")),
+                unquote_splicing(symbol("c")),
+                unquote_splicing(symbol("d"))
+            ])))
+        );
     }
 
     #[test]
     fn test_parse_lambda() {
         let mut parser = Parser::new(r"(\ x x)");
         assert_eq!(parser.parse_exp(), Ok(lambda("x", symbol("x"))));
+
+        let mut parser = Parser::new(r"(\ (x y) (+ x y))");
+        assert_eq!(
+            parser.parse_exp(),
+            Ok(lambda_n(
+                &["x", "y"],
+                list(&[symbol("+"), symbol("x"), symbol("y")])
+            ))
+        );
+        assert_eq!(
+            lambda_n(&["x", "y"], symbol("x")),
+            lambda("x", lambda("y", symbol("x")))
+        );
+
+        let mut parser = Parser::new(r"(\ (x . rest) rest)");
+        assert_eq!(
+            parser.parse_exp(),
+            Ok(lambda("x", variadic("rest", symbol("rest"))))
+        );
+
+        let mut parser = Parser::new(r"(\ (. rest) rest)");
+        assert_eq!(parser.parse_exp(), Ok(variadic("rest", symbol("rest"))));
+
+        let mut parser = Parser::new(r"(\ (x . y z) x)");
+        assert!(matches!(
+            parser.parse_exp(),
+            Err(ParseError::UnmatchedParen(_))
+        ));
+
+        let mut parser = Parser::new(r"(\ (x 1) x)");
+        assert!(matches!(
+            parser.parse_exp(),
+            Err(ParseError::ExpectedSymbol(_))
+        ));
     }
 
     #[test]
@@ -318,6 +1049,99 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_cond() {
+        let mut parser = Parser::new("(cond (a 1) (b 2) (else 3))");
+        assert_eq!(
+            parser.parse_exp(),
+            Ok(if_(
+                symbol("a"),
+                integer(1),
+                if_(symbol("b"), integer(2), integer(3))
+            ))
+        );
+
+        let mut parser = Parser::new("(cond (a 1))");
+        assert_eq!(parser.parse_exp(), Ok(if_(symbol("a"), integer(1), nil())));
+
+        let mut parser = Parser::new("(cond (else 1) (a 2))");
+        assert!(matches!(
+            parser.parse_exp(),
+            Err(ParseError::TrailingInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_begin() {
+        let mut parser = Parser::new("(begin a b c)");
+        assert_eq!(
+            parser.parse_exp(),
+            Ok(let_(
+                ("#_", symbol("a")),
+                let_(("#_", symbol("b")), symbol("c"))
+            ))
+        );
+
+        let mut parser = Parser::new("(begin)");
+        assert_eq!(parser.parse_exp(), Ok(nil()));
+    }
+
+    #[test]
+    fn test_parse_letrec() {
+        let mut parser = Parser::new("(letrec (f (\\ x (f x))) f)");
+        assert_eq!(
+            parser.parse_exp(),
+            Ok(letrec(
+                &[("f", lambda("x", list(&[symbol("f"), symbol("x")])))],
+                symbol("f")
+            ))
+        );
+
+        let mut parser = Parser::new("(letrec ((f g) (g f)) f)");
+        assert_eq!(
+            parser.parse_exp(),
+            Ok(letrec(
+                &[("f", symbol("g")), ("g", symbol("f"))],
+                symbol("f")
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_when_unless() {
+        let mut parser = Parser::new("(when a 1)");
+        assert_eq!(parser.parse_exp(), Ok(if_(symbol("a"), integer(1), nil())));
+
+        let mut parser = Parser::new("(unless a 1)");
+        assert_eq!(parser.parse_exp(), Ok(if_(symbol("a"), nil(), integer(1))));
+    }
+
+    #[test]
+    fn test_parse_and_or() {
+        let mut parser = Parser::new("(and a b c)");
+        assert_eq!(
+            parser.parse_exp(),
+            Ok(if_(
+                symbol("a"),
+                if_(symbol("b"), symbol("c"), bool(false)),
+                bool(false)
+            ))
+        );
+
+        let mut parser = Parser::new("(or a b)");
+        assert_eq!(
+            parser.parse_exp(),
+            Ok(if_(symbol("a"), bool(true), symbol("b")))
+        );
+
+        let mut parser = Parser::new("(and)");
+        assert_eq!(parser.parse_exp(), Ok(bool(true)));
+        let mut parser = Parser::new("(or)");
+        assert_eq!(parser.parse_exp(), Ok(bool(false)));
+        let mut parser = Parser::new("(or a)");
+        assert_eq!(parser.parse_exp(), Ok(symbol("a")));
+    }
+
     #[test]
     fn test_parse_let() {
         let mut parser = Parser::new("(let (x 1) 2)");
@@ -330,10 +1154,185 @@ mod tests {
                 ("x", integer(1)),
                 let_(
                     ("y", integer(2)),
-                    list(&vec![symbol("+"), symbol("x"), symbol("y")])
+                    list(&[symbol("+"), symbol("x"), symbol("y")])
                 )
             ))
         );
+
+        let mut parser = Parser::new("(let ((x 1) (y x)) (+ x y))");
+        assert_eq!(
+            parser.parse_exp(),
+            Ok(let_(
+                ("x", integer(1)),
+                let_(
+                    ("y", symbol("x")),
+                    list(&[symbol("+"), symbol("x"), symbol("y")])
+                )
+            ))
+        );
+
+        let mut parser = Parser::new("(let () 1)");
+        assert_eq!(parser.parse_exp(), Ok(integer(1)));
+    }
+
+    #[test]
+    fn test_parse_named_let() {
+        let mut parser = Parser::new("(let loop ((i 0) (acc nil)) (loop i acc))");
+        assert_eq!(
+            parser.parse_exp(),
+            Ok(letrec(
+                &[(
+                    "loop",
+                    lambda(
+                        "i",
+                        lambda("acc", list(&[symbol("loop"), symbol("i"), symbol("acc")]))
+                    )
+                )],
+                list(&[symbol("loop"), integer(0), nil()])
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_case() {
+        let mut parser = Parser::new("(case x (1 'one) ('a \"a\"))");
+        assert_eq!(
+            parser.parse_exp(),
+            Ok(case(
+                symbol("x"),
+                &[
+                    (integer(1), quote(symbol("one"))),
+                    (quote(symbol("a")), string("a"))
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_case_guard() {
+        let mut parser = Parser::new("(case x (n (when (> n 0)) 'pos) (n (when (< n 0) 'neg)))");
+        assert_eq!(
+            parser.parse_exp(),
+            Ok(Exp::Case(
+                Rc::new(symbol("x")),
+                vec![
+                    (
+                        symbol("n"),
+                        Some(list(&[symbol(">"), symbol("n"), integer(0)])),
+                        quote(symbol("pos"))
+                    ),
+                    (
+                        symbol("n"),
+                        None,
+                        if_(
+                            list(&[symbol("<"), symbol("n"), integer(0)]),
+                            quote(symbol("neg")),
+                            nil()
+                        )
+                    ),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_case_else() {
+        let mut parser = Parser::new("(case x (1 'one) (else 'other))");
+        assert_eq!(
+            parser.parse_exp(),
+            Ok(case(
+                symbol("x"),
+                &[
+                    (integer(1), quote(symbol("one"))),
+                    (symbol("_"), quote(symbol("other")))
+                ]
+            ))
+        );
+
+        let mut parser = Parser::new("(case x (else 'other) (1 'one))");
+        assert!(matches!(
+            parser.parse_exp(),
+            Err(ParseError::TrailingInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_exp_str() {
+        assert_eq!(
+            parse_exp("(+ 1 2)"),
+            Ok(list(&[symbol("+"), integer(1), integer(2)]))
+        );
+        assert!(matches!(
+            parse_exp("(+ 1 2) 3"),
+            Err(ParseError::TrailingInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_module_str() {
+        let module = parse_module("(module main (define foo (x) x) (macro id (x) x))").unwrap();
+        assert_eq!(module.name, "main");
+        assert_eq!(
+            module.defines.get(&"foo".into()),
+            Some(&lambda("x", symbol("x")))
+        );
+        assert_eq!(
+            module.macros.get(&"id".into()),
+            Some(&(symbol("x"), vec![symbol("x")]))
+        );
+
+        let module = parse_module("(module main (define-macro id (x) x))").unwrap();
+        assert_eq!(
+            module.macros.get(&"id".into()),
+            Some(&(symbol("x"), vec![symbol("x")]))
+        );
+    }
+
+    #[test]
+    fn test_parse_interface() {
+        let module = parse_module(
+            "(module main (import math) (export main) (import io) (define main () 1))",
+        )
+        .unwrap();
+        assert_eq!(module.imports, vec!["math".to_string(), "io".to_string()]);
+        assert_eq!(module.exports, vec!["main".into()]);
+        assert!(matches!(
+            parse_module("(module main (import 1))"),
+            Err(ParseError::ExpectedSymbol(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        let module = parse_module(
+            "; A module.
+(module main
+  #| The identity.
+     #| Block comments nest. |# |#
+  (define id (x) #;(+ x 1) x) ; trailing
+  #;(define unused () 1))",
+        )
+        .unwrap();
+        assert_eq!(module.defines.len(), 1);
+        assert_eq!(
+            module.defines.get(&"id".into()),
+            Some(&lambda("x", symbol("x")))
+        );
+    }
+
+    #[test]
+    fn test_parse_span() {
+        let mut parser = Parser::new("(+ 1\n  foo)");
+        let exp = parser.parse_exp().unwrap();
+        assert_eq!(
+            exp.span(),
+            Some(Span::new(Location::new(0, 0), Location::new(1, 6)))
+        );
+        let elems = exp.as_list().unwrap();
+        assert_eq!(
+            elems[2].span(),
+            Some(Span::new(Location::new(1, 2), Location::new(1, 5)))
+        );
     }
 
     #[test]
@@ -341,21 +1340,21 @@ mod tests {
         let mut parser = Parser::new("(+ 1 2)");
         assert_eq!(
             parser.parse_exp(),
-            Ok(list(&vec![symbol("+"), integer(1), integer(2)]))
+            Ok(list(&[symbol("+"), integer(1), integer(2)]))
         );
 
         let mut parser = Parser::new("(+ 1 (+ 2 3))");
         assert_eq!(
             parser.parse_exp(),
-            Ok(list(&vec![
+            Ok(list(&[
                 symbol("+"),
                 integer(1),
-                list(&vec![symbol("+"), integer(2), integer(3)])
+                list(&[symbol("+"), integer(2), integer(3)])
             ]))
         );
 
         let mut parser = Parser::new("()");
-        assert_eq!(parser.parse_exp(), Ok(list(&vec![])));
+        assert_eq!(parser.parse_exp(), Ok(list(&[])));
     }
 
     #[test]
@@ -368,11 +1367,13 @@ mod tests {
                 vec![
                     (
                         "foo".to_string(),
-                        lambda("x", list(&vec![symbol("+"), integer(2), integer(4)]))
+                        lambda("x", list(&[symbol("+"), integer(2), integer(4)]))
                     ),
                     ("bar".to_string(), integer(2))
                 ],
-                vec![]
+                vec![],
+                vec![],
+                Interface::default()
             ))
         );
     }
@@ -395,11 +1396,13 @@ mod tests {
                 vec![
                     (
                         "foo".to_string(),
-                        lambda("x", list(&vec![symbol("+"), integer(2), integer(4)]))
+                        lambda("x", list(&[symbol("+"), integer(2), integer(4)]))
                     ),
                     ("bar".to_string(), integer(2))
                 ],
-                vec![]
+                vec![],
+                vec![],
+                Interface::default()
             ))
         );
     }
@@ -426,7 +1429,9 @@ mod tests {
                         unquote(symbol("then"))
                     ),),
                     vec![symbol("cond"), symbol("then"), symbol("else")],
-                )]
+                )],
+                vec![],
+                Interface::default()
             ))
         );
     }
@@ -0,0 +1,331 @@
+//! A pretty-printer in the style of Wadler's "A prettier printer".
+//!
+//! An expression is first turned into a [`Doc`], which describes the ways it
+//! may be laid out, and the doc is then laid out to fit a line width. Each
+//! list is a group that is printed on one line if it fits, and with one
+//! element per line otherwise.
+
+use crate::{ast::Exp, prelude::*};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Doc {
+    Text(String),
+    /// A space, or a line break if the enclosing group does not fit.
+    Line,
+    /// A line break even in a group that would fit, preceded by an empty line
+    /// if `true`.
+    HardLine(bool),
+    /// Indents the lines broken inside the doc by a further amount.
+    Nest(usize, Box<Doc>),
+    /// Indents the lines broken inside the doc to the column it starts at.
+    Align(Box<Doc>),
+    Concat(Vec<Doc>),
+    /// Lays the doc out on a single line if it fits, and breaks its lines
+    /// otherwise.
+    Group(Box<Doc>),
+}
+
+pub(crate) fn text(s: &str) -> Doc {
+    Doc::Text(s.to_string())
+}
+
+pub(crate) fn nest(indent: usize, doc: Doc) -> Doc {
+    Doc::Nest(indent, Box::new(doc))
+}
+
+pub(crate) fn align(doc: Doc) -> Doc {
+    Doc::Align(Box::new(doc))
+}
+
+pub(crate) fn group(doc: Doc) -> Doc {
+    Doc::Group(Box::new(doc))
+}
+
+/// The docs separated by lines.
+fn lines(docs: impl IntoIterator<Item = Doc>) -> Doc {
+    let mut result = vec![];
+    for doc in docs {
+        if !result.is_empty() {
+            result.push(Doc::Line);
+        }
+        result.push(doc);
+    }
+    Doc::Concat(result)
+}
+
+/// `(head first` followed by the rest on lines of their own, indented by two
+/// columns. Used for forms with a body, such as `let` and `if`.
+fn body_form(head: &str, first: Doc, rest: impl IntoIterator<Item = Doc>) -> Doc {
+    let mut body = vec![];
+    for doc in rest {
+        body.push(Doc::Line);
+        body.push(doc);
+    }
+    group(align(Doc::Concat(vec![
+        text("("),
+        text(head),
+        text(" "),
+        first,
+        nest(2, Doc::Concat(body)),
+        text(")"),
+    ])))
+}
+
+/// Elements between delimiters, aligned with the first one.
+fn seq(open: &str, elems: impl IntoIterator<Item = Doc>, close: &str) -> Doc {
+    group(Doc::Concat(vec![
+        text(open),
+        align(lines(elems)),
+        text(close),
+    ]))
+}
+
+/// A call, with the arguments aligned with the first one.
+fn call(head: Doc, args: impl IntoIterator<Item = Doc>) -> Doc {
+    let args = args.into_iter().collect::<Vec<_>>();
+    if args.is_empty() {
+        return Doc::Concat(vec![text("("), head, text(")")]);
+    }
+    group(Doc::Concat(vec![
+        text("("),
+        head,
+        text(" "),
+        align(lines(args)),
+        text(")"),
+    ]))
+}
+
+fn is_body_form(sym: &str) -> bool {
+    matches!(
+        sym,
+        "define" | "macro" | "define-macro" | "deftest" | "module" | "when" | "unless" | "begin"
+    )
+}
+
+impl Doc {
+    /// The doc of an expression. Laid out on one line, it is the
+    /// [readable](Exp::to_readable_string) form of the expression.
+    pub fn from_exp(exp: &Exp) -> Doc {
+        match exp {
+            Exp::Spanned(_, exp) => Doc::from_exp(exp),
+            Exp::List(exps) => match exps.split_first() {
+                Some((head, [first, rest @ ..])) if head.as_symbol().is_some_and(is_body_form) => {
+                    body_form(
+                        &head.to_string(),
+                        Doc::from_exp(first),
+                        rest.iter().map(Doc::from_exp),
+                    )
+                }
+                Some((head, args)) if head.as_symbol().is_some() => {
+                    call(Doc::from_exp(head), args.iter().map(Doc::from_exp))
+                }
+                _ => seq("(", exps.iter().map(Doc::from_exp), ")"),
+            },
+            Exp::Apply(f, arg) => call(Doc::from_exp(f), [Doc::from_exp(arg)]),
+            Exp::Lambda(x, body) | Exp::Closure(x, body, _) => {
+                body_form("\\", text(&format!("({})", x)), [Doc::from_exp(body)])
+            }
+            Exp::Variadic(f) => match f.node() {
+                Exp::Lambda(x, body) | Exp::Closure(x, body, _) => {
+                    body_form("\\", text(&format!("(. {})", x)), [Doc::from_exp(body)])
+                }
+                _ => text(&exp.to_readable_string()),
+            },
+            Exp::If(cond, then, else_) => body_form(
+                "if",
+                Doc::from_exp(cond),
+                [Doc::from_exp(then), Doc::from_exp(else_)],
+            ),
+            Exp::Let((x, bind), body) => body_form(
+                "let",
+                seq("(", [text(&x.to_string()), Doc::from_exp(bind)], ")"),
+                [Doc::from_exp(body)],
+            ),
+            Exp::LetRec(binds, body) => body_form(
+                "letrec",
+                seq(
+                    "(",
+                    binds.iter().map(|(x, bind)| {
+                        seq("(", [text(&x.to_string()), Doc::from_exp(bind)], ")")
+                    }),
+                    ")",
+                ),
+                [Doc::from_exp(body)],
+            ),
+            Exp::Case(exp, cases) => body_form(
+                "case",
+                Doc::from_exp(exp),
+                cases.iter().map(|(pattern, guard, body)| {
+                    let mut head = vec![Doc::from_exp(pattern)];
+                    if let Some(guard) = guard {
+                        head.push(text(" "));
+                        head.push(call(text("when"), [Doc::from_exp(guard)]));
+                    }
+                    group(align(Doc::Concat(vec![
+                        text("("),
+                        Doc::Concat(head),
+                        nest(1, Doc::Concat(vec![Doc::Line, Doc::from_exp(body)])),
+                        text(")"),
+                    ])))
+                }),
+            ),
+            Exp::Try(body, x, handler) => body_form(
+                "try",
+                Doc::from_exp(body),
+                [body_form(
+                    "catch",
+                    text(&x.to_string()),
+                    [Doc::from_exp(handler)],
+                )],
+            ),
+            Exp::Quote(exp) => Doc::Concat(vec![text("'"), Doc::from_exp(exp)]),
+            Exp::UnQuote(exp) => Doc::Concat(vec![text("~"), Doc::from_exp(exp)]),
+            Exp::UnQuoteSplicing(exp) => Doc::Concat(vec![text("~@"), Doc::from_exp(exp)]),
+            Exp::Values(exps) => seq("#<values ", exps.iter().map(Doc::from_exp), ">"),
+            Exp::Map(map) => seq(
+                "{",
+                map.iter().map(|(key, value)| {
+                    group(Doc::Concat(vec![
+                        Doc::from_exp(key),
+                        nest(2, Doc::Concat(vec![Doc::Line, Doc::from_exp(value)])),
+                    ]))
+                }),
+                "}",
+            ),
+            Exp::Set(set) => seq("#{", set.iter().map(Doc::from_exp), "}"),
+            Exp::Vector(exps) => seq("[", exps.iter().map(Doc::from_exp), "]"),
+            _ => text(&exp.to_readable_string()),
+        }
+    }
+
+    /// Lays the doc out in lines of at most `width` columns where possible.
+    pub fn layout(&self, width: usize) -> String {
+        let mut out = String::new();
+        let mut column = 0;
+        let mut stack = vec![(0, false, self)];
+        while let Some((indent, flat, doc)) = stack.pop() {
+            match doc {
+                Doc::Text(s) => {
+                    out.push_str(s);
+                    column += s.chars().count();
+                }
+                Doc::Line if flat => {
+                    out.push(' ');
+                    column += 1;
+                }
+                Doc::Line | Doc::HardLine(false) => {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                    column = indent;
+                }
+                Doc::HardLine(true) => {
+                    out.push_str("\n\n");
+                    out.push_str(&" ".repeat(indent));
+                    column = indent;
+                }
+                Doc::Nest(more, doc) => stack.push((indent + more, flat, doc)),
+                Doc::Align(doc) => stack.push((column, flat, doc)),
+                Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, flat, doc))),
+                Doc::Group(doc) => {
+                    let fits = flat || fits(width as isize - column as isize, doc, &stack);
+                    stack.push((indent, fits, doc));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Whether `doc` laid out on one line, followed by the rest of the line, fits
+/// in `remaining` columns.
+fn fits(mut remaining: isize, doc: &Doc, rest: &[(usize, bool, &Doc)]) -> bool {
+    let mut stack = vec![(true, doc)];
+    let mut rest = rest.iter().rev();
+    while remaining >= 0 {
+        let (flat, doc) = match stack.pop() {
+            Some(next) => next,
+            None => match rest.next() {
+                Some(&(_, flat, doc)) => (flat, doc),
+                None => return true,
+            },
+        };
+        match doc {
+            Doc::Text(s) => remaining -= s.chars().count() as isize,
+            Doc::Line if flat => remaining -= 1,
+            Doc::Line => return true,
+            // A hard line cannot be laid out flat.
+            Doc::HardLine(_) => return !flat,
+            Doc::Nest(_, doc) | Doc::Align(doc) | Doc::Group(doc) => stack.push((flat, doc)),
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (flat, doc))),
+        }
+    }
+    false
+}
+
+impl Exp {
+    /// Prints the expression readably, breaking lists that do not fit in
+    /// `width` columns across lines with indentation.
+    pub fn pretty(&self, width: usize) -> String {
+        Doc::from_exp(self).layout(width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ast::*, eval::EvalError, parser::parse_exp};
+
+    #[test]
+    fn test_pretty() {
+        let exp =
+            parse_exp(r#"(let (xs '(1 2 "three")) (if (null? xs) nil (map (\ x (list x x)) xs)))"#)
+                .unwrap();
+        assert_eq!(exp.pretty(100), exp.to_readable_string());
+        assert_eq!(
+            exp.pretty(40),
+            r#"(let (xs '(1 2 "three"))
+  (if (null? xs)
+    nil
+    (map (\ (x) (list x x)) xs)))"#
+        );
+        assert_eq!(
+            exp.pretty(24),
+            r#"(let (xs '(1 2 "three"))
+  (if (null? xs)
+    nil
+    (map (\ (x)
+           (list x x))
+         xs)))"#
+        );
+    }
+
+    #[test]
+    fn test_pretty_data() {
+        let exp = list(&[
+            map_of(&[(symbol("a"), integer(1)), (symbol("b"), integer(2))]),
+            vector(&[string("x"), string("y")]),
+        ]);
+        assert_eq!(exp.pretty(80), r#"({a 1 b 2} ["x" "y"])"#);
+        assert_eq!(
+            exp.pretty(8),
+            r#"({a 1
+  b 2}
+ ["x"
+  "y"])"#
+        );
+    }
+
+    #[test]
+    fn test_pretty_error_message() {
+        let short = EvalError::NoMatchingCase(list(&[string("a"), integer(1)]));
+        assert_eq!(short.to_string(), r#"no case matched ("a" 1)"#);
+
+        let long = EvalError::NoMatchingCase(list(&vec![integer(1000000); 9]));
+        assert_eq!(
+            long.to_string(),
+            format!(
+                "no case matched \n    (1000000\n{}     1000000)\n",
+                "     1000000\n".repeat(7)
+            )
+        );
+    }
+}
@@ -0,0 +1,233 @@
+#[cfg(not(feature = "std"))]
+use core::sync::atomic::AtomicUsize;
+#[cfg(not(feature = "std"))]
+use core::{
+    cell::UnsafeCell,
+    ops::Deref,
+    sync::atomic::{
+        AtomicBool,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+use core::{cmp::Ordering, fmt::Display, ops::DerefMut};
+#[cfg(feature = "std")]
+use std::{
+    cell::Cell,
+    sync::{LazyLock, Mutex},
+};
+
+use crate::{prelude::*, HashMap};
+
+/// An interned symbol name.
+///
+/// Symbols with the same name share an id, so comparing and hashing them does
+/// not touch the name. Names are interned for the lifetime of the process,
+/// see [`interned_bytes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SymbolId(u32);
+
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<&'static str, SymbolId>,
+    names: Vec<&'static str>,
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    /// The bytes taken by the names this thread has interned.
+    static INTERNED_BYTES: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The bytes taken by the names interned so far, without `std` threads to
+/// tell apart.
+#[cfg(not(feature = "std"))]
+static INTERNED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The bytes taken by the names the current thread has interned so far, which
+/// are never freed. An evaluation counts the names a builtin interns against
+/// its [`EvalConfig::max_memory`](crate::eval::EvalConfig::max_memory) by
+/// comparing this before and after, so names interned by other threads are
+/// not billed to it. Without `std` the count covers every thread.
+pub fn interned_bytes() -> usize {
+    #[cfg(feature = "std")]
+    return INTERNED_BYTES.with(Cell::get);
+    #[cfg(not(feature = "std"))]
+    return INTERNED_BYTES.load(Relaxed);
+}
+
+fn count_interned(bytes: usize) {
+    #[cfg(feature = "std")]
+    INTERNED_BYTES.with(|count| count.set(count.get().saturating_add(bytes)));
+    #[cfg(not(feature = "std"))]
+    INTERNED_BYTES.fetch_add(bytes, Relaxed);
+}
+
+#[cfg(feature = "std")]
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(Default::default);
+
+#[cfg(feature = "std")]
+fn interner() -> impl DerefMut<Target = Interner> {
+    INTERNER.lock().unwrap()
+}
+
+#[cfg(not(feature = "std"))]
+static INTERNER: SpinLock<Interner> = SpinLock::new(Interner {
+    ids: HashMap::new(),
+    names: Vec::new(),
+});
+
+#[cfg(not(feature = "std"))]
+fn interner() -> impl DerefMut<Target = Interner> {
+    INTERNER.lock()
+}
+
+/// Guards the interner where there is no `std` mutex. The lock is only held
+/// while a name is looked up or added, so waiting on it is short.
+#[cfg(not(feature = "std"))]
+struct SpinLock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: the value is only reached through a guard, and `locked` lets one
+// guard exist at a time.
+#[cfg(not(feature = "std"))]
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+#[cfg(not(feature = "std"))]
+impl<T> SpinLock<T> {
+    const fn new(value: T) -> Self {
+        SpinLock {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    fn lock(&self) -> SpinGuard<'_, T> {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Acquire, Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        SpinGuard { lock: self }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+struct SpinGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+#[cfg(not(feature = "std"))]
+impl<T> Deref for SpinGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the guard holds the lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<T> DerefMut for SpinGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the guard holds the lock.
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl<T> Drop for SpinGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Release);
+    }
+}
+
+impl SymbolId {
+    pub fn intern(name: &str) -> Self {
+        Self::intern_counted(name).0
+    }
+
+    /// Interns `name`, also returning the bytes this call added, which are
+    /// zero if the name was already interned.
+    pub fn intern_counted(name: &str) -> (Self, usize) {
+        let mut interner = interner();
+        if let Some(id) = interner.ids.get(name) {
+            return (*id, 0);
+        }
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        let id = SymbolId(interner.names.len() as u32);
+        interner.names.push(name);
+        interner.ids.insert(name, id);
+        // The name, its entry in `ids` and its slot in `names`.
+        let bytes = name.len() + 3 * core::mem::size_of::<&str>();
+        count_interned(bytes);
+        (id, bytes)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        interner().names[self.0 as usize]
+    }
+}
+
+impl From<&str> for SymbolId {
+    fn from(name: &str) -> Self {
+        SymbolId::intern(name)
+    }
+}
+
+/// Symbols are ordered by name.
+impl Ord for SymbolId {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self == other {
+            Ordering::Equal
+        } else {
+            self.as_str().cmp(other.as_str())
+        }
+    }
+}
+
+impl PartialOrd for SymbolId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for SymbolId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let a = SymbolId::intern("intern-a");
+        assert_eq!(a, SymbolId::intern("intern-a"));
+        assert_ne!(a, SymbolId::intern("intern-b"));
+        assert_eq!(a.as_str(), "intern-a");
+        assert!(a < SymbolId::intern("intern-b"));
+    }
+
+    #[test]
+    fn test_intern_counted() {
+        let (id, bytes) = SymbolId::intern_counted("intern-counted");
+        assert!(bytes > "intern-counted".len());
+        assert_eq!(SymbolId::intern_counted("intern-counted"), (id, 0));
+
+        // Names interned by another thread are not counted on this one.
+        #[cfg(feature = "std")]
+        {
+            let before = interned_bytes();
+            std::thread::spawn(|| SymbolId::intern("intern-counted-elsewhere"))
+                .join()
+                .unwrap();
+            assert_eq!(interned_bytes(), before);
+        }
+    }
+}
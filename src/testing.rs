@@ -0,0 +1,78 @@
+//! Running the tests of a module, written as `(deftest name body)` forms.
+//!
+//! A test passes if its body evaluates without an error. `assert` and
+//! `assert-eq` fail with an error that shows the failing call.
+
+use crate::{ast::Module, eval::EvalError, prelude::*};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResult {
+    pub name: String,
+    /// The error the body failed with, if the test failed.
+    pub error: Option<EvalError>,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Runs the tests of a loaded module in the order they are written. A failing
+/// test does not stop the others.
+pub fn run_tests(module: &Module) -> Vec<TestResult> {
+    module
+        .tests
+        .iter()
+        .map(|(name, body)| TestResult {
+            name: name.clone(),
+            error: module.eval(body.clone()).err(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::*, loader::load_module};
+
+    #[test]
+    fn test_run_tests() {
+        let source = r#"
+        (module test
+          (define double (x) (* x 2))
+          (deftest doubles (begin (assert-eq 4 (double 2)) (assert (= (double 0) 0))))
+          (deftest fails (assert-eq 5 (double 2)))
+          (deftest raises (assert (> (double 1) 3))))
+        "#;
+        let module = load_module(source).unwrap();
+        let results = run_tests(&module);
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| (result.name.as_str(), result.passed()))
+                .collect::<Vec<_>>(),
+            vec![("doubles", true), ("fails", false), ("raises", false)]
+        );
+        assert_eq!(
+            results[1].error.as_ref().map(EvalError::kind),
+            Some(&EvalError::AssertEqFailed(Box::new((
+                list(&[
+                    symbol("assert-eq"),
+                    integer(5),
+                    list(&[symbol("double"), integer(2)])
+                ]),
+                integer(5),
+                integer(4)
+            ))))
+        );
+        assert_eq!(
+            results[2].error.as_ref().unwrap().kind().to_string(),
+            "assertion failed: (assert (> (double 1) 3))"
+        );
+        assert_eq!(
+            results[1].error.as_ref().unwrap().kind().to_string(),
+            "assertion failed: (assert-eq 5 (double 2)): expected 5, got 4"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use crate::prelude::*;
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Location {
     pub line: usize,
@@ -10,6 +12,19 @@ impl Location {
     }
 }
 
+/// Source range from `start` up to, but not including, `end`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: Location,
+    pub end: Location,
+}
+
+impl Span {
+    pub fn new(start: Location, end: Location) -> Span {
+        Span { start, end }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Token {
     pub kind: TokenKind,
@@ -31,17 +46,73 @@ impl Token {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, Clone)]
 pub enum TokenKind {
     LParen,
     RParen,
+    /// `[`, which opens a vector literal.
+    LBracket,
+    RBracket,
+    /// `{`, which opens a map literal.
+    LBrace,
+    RBrace,
     Integer(i64),
+    #[cfg(feature = "bignum")]
+    BigInt(num_bigint::BigInt),
+    /// A fraction such as `1/3`, as written.
+    Rational(i64, i64),
+    Float(f64),
     Symbol(String),
     String(String),
+    /// `#u8(1 2 255)` or `#x"0102ff"`.
+    Bytes(Vec<u8>),
+    /// `#\\a`, or a named character such as `#\\space`.
+    Char(char),
+    Keyword(String),
     Quote,
+    /// `` ` ``, which quotes like `'`.
+    QuasiQuote,
+    /// `~` or `,`.
     UnQuote,
+    /// `~@` or `,@`.
+    UnQuoteSplicing,
+    /// A lone `.`, as in the parameter list `(x . rest)`.
+    Dot,
+}
+
+/// Floats compare by their bits, like [`Exp::Float`](crate::ast::Exp::Float),
+/// so that token equality is reflexive.
+impl PartialEq for TokenKind {
+    fn eq(&self, other: &Self) -> bool {
+        use TokenKind::*;
+        match (self, other) {
+            (Integer(a), Integer(b)) => a == b,
+            #[cfg(feature = "bignum")]
+            (BigInt(a), BigInt(b)) => a == b,
+            (Rational(n1, d1), Rational(n2, d2)) => (n1, d1) == (n2, d2),
+            (Float(a), Float(b)) => a.to_bits() == b.to_bits(),
+            (Symbol(a), Symbol(b)) | (String(a), String(b)) => a == b,
+            (Bytes(a), Bytes(b)) => a == b,
+            (Char(a), Char(b)) => a == b,
+            (Keyword(a), Keyword(b)) => a == b,
+            (LParen, LParen)
+            | (RParen, RParen)
+            | (LBracket, LBracket)
+            | (RBracket, RBracket)
+            | (LBrace, LBrace)
+            | (RBrace, RBrace)
+            | (Quote, Quote)
+            | (QuasiQuote, QuasiQuote)
+            | (UnQuote, UnQuote)
+            | (UnQuoteSplicing, UnQuoteSplicing)
+            | (Dot, Dot) => true,
+            _ => false,
+        }
+    }
 }
 
+impl Eq for TokenKind {}
+
 fn separator(ch: char) -> bool {
     matches!(ch, '\n') || ch.is_whitespace()
 }
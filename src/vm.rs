@@ -0,0 +1,418 @@
+use alloc::rc::Rc;
+
+use crate::{
+    ast::{apply, Env, Exp, Module},
+    compile::{compile, Chunk, Op},
+    eval::{
+        apply_to_variadic, call_builtin, call_host, eval_in, lookup, match_pattern, EvalConfig,
+        EvalError, Result, VariableGenerator,
+    },
+    prelude::*,
+    symbol::SymbolId,
+    HashMap,
+};
+
+struct Frame {
+    chunk: Rc<Chunk>,
+    pc: usize,
+    env: Env,
+    /// Arguments still to be applied to the value this frame returns, last
+    /// argument first.
+    pending: Vec<Exp>,
+}
+
+/// Executes bytecode produced by [`compile`].
+///
+/// Values are the same [`Exp`]s the tree-walking evaluator produces, so
+/// closures and builtins can be passed freely between the two. The body of a
+/// closure is compiled the first time it is called and cached afterwards.
+pub struct Vm<'a> {
+    module: &'a Module,
+    gen: VariableGenerator,
+    chunks: HashMap<*const Exp, (Rc<Exp>, Rc<Chunk>)>,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(module: &'a Module) -> Self {
+        Self::with_config(module, EvalConfig::default())
+    }
+
+    /// A VM whose runs stay within the limits of `config`, which it charges
+    /// like the tree-walking evaluator.
+    pub fn with_config(module: &'a Module, config: EvalConfig) -> Self {
+        Self::with_generator(module, VariableGenerator::with_config(config))
+    }
+
+    /// A VM that carries on with `gen`, so that its runs draw on the limits
+    /// left by earlier evaluations. [`Vm::into_generator`] gives it back.
+    pub fn with_generator(module: &'a Module, gen: VariableGenerator) -> Self {
+        Vm {
+            module,
+            gen,
+            chunks: HashMap::new(),
+        }
+    }
+
+    pub fn into_generator(self) -> VariableGenerator {
+        self.gen
+    }
+
+    fn chunk_for(&mut self, body: &Rc<Exp>) -> Rc<Chunk> {
+        let module = self.module;
+        let (_, chunk) = self
+            .chunks
+            .entry(Rc::as_ptr(body))
+            // The body is kept alive so that its address is not reused.
+            .or_insert_with(|| (body.clone(), Rc::new(compile(body, module))));
+        chunk.clone()
+    }
+
+    /// Applies `f` to `args` one at a time. A closure pushes a frame for its
+    /// body, which applies the remaining arguments to its result on return.
+    fn apply_args(
+        &mut self,
+        mut f: Exp,
+        mut pending: Vec<Exp>,
+        frames: &mut Vec<Frame>,
+        stack: &mut Vec<Exp>,
+    ) -> Result<()> {
+        loop {
+            if let Exp::Variadic(g) = f {
+                let args = core::mem::take(&mut pending).into_iter().rev().collect();
+                pending.push(Exp::List(args));
+                f = Rc::unwrap_or_clone(g);
+            }
+            let Some(arg) = pending.pop() else {
+                stack.push(f);
+                return Ok(());
+            };
+            if let Some(g) = apply_to_variadic(&f, &arg) {
+                f = g;
+                continue;
+            }
+            let (x, body, env) = match f {
+                Exp::Closure(x, body, env) => (x, body, env),
+                Exp::Lambda(x, body) => (x, body, Env::new()),
+                Exp::BuildIn(g) => {
+                    f = call_builtin(g, &[arg], self.module, &mut self.gen)?;
+                    continue;
+                }
+                Exp::HostFn(g) => {
+                    f = call_host(&g, &[arg], &mut self.gen)?;
+                    continue;
+                }
+                f => return Err(EvalError::FailedToApply(f, arg)),
+            };
+            if frames.len() >= self.gen.config().max_depth {
+                return Err(EvalError::DepthLimitExceeded);
+            }
+            let chunk = self.chunk_for(&body);
+            frames.push(Frame {
+                chunk,
+                pc: 0,
+                env: env.bind(x, arg),
+                pending,
+            });
+            return Ok(());
+        }
+    }
+
+    fn call(
+        &mut self,
+        f: Exp,
+        mut args: Vec<Exp>,
+        frames: &mut Vec<Frame>,
+        stack: &mut Vec<Exp>,
+    ) -> Result<()> {
+        match f {
+            Exp::BuildIn(f) => {
+                stack.push(call_builtin(f, &args, self.module, &mut self.gen)?);
+                return Ok(());
+            }
+            Exp::HostFn(f) => {
+                stack.push(call_host(&f, &args, &mut self.gen)?);
+                return Ok(());
+            }
+            _ => {}
+        }
+        // A call in tail position replaces the calling frame. If the frame still
+        // has arguments to apply to its result it is kept, so that a variadic
+        // function only takes the arguments of its own call.
+        let frame = frames.last().unwrap();
+        if frame.chunk.ops.get(frame.pc) == Some(&Op::Return) && frame.pending.is_empty() {
+            frames.pop();
+        }
+        args.reverse();
+        self.apply_args(f, args, frames, stack)
+    }
+
+    fn step(&mut self, frames: &mut Vec<Frame>, stack: &mut Vec<Exp>) -> Result<()> {
+        self.gen.step()?;
+        let frame = frames.last_mut().unwrap();
+        let chunk = frame.chunk.clone();
+        let op = &chunk.ops[frame.pc];
+        frame.pc += 1;
+        match op {
+            Op::Const(value) => stack.push(value.clone()),
+            Op::Load(sym) => stack.push(lookup(*sym, &frame.env, self.module)?),
+            Op::Closure(x, body) => {
+                stack.push(Exp::Closure(*x, body.clone(), frame.env.clone()));
+            }
+            Op::Variadic => {
+                let f = stack.pop().unwrap();
+                stack.push(Exp::Variadic(Rc::new(f)));
+            }
+            Op::Bind(x) => {
+                let value = stack.pop().unwrap();
+                frame.env = frame.env.bind(*x, value);
+            }
+            Op::BindRec(binds) => {
+                if let Some((_, b)) = binds
+                    .iter()
+                    .find(|(_, b)| !matches!(b.node(), Exp::Lambda(..)))
+                {
+                    return Err(EvalError::ExpectedLambda(b.clone()));
+                }
+                frame.env = frame.env.bind_rec(binds.clone());
+            }
+            Op::Unbind => frame.env = frame.env.parent(),
+            Op::Jump(target) => frame.pc = *target,
+            Op::JumpUnless(target, exp) => match stack.pop().unwrap() {
+                Exp::Bool(true) => {}
+                Exp::Bool(false) => frame.pc = *target,
+                _ => return Err(EvalError::ExpectedBool(exp.clone())),
+            },
+            Op::Match(pattern, target) => {
+                match match_pattern(pattern, stack.last().unwrap(), frame.env.clone()) {
+                    Some(env) => frame.env = env,
+                    None => frame.pc = *target,
+                }
+            }
+            Op::NoMatchingCase => return Err(EvalError::NoMatchingCase(stack.pop().unwrap())),
+            Op::Pop => {
+                stack.pop();
+            }
+            Op::Eval(exp) => {
+                let env = frame.env.clone();
+                stack.push(eval_in(exp.clone(), env, self.module, &mut self.gen)?);
+            }
+            Op::Call(n) => {
+                let args = stack.split_off(stack.len() - n);
+                let f = stack.pop().unwrap();
+                self.call(f, args, frames, stack)?;
+            }
+            Op::Return => {
+                let value = stack.pop().unwrap();
+                let frame = frames.pop().unwrap();
+                self.apply_args(value, frame.pending, frames, stack)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `chunk` to completion and returns its value.
+    pub fn run(&mut self, chunk: Rc<Chunk>) -> Result<Exp> {
+        let mut frames = vec![Frame {
+            chunk,
+            pc: 0,
+            env: Env::new(),
+            pending: vec![],
+        }];
+        let mut stack = vec![];
+        loop {
+            match self.step(&mut frames, &mut stack) {
+                Ok(()) if frames.is_empty() => return Ok(stack.pop().unwrap()),
+                Ok(()) => {}
+                Err(err) => {
+                    let span = frames.iter().rev().find_map(|frame| {
+                        frame.pc.checked_sub(1).and_then(|pc| frame.chunk.spans[pc])
+                    });
+                    return Err(match span {
+                        Some(span) => err.located(span),
+                        None => err,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Compiles and runs `exp`.
+    pub fn eval(&mut self, exp: &Exp) -> Result<Exp> {
+        let chunk = Rc::new(compile(exp, self.module));
+        self.run(chunk)
+    }
+
+    /// Applies the definition `name` of the module to `args` and runs it.
+    pub fn run_function(&mut self, name: &str, args: Vec<Exp>) -> Result<Exp> {
+        let mut exp = self
+            .module
+            .defines
+            .get(&SymbolId::intern(name))
+            .cloned()
+            .ok_or_else(|| EvalError::SymbolNotFound(name.to_string()))?;
+        for arg in args {
+            exp = apply(exp, arg);
+        }
+        self.eval(&exp)
+            .map_err(|err| err.called_from(SymbolId::intern(name)))
+    }
+}
+
+impl Module {
+    /// Like [`Module::run`], but compiles the program to bytecode and runs it
+    /// on the [`Vm`]. Worth it for long-running programs, where the one-off
+    /// compilation cost is outweighed by faster execution. The bytecode does
+    /// not keep track of calls, so the trace of an error only names the called
+    /// function.
+    pub fn run_compiled(&self, name: &str, args: Vec<Exp>) -> Result<Exp> {
+        self.run_compiled_with(name, args, EvalConfig::default())
+    }
+
+    /// Like [`Module::run_compiled`], within the limits of `config`.
+    pub fn run_compiled_with(&self, name: &str, args: Vec<Exp>, config: EvalConfig) -> Result<Exp> {
+        Vm::with_config(self, config).run_function(name, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::*, buildin::default_module, eval::eval_default_module, loader::load_module,
+        parser::parse_exp,
+    };
+
+    fn vm_eval(source: &str) -> Result<Exp> {
+        let module = default_module();
+        Vm::new(&module).eval(&parse_exp(source).unwrap())
+    }
+
+    #[test]
+    fn test_vm_matches_eval() {
+        for source in [
+            "(+ 1 (* 2 3))",
+            "(if (= 1 2) 'a 'b)",
+            "(let (x 1) (let (f (\\ y (+ x y))) (let (x 10) (f 2))))",
+            "(case (+ 1 1) (1 'one) (2 'two))",
+            "(case (list 1 (list 2 3)) ((list a (b c)) (+ a (* b c))))",
+            "(let (x 1) (list (case (list 2 3) ((cons x t) x)) x))",
+            "(list (case 5 (n (when (< n 0)) 'neg) (n (when (> n 0)) n)) 'after)",
+            "(case 1 (n (when false) n))",
+            "(list (case (list 1 2 3) ((list _ x _) x)) (case 5 (1 'one) (else 'other)))",
+            "(map (\\ x (* x x)) (list 1 2 3))",
+            "(foldr + 0 (list 1 2 3))",
+            "((\\ x (\\ y (- x y))) 5 3)",
+            "((\\ (x y z) (- x (+ y z))) 5 3 1)",
+            "((\\ (x . rest) (list x rest)) 1 2 3)",
+            "((\\ (x . rest) rest) 1)",
+            "((\\ (x y) (\\ (. zs) (list x y zs))) 1 2 3)",
+            "(list 1 '(a b) \"s\")",
+            "'(1 ~(+ 1 1))",
+            "(let (xs (list 2 3)) `(1 ,@xs ,(+ 2 2)))",
+            "(and (< 1 2) (or false (not false)))",
+            "(letrec ((even? (\\ n (if (= n 0) true (odd? (- n 1)))))
+                      (odd? (\\ n (if (= n 0) false (even? (- n 1))))))
+               (list (even? 10) (odd? 10)))",
+            "(letrec (x 1) x)",
+        ] {
+            let exp = parse_exp(source).unwrap();
+            assert_eq!(vm_eval(source), eval_default_module(exp), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_vm_tail_call() {
+        let module = load_module(
+            "(module main
+              (define count (n acc) (if (= n 0) acc (count (- n 1) (+ acc 1))))
+              (define main () (count 100000 0)))",
+        )
+        .unwrap();
+        assert_eq!(module.run_compiled("main", vec![]), Ok(integer(100000)));
+    }
+
+    #[test]
+    fn test_vm_macro() {
+        let module = load_module(
+            "(module main
+              (macro if-not (c e1 e2) '(if ~c ~e2 ~e1))
+              (define main () (if-not (= 1 2) 'yes 'no)))",
+        )
+        .unwrap();
+        assert_eq!(
+            module.run_compiled("main", vec![]),
+            module.run("main", vec![])
+        );
+    }
+
+    #[test]
+    fn test_vm_error() {
+        let err = vm_eval("(+ 1\n   (first 2))").unwrap_err();
+        assert!(matches!(err.kind(), EvalError::TypeMismatch { .. }));
+        assert_eq!(err.span().map(|span| span.start.line), Some(1));
+
+        assert_eq!(
+            vm_eval("(if 1 2 3)").map_err(|err| err.kind().clone()),
+            Err(EvalError::ExpectedBool(if_(
+                integer(1),
+                integer(2),
+                integer(3)
+            )))
+        );
+    }
+
+    #[test]
+    fn test_vm_fuel() {
+        let module = load_module("(module main (define loop (n) (loop (+ n 1))))").unwrap();
+        let config = EvalConfig {
+            fuel: Some(10_000),
+            ..EvalConfig::default()
+        };
+        assert_eq!(
+            module
+                .run_compiled_with("loop", vec![integer(0)], config)
+                .map_err(|err| err.kind().clone()),
+            Err(EvalError::FuelExhausted)
+        );
+    }
+
+    #[test]
+    fn test_vm_cancel() {
+        use crate::eval::CancelToken;
+
+        let module = load_module("(module main (define loop (n) (loop (+ n 1))))").unwrap();
+        let cancel = CancelToken::new();
+        let canceller = {
+            let cancel = cancel.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                cancel.cancel();
+            })
+        };
+        let config = EvalConfig {
+            cancel: Some(cancel),
+            ..EvalConfig::default()
+        };
+        assert_eq!(
+            module
+                .run_compiled_with("loop", vec![integer(0)], config)
+                .map_err(|err| err.kind().clone()),
+            Err(EvalError::Cancelled)
+        );
+        canceller.join().unwrap();
+
+        #[cfg(feature = "std")]
+        {
+            let config = EvalConfig {
+                deadline: Some(std::time::Instant::now() + std::time::Duration::from_millis(20)),
+                ..EvalConfig::default()
+            };
+            assert_eq!(
+                module
+                    .run_compiled_with("loop", vec![integer(0)], config)
+                    .map_err(|err| err.kind().clone()),
+                Err(EvalError::Cancelled)
+            );
+        }
+    }
+}
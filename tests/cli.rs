@@ -0,0 +1,40 @@
+//! Runs the `topogi` binary on scripts written to a temporary directory. The
+//! scripts print, so they need the `std-io` feature.
+#![cfg(feature = "std-io")]
+
+use std::{fs, path::PathBuf, process::Command};
+
+fn script(name: &str, source: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("topogi-cli-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, source).unwrap();
+    path
+}
+
+#[test]
+fn test_run() {
+    let path = script(
+        "ok.tg",
+        "(module main (define main () (begin (println \"hi\") (+ 1 2))))",
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_topogi"))
+        .arg("run")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "hi\n3\n");
+    assert!(output.stderr.is_empty());
+
+    let path = script("fails.tg", "(module main (define main () (first 2)))");
+    let output = Command::new(env!("CARGO_BIN_EXE_topogi"))
+        .arg("run")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("error: list/first expects"), "{}", stderr);
+}
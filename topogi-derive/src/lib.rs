@@ -0,0 +1,170 @@
+//! `#[derive(ToExp, FromExp)]` for the conversion traits of topogi-lang.
+//!
+//! A struct with named fields becomes a map from keywords naming its fields,
+//! written in kebab case, so that `max_depth` is `:max-depth`:
+//!
+//! ```text
+//! #[derive(ToExp, FromExp)]
+//! struct Server {
+//!     host: String,
+//!     port: i64,
+//!     #[topogi(rename = "timeout-ms")]
+//!     timeout: Option<i64>,
+//! }
+//! ```
+//!
+//! reads `{:host "localhost" :port 8080}`. With `#[topogi(alist)]` on the
+//! struct it is written as an association list `((:host "localhost") ...)`
+//! instead. Either form can be read, and an `Option` field may be left out.
+//! A struct with a single unnamed field converts as that field.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Fields, Generics, LitStr,
+    Result,
+};
+
+#[proc_macro_derive(ToExp, attributes(topogi))]
+pub fn derive_to_exp(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_to_exp(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(FromExp, attributes(topogi))]
+pub fn derive_from_exp(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_from_exp(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// The options of `#[topogi(...)]` on a struct or a field.
+#[derive(Default)]
+struct Options {
+    alist: bool,
+    rename: Option<String>,
+}
+
+fn options(attrs: &[Attribute]) -> Result<Options> {
+    let mut options = Options::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("topogi")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("alist") {
+                options.alist = true;
+                Ok(())
+            } else if meta.path.is_ident("rename") {
+                options.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("expected `alist` or `rename`"))
+            }
+        })?;
+    }
+    Ok(options)
+}
+
+/// The fields of a struct, which may not be an enum or a union.
+fn struct_fields(input: &DeriveInput) -> Result<&Fields> {
+    match &input.data {
+        Data::Struct(data) => Ok(&data.fields),
+        _ => Err(Error::new_spanned(
+            &input.ident,
+            "ToExp and FromExp can only be derived for structs",
+        )),
+    }
+}
+
+/// The keyword naming each named field, with its identifier.
+fn field_names<'a>(
+    fields: impl IntoIterator<Item = &'a syn::Field>,
+) -> Result<Vec<(&'a syn::Ident, String)>> {
+    fields
+        .into_iter()
+        .map(|field| {
+            let ident = field.ident.as_ref().expect("named field");
+            let name = match options(&field.attrs)?.rename {
+                Some(name) => name,
+                None => ident.to_string().trim_start_matches("r#").replace('_', "-"),
+            };
+            Ok((ident, name))
+        })
+        .collect()
+}
+
+/// `generics` with every type parameter bounded by `bound`.
+fn bounded(generics: &Generics, bound: syn::Path) -> Generics {
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
+
+fn expand_to_exp(input: &DeriveInput) -> Result<TokenStream2> {
+    let alist = options(&input.attrs)?.alist;
+    let body = match struct_fields(input)? {
+        Fields::Named(fields) => {
+            let entries = field_names(&fields.named)?
+                .into_iter()
+                .map(|(ident, name)| {
+                    quote!((#name, ::topogi_lang::convert::ToExp::to_exp(&self.#ident)))
+                });
+            quote!(::topogi_lang::convert::fields_to_exp(&[#(#entries),*], #alist))
+        }
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+            quote!(::topogi_lang::convert::ToExp::to_exp(&self.0))
+        }
+        fields => return Err(Error::new_spanned(fields, unsupported())),
+    };
+    let generics = bounded(&input.generics, parse_quote!(::topogi_lang::convert::ToExp));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let ident = &input.ident;
+    Ok(quote! {
+        impl #impl_generics ::topogi_lang::convert::ToExp for #ident #ty_generics #where_clause {
+            fn to_exp(&self) -> ::topogi_lang::ast::Exp {
+                #body
+            }
+        }
+    })
+}
+
+fn expand_from_exp(input: &DeriveInput) -> Result<TokenStream2> {
+    let ident = &input.ident;
+    let body = match struct_fields(input)? {
+        Fields::Named(fields) => {
+            let fields = field_names(&fields.named)?
+                .into_iter()
+                .map(|(ident, name)| quote!(#ident: fields.get(#name)?));
+            quote! {
+                let fields = ::topogi_lang::convert::Fields::new(exp)?;
+                ::core::result::Result::Ok(#ident { #(#fields),* })
+            }
+        }
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => quote! {
+            ::topogi_lang::convert::FromExp::from_exp(exp).map(#ident)
+        },
+        fields => return Err(Error::new_spanned(fields, unsupported())),
+    };
+    let generics = bounded(
+        &input.generics,
+        parse_quote!(::topogi_lang::convert::FromExp),
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::topogi_lang::convert::FromExp for #ident #ty_generics #where_clause {
+            fn from_exp(
+                exp: &::topogi_lang::ast::Exp,
+            ) -> ::topogi_lang::eval::Result<Self> {
+                #body
+            }
+        }
+    })
+}
+
+fn unsupported() -> &'static str {
+    "ToExp and FromExp can only be derived for structs with named fields or a single unnamed field"
+}
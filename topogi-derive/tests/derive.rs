@@ -0,0 +1,99 @@
+use topogi_lang::{
+    ast::*,
+    convert::{FromExp, ToExp},
+    eval::EvalError,
+    interpreter::Interpreter,
+};
+
+#[derive(Debug, PartialEq, ToExp, FromExp)]
+struct Server {
+    host: String,
+    port: i64,
+    max_connections: Option<i64>,
+    #[topogi(rename = "tags")]
+    labels: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, ToExp, FromExp)]
+#[topogi(alist)]
+struct Config {
+    name: Name,
+    servers: Vec<Server>,
+}
+
+#[derive(Debug, PartialEq, ToExp, FromExp)]
+struct Name(String);
+
+#[derive(Debug, PartialEq, ToExp, FromExp)]
+struct Wrapper<T> {
+    value: T,
+}
+
+#[test]
+fn test_to_exp() {
+    let server = Server {
+        host: "localhost".to_string(),
+        port: 8080,
+        max_connections: None,
+        labels: vec!["web".to_string()],
+    };
+    assert_eq!(
+        server.to_exp(),
+        map_of(&[
+            (keyword("host"), string("localhost")),
+            (keyword("port"), integer(8080)),
+            (keyword("max-connections"), Exp::Nil),
+            (keyword("tags"), list(&[string("web")])),
+        ])
+    );
+    let config = Config {
+        name: Name("prod".to_string()),
+        servers: vec![server],
+    };
+    let exp = config.to_exp();
+    assert_eq!(
+        exp.as_list().unwrap()[0],
+        list(&[keyword("name"), string("prod")])
+    );
+    assert_eq!(Config::from_exp(&exp), Ok(config));
+    assert_eq!(
+        Wrapper { value: 1i64 }.to_exp(),
+        map_of(&[(keyword("value"), integer(1))])
+    );
+}
+
+#[test]
+fn test_from_script() {
+    let mut interpreter = Interpreter::new();
+    let exp = interpreter
+        .eval_str(
+            r#"(list (list :name "prod")
+                     (list :servers (list {:host "a" :port (* 80 100) :tags '()})))"#,
+        )
+        .unwrap();
+    assert_eq!(
+        Config::from_exp(&exp),
+        Ok(Config {
+            name: Name("prod".to_string()),
+            servers: vec![Server {
+                host: "a".to_string(),
+                port: 8000,
+                max_connections: None,
+                labels: vec![],
+            }],
+        })
+    );
+}
+
+#[test]
+fn test_missing_field() {
+    let exp = map_of(&[(keyword("host"), string("a"))]);
+    assert_eq!(
+        Server::from_exp(&exp),
+        Err(EvalError::MissingField("port".to_string()))
+    );
+    assert!(matches!(
+        Wrapper::<i64>::from_exp(&map_of(&[(keyword("value"), string("1"))])),
+        Err(EvalError::TypeMismatch { .. })
+    ));
+}